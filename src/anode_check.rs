@@ -0,0 +1,183 @@
+//!
+//! Per-anode diagnostics: connecting each anode wire on its own to measure the path through it.
+//!
+//! In normal running several anodes share the drive current, so a corroded or broken wire hides
+//! behind the others. `check_anodes` connects each anode in turn, drives a small probe current,
+//! and records voltage, current and resistance for that anode alone. An anode that passes too
+//! little of the probe current is open; one whose resistance is well above the median of the
+//! others is suspect (corroded, or barely in the melt). Either counts as failed.
+//!
+//! The results can leave failed anodes out of a pattern (`AnodeSequence::excluding`), or balance
+//! a pattern's dwells by anode resistance (`AnodeSequence::balanced`).
+//!
+
+use serde::Serialize;
+
+use crate::*;
+use crate::anode_pattern::*;
+
+/// What a check made of an anode
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AnodeHealth {
+    #[default]
+    Ok,
+    /// Conducting, but with resistance well above the other anodes
+    High,
+    /// Too little of the probe current flows
+    Open,
+}
+
+impl AnodeHealth {
+    pub fn name(&self) -> &'static str {
+        match self {
+            AnodeHealth::Ok => "ok",
+            AnodeHealth::High => "high",
+            AnodeHealth::Open => "open",
+        }
+    }
+
+    pub fn is_failed(&self) -> bool {
+        *self != AnodeHealth::Ok
+    }
+}
+
+impl std::fmt::Display for AnodeHealth {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.pad(self.name())
+    }
+}
+
+/// Measurements with one anode connected
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct AnodeReading {
+    /// Anode number, from 1
+    pub anode: usize,
+    pub volts: f32,
+    pub measured_ma: f32,
+    pub ohms: f32,
+    pub health: AnodeHealth,
+}
+
+/// How a per-anode check is run and judged
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AnodeCheckConfig {
+    /// Drive current with each anode connected
+    pub probe_ma: f32,
+    /// Wait after switching relays, before driving current
+    pub settle: Duration,
+    /// Fraction of the probe current below which an anode is open
+    pub open_fraction: f32,
+    /// Resistance, as a multiple of the median of the conducting anodes, above which an anode is suspect
+    pub high_ratio: f32,
+}
+
+impl Default for AnodeCheckConfig {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+impl AnodeCheckConfig {
+    /// Starting points, to be tuned against the rig. The probe current goes no higher than twice the
+    /// smallest increment: above that, `drive_current_and_measure` trusts the current source over a low
+    /// measured current, which would hide an open anode.
+    pub const DEFAULT: AnodeCheckConfig = AnodeCheckConfig {
+        probe_ma: 2. * MIN_DRIVE_CURRENT_INCR_MA,
+        settle: Duration::from_millis(200),
+        open_fraction: 0.5,
+        high_ratio: 2.,
+    };
+}
+
+/// The readings from one check of every anode
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct AnodeCheck {
+    /// UTC epoch milliseconds at which the check finished
+    pub epoch_ms: i64,
+    pub probe_ma: f32,
+    pub readings: Vec<AnodeReading>,
+}
+
+impl AnodeCheck {
+    /// Judge raw (volts, mA, ohms) readings, one per anode in anode order
+    pub fn judge(epoch_ms: i64, config: &AnodeCheckConfig, raw: &[(f32, f32, f32)]) -> Self {
+        let open = |measured_ma: f32, ohms: f32|
+            measured_ma < config.probe_ma * config.open_fraction || !(ohms.is_finite() && ohms < INF_INTER_ELECTRODE_OHMS);
+        let mut conducting: Vec<f32> = raw.iter()
+            .filter(|(_, measured_ma, ohms)| !open(*measured_ma, *ohms))
+            .map(|(_, _, ohms)| *ohms)
+            .collect();
+        conducting.sort_by(f32::total_cmp);
+        // the median needs others to compare with
+        let median_ohms = (conducting.len() >= 2).then(|| conducting[conducting.len() / 2]);
+        let readings = raw.iter().enumerate().map(|(idx, (volts, measured_ma, ohms))| {
+            let health =
+                if open(*measured_ma, *ohms) { AnodeHealth::Open }
+                else if median_ohms.is_some_and(|median_ohms| *ohms > median_ohms * config.high_ratio) { AnodeHealth::High }
+                else { AnodeHealth::Ok };
+            AnodeReading { anode: idx + 1, volts: *volts, measured_ma: *measured_ma, ohms: *ohms, health }
+        }).collect();
+        Self { epoch_ms, probe_ma: config.probe_ma, readings }
+    }
+
+    /// The anodes that failed the check
+    pub fn failed_mask(&self) -> AnodeMask {
+        self.readings.iter()
+            .filter(|reading| reading.health.is_failed())
+            .fold(0, |mask, reading| mask | 1 << (reading.anode - 1))
+    }
+
+    /// Resistance through each anode, in anode order, for those that conduct
+    pub fn anode_ohms(&self) -> Vec<Option<f32>> {
+        self.readings.iter().map(|reading| (reading.health != AnodeHealth::Open).then_some(reading.ohms)).collect()
+    }
+
+    /// One line summary, e.g. `1 12.3 Ω, 2 open, 3 41.0 Ω high`
+    pub fn summary(&self) -> String {
+        self.readings.iter().map(|reading| match reading.health {
+            AnodeHealth::Open => format!("{} open", reading.anode),
+            AnodeHealth::High => format!("{} {:.1} Ω high", reading.anode, reading.ohms),
+            AnodeHealth::Ok => format!("{} {:.1} Ω", reading.anode, reading.ohms),
+        }).collect::<Vec<_>>().join(", ")
+    }
+}
+
+impl std::fmt::Display for AnodeCheck {
+    /// A table of the readings
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Anode check at {:.1} mA:", self.probe_ma)?;
+        writeln!(f, "anode        V       mA         Ω  health")?;
+        for reading in &self.readings {
+            writeln!(f, "{:>5} {:>8.3} {:>8.3} {:>9.2}  {}",
+                reading.anode, reading.volts, reading.measured_ma, reading.ohms, reading.health)?;
+        }
+        Ok(())
+    }
+}
+
+/// Connect each anode on its own (anode `n` on octo relay channel `relay_channels[n - 1]`),
+/// drive the probe current and measure. Leaves the drive current at zero and every anode
+/// disconnected: whoever normally switches the anode relays must be kept off them meanwhile.
+pub async fn check_anodes(ctx: &mut tokio_modbus::client::Context, relay_channels: &[u8], config: &AnodeCheckConfig)
+-> Result<AnodeCheck, Box<dyn std::error::Error>>
+{
+    check_anode_relays(relay_channels)?;
+    let measured = async {
+        let mut raw = Vec::with_capacity(relay_channels.len());
+        for idx in 0..relay_channels.len() {
+            write_anode_relays(ctx, relay_channels, 1 << idx).await?;
+            sleep(config.settle).await;
+            raw.push(drive_current_and_measure(ctx, config.probe_ma).await?);
+        }
+        Ok::<_, Box<dyn std::error::Error>>(raw)
+    }.await;
+
+    // whatever the outcome, stop driving current and disconnect the anodes, then report the first failure
+    let drive_off = set_electrode_current_drive(ctx, 0.).await;
+    let disconnected = write_anode_relays(ctx, relay_channels, 0).await;
+    let measured = measured?;
+    drive_off?;
+    disconnected?;
+    Ok(AnodeCheck::judge(chrono::Utc::now().timestamp_millis(), config, &measured))
+}
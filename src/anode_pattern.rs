@@ -0,0 +1,530 @@
+//!
+//! Anode connection patterns, and a driver that switches the anode relays on its own time base.
+//!
+//! The anode ring has up to eight supply wires, each switched onto the current source by a relay.
+//! An `AnodeSequence` is a cycle of steps, each a set of anodes held for a dwell time, built from
+//! a pattern: all on, a single anode rotating, adjacent pairs rotating (a primary with a trailing
+//! secondary), opposite pairs, a fresh random order each cycle, or a custom list of anode sets.
+//! Which step is active is a pure function of the time since the sequence started.
+//!
+//! `spawn_anode_driver` applies an `AnodeDrive` (a fixed set, or a sequence) on a task of its own,
+//! with its own Modbus handle, waking at each step boundary: dwell times hold however long the
+//! control loop takes. The relays are also rewritten periodically, in case a write was lost.
+//! The driver can release the relays to another user, such as a per-anode check (see `anode_check`).
+//!
+
+use std::str::FromStr;
+
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+
+use crate::*;
+use crate::cli::parse_u16;
+
+/// Most anode wires the ring has
+pub const MAX_ANODES: usize = 8;
+
+/// A set of anodes: bit 0 is anode 1
+pub type AnodeMask = u8;
+
+/// Octo relay channel switching each anode, in anode order, unless others are given (see `parse_anode_relays`):
+/// each relay connects a pair of the ring's eight supply wires. Relay 7 is the furnace heater, so switching
+/// the wires one by one needs spare relay channels elsewhere.
+pub const DEFAULT_ANODE_RELAY_CHANNELS: [u8; 4] = [1, 2, 3, 4];
+
+/// Channels on the octo relay board
+const OCTO_RELAY_CHANNEL_COUNT: u8 = 8;
+
+/// Relays are rewritten at least this often, even when nothing changes
+const ANODE_REFRESH_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Longest a relay write may take before it's retried on the next wakeup
+const ANODE_WRITE_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Most a balanced sequence stretches, or shrinks, a step's dwell
+const MAX_BALANCE_FACTOR: f32 = 2.;
+
+/// Every anode of `anode_count`
+pub fn all_anodes_mask(anode_count: usize) -> AnodeMask {
+    ((1u16 << anode_count) - 1) as AnodeMask
+}
+
+/// Anodes in a mask, numbered from 1, e.g. `1+2`
+pub fn format_anode_mask(mask: AnodeMask) -> String {
+    let anodes: Vec<String> = (0..MAX_ANODES).filter(|idx| mask & (1 << idx) != 0).map(|idx| (idx + 1).to_string()).collect();
+    if anodes.is_empty() { "none".to_string() } else { anodes.join("+") }
+}
+
+/// How anodes take turns on the current source
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AnodePattern {
+    AllOn,
+    /// One anode at a time, around the ring
+    Single,
+    /// An anode with the one before it still connected, around the ring: smooths field changes
+    Pairs,
+    /// Diametrically opposite anodes together, around the ring (needs an even anode count)
+    OppositePairs,
+    /// One anode at a time, in a fresh shuffled order each cycle, so each still gets an equal share
+    Random,
+    /// Explicit anode sets, in order
+    Custom(Vec<AnodeMask>),
+}
+
+impl AnodePattern {
+    /// The steps of one cycle for `anode_count` anodes; a random pattern is shuffled per cycle later
+    pub fn steps(&self, anode_count: usize) -> Result<Vec<AnodeMask>, String> {
+        if !(1..=MAX_ANODES).contains(&anode_count) {
+            return Err(format!("anode count {anode_count} outside 1..{MAX_ANODES}"));
+        }
+        let anode = |idx: usize| 1 << (idx % anode_count);
+        Ok(match self {
+            AnodePattern::AllOn => vec![all_anodes_mask(anode_count)],
+            AnodePattern::Single | AnodePattern::Random => (0..anode_count).map(anode).collect(),
+            AnodePattern::Pairs => (0..anode_count).map(|idx| anode(idx + 1) | anode(idx)).collect(),
+            AnodePattern::OppositePairs => {
+                if !anode_count.is_multiple_of(2) {
+                    return Err(format!("opposite pairs need an even number of anodes, not {anode_count}"));
+                }
+                (0..anode_count / 2).map(|idx| anode(idx) | anode(idx + anode_count / 2)).collect()
+            }
+            AnodePattern::Custom(steps) => {
+                if steps.is_empty() {
+                    return Err("custom anode pattern has no steps".into());
+                }
+                if let Some(step) = steps.iter().find(|step| **step & !all_anodes_mask(anode_count) != 0) {
+                    return Err(format!("anode step {} uses anodes beyond {anode_count}", format_anode_mask(*step)));
+                }
+                steps.clone()
+            }
+        })
+    }
+}
+
+impl FromStr for AnodePattern {
+    type Err = String;
+
+    /// `all`, `single`, `pairs`, `opposite`, `random`, or custom steps such as `1+5,2+6,3,4`
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        match text.to_ascii_lowercase().as_str() {
+            "all" => Ok(AnodePattern::AllOn),
+            "single" => Ok(AnodePattern::Single),
+            "pairs" => Ok(AnodePattern::Pairs),
+            "opposite" => Ok(AnodePattern::OppositePairs),
+            "random" => Ok(AnodePattern::Random),
+            custom => {
+                let steps = custom.split(',').map(|step| {
+                    step.split('+').try_fold(0 as AnodeMask, |mask, anode| {
+                        match anode.trim().parse::<usize>() {
+                            Ok(anode) if (1..=MAX_ANODES).contains(&anode) => Ok(mask | 1 << (anode - 1)),
+                            _ => Err(format!("Bad anode {anode:?} in pattern {text:?}: use all, single, pairs, \
+                                opposite, random, or steps like 1+5,2+6")),
+                        }
+                    })
+                }).collect::<Result<Vec<_>, _>>()?;
+                Ok(AnodePattern::Custom(steps))
+            }
+        }
+    }
+}
+
+impl std::fmt::Display for AnodePattern {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AnodePattern::AllOn => f.write_str("all"),
+            AnodePattern::Single => f.write_str("single"),
+            AnodePattern::Pairs => f.write_str("pairs"),
+            AnodePattern::OppositePairs => f.write_str("opposite"),
+            AnodePattern::Random => f.write_str("random"),
+            AnodePattern::Custom(steps) =>
+                f.write_str(&steps.iter().map(|step| format_anode_mask(*step)).collect::<Vec<_>>().join(",")),
+        }
+    }
+}
+
+/// A pattern laid out in time: the steps of a cycle and how long each is held
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AnodeSequence {
+    pub pattern: AnodePattern,
+    pub anode_count: usize,
+    steps: Vec<AnodeMask>,
+    /// Dwell of each step, in milliseconds
+    dwells_ms: Vec<u64>,
+    cycle_ms: u64,
+    /// Anodes left out of the pattern
+    excluded: AnodeMask,
+    /// Whether the dwells were balanced by anode resistance
+    balanced: bool,
+}
+
+impl AnodeSequence {
+    /// Lay out `pattern` for `anode_count` anodes; step dwells repeat `dwell_ms` cyclically,
+    /// so a single value applies to every step
+    pub fn new(pattern: AnodePattern, anode_count: usize, dwell_ms: &[u64]) -> Result<Self, String> {
+        let steps = pattern.steps(anode_count)?;
+        if dwell_ms.is_empty() || dwell_ms.contains(&0) {
+            return Err("anode dwell times must be given, and above 0 ms".into());
+        }
+        let dwells_ms: Vec<u64> = (0..steps.len()).map(|idx| dwell_ms[idx % dwell_ms.len()]).collect();
+        let cycle_ms = dwells_ms.iter().sum();
+        Ok(Self { pattern, anode_count, steps, dwells_ms, cycle_ms, excluded: 0, balanced: false })
+    }
+
+    /// The sequence without the `excluded` anodes: they leave every step, and steps left empty are dropped
+    pub fn excluding(&self, excluded: AnodeMask) -> Result<Self, String> {
+        let (steps, dwells_ms): (Vec<AnodeMask>, Vec<u64>) = self.steps.iter().zip(&self.dwells_ms)
+            .map(|(step, dwell_ms)| (step & !excluded, *dwell_ms))
+            .filter(|(step, _)| *step != 0)
+            .unzip();
+        if steps.is_empty() {
+            return Err(format!("excluding anodes {} leaves none of {self}", format_anode_mask(excluded)));
+        }
+        let cycle_ms = dwells_ms.iter().sum();
+        Ok(Self { steps, dwells_ms, cycle_ms, excluded: self.excluded | excluded, ..self.clone() })
+    }
+
+    /// The sequence with each step's dwell scaled by the resistance through its anodes, relative to
+    /// the average step, so that at a steady drive voltage each step passes about the same charge.
+    /// `anode_ohms` is by anode, `None` where unknown; steps with no known anode keep their dwell.
+    pub fn balanced(&self, anode_ohms: &[Option<f32>]) -> Self {
+        // anodes in a step are in parallel
+        let step_ohms: Vec<Option<f32>> = self.steps.iter().map(|step| {
+            let siemens: f32 = anode_ohms.iter().enumerate()
+                .filter(|(idx, _)| step & (1 << idx) != 0)
+                .filter_map(|(_, ohms)| ohms.filter(|ohms| ohms.is_finite() && *ohms > 0.))
+                .map(|ohms| 1. / ohms)
+                .sum();
+            (siemens > 0.).then(|| 1. / siemens)
+        }).collect();
+        let known: Vec<f32> = step_ohms.iter().flatten().copied().collect();
+        if known.is_empty() {
+            return self.clone();
+        }
+        let mean_ohms = known.iter().sum::<f32>() / known.len() as f32;
+        let dwells_ms: Vec<u64> = self.dwells_ms.iter().zip(&step_ohms).map(|(dwell_ms, ohms)| {
+            let factor = ohms.map_or(1., |ohms| (ohms / mean_ohms).clamp(1. / MAX_BALANCE_FACTOR, MAX_BALANCE_FACTOR));
+            ((*dwell_ms as f32 * factor).round() as u64).max(1)
+        }).collect();
+        let cycle_ms = dwells_ms.iter().sum();
+        Self { dwells_ms, cycle_ms, balanced: true, ..self.clone() }
+    }
+
+    pub fn cycle_ms(&self) -> u64 {
+        self.cycle_ms
+    }
+
+    /// The anodes connected `elapsed_ms` into the sequence, and how long until that changes
+    pub fn mask_at(&self, elapsed_ms: u64) -> (AnodeMask, u64) {
+        let cycle = elapsed_ms / self.cycle_ms;
+        let mut into_cycle_ms = elapsed_ms % self.cycle_ms;
+        // a shuffled step keeps its own dwell
+        let step_at = |position: usize| match self.pattern {
+            AnodePattern::Random => shuffled_index(cycle, position, self.steps.len()),
+            _ => position,
+        };
+        let mut position = 0;
+        while into_cycle_ms >= self.dwells_ms[step_at(position)] {
+            into_cycle_ms -= self.dwells_ms[step_at(position)];
+            position += 1;
+        }
+        let step_idx = step_at(position);
+        (self.steps[step_idx], self.dwells_ms[step_idx] - into_cycle_ms)
+    }
+}
+
+impl std::fmt::Display for AnodeSequence {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let dwells: Vec<String> = if self.dwells_ms.iter().all(|dwell_ms| *dwell_ms == self.dwells_ms[0]) {
+            vec![self.dwells_ms[0].to_string()]
+        }
+        else { self.dwells_ms.iter().map(u64::to_string).collect() };
+        write!(f, "{} over {} anodes", self.pattern, self.anode_count)?;
+        if self.excluded != 0 {
+            write!(f, " without {}", format_anode_mask(self.excluded))?;
+        }
+        write!(f, ", dwell {} ms", dwells.join(","))?;
+        if self.balanced {
+            f.write_str(" balanced")?;
+        }
+        Ok(())
+    }
+}
+
+/// Position `step_idx` of a permutation of `len` items that is fixed for each `cycle`
+fn shuffled_index(cycle: u64, step_idx: usize, len: usize) -> usize {
+    // Fisher-Yates with a small xorshift generator seeded by the cycle number
+    let mut state = cycle.wrapping_mul(0x9E37_79B9_7F4A_7C15) | 1;
+    let mut order: Vec<usize> = (0..len).collect();
+    for idx in (1..len).rev() {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        order.swap(idx, (state % (idx as u64 + 1)) as usize);
+    }
+    order[step_idx]
+}
+
+/// What the anode relays should be doing
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AnodeDrive {
+    Fixed(AnodeMask),
+    /// Relays left to someone else, e.g. a per-anode check: the driver doesn't touch them
+    Released,
+    /// A sequence running since a UTC epoch millisecond
+    Sequence { sequence: AnodeSequence, start_ms: i64 },
+}
+
+impl AnodeDrive {
+    /// The anodes connected at `epoch_ms`, and when that next changes (none, if released)
+    pub fn mask_at(&self, epoch_ms: i64) -> (AnodeMask, Option<i64>) {
+        match self {
+            AnodeDrive::Fixed(mask) => (*mask, None),
+            AnodeDrive::Released => (0, None),
+            AnodeDrive::Sequence { sequence, start_ms } => {
+                let (mask, remaining_ms) = sequence.mask_at((epoch_ms - start_ms).max(0) as u64);
+                (mask, Some(epoch_ms + remaining_ms as i64))
+            }
+        }
+    }
+}
+
+impl std::fmt::Display for AnodeDrive {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AnodeDrive::Fixed(mask) => write!(f, "fixed {}", format_anode_mask(*mask)),
+            AnodeDrive::Released => f.write_str("released"),
+            AnodeDrive::Sequence { sequence, .. } => write!(f, "{sequence}"),
+        }
+    }
+}
+
+/// Parse the relay channels switching the anodes, one per anode in anode order, e.g. `1,2,3,4,5,6,8`
+pub fn parse_anode_relays(list: &str) -> Result<Vec<u8>, String> {
+    let relay_channels = list.split(',')
+        .map(|channel| parse_u16(channel.trim()).ok()
+            .and_then(|channel| u8::try_from(channel).ok())
+            .ok_or_else(|| format!("bad relay channel {channel:?}: use 1..{OCTO_RELAY_CHANNEL_COUNT}")))
+        .collect::<Result<Vec<u8>, String>>()?;
+    check_anode_relays(&relay_channels)?;
+    Ok(relay_channels)
+}
+
+/// Check that relay channels can switch anodes: one to eight distinct octo relay channels,
+/// none of them the furnace heater's
+pub fn check_anode_relays(relay_channels: &[u8]) -> Result<(), String> {
+    if !(1..=MAX_ANODES).contains(&relay_channels.len()) {
+        return Err(format!("{} anode relays: between 1 and {MAX_ANODES} are supported", relay_channels.len()));
+    }
+    if let Some(channel) = relay_channels.iter().find(|channel| !(1..=OCTO_RELAY_CHANNEL_COUNT).contains(*channel)) {
+        return Err(format!("bad relay channel {channel}: use 1..{OCTO_RELAY_CHANNEL_COUNT}"));
+    }
+    if relay_channels.contains(&FURNACE_RELAY_CHANNEL) {
+        return Err(format!("relay channel {FURNACE_RELAY_CHANNEL} switches the furnace heater, not an anode"));
+    }
+    if let Some((idx, channel)) = relay_channels.iter().enumerate().find(|(idx, channel)| relay_channels[..*idx].contains(channel)) {
+        return Err(format!("relay channel {channel} switches anode {} and an earlier anode", idx + 1));
+    }
+    Ok(())
+}
+
+/// Parse step dwell times such as `1000` or `2000,500`, in milliseconds
+pub fn parse_anode_dwells(list: &str) -> Result<Vec<u64>, String> {
+    list.split(',')
+        .map(|item| item.trim().parse::<u64>().ok().filter(|ms| *ms > 0).ok_or_else(|| format!("Bad dwell time: {item:?}")))
+        .collect()
+}
+
+/// Handle on the anode relay task
+pub struct AnodeDriver {
+    drive: watch::Sender<AnodeDrive>,
+    /// The mask last written to the relays, or `None` while they're released
+    applied: watch::Receiver<Option<AnodeMask>>,
+    anode_count: usize,
+    task: JoinHandle<()>,
+}
+
+impl AnodeDriver {
+    /// Change what the relays do; setting the same drive again doesn't restart a sequence
+    pub fn set(&self, drive: &AnodeDrive) {
+        self.drive.send_if_modified(|current| {
+            if current == drive { false } else { *current = drive.clone(); true }
+        });
+    }
+
+    /// The anodes connected, as last written to the relays (none while they're released)
+    pub fn connections(&self) -> Vec<bool> {
+        let mask = self.applied.borrow().unwrap_or(0);
+        (0..self.anode_count).map(|idx| mask & (1 << idx) != 0).collect()
+    }
+
+    /// Hand the relays over, waiting for any write in progress to finish; `set` another drive to take them back
+    pub async fn release(&self) -> Result<(), Box<dyn std::error::Error>> {
+        self.set(&AnodeDrive::Released);
+        let mut applied = self.applied.clone();
+        tokio::time::timeout(ANODE_WRITE_TIMEOUT * 2, applied.wait_for(Option::is_none)).await
+            .map_err(|_| "anode driver didn't release the relays")?
+            .map_err(|_| "anode driver has stopped")?;
+        Ok(())
+    }
+
+    /// Stop switching relays, leaving them as they are
+    pub fn stop(self) {
+        self.task.abort();
+    }
+}
+
+/// Write an anode mask to the relays, anode `n` on octo relay channel `relay_channels[n - 1]`
+pub async fn write_anode_relays(ctx: &mut tokio_modbus::client::Context, relay_channels: &[u8], mask: AnodeMask)
+-> Result<(), Box<dyn std::error::Error>>
+{
+    check_anode_relays(relay_channels)?;
+    let contiguous = relay_channels.iter().enumerate().all(|(idx, channel)| *channel as usize == idx + 1);
+    if contiguous {
+        let values: Vec<bool> = (0..relay_channels.len()).map(|idx| mask & (1 << idx) != 0).collect();
+        return write_wav_octo_relays(ctx, &values).await;
+    }
+    for (idx, channel) in relay_channels.iter().enumerate() {
+        toggle_wav_octo_relay(ctx, *channel, mask & (1 << idx) != 0).await?;
+    }
+    Ok(())
+}
+
+/// Start switching the anode relays on `relay_channels` (one per anode, in anode order), all off to begin with
+pub fn spawn_anode_driver(mut ctx: tokio_modbus::client::Context, relay_channels: Vec<u8>)
+-> Result<AnodeDriver, String>
+{
+    check_anode_relays(&relay_channels)?;
+    let anode_count = relay_channels.len();
+    let (drive, mut drive_rx) = watch::channel(AnodeDrive::Fixed(0));
+    let (task_applied, applied) = watch::channel(Some(0));
+    let task = tokio::spawn(async move {
+        let mut written: Option<AnodeMask> = None;
+        let mut last_write = tokio::time::Instant::now();
+        loop {
+            let now_ms = chrono::Utc::now().timestamp_millis();
+            let drive = drive_rx.borrow_and_update().clone();
+            if drive == AnodeDrive::Released {
+                // whoever took the relays over may leave them in any state
+                written = None;
+                task_applied.send_replace(None);
+                if drive_rx.changed().await.is_err() { break }
+                continue;
+            }
+            let (mask, next_change_ms) = drive.mask_at(now_ms);
+            let mask = mask & all_anodes_mask(anode_count);
+            if written != Some(mask) || last_write.elapsed() >= ANODE_REFRESH_INTERVAL {
+                match tokio::time::timeout(ANODE_WRITE_TIMEOUT, write_anode_relays(&mut ctx, &relay_channels, mask)).await {
+                    Ok(Ok(())) => {
+                        written = Some(mask);
+                        task_applied.send_replace(Some(mask));
+                    }
+                    Ok(Err(e)) => {
+                        println!("{now_ms} anode relays {} failed: {e}", format_anode_mask(mask));
+                        written = None;
+                    }
+                    Err(_) => {
+                        println!("{now_ms} anode relays {} timed out", format_anode_mask(mask));
+                        written = None;
+                    }
+                }
+                last_write = tokio::time::Instant::now();
+            }
+            let until_change = next_change_ms
+                .map(|change_ms| Duration::from_millis((change_ms - chrono::Utc::now().timestamp_millis()).max(1) as u64))
+                .unwrap_or(ANODE_REFRESH_INTERVAL);
+            let wait = until_change.min(ANODE_REFRESH_INTERVAL)
+                .min(ANODE_REFRESH_INTERVAL.saturating_sub(last_write.elapsed()).max(Duration::from_millis(1)));
+            tokio::select! {
+                changed = drive_rx.changed() => if changed.is_err() { break },
+                _ = sleep(wait) => {}
+            }
+        }
+    });
+    Ok(AnodeDriver { drive, applied, anode_count, task })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn steps_change_on_dwell_boundaries() {
+        let sequence = AnodeSequence::new(AnodePattern::Pairs, 4, &[1000, 500]).unwrap();
+        assert_eq!(sequence.cycle_ms(), 3000);
+        assert_eq!(sequence.mask_at(0), (0b0011, 1000));
+        assert_eq!(sequence.mask_at(999), (0b0011, 1));
+        assert_eq!(sequence.mask_at(1000), (0b0110, 500));
+        assert_eq!(sequence.mask_at(1499), (0b0110, 1));
+        assert_eq!(sequence.mask_at(1500), (0b1100, 1000));
+        assert_eq!(sequence.mask_at(2750), (0b1001, 250));
+        // and around again
+        assert_eq!(sequence.mask_at(3000), (0b0011, 1000));
+        assert_eq!(sequence.mask_at(3000 * 7 + 1200), (0b0110, 300));
+    }
+
+    #[test]
+    fn random_is_a_permutation_each_cycle() {
+        let sequence = AnodeSequence::new(AnodePattern::Random, 5, &[100]).unwrap();
+        let mut orders = Vec::new();
+        for cycle in 0..20 {
+            let order: Vec<AnodeMask> = (0..5).map(|step| sequence.mask_at(cycle * 500 + step * 100).0).collect();
+            let mut sorted = order.clone();
+            sorted.sort();
+            assert_eq!(sorted, [0b00001, 0b00010, 0b00100, 0b01000, 0b10000], "cycle {cycle}: {order:?}");
+            orders.push(order);
+        }
+        assert!(orders.iter().any(|order| *order != orders[0]), "every cycle in the same order");
+    }
+
+    #[test]
+    fn excluding_drops_emptied_steps() {
+        let sequence = AnodeSequence::new(AnodePattern::Pairs, 4, &[1000]).unwrap();
+        let without = sequence.excluding(0b0011).unwrap();
+        assert_eq!(without.cycle_ms(), 3000);
+        assert_eq!(without.mask_at(0), (0b0100, 1000));
+        assert_eq!(without.mask_at(1000), (0b1100, 1000));
+        assert_eq!(without.mask_at(2000), (0b1000, 1000));
+        assert_eq!(without.to_string(), "pairs over 4 anodes without 1+2, dwell 1000 ms");
+        assert!(sequence.excluding(0b1111).is_err());
+    }
+
+    #[test]
+    fn balancing_clamps_the_dwell_factor() {
+        let sequence = AnodeSequence::new(AnodePattern::Single, 4, &[1000]).unwrap();
+        // mean 257.5 Ω: the low anodes would get 0.04 of the dwell and the high one 3.9 times it
+        let balanced = sequence.balanced(&[Some(10.), Some(10.), Some(10.), Some(1000.)]);
+        let dwells: Vec<u64> = (0..4).map(|step| balanced.mask_at([0, 500, 1000, 1500][step]).1).collect();
+        assert_eq!(dwells, [500, 500, 500, 2000]);
+
+        // unknown anodes keep their dwell, and the known ones balance among themselves
+        let balanced = sequence.balanced(&[Some(10.), Some(20.), None, None]);
+        assert_eq!(balanced.cycle_ms(), 667 + 1333 + 1000 + 1000);
+        assert_eq!(sequence.balanced(&[None; 4]), sequence);
+    }
+
+    #[test]
+    fn custom_pattern_grammar() {
+        let pattern: AnodePattern = "1+5,2+6,3,4".parse().unwrap();
+        assert_eq!(pattern, AnodePattern::Custom(vec![0b0001_0001, 0b0010_0010, 0b0000_0100, 0b0000_1000]));
+        assert_eq!(pattern.to_string(), "1+5,2+6,3,4");
+        assert_eq!(" 2 + 1 ".parse::<AnodePattern>().unwrap(), AnodePattern::Custom(vec![0b0011]));
+        assert_eq!("Opposite".parse::<AnodePattern>().unwrap(), AnodePattern::OppositePairs);
+        for bad in ["1+9", "0", "1,,2", "1+x", ""] {
+            assert!(bad.parse::<AnodePattern>().is_err(), "{bad:?}");
+        }
+        // anodes beyond those wired
+        assert!(pattern.steps(4).is_err());
+        assert!(pattern.steps(6).is_ok());
+    }
+
+    #[test]
+    fn anode_relays_keep_off_the_furnace() {
+        assert_eq!(parse_anode_relays("1,2,3,4,5,6,8").unwrap(), [1, 2, 3, 4, 5, 6, 8]);
+        assert!(parse_anode_relays("5,6,7,8").is_err());
+        assert!(parse_anode_relays("1,2,3,4,5,6,7,8").is_err());
+        assert!(parse_anode_relays("1,2,1").is_err());
+        assert!(parse_anode_relays("0").is_err());
+        assert!(parse_anode_relays("9").is_err());
+        assert!(check_anode_relays(&[]).is_err());
+    }
+}
@@ -10,9 +10,10 @@ use tokio_modbus::client::{Client};
 use std::fs::File;
 use std::io::{BufWriter, Write};
 
-use ctrlc;
 use approx::{abs_diff_ne};
 use craven_control::*;
+use craven_control::bus::BusStats;
+use craven_control::cli::*;
 
 /// This dictates, on average, how often the main loop runs 
 const INTER_LOOP_DELAY: Duration = Duration::from_millis(1000);
@@ -113,22 +114,6 @@ async fn read_dual_tk_temps(ctx: &mut tokio_modbus::client::Context)
     read_ykktc1202_dual_tk_temps(ctx).await
 }
 
- /// Set the output drive current of the test electrodes 
-async fn set_electrode_current_drive(ctx: &mut tokio_modbus::client::Context, milliamps: f32) -> Result<(), Box<dyn std::error::Error>> 
-{
-    // set_ykpvccs0100_current_drive(ctx, milliamps).await
-    set_ykpvccs1000_current_drive(ctx, milliamps).await
-}
-
-/// Read the reported current from the current source
-async fn read_electrode_current_drive(ctx: &mut tokio_modbus::client::Context) -> Result<f32, Box<dyn std::error::Error>> 
-{
-    //read_ykpvccs0100_current_drive(ctx).await
-    read_ykpvccs1000_current_drive(ctx).await
-}
-
-
-
 /// 
 /// Verify that all the modules we expect to be connected to the RS-485 Modbus are,
 /// in fact, connected.
@@ -237,10 +222,10 @@ async fn control_furnace(ctx: &mut tokio_modbus::client::Context, state: &mut Fu
                 }
                 (tk1_c + tk2_c) / 2f32 
             }
-            else { tk1_c.into()}
+            else { tk1_c}
         }
-        else if ch2_tk_opt.is_some() { tk2_c.into() }
-        else { MAX_PROBE_TEMP_C as f32};
+        else if ch2_tk_opt.is_some() { tk2_c }
+        else { MAX_PROBE_TEMP_C};
     
     state.measured_temp_c = avg_core_tk_c;
 
@@ -408,7 +393,7 @@ async fn drive_current_and_measure(ctx: &mut tokio_modbus::client::Context,
             INF_INTER_ELECTRODE_OHMS // arbitrary value based on previous experiments
         };
 
-    return Ok((measured_volts, measured_milliamps, measured_ohms))
+    Ok((measured_volts, measured_milliamps, measured_ohms))
 }
 
 
@@ -601,11 +586,15 @@ fn cyclic_voltage_at_time_ms(phase_duration_ms: u64) -> f32
  */
 #[tokio::main(flavor = "current_thread")]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    // Connect to Modbus apparatus via TCP server bridge (a WiFi bridge on our local network)
-    let socket_addr: std::net::SocketAddr = "10.0.1.151:502".parse()?;
+    // By default, connect to Modbus apparatus via TCP server bridge (a WiFi bridge on our local network)
+    let (bus_options, extra_args) = BusOptions::from_args(std::env::args().skip(1))?;
+    if !extra_args.is_empty() {
+        eprintln!("Usage: all_up [bus options]\n\n{BUS_OPTIONS_USAGE}");
+        std::process::exit(1);
+    }
 
-    println!("Connecting to: '{socket_addr:?}'");
-    let mut ctx: client::Context = tcp::connect(socket_addr).await?;
+    println!("Connecting to: {}", bus_options.describe());
+    let mut ctx: client::Context = bus_options.connect(BusStats::new()).await?;
     
     enumerate_required_modules(&mut ctx).await?;
 
@@ -657,7 +646,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
         let furnace_res = 
             tokio::time::timeout(MODBUS_TRANSACTION_TIMEOUT,control_furnace(&mut ctx, &mut furnace_state)).await;
-        if !furnace_res.is_ok() { 
+        if furnace_res.is_err() { 
             running.store(false, Ordering::SeqCst);
             eprintln!("control_furnace timeout: {:?}",furnace_res);
             continue;
@@ -668,7 +657,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         {
             let elec_res = 
                 tokio::time::timeout(MODBUS_TRANSACTION_TIMEOUT, control_electrodes(&mut ctx, &mut electrode_state)).await;
-            if !elec_res.is_ok() { 
+            if elec_res.is_err() { 
                 running.store(false, Ordering::SeqCst);
                 eprintln!("control_electrodes timeout: {:?}",elec_res);
                 continue;
@@ -704,8 +693,8 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     // We reconnect to modbus to flush any cruft buffered at the WiFi bridge.
     ctx.disconnect().await?;
     sleep(Duration::from_secs(2)).await;
-    println!("Reconnecting to: '{socket_addr:?}' ...");
-    ctx = tcp::connect(socket_addr).await?;
+    println!("Reconnecting to: {} ...", bus_options.describe());
+    ctx = bus_options.connect(BusStats::new()).await?;
     zero_control_outputs(&mut ctx).await?;
     ctx.disconnect().await?;
 
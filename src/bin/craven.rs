@@ -0,0 +1,913 @@
+//!
+//! One command-line tool for bringing up and exercising the Modbus devices,
+//! replacing the per-device test programs that had TTY paths and node IDs compiled in.
+//!
+//! Usage: `craven [bus options] <command> [args]`
+//!
+//! | Command | Effect |
+//! |-------|----------|
+//! | `scan [<first> <last>] [--reg <addr>]` | List the node IDs that respond on the bus |
+//! | `configure node <new id> [--reg <addr>]` | Change the node ID of `--node` (default 0x01) |
+//! | `configure baud <code> <new rate> [--reg <addr>]` | Change the baud rate of `--node`, then reconnect at the new rate |
+//! | `read <addr> [<count>] [--input \| --coils \| --discrete] [<format>]` | Read holding (or other) registers from `--node` |
+//! | `read <model>.<register>` | Read a register by its device map name, scaled to its units |
+//! | `write <addr> <value>... [--coils] [<format>]` | Write holding registers (or coils: `on`/`off`) on `--node` |
+//! | `write <model>.<register> <value>` | Write a register by its device map name, in its units |
+//! | `watch <addr> [<count>] [...] \| <model>.<register>... [--interval <ms>] [--for <secs>] [--changes]` | Poll registers, highlighting values that change |
+//! | `relay [<channel> \| all] [on \| off]` | Show or switch the octo relay channels (1-8) |
+//! | `anodes check [--probe <mA>] [--relays <channel>,...]` | Connect each anode on its own at a probe current, and report a table of voltage, current and resistance |
+//! | `ai [<channel> \| all] [--cal <gain>,<offset>[,V\|mA]]` | Read the analog input channels (1-8), in volts or milliamps by channel mode |
+//! | `ai mode <channel> \| all <mode>` | Set analog input channel modes: `0-5V`, `1-5V`, `0-20mA`, `4-20mA` or `raw` |
+//! | `ao [<channel> \| all] [<mA>]` | Show, or set, the analog output channels (1-8), clamped to each channel's range and verified |
+//! | `ao mode <channel> \| all <mode> \| code <channel> <code> \| safe` | Set analog output modes (`0-20mA`, `4-20mA` or `raw`), a raw DAC code, or the safe power-on configuration |
+//! | `current [<mA> \| off \| info] [--model <model>] [--limit <mA>]` | Show, or set, the precision current source drive (clamped to the model's range, and verified); a drive current is preceded by a small test setpoint that checks the model's scale against the electrode IV meter |
+//! | `stepper status \| stop \| fwd \| rev \| speed <rpm> \| jog <mm> \| mode run\|loop` | Drive the dipper stepper motor |
+//! | `stepper home` | Home the dipper against its upper limit |
+//! | `stepper move <mm>` | Move the dipper a measured distance (by pulse count): positive is down |
+//! | `stepper moveto <mm> [--from <mm>]` | Home the dipper (or take `--from` as its position), then move to a position below home, inside the soft limits |
+//! | `stepper probe [--from <mm>]` | Home the dipper (or take `--from` as its position), then probe the melt surface and report the level and volume |
+//! | `stepper config [<param>=<value>...]` | Show, or change, the stepper controller's configuration; every change is validated, then written and read back |
+//! | `run [--console]` | Run the potslide controller on the same bus |
+//! | `record [<secs>] [--interval <ms>] [--out <file.csv>] [--pyro]` | Record temperatures and electrode readings to CSV; `--pyro` also relays the pyrometer's 4-20 mA loop and records it |
+//! | `map [<model> \| all]` | List the device models, or print a model's register map as Markdown |
+//! | `replay <capture file> [--listen <spec>] [--realtime] [--quiet]` | Serve a `--capture` file back as a fake bus, to reproduce a field problem |
+//!
+//! Node ID and register arguments are decimal, or hex with a `0x` prefix.
+//! `<format>` is `--format dec|hex|bin|u16|i16|u32|i32|f32|bcd16|bcd32`, with `--order abcd|cdab|badc|dcba`
+//! for 32 bit values and `--scale <k>` for engineering units per count; `<count>` then counts values, not registers.
+//! A named register uses the model's node ID unless `--node` is given.
+//!
+
+use std::fs::File;
+use std::io::{BufWriter, IsTerminal, Write};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::time::sleep;
+use tokio_modbus::prelude::*;
+
+use craven_control::*;
+use craven_control::anode_check::*;
+use craven_control::anode_pattern::*;
+use craven_control::bus::*;
+use craven_control::capture::*;
+use craven_control::cli::*;
+use craven_control::dipper::*;
+use craven_control::melt_level::*;
+use craven_control::peek::*;
+use craven_control::register_map::*;
+use craven_control::units::*;
+use craven_control::wa8tai::*;
+use craven_control::wa26419::*;
+use craven_control::smc05::*;
+use craven_control::ykpvccs::*;
+
+const USAGE: &str = "\
+Usage: craven [bus options] <command> [args]
+
+Commands:
+  scan [<first> <last>] [--reg <addr>]        List the node IDs that respond
+  configure node <new id> [--reg <addr>]      Change the node ID of --node (default 0x01)
+  configure baud <code> <new rate> [--reg <addr>]
+                                              Change the baud rate of --node
+  read <addr> [<count>] [--input|--coils|--discrete] [<format>]
+                                              Read registers from --node
+  read <model>.<register>                     Read a register named in a device map
+  write <addr> <value>... [--coils] [<format>]
+                                              Write registers (or coils: on/off) on --node
+  write <model>.<register> <value>            Write a named register, in its units
+  watch <addr> [<count>] [--input|--coils|--discrete] [<format>]
+  watch <model>.<register>...
+        [--interval <ms>] [--for <secs>] [--changes]
+                                              Poll registers, highlighting changes
+  relay [<channel>|all] [on|off]              Show or switch octo relay channels 1-8
+  anodes check [--probe <mA>] [--relays <channel>,...]
+                                              Measure each anode on its own at a probe
+                                              current (default relays 1,2,3,4)
+  ai [<channel>|all] [--cal <gain>,<offset>[,V|mA]]
+                                              Read analog input channels 1-8; --cal
+                                              converts raw mode codes
+  ai mode <channel>|all <mode>                Set channel modes: 0-5V, 1-5V, 0-20mA,
+                                              4-20mA or raw
+  ao [<channel>|all] [<mA>]                   Show or set analog output channels 1-8
+  ao mode <channel>|all <mode>                Set channel modes: 0-20mA, 4-20mA or raw
+  ao code <channel> <code>                    Set a raw mode channel's DAC code
+  ao safe                                     All channels 4-20mA, at 4 mA
+  current [<mA>|off|info] [--model <model>] [--limit <mA>]
+                                              Show or set the current source drive,
+                                              after a scale check at a small test current;
+                                              model ykpvccs0100 or ykpvccs1000
+                                              (default $CRAVEN_CURRENT_SOURCE)
+  stepper status|stop|fwd|rev|speed <rpm>|jog <mm>|mode run|loop
+                                              Drive the dipper stepper motor
+  stepper home                                Home the dipper against its upper limit
+  stepper move <mm>                           Move the dipper a measured distance, + is down
+  stepper moveto <mm> [--from <mm>]           Home (or take --from as the position), then
+                                              move to a position below home
+  stepper probe [--from <mm>]                 Home (or take --from as the position), then
+                                              probe the melt level
+  stepper config [<param>=<value>...]         Show or change the stepper configuration:
+                                              mode, loops, pulses-per-rev, power-on-start,
+                                              fwd-|rev-speed, -accel, -pulses, -delay
+  run [--console]                             Run the potslide controller
+  record [<secs>] [--interval <ms>] [--out <file.csv>] [--pyro]
+                                              Record temperatures and electrode readings;
+                                              --pyro also relays the pyrometer loop
+  map [<model>|all]                           List device models, or print a register map
+  replay <capture file> [--listen <spec>] [--realtime] [--quiet]
+                                              Answer requests from a --capture file
+                                              (default listen tcp://127.0.0.1:5020)
+";
+
+/// How long `scan` waits for each node ID to answer
+const SCAN_RESPONSE_TIMEOUT: Duration = Duration::from_millis(300);
+
+/// Let a device restart after its communication settings change
+const DEVICE_RESET_DELAY: Duration = Duration::from_millis(1000);
+
+/// Default time between `record` samples
+const DEFAULT_RECORD_INTERVAL: Duration = Duration::from_millis(1000);
+
+/// Where `replay` listens unless told otherwise
+const DEFAULT_REPLAY_LISTEN: &str = "tcp://127.0.0.1:5020";
+
+/// Default time between `watch` polls
+const DEFAULT_WATCH_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Narrowest `watch` column, so short values still line up under their labels
+const WATCH_MIN_COLUMN_WIDTH: usize = 6;
+
+/// Number of channels on the octo relay board
+const OCTO_RELAY_CHANNELS: u16 = 8;
+
+type CliResult = Result<(), Box<dyn std::error::Error>>;
+
+/// Remove a `--flag` from the arguments, reporting whether it was present
+fn take_flag(args: &mut Vec<String>, flag: &str) -> bool {
+    let before = args.len();
+    args.retain(|arg| arg != flag);
+    args.len() != before
+}
+
+/// Remove a `--name <value>` option from the arguments
+fn take_option(args: &mut Vec<String>, name: &str) -> Result<Option<String>, String> {
+    let Some(idx) = args.iter().position(|arg| arg == name) else { return Ok(None) };
+    if idx + 1 >= args.len() {
+        return Err(format!("{name} needs a value"));
+    }
+    let value = args.remove(idx + 1);
+    args.remove(idx);
+    Ok(Some(value))
+}
+
+fn parse_f32(text: &str, what: &str) -> Result<f32, String> {
+    text.parse::<f32>().ok().filter(|value| value.is_finite()).ok_or_else(|| format!("bad {what}: {text:?}"))
+}
+
+/// Take the table flags and `--format`/`--order`/`--scale` options, then the targets:
+/// `<addr> [<count>]` on `--node`, or one or more `<model>.<register>` names
+fn take_peeks(options: &BusOptions, args: &mut Vec<String>, targets: usize) -> Result<Vec<Peek>, String> {
+    let input = take_flag(args, "--input");
+    let coils = take_flag(args, "--coils");
+    let discrete = take_flag(args, "--discrete");
+    let format = take_option(args, "--format")?;
+    let order = take_option(args, "--order")?;
+    let scale = take_option(args, "--scale")?;
+    let table_flag = input || coils || discrete;
+    if let Some(unknown) = args.iter().find(|arg| arg.starts_with("--")) {
+        return Err(format!("unknown option {unknown:?}"));
+    }
+    let named = args.iter().take(targets).take_while(|arg| Peek::is_register_name(arg)).count();
+    if named > 0 {
+        if table_flag || format.is_some() || order.is_some() || scale.is_some() {
+            return Err("named registers take their table and format from the device map".into());
+        }
+        return args.drain(..named).map(|name| Peek::named(&name, options.node_id)).collect();
+    }
+    let table = match (input, coils, discrete) {
+        (false, false, false) => Table::Holding,
+        (true, false, false) => Table::Input,
+        (false, true, false) => Table::Coil,
+        (false, false, true) => Table::Discrete,
+        _ => return Err("give only one of --input, --coils and --discrete".into()),
+    };
+    let format = ValueFormat::parse(format.as_deref(), order.as_deref(), scale.as_deref())?;
+    let node_id = options.node_id.ok_or("a register address needs --node (or name a register as <model>.<register>)")?;
+    if args.is_empty() {
+        return Err("missing register address".into());
+    }
+    let addr = parse_u16(&args.remove(0))?;
+    let count = match args.first() {
+        Some(count) if targets > 1 => {
+            let count = parse_u16(count)?;
+            args.remove(0);
+            count
+        }
+        _ => 1,
+    };
+    Ok(vec![Peek::at(node_id, table, addr, count, format)?])
+}
+
+/// Print a peek's values one per line, with label, value and unit
+fn print_peek(peek: &Peek, values: &[String]) {
+    for (label, value) in peek.labels().iter().zip(values) {
+        match peek.unit() {
+            "" => println!("{label}  {value}"),
+            unit => println!("{label}  {value} {unit}"),
+        }
+    }
+}
+
+async fn cmd_scan(options: &BusOptions, mut args: Vec<String>) -> CliResult {
+    let reg = take_option(&mut args, "--reg")?.map(|reg| parse_u16(&reg)).transpose()?.unwrap_or(0);
+    let (first, last) = match args.as_slice() {
+        [] => (1, NODEID_MAX),
+        [first, last] => (parse_node_id(first)?, parse_node_id(last)?),
+        _ => return Err("usage: scan [<first> <last>] [--reg <addr>]".into()),
+    };
+    // a missing node is the normal case here: don't retry
+    let scan_options = BusOptions { read_retries: 0, request_timeout: Some(SCAN_RESPONSE_TIMEOUT), ..options.clone() };
+    let mut ctx = scan_options.connect(BusStats::new()).await?;
+    println!("Scanning node IDs 0x{first:02X}..0x{last:02X} (register 0x{reg:04X}) on {} ...", options.describe());
+
+    let mut found = 0;
+    for node_id in first..=last {
+        ctx.set_slave(Slave(node_id));
+        match ctx.read_holding_registers(reg, 1).await {
+            Ok(Ok(values)) => {
+                println!("0x{node_id:02X} ({node_id:3})  responds: 0x{:04X}", values[0]);
+                found += 1;
+            }
+            Ok(Err(exception)) => {
+                println!("0x{node_id:02X} ({node_id:3})  responds with exception: {exception}");
+                found += 1;
+            }
+            Err(_) => {}
+        }
+    }
+    println!("{found} node(s) found");
+    ctx.disconnect().await?;
+    Ok(())
+}
+
+/// The address of a named register on the model we configure at `node_id`, from its register map
+fn known_register(node_id: u8, name: &str) -> Option<u16> {
+    device_map_for_node(node_id)?.register(name).map(|spec| spec.address)
+}
+
+/// The YK-PVCCS current source only keeps configuration changes once told to save them
+async fn persist_if_current_source(ctx: &mut tokio_modbus::client::Context, node_id: u8) -> CliResult {
+    if node_id == NODEID_YKPVCCS010_CURR_SRC {
+        println!("persisting YK-PVCCS node configuration");
+        CurrentSource::from_env()?.at_node(node_id).save_config(ctx).await?;
+    }
+    Ok(())
+}
+
+async fn cmd_configure(options: &BusOptions, mut args: Vec<String>) -> CliResult {
+    let reg = take_option(&mut args, "--reg")?.map(|reg| parse_u16(&reg)).transpose()?;
+    let old_node_id = options.node_or(NODEID_DEFAULT);
+    match args.as_slice() {
+        [what, new_id] if what == "node" => {
+            let new_node_id = parse_node_id(new_id)?;
+            let reg = reg.or_else(|| known_register(new_node_id, "NODE_ADDRESS"))
+                .ok_or("unknown device: give its node ID register with --reg")?;
+            let mut ctx = options.connect(BusStats::new()).await?;
+            ctx.set_slave(Slave(old_node_id));
+            let existing = ctx.read_holding_registers(reg, 1).await??[0];
+            println!("Node 0x{old_node_id:02X} register 0x{reg:04X} reads {existing} (0x{existing:02X})");
+            if existing != old_node_id as u16 {
+                return Err(format!("node 0x{old_node_id:02X} does not report its own ID at register 0x{reg:04X}").into());
+            }
+            println!("Writing new node ID 0x{new_node_id:02X} ...");
+            ctx.write_single_register(reg, new_node_id as u16).await??;
+            sleep(DEVICE_RESET_DELAY).await;
+
+            ctx.set_slave(Slave(new_node_id));
+            let latest = ctx.read_holding_registers(reg, 1).await??[0];
+            if latest != new_node_id as u16 {
+                return Err(format!("node 0x{new_node_id:02X} reports node ID {latest} after the change").into());
+            }
+            persist_if_current_source(&mut ctx, new_node_id).await?;
+            println!("Node ID 0x{old_node_id:02X} -> 0x{new_node_id:02X} verified");
+            ctx.disconnect().await?;
+        }
+        [what, code, new_rate] if what == "baud" => {
+            let code = parse_u16(code)?;
+            let new_baud_rate: u32 = new_rate.parse().map_err(|_| format!("bad baud rate: {new_rate:?}"))?;
+            let reg = reg.or_else(|| known_register(old_node_id, "BAUD"))
+                .ok_or("unknown device: give its baud rate register with --reg")?;
+            let Transport::Rtu { tty_path, baud_rate } = options.transport_for(old_node_id) else {
+                return Err("baud rate changes need a direct serial connection (an rtu:// transport)".into());
+            };
+            let mut ctx = options.connect(BusStats::new()).await?;
+            ctx.set_slave(Slave(old_node_id));
+            println!("Node 0x{old_node_id:02X} at {baud_rate} baud: writing code {code} to register 0x{reg:04X} ...");
+            // the device may switch rates before it answers
+            if let Err(e) = ctx.write_single_register(reg, code).await {
+                eprintln!("no reply to the baud rate change (expected for some devices): {e}");
+            }
+            ctx.disconnect().await?;
+            sleep(DEVICE_RESET_DELAY).await;
+
+            let new_options = BusOptions {
+                transport: Transport::Rtu { tty_path: tty_path.clone(), baud_rate: new_baud_rate },
+                routes: Vec::new(),
+                ..options.clone()
+            };
+            let mut ctx = new_options.connect(BusStats::new()).await?;
+            ctx.set_slave(Slave(old_node_id));
+            let latest = ctx.read_holding_registers(reg, 1).await??[0];
+            if latest != code {
+                return Err(format!("register 0x{reg:04X} reads {latest} at {new_baud_rate} baud, expected {code}").into());
+            }
+            persist_if_current_source(&mut ctx, old_node_id).await?;
+            println!("Node 0x{old_node_id:02X} now at {new_baud_rate} baud");
+            ctx.disconnect().await?;
+        }
+        _ => return Err("usage: configure node <new id> [--reg <addr>] | configure baud <code> <new rate> [--reg <addr>]".into()),
+    }
+    Ok(())
+}
+
+async fn cmd_read(options: &BusOptions, mut args: Vec<String>) -> CliResult {
+    const READ_USAGE: &str = "usage: read <addr> [<count>] [--input|--coils|--discrete] [--format <f>] [--order <o>] [--scale <k>]\n       read <model>.<register>";
+    let peeks = take_peeks(options, &mut args, 2).map_err(|e| format!("{e}\n{READ_USAGE}"))?;
+    if !args.is_empty() {
+        return Err(READ_USAGE.into());
+    }
+    let mut ctx = options.connect(BusStats::new()).await?;
+    for peek in &peeks {
+        let values = peek.read(&mut ctx).await?;
+        println!("node 0x{:02X} {:?}:", peek.node_id, peek.table);
+        print_peek(peek, &values);
+    }
+    ctx.disconnect().await?;
+    Ok(())
+}
+
+async fn cmd_write(options: &BusOptions, mut args: Vec<String>) -> CliResult {
+    const WRITE_USAGE: &str = "usage: write <addr> <value>... [--coils] [--format <f>] [--order <o>] [--scale <k>]\n       write <model>.<register> <value>";
+    let peek = take_peeks(options, &mut args, 1).map_err(|e| format!("{e}\n{WRITE_USAGE}"))?.remove(0);
+    if args.is_empty() {
+        return Err(WRITE_USAGE.into());
+    }
+    // read back everything written
+    let readback = match peek.spec {
+        Some(_) => peek.clone(),
+        None => Peek::at(peek.node_id, peek.table, peek.address, args.len() as u16, peek.format)?,
+    };
+    let mut ctx = options.connect(BusStats::new()).await?;
+    peek.write(&mut ctx, &args).await?;
+    if peek.spec.is_none_or(|spec| spec.is_readable()) {
+        let values = readback.read(&mut ctx).await?;
+        println!("node 0x{:02X} {:?} now:", peek.node_id, peek.table);
+        print_peek(&readback, &values);
+    }
+    ctx.disconnect().await?;
+    Ok(())
+}
+
+async fn cmd_watch(options: &BusOptions, mut args: Vec<String>) -> CliResult {
+    const WATCH_USAGE: &str = "usage: watch <addr> [<count>] [--input|--coils|--discrete] [--format <f>] [--order <o>] [--scale <k>]\n       watch <model>.<register>...\n       [--interval <ms>] [--for <secs>] [--changes]";
+    let interval = take_option(&mut args, "--interval")?
+        .map(|ms| ms.parse().map(Duration::from_millis).map_err(|_| format!("bad interval: {ms:?}")))
+        .transpose()?
+        .unwrap_or(DEFAULT_WATCH_INTERVAL);
+    let duration = take_option(&mut args, "--for")?
+        .map(|secs| secs.parse().map(Duration::from_secs).map_err(|_| format!("bad duration: {secs:?}")))
+        .transpose()?;
+    let changes_only = take_flag(&mut args, "--changes");
+    let peeks = take_peeks(options, &mut args, usize::MAX).map_err(|e| format!("{e}\n{WATCH_USAGE}"))?;
+    if !args.is_empty() {
+        return Err(WATCH_USAGE.into());
+    }
+
+    let labels: Vec<String> = peeks.iter().flat_map(|peek| peek.labels()).collect();
+    let mut watch = Watch::new(labels.len());
+    let highlight = std::io::stdout().is_terminal();
+    let mut ctx = options.connect(BusStats::new()).await?;
+    let mut node_ids: Vec<u8> = peeks.iter().map(|peek| peek.node_id).collect();
+    node_ids.dedup();
+    println!("Watching node(s) {} every {interval:?}, Ctrl-C to stop ...",
+        node_ids.iter().map(|node_id| format!("0x{node_id:02X}")).collect::<Vec<_>>().join(", "));
+
+    // columns are sized on the first good poll, so the header lines up with the values
+    let mut widths: Vec<usize> = Vec::new();
+
+    let start = tokio::time::Instant::now();
+    let ctrl_c = tokio::signal::ctrl_c();
+    tokio::pin!(ctrl_c);
+    loop {
+        let timestamp = chrono::Local::now().format("%H:%M:%S%.3f");
+        let mut values = Vec::with_capacity(labels.len());
+        let mut failure = None;
+        for peek in &peeks {
+            match peek.read(&mut ctx).await {
+                Ok(peek_values) => values.extend(peek_values),
+                Err(e) => {
+                    failure = Some(format!("node 0x{:02X} 0x{:04X}: {e}", peek.node_id, peek.address));
+                    break;
+                }
+            }
+        }
+        if let Some(e) = failure {
+            watch.record_error();
+            println!("{timestamp}  read failed: {e}");
+        }
+        else {
+            let changed = watch.update(&values);
+            let first_values = widths.is_empty();
+            if first_values {
+                widths = labels.iter().zip(&values)
+                    .map(|(label, value)| label.len().max(value.len()).max(WATCH_MIN_COLUMN_WIDTH))
+                    .collect();
+                let header: Vec<String> = labels.iter().zip(&widths).map(|(label, width)| format!("{label:>width$} ")).collect();
+                println!("{:12}  {}", "time", header.join(" "));
+            }
+            if first_values || !changes_only || changed.contains(&true) {
+                let mut cells = Vec::with_capacity(values.len());
+                for ((value, width), changed) in values.iter().zip(widths.iter_mut()).zip(&changed) {
+                    *width = (*width).max(value.len());
+                    cells.push(match (changed, highlight) {
+                        (false, _) => format!("{value:>width$} ", width = *width),
+                        (true, true) => format!("\x1b[7m{value:>width$}\x1b[0m ", width = *width),
+                        (true, false) => format!("{value:>width$}*", width = *width),
+                    });
+                }
+                println!("{timestamp}  {}", cells.join(" "));
+            }
+        }
+
+        if duration.is_some_and(|duration| start.elapsed() >= duration) {
+            break;
+        }
+        tokio::select! {
+            _ = &mut ctrl_c => break,
+            _ = sleep(interval) => {}
+        }
+    }
+    ctx.disconnect().await?;
+
+    println!("{} poll(s), {} failed; changes per value:", watch.polls, watch.errors);
+    for (label, changes) in labels.iter().zip(&watch.changes) {
+        println!("  {label}  {changes}");
+    }
+    Ok(())
+}
+
+async fn cmd_replay(mut args: Vec<String>) -> CliResult {
+    const REPLAY_USAGE: &str = "usage: replay <capture file> [--listen <spec>] [--realtime] [--quiet]";
+    let listen: Transport = take_option(&mut args, "--listen")?.as_deref().unwrap_or(DEFAULT_REPLAY_LISTEN).parse()?;
+    let realtime = take_flag(&mut args, "--realtime");
+    let quiet = take_flag(&mut args, "--quiet");
+    let [path] = args.as_slice() else {
+        return Err(REPLAY_USAGE.into());
+    };
+    let records = load_capture(Path::new(path))?;
+    let (Some(first), Some(last)) = (records.first(), records.last()) else {
+        return Err(format!("{path}: no records").into());
+    };
+    let span = Duration::from_millis((last.time_ms - first.time_ms).max(0) as u64);
+    let failed = records.iter().filter(|record| record.error.is_some()).count();
+    let mut buses: Vec<&str> = records.iter().map(|record| record.bus.as_str()).collect();
+    buses.sort();
+    buses.dedup();
+
+    let mut replay = Replay::new(records.clone());
+    replay.realtime = realtime;
+    replay.verbose = !quiet;
+    println!("{} records over {span:?} from {} ({failed} without a response), {} distinct requests",
+        records.len(), buses.join(", "), replay.request_count());
+    println!("Replaying on {listen}, Ctrl-C to stop ...");
+    tokio::select! {
+        res = serve_replay(Arc::new(replay), &listen) => res,
+        _ = tokio::signal::ctrl_c() => Ok(()),
+    }
+}
+
+async fn cmd_relay(options: &BusOptions, args: Vec<String>) -> CliResult {
+    let node_id = options.node_or(NODEID_WAV_OCTO_RELAY);
+    let mut ctx = options.connect(BusStats::new()).await?;
+    ctx.set_slave(Slave(node_id));
+    match args.as_slice() {
+        [] => {}
+        [channel, state] if channel == "all" => {
+            let active = parse_coil(state)?;
+            ctx.write_multiple_coils(0, &[active; OCTO_RELAY_CHANNELS as usize]).await??;
+        }
+        [channel, state] => {
+            let channel = u8::try_from(parse_u16(channel)?).unwrap_or(u8::MAX);
+            channel_register(&OCTO_RELAYS, channel)?.write_to(&mut ctx, node_id, parse_coil(state)?).await?;
+        }
+        _ => return Err("usage: relay [<channel>|all] [on|off]".into()),
+    }
+    let states = ctx.read_coils(0, OCTO_RELAY_CHANNELS).await??;
+    let states: Vec<String> = states.iter().enumerate()
+        .map(|(idx, on)| format!("{}:{}", idx + 1, if *on { "on" } else { "off" }))
+        .collect();
+    println!("relays {}", states.join(" "));
+    ctx.disconnect().await?;
+    Ok(())
+}
+
+async fn cmd_anodes(options: &BusOptions, mut args: Vec<String>) -> CliResult {
+    const ANODES_USAGE: &str = "usage: anodes check [--probe <mA>] [--relays <channel>,...]";
+    let mut config = AnodeCheckConfig::DEFAULT;
+    if let Some(probe) = take_option(&mut args, "--probe")? {
+        config.probe_ma = parse_f32(&probe, "probe current")?;
+    }
+    let relay_channels: Vec<u8> = match take_option(&mut args, "--relays")? {
+        Some(list) => parse_anode_relays(&list)?,
+        None => DEFAULT_ANODE_RELAY_CHANNELS.to_vec(),
+    };
+    if args != ["check"] {
+        return Err(ANODES_USAGE.into());
+    }
+    let mut ctx = options.connect(BusStats::new()).await?;
+    let check = check_anodes(&mut ctx, &relay_channels, &config).await?;
+    print!("{check}");
+    if check.failed_mask() != 0 {
+        println!("anodes {} failed", format_anode_mask(check.failed_mask()));
+    }
+    ctx.disconnect().await?;
+    Ok(())
+}
+
+async fn cmd_ai(options: &BusOptions, mut args: Vec<String>) -> CliResult {
+    const AI_USAGE: &str = "usage: ai [<channel>|all] [--cal <gain>,<offset>[,V|mA]] | ai mode <channel>|all <mode>";
+    let calibration: Option<RawCalibration> = take_option(&mut args, "--cal")?.map(|cal| cal.parse()).transpose()?;
+    let mut adc = Wa8tai::new().at_node(options.node_or(NODEID_WA8TAI_IV_ADC));
+    let mut ctx = options.connect(BusStats::new()).await?;
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    let channels: Vec<u8> = match args.as_slice() {
+        ["mode", "all", mode] => {
+            adc.set_modes(&mut ctx, [mode.parse()?; WA8TAI_CHANNEL_COUNT]).await?;
+            (1..=WA8TAI_CHANNEL_COUNT as u8).collect()
+        }
+        ["mode", channel, mode] => {
+            let channel = u8::try_from(parse_u16(channel)?).unwrap_or(u8::MAX);
+            adc.set_mode(&mut ctx, channel, mode.parse()?).await?;
+            vec![channel]
+        }
+        [] | ["all"] => (1..=WA8TAI_CHANNEL_COUNT as u8).collect(),
+        [channel] => vec![u8::try_from(parse_u16(channel)?).unwrap_or(u8::MAX)],
+        _ => return Err(AI_USAGE.into()),
+    };
+    for channel in &channels {
+        adc.set_calibration(*channel, calibration)?;
+    }
+    let readings = adc.read_all(&mut ctx).await?;
+    for channel in channels {
+        let mode = adc.mode(channel)?.map_or("?".to_string(), |mode| mode.to_string());
+        println!("ai {channel}: {mode:>6}  {}", readings[channel as usize - 1]);
+    }
+    ctx.disconnect().await?;
+    Ok(())
+}
+
+async fn cmd_ao(options: &BusOptions, args: Vec<String>) -> CliResult {
+    const AO_USAGE: &str = "usage: ao [<channel>|all] [<mA>] | ao mode <channel>|all <mode> | ao code <channel> <code> | ao safe";
+    let all_channels: Vec<u8> = (1..=WA26419_CHANNEL_COUNT as u8).collect();
+    let parse_channel = |text: &str| parse_u16(text).map(|channel| u8::try_from(channel).unwrap_or(u8::MAX));
+    let mut dac = Wa26419::new().at_node(options.node_or(NODEID_WA26419_8CH_DAC));
+    let mut ctx = options.connect(BusStats::new()).await?;
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    let channels = match args.as_slice() {
+        [] | ["all"] => all_channels,
+        ["safe"] => {
+            dac.apply_safe_defaults(&mut ctx).await?;
+            all_channels
+        }
+        ["mode", "all", mode] => {
+            dac.set_modes(&mut ctx, [mode.parse()?; WA26419_CHANNEL_COUNT]).await?;
+            all_channels
+        }
+        ["mode", channel, mode] => {
+            let channel = parse_channel(channel)?;
+            dac.set_mode(&mut ctx, channel, mode.parse()?).await?;
+            vec![channel]
+        }
+        ["code", channel, code] => {
+            let channel = parse_channel(channel)?;
+            dac.set_code(&mut ctx, channel, parse_u16(code)?).await?;
+            vec![channel]
+        }
+        ["all", milliamps] => {
+            let milliamps = Milliamps(parse_f32(milliamps, "output current")?);
+            let ordered = dac.set_outputs(&mut ctx, [milliamps; WA26419_CHANNEL_COUNT]).await?;
+            if ordered.iter().any(|ordered| *ordered != milliamps) {
+                println!("{milliamps} ordered as {} (channel ranges)",
+                    ordered.map(|ordered| ordered.to_string()).join(", "));
+            }
+            all_channels
+        }
+        [channel] => vec![parse_channel(channel)?],
+        [channel, milliamps] => {
+            let channel = parse_channel(channel)?;
+            let milliamps = Milliamps(parse_f32(milliamps, "output current")?);
+            let ordered = dac.set_output(&mut ctx, channel, milliamps).await?;
+            if ordered != milliamps {
+                println!("{milliamps} ordered as {ordered} (channel range)");
+            }
+            vec![channel]
+        }
+        _ => return Err(AO_USAGE.into()),
+    };
+    if dac.mode(channels[0])?.is_none() {
+        dac.read_modes(&mut ctx).await?;
+    }
+    let outputs = dac.read_outputs(&mut ctx).await?;
+    for channel in channels {
+        let mode = dac.mode(channel)?.ok_or("WA26419 modes unknown")?;
+        let output = outputs[channel as usize - 1];
+        match mode {
+            AnalogOutputMode::RawCode => println!("ao {channel}: {mode:>6}  code {output}"),
+            _ => println!("ao {channel}: {mode:>6}  {}", word_to_milliamps(output)),
+        }
+    }
+    ctx.disconnect().await?;
+    Ok(())
+}
+
+async fn cmd_current(options: &BusOptions, mut args: Vec<String>) -> CliResult {
+    const CURRENT_USAGE: &str = "usage: current [<mA>|off|info] [--model <model>] [--limit <mA>]";
+    let model = match take_option(&mut args, "--model")? {
+        Some(model) => model.parse()?,
+        None => CurrentSourceModel::from_env()?,
+    };
+    let mut source = CurrentSource::new(model).at_node(options.node_or(NODEID_YKPVCCS010_CURR_SRC));
+    if let Some(limit) = take_option(&mut args, "--limit")? {
+        source = source.with_limit(parse_f32(&limit, "limit")?);
+    }
+    let mut ctx = options.connect(BusStats::new()).await?;
+    match args.iter().map(String::as_str).collect::<Vec<_>>().as_slice() {
+        [] => {}
+        ["info"] => {
+            let config = source.identify(&mut ctx).await?;
+            println!("{} ({}) at node 0x{:02X}: baud code {} ({}), range 0-{} mA in {} mA steps, limit {} mA",
+                model, model.map().description, source.node_id, config.baud_code,
+                config.baud_rate.map_or("unknown rate".to_string(), |rate| format!("{rate} baud")),
+                model.max_milliamps(), model.resolution_milliamps(), source.limit_milliamps);
+        }
+        ["off"] => {
+            source.output_off(&mut ctx).await?;
+            sleep(CURRENT_SOURCE_WAIT_TIME).await;
+        }
+        [milliamps] => {
+            let milliamps = parse_f32(milliamps, "drive current")?;
+            if milliamps > 0. {
+                // a source configured as the wrong model would drive ten times, or a tenth of, the current
+                match source.check_scale(&mut ctx, CURRENT_SOURCE_WAIT_TIME).await? {
+                    Some(measured_ma) => println!("{model} scale checked: {SCALE_CHECK_MILLIAMPS} mA test setpoint measured as {measured_ma:.2} mA"),
+                    None => println!("No current flows at the electrodes: {model} scale not checked"),
+                }
+            }
+            let ordered = source.set_drive(&mut ctx, milliamps).await?;
+            if ordered != milliamps {
+                println!("{milliamps} mA ordered as {ordered} mA (model range, resolution and limit)");
+            }
+            sleep(CURRENT_SOURCE_WAIT_TIME).await;
+        }
+        _ => return Err(CURRENT_USAGE.into()),
+    }
+    let ordered = source.read_setpoint(&mut ctx).await?;
+    let reported_ma = source.read_monitor(&mut ctx).await?;
+    println!("current source: ordered {ordered} mA, reported {reported_ma:.1} mA");
+    ctx.disconnect().await?;
+    Ok(())
+}
+
+async fn cmd_stepper(options: &BusOptions, mut args: Vec<String>) -> CliResult {
+    const STEPPER_USAGE: &str = "usage: stepper status|stop|fwd|rev|speed <rpm>|jog <mm>|mode run|loop|config [<param>=<value>...]\
+        |home|move <mm>|moveto <mm> [--from <mm>]|probe [--from <mm>]";
+    let from_mm = take_option(&mut args, "--from")?.map(|from| parse_f32(&from, "dipper position")).transpose()?;
+    let mut ctx = options.connect(BusStats::new()).await?;
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    match args.as_slice() {
+        ["status"] | [] => {}
+        ["stop"] => stop_smc05_rotation(&mut ctx).await?,
+        ["fwd"] => {
+            enable_sport_mode03(&mut ctx).await?;
+            start_smc05_fwd_rotation(&mut ctx).await?;
+        }
+        ["rev"] => {
+            enable_sport_mode03(&mut ctx).await?;
+            start_smc05_rev_rotation(&mut ctx).await?;
+        }
+        ["speed", rpm] => {
+            let rpm = parse_f32(rpm, "speed")?;
+            set_fwd_speed(&mut ctx, rpm).await?;
+            set_rev_speed(&mut ctx, rpm).await?;
+        }
+        ["jog", distance] => jog_smc05_distance(&mut ctx, parse_f32(distance, "jog distance")?).await?,
+        ["mode", mode] => set_smc05_sport_mode(&mut ctx, mode.parse()?).await?,
+        ["config", settings @ ..] if !settings.is_empty() => {
+            let mut config = read_smc05_config(&mut ctx).await?;
+            for setting in settings {
+                apply_stepper_setting(&mut config, setting)?;
+            }
+            write_smc05_config(&mut ctx, &config).await?;
+        }
+        ["config"] => {}
+        ["home"] => {
+            let mut axis = DipperAxis::new(DipperAxisConfig::DEFAULT.with_controller_pulses_per_rev(&mut ctx).await?);
+            axis.home(&mut ctx).await?;
+        }
+        ["move", distance] => {
+            // a measured move from wherever the dipper is: without a home there are no soft limits
+            let config = DipperAxisConfig { min_mm: f32::MIN, max_mm: f32::MAX, ..DipperAxisConfig::DEFAULT };
+            let mut axis = DipperAxis::new(config.with_controller_pulses_per_rev(&mut ctx).await?);
+            axis.set_reference(&mut ctx, 0.).await?;
+            let moved_mm = axis.move_to(&mut ctx, parse_f32(distance, "move distance")?).await?;
+            println!("dipper moved {moved_mm:.2} mm");
+        }
+        ["moveto", position] => {
+            let mut axis = DipperAxis::new(DipperAxisConfig::DEFAULT.with_controller_pulses_per_rev(&mut ctx).await?);
+            match from_mm {
+                Some(from_mm) => axis.set_reference(&mut ctx, from_mm).await?,
+                None => axis.home(&mut ctx).await?,
+            }
+            let stopped_mm = axis.move_to(&mut ctx, parse_f32(position, "dipper position")?).await?;
+            println!("dipper at {stopped_mm:.2} mm below home");
+        }
+        ["probe"] => {
+            let mut axis = DipperAxis::new(DipperAxisConfig::DEFAULT.with_controller_pulses_per_rev(&mut ctx).await?);
+            match from_mm {
+                Some(from_mm) => axis.set_reference(&mut ctx, from_mm).await?,
+                None => axis.home(&mut ctx).await?,
+            }
+            let mut melt_level = MeltLevelLog::new(CrucibleGeometry::DEFAULT);
+            let surface_mm = probe_melt_surface(&mut ctx, &mut axis, &melt_level.geometry, &MeltProbeConfig::DEFAULT, None).await?;
+            let sample = melt_level.record(chrono::Utc::now().timestamp_millis(), surface_mm);
+            println!("melt {sample}");
+            if melt_level.below_anode_ring() {
+                println!("melt is below the top of the anode ring ({:.2} mm)", melt_level.geometry.anode_ring_top_mm);
+            }
+        }
+        _ => return Err(STEPPER_USAGE.into()),
+    }
+    let status = read_smc05_status(&mut ctx).await?;
+    println!("stepper: {} {} at {} rpm, pulses {} actions {}",
+        status.status, status.direction, status.speed_rpm, status.pulse_count, status.action_count);
+    report_smc05_system_config(&mut ctx).await?;
+    ctx.disconnect().await?;
+    Ok(())
+}
+
+/// Apply one `stepper config` `<param>=<value>` setting
+fn apply_stepper_setting(config: &mut Smc05Config, setting: &str) -> Result<(), String> {
+    const PARAMS: &str = "mode, loops, pulses-per-rev, power-on-start, \
+        fwd-speed, fwd-accel, fwd-pulses, fwd-delay, rev-speed, rev-accel, rev-pulses, rev-delay";
+    let (param, value) = setting.split_once('=')
+        .ok_or_else(|| format!("bad setting {setting:?}: use <param>=<value> with param one of {PARAMS}"))?;
+    let (profile, field) = match param.split_once('-') {
+        Some(("fwd", field)) => (Some(&mut config.forward), field),
+        Some(("rev", field)) => (Some(&mut config.reverse), field),
+        _ => (None, param),
+    };
+    match (profile, field) {
+        (Some(profile), "speed") => profile.speed_rpm = parse_f32(value, param)?,
+        (Some(profile), "accel") => profile.acceleration = parse_u16(value)?,
+        (Some(profile), "pulses") => profile.pulses = parse_u16(value)?,
+        (Some(profile), "delay") => profile.delay_secs = parse_f32(value, param)?,
+        (None, "mode") => config.sport_mode = value.parse()?,
+        (None, "loops") => config.loop_count = parse_u16(value)?,
+        (None, "pulses-per-rev") => config.pulses_per_rev = parse_u16(value)?,
+        (None, "power-on-start") => config.power_on_start = parse_coil(value)?,
+        _ => return Err(format!("unknown stepper parameter {param:?}: use one of {PARAMS}")),
+    }
+    Ok(())
+}
+
+/// Run the controller, which is installed alongside this tool, with the same bus options
+fn cmd_run(options: &BusOptions, args: Vec<String>) -> CliResult {
+    let controller = std::env::current_exe()?.with_file_name("potslide");
+    let status = std::process::Command::new(&controller)
+        .args(options.to_args())
+        .args(args)
+        .status()
+        .map_err(|e| format!("couldn't start {}: {e}", controller.display()))?;
+    if !status.success() {
+        return Err(format!("potslide exited with {status}").into());
+    }
+    Ok(())
+}
+
+async fn cmd_record(options: &BusOptions, mut args: Vec<String>) -> CliResult {
+    let interval = take_option(&mut args, "--interval")?
+        .map(|millis| millis.parse().map(Duration::from_millis).map_err(|_| format!("bad --interval: {millis:?}")))
+        .transpose()?
+        .unwrap_or(DEFAULT_RECORD_INTERVAL);
+    let out_path = take_option(&mut args, "--out")?
+        .unwrap_or_else(|| format!("./data/{}_record.csv", chrono::Utc::now().timestamp()));
+    let pyro = take_flag(&mut args, "--pyro");
+    let duration = match args.as_slice() {
+        [] => None,
+        [secs] => Some(Duration::from_secs(secs.parse().map_err(|_| format!("bad duration: {secs:?}"))?)),
+        _ => return Err("usage: record [<secs>] [--interval <ms>] [--out <file.csv>] [--pyro]".into()),
+    };
+
+    let mut ctx = options.connect(BusStats::new()).await?;
+    let mut csv_writer = BufWriter::new(File::create(&out_path)?);
+    println!("Recording to {out_path:?} every {interval:?}, Ctrl-C to stop ...");
+    const RECORD_CSV_HEADER: &str = "epoch_ms,TK1_C,TK2_C,elec_V,elec_mA,drive_mA";
+    // The pyrometer's loop current, as read by the YK-DAQ1402, and the same current
+    // re-driven by the N4IOA01 and read back through the N4AIA04
+    const PYRO_CSV_COLUMNS: &str = ",pyro_mA,loop_mA";
+    let header = format!("{RECORD_CSV_HEADER}{}", if pyro { PYRO_CSV_COLUMNS } else { "" });
+    println!("{header}");
+    writeln!(csv_writer, "{header}")?;
+
+    let start = tokio::time::Instant::now();
+    let ctrl_c = tokio::signal::ctrl_c();
+    tokio::pin!(ctrl_c);
+    loop {
+        let (tk1_c, tk2_c) = read_dual_tk_temps(&mut ctx).await?;
+        let (volts, milliamps) = read_wdcu3003_iv_adc(&mut ctx).await?;
+        ctx.set_slave(Slave(NODEID_YKPVCCS010_CURR_SRC));
+        let drive_ma = read_electrode_current_drive(&mut ctx).await?;
+        let temp_text = |temp_c: Option<f32>| temp_c.map(|t| format!("{t:.1}")).unwrap_or_default();
+        let mut line = format!("{},{},{},{volts:.3},{milliamps:.2},{drive_ma:.1}",
+            chrono::Utc::now().timestamp_millis(), temp_text(tk1_c), temp_text(tk2_c));
+        if pyro {
+            let pyro_ma = ykdaq1402::CURRENT.read(&mut ctx).await?;
+            set_n4ioa01_0420_current_loop_drive(&mut ctx, pyro_ma).await?;
+            let loop_ma = n4aia04::CURRENT_1.read(&mut ctx).await?;
+            line.push_str(&format!(",{pyro_ma:.1},{loop_ma:.1}"));
+        }
+        println!("{line}");
+        writeln!(csv_writer, "{line}")?;
+        csv_writer.flush()?;
+
+        if duration.is_some_and(|duration| start.elapsed() >= duration) {
+            break;
+        }
+        tokio::select! {
+            _ = &mut ctrl_c => break,
+            _ = sleep(interval) => {}
+        }
+    }
+    ctx.disconnect().await?;
+    Ok(())
+}
+
+fn cmd_map(args: Vec<String>) -> CliResult {
+    match args.as_slice() {
+        [] => {
+            for map in DEVICE_MAPS.iter() {
+                println!("{:<16} 0x{:02X}  {}", map.model, map.node_id, map.description);
+            }
+        }
+        [all] if all == "all" => {
+            for map in DEVICE_MAPS.iter() {
+                println!("{}", map.to_markdown());
+            }
+        }
+        [model] => {
+            let map = device_map(model).ok_or_else(|| format!("unknown model {model:?}: run `craven map` for the list"))?;
+            print!("{}", map.to_markdown());
+        }
+        _ => return Err("usage: map [<model> | all]".into()),
+    }
+    Ok(())
+}
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() {
+    let (options, mut args) = match BusOptions::from_args(std::env::args().skip(1)) {
+        Ok(parsed) => parsed,
+        Err(e) => {
+            eprintln!("craven: {e}\n\n{USAGE}\n{BUS_OPTIONS_USAGE}");
+            std::process::exit(1);
+        }
+    };
+    if args.is_empty() || take_flag(&mut args, "--help") {
+        println!("{USAGE}\n{BUS_OPTIONS_USAGE}");
+        return;
+    }
+    let command = args.remove(0);
+    let result = match command.as_str() {
+        "scan" => cmd_scan(&options, args).await,
+        "configure" => cmd_configure(&options, args).await,
+        "read" => cmd_read(&options, args).await,
+        "write" => cmd_write(&options, args).await,
+        "watch" => cmd_watch(&options, args).await,
+        "replay" => cmd_replay(args).await,
+        "relay" => cmd_relay(&options, args).await,
+        "anodes" => cmd_anodes(&options, args).await,
+        "ai" => cmd_ai(&options, args).await,
+        "ao" => cmd_ao(&options, args).await,
+        "current" => cmd_current(&options, args).await,
+        "stepper" => cmd_stepper(&options, args).await,
+        "run" => cmd_run(&options, args),
+        "record" => cmd_record(&options, args).await,
+        "map" => cmd_map(args),
+        _ => Err(format!("unknown command {command:?}\n\n{USAGE}\n{BUS_OPTIONS_USAGE}").into()),
+    };
+    if let Err(e) = result {
+        eprintln!("craven: {e}");
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,294 @@
+//!
+//! Render a multi-panel time-series report from a controller run log.
+//! Panels show melt temperature, heater state, drive current, electrode voltage and resistance,
+//! and the charge delivered (for logs that record it), with drive phases shaded in the background and fault intervals marked in red.
+//!
+//! Usage:
+//!     plot_run <run_log.csv> [report.svg | report.png] [--font <file.ttf>] [--size <W>x<H>]
+//!
+//! The output format is chosen from the output file extension (default: the log path with `.svg`).
+//! Text is rendered with a pure-Rust font rasterizer, so a TrueType font file is needed;
+//! a few common system font locations are searched if `--font` is not given.
+//!
+
+use std::error::Error;
+use std::path::{Path, PathBuf};
+
+use plotters::coord::Shift;
+use plotters::prelude::*;
+use plotters::style::{FontStyle, register_font};
+
+use craven_control::INF_INTER_ELECTRODE_OHMS;
+use craven_control::run_log::*;
+
+/// Font files tried, in order, when no `--font` is given
+const DEFAULT_FONT_PATHS: [&str; 6] = [
+    "/usr/share/fonts/truetype/dejavu/DejaVuSans.ttf",
+    "/usr/share/fonts/truetype/DejaVuSans.ttf",
+    "/usr/share/fonts/TTF/DejaVuSans.ttf",
+    "/usr/share/fonts/dejavu/DejaVuSans.ttf",
+    "/System/Library/Fonts/Supplemental/Arial.ttf",
+    "/Library/Fonts/Arial.ttf",
+];
+
+/// Default report image size in pixels
+const DEFAULT_REPORT_SIZE: (u32, u32) = (1400, 1550);
+
+/// Background shading for each drive phase, indexed like `DRIVE_PHASE_NAMES`
+const PHASE_COLORS: [RGBColor; 5] = [
+    RGBColor(160, 160, 160),
+    RGBColor(255, 165, 0),
+    RGBColor(46, 139, 87),
+    RGBColor(65, 105, 225),
+    RGBColor(148, 0, 211),
+];
+
+const FAULT_COLOR: RGBColor = RGBColor(220, 20, 60);
+
+/// Extracts one plotted value from a sample, or None where there's nothing meaningful to plot
+type SampleValueFn = fn(&RunLogSample) -> Option<f64>;
+
+/// One line in a panel
+struct SeriesSpec {
+    label: &'static str,
+    color: RGBColor,
+    value: SampleValueFn,
+}
+
+/// One stacked chart in the report
+struct PanelSpec {
+    title: &'static str,
+    y_desc: &'static str,
+    /// Draw as a step function (for on/off signals)
+    step: bool,
+    series: Vec<SeriesSpec>,
+}
+
+fn report_panels() -> Vec<PanelSpec> {
+    vec![
+        PanelSpec { title: "Melt temperature", y_desc: "°C", step: false, series: vec![
+            SeriesSpec { label: "avg", color: RED, value: |s| Some(s.avg_temp_c as f64) },
+            SeriesSpec { label: "setpoint", color: BLACK, value: |s| s.setpoint_c.map(|v| v as f64) },
+        ]},
+        PanelSpec { title: "Heater", y_desc: "on", step: true, series: vec![
+            SeriesSpec { label: "heater", color: RGBColor(255, 120, 0), value: |s| Some(s.heater_on as u8 as f64) },
+            SeriesSpec { label: "dipper", color: RGBColor(0, 128, 128), value: |s| Some(s.dipper_enabled as u8 as f64 * 0.5) },
+        ]},
+        PanelSpec { title: "Drive current", y_desc: "mA", step: false, series: vec![
+            SeriesSpec { label: "ordered", color: BLACK, value: |s| Some(s.target_drive_ma as f64) },
+            SeriesSpec { label: "measured", color: BLUE, value: |s| Some(s.measured_ma as f64) },
+        ]},
+        PanelSpec { title: "Electrode potential", y_desc: "V", step: false, series: vec![
+            SeriesSpec { label: "measured", color: RGBColor(0, 128, 0), value: |s| Some(s.measured_volts as f64) },
+        ]},
+        PanelSpec { title: "Inter-electrode resistance", y_desc: "Ω", step: false, series: vec![
+            SeriesSpec { label: "measured", color: RGBColor(120, 120, 120), value: |s| finite_ohms(s.measured_ohms) },
+            SeriesSpec { label: "EWMA", color: MAGENTA, value: |s| finite_ohms(s.ohms_ewma) },
+        ]},
+        PanelSpec { title: "Charge delivered", y_desc: "C", step: false, series: vec![
+            SeriesSpec { label: "run", color: RGBColor(139, 69, 19), value: |s| s.charge_coulombs.map(|v| v as f64) },
+        ]},
+    ]
+}
+
+/// Open-circuit readings are logged as an arbitrary large resistance: leave those out of the plot
+fn finite_ohms(ohms: f32) -> Option<f64> {
+    if ohms >= INF_INTER_ELECTRODE_OHMS { None } else { Some(ohms as f64) }
+}
+
+/// A time interval (in minutes since run start) carrying some value
+struct Span<T> {
+    start_min: f64,
+    end_min: f64,
+    value: T,
+}
+
+/// Group consecutive samples that share the same key into spans
+fn spans_by<T: PartialEq + Copy>(samples: &[RunLogSample], x: &[f64], key: impl Fn(&RunLogSample) -> Option<T>) -> Vec<Span<T>> {
+    let mut spans: Vec<Span<T>> = Vec::new();
+    for (idx, sample) in samples.iter().enumerate() {
+        let end_min = x.get(idx + 1).copied().unwrap_or(x[idx]);
+        match (key(sample), spans.last_mut()) {
+            (Some(value), Some(last)) if last.value == value && last.end_min == x[idx] => last.end_min = end_min,
+            (Some(value), _) => spans.push(Span { start_min: x[idx], end_min, value }),
+            (None, _) => {}
+        }
+    }
+    spans
+}
+
+/// Split a series into contiguous runs of plottable points
+fn series_segments(samples: &[RunLogSample], x: &[f64], value: SampleValueFn, step: bool) -> Vec<Vec<(f64, f64)>> {
+    let mut segments = vec![];
+    let mut current: Vec<(f64, f64)> = vec![];
+    for (sample, &t) in samples.iter().zip(x) {
+        match value(sample) {
+            Some(v) => {
+                if step && let Some(&(_, prev)) = current.last() {
+                    current.push((t, prev));
+                }
+                current.push((t, v));
+            }
+            None => {
+                if !current.is_empty() { segments.push(std::mem::take(&mut current)); }
+            }
+        }
+    }
+    if !current.is_empty() { segments.push(current); }
+    segments
+}
+
+fn value_range(samples: &[RunLogSample], panel: &PanelSpec) -> (f64, f64) {
+    let values = panel.series.iter()
+        .flat_map(|series| samples.iter().filter_map(series.value));
+    let (lo, hi) = values.fold((f64::MAX, f64::MIN), |(lo, hi), v| (lo.min(v), hi.max(v)));
+    if lo > hi { return (0., 1.); }
+    let pad = if hi > lo { (hi - lo) * 0.08 } else { 0.5 };
+    (lo - pad, hi + pad)
+}
+
+fn draw_report<DB: DrawingBackend>(root: DrawingArea<DB, Shift>, title: &str, samples: &[RunLogSample])
+-> Result<(), Box<dyn Error>>
+where DB::ErrorType: 'static
+{
+    let start_ms = samples[0].epoch_ms;
+    let x: Vec<f64> = samples.iter().map(|s| (s.epoch_ms - start_ms) as f64 / 60_000.).collect();
+    let x_range = 0f64..x.last().copied().unwrap_or(0.).max(1. / 60.);
+
+    let phase_spans = spans_by(samples, &x, |s| s.drive_phase);
+    let fault_spans = spans_by(samples, &x, |s| if s.faults != 0 { Some(s.faults) } else { None });
+
+    root.fill(&WHITE)?;
+    let root = root.titled(title, ("sans-serif", 22))?;
+    let panels = report_panels();
+    let areas = root.split_evenly((panels.len(), 1));
+
+    for (panel_idx, (panel, area)) in panels.iter().zip(areas.iter()).enumerate() {
+        let (y_lo, y_hi) = value_range(samples, panel);
+        let mut chart = ChartBuilder::on(area)
+            .caption(panel.title, ("sans-serif", 16))
+            .margin(6)
+            .x_label_area_size(28)
+            .y_label_area_size(64)
+            .build_cartesian_2d(x_range.clone(), y_lo..y_hi)?;
+        chart.configure_mesh()
+            .x_desc("minutes")
+            .y_desc(panel.y_desc)
+            .light_line_style(WHITE.mix(0.))
+            .draw()?;
+
+        chart.draw_series(phase_spans.iter().map(|span| {
+            let color = PHASE_COLORS.get(span.value as usize).copied().unwrap_or(PHASE_COLORS[0]);
+            Rectangle::new([(span.start_min, y_lo), (span.end_min, y_hi)], color.mix(0.12).filled())
+        }))?;
+        chart.draw_series(fault_spans.iter().map(|span| {
+            Rectangle::new([(span.start_min, y_lo), (span.end_min, y_hi)], FAULT_COLOR.mix(0.25).filled())
+        }))?;
+        chart.draw_series(fault_spans.iter().map(|span| {
+            PathElement::new(vec![(span.start_min, y_lo), (span.start_min, y_hi)], FAULT_COLOR.stroke_width(1))
+        }))?;
+
+        // label phases and faults along the top of the first panel only
+        if panel_idx == 0 {
+            let label_y = y_hi - (y_hi - y_lo) * 0.04;
+            chart.draw_series(phase_spans.iter().map(|span| {
+                Text::new(drive_phase_name(span.value), (span.start_min, label_y), ("sans-serif", 13).into_font())
+            }))?;
+            let fault_label_y = y_lo + (y_hi - y_lo) * 0.12;
+            chart.draw_series(fault_spans.iter().map(|span| {
+                Text::new(describe_faults(span.value), (span.start_min, fault_label_y),
+                    ("sans-serif", 12).into_font().color(&FAULT_COLOR))
+            }))?;
+        }
+
+        for series in &panel.series {
+            let segments = series_segments(samples, &x, series.value, panel.step);
+            let color = series.color;
+            for (seg_idx, segment) in segments.into_iter().enumerate() {
+                let drawn = chart.draw_series(LineSeries::new(segment, color.stroke_width(2)))?;
+                if seg_idx == 0 {
+                    drawn.label(series.label)
+                        .legend(move |(lx, ly)| PathElement::new(vec![(lx, ly), (lx + 18, ly)], color.stroke_width(2)));
+                }
+            }
+        }
+        chart.configure_series_labels()
+            .position(SeriesLabelPosition::UpperRight)
+            .background_style(WHITE.mix(0.8))
+            .border_style(BLACK)
+            .label_font(("sans-serif", 12))
+            .draw()?;
+    }
+
+    root.present()?;
+    Ok(())
+}
+
+/// Load a TrueType font and make it the "sans-serif" family used by the charts
+fn load_font(font_arg: Option<&str>) -> Result<(), Box<dyn Error>> {
+    let font_path = match font_arg {
+        Some(path) => PathBuf::from(path),
+        None => DEFAULT_FONT_PATHS.iter().map(PathBuf::from).find(|path| path.exists())
+            .ok_or("no font found: pass one with --font <file.ttf>")?,
+    };
+    let font_bytes: &'static [u8] = Box::leak(std::fs::read(&font_path)?.into_boxed_slice());
+    register_font("sans-serif", FontStyle::Normal, font_bytes)
+        .map_err(|_| format!("{font_path:?} is not a usable font file"))?;
+    Ok(())
+}
+
+fn print_usage() {
+    eprintln!("usage: plot_run <run_log.csv> [report.svg | report.png] [--font <file.ttf>] [--size <W>x<H>]");
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let mut positional: Vec<String> = vec![];
+    let mut font_arg: Option<String> = None;
+    let mut size = DEFAULT_REPORT_SIZE;
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--font" => font_arg = Some(args.next().ok_or("--font needs a file path")?),
+            "--size" => {
+                let size_arg = args.next().ok_or("--size needs <W>x<H>")?;
+                let (w, h) = size_arg.split_once('x').ok_or("--size needs <W>x<H>")?;
+                size = (w.parse()?, h.parse()?);
+            }
+            "-h" | "--help" => {
+                print_usage();
+                return Ok(());
+            }
+            _ => positional.push(arg),
+        }
+    }
+
+    let Some(log_path) = positional.first().map(PathBuf::from) else {
+        print_usage();
+        std::process::exit(1);
+    };
+    let out_path = positional.get(1).map(PathBuf::from)
+        .unwrap_or_else(|| log_path.with_extension("svg"));
+
+    let samples = read_run_log(&log_path)?;
+    if samples.is_empty() {
+        return Err(format!("{log_path:?} contains no samples").into());
+    }
+    load_font(font_arg.as_deref())?;
+
+    let start_dt = chrono::DateTime::from_timestamp_millis(samples[0].epoch_ms).unwrap_or_default();
+    let duration_min = (samples[samples.len() - 1].epoch_ms - samples[0].epoch_ms) as f64 / 60_000.;
+    let title = format!("{}  —  started {}  ({:.1} min)",
+        log_path.file_name().unwrap_or_default().to_string_lossy(),
+        start_dt.format("%Y-%m-%d %H:%M:%S UTC"),
+        duration_min);
+
+    match out_path.extension().and_then(|ext| ext.to_str()) {
+        Some("svg") => draw_report(SVGBackend::new(&out_path, size).into_drawing_area(), &title, &samples)?,
+        Some("png") => draw_report(BitMapBackend::new(&out_path, size).into_drawing_area(), &title, &samples)?,
+        _ => return Err(format!("unsupported output type {:?}: use .svg or .png", out_path).into()),
+    }
+
+    println!("Wrote {} samples to {}", samples.len(), Path::new(&out_path).display());
+    Ok(())
+}
@@ -6,6 +6,27 @@
 //! - simple linear wire/rod cathode (geometry used for current density calculations)
 //! - optional linear rail motion of cathode immerse/extract (where the name potslide comes from)
 //!
+//! Usage: `potslide [--console] [--dip-profile <file>] [--anode-relays <channel>,...] [--anode-pattern <pattern>]
+//! [--anode-dwell <ms>[,<ms>...]] [--anode-check <minutes>] [--anode-exclude] [--anode-balance] [--reaction <name>] [--charge-target <phase>=<C>]
+//! [bus options]`,
+//! bus options as for `craven` (`--tcp`, `--rtu`, ...)
+//! With `--console`, a full-screen operator console replaces the stdin command interface,
+//! and the regular progress output is written to `./data/<start secs>_console.log` instead.
+//! With `--dip-profile`, the enabled dipper follows the recipe in that JSON file (see `dip_profile`)
+//! instead of chasing surface contact; it starts over each time the dipper is enabled.
+//! `--anode-relays` lists the relay channel switching each anode, in anode order (by default 1,2,3,4),
+//! which sets how many anodes there are, up to eight.
+//! `--anode-pattern` and `--anode-dwell` choose how the anodes take turns during Elongation
+//! (see `anode_pattern`, and the `pattern` operator command); by default, adjacent pairs for 1 s each.
+//! `--anode-check` measures each anode on its own (see `anode_check`) at that interval in minutes once
+//! the drive phases start, as does the `anodes check` command; `--anode-exclude` then leaves failed
+//! anodes out of the patterns, and `--anode-balance` weights Elongation dwells by anode resistance.
+//! The charge and energy delivered are totalled per phase and for the run (see `charge`), with the
+//! theoretical carbon deposited by the `--reaction` named (by default `carbonate`).
+//! `--charge-target nucleation=<C>` ends Nucleation on that charge instead of after a fixed time, and
+//! `--charge-target elongation=<C>` ends Elongation on that charge if it hasn't already ended;
+//! the `charge` command sets the same targets.
+//!
 //! 
 
 use std::time::Duration;
@@ -13,7 +34,7 @@ use tokio::time::{sleep, sleep_until};
 use tokio_modbus::prelude::*;
 
 use tokio_modbus::client::{Client};
-use tokio::io::{AsyncBufReadExt, BufReader};
+use tokio::io::{AsyncBufReadExt, BufReader, Lines, Stdin};
 
 use std::fs::File;
 use std::io::{BufWriter, Write};
@@ -21,6 +42,21 @@ use std::io::{BufWriter, Write};
 use approx::{abs_diff_ne};
 use craven_control::*;
 use craven_control::smc05::*;
+use craven_control::dipper::*;
+use craven_control::dip_profile::*;
+use craven_control::contact::*;
+use craven_control::melt_level::*;
+use craven_control::anode_pattern::*;
+use craven_control::anode_check::*;
+use craven_control::charge::*;
+use craven_control::run_log::*;
+use craven_control::telemetry::*;
+use craven_control::operator::*;
+use craven_control::http_api::*;
+use craven_control::bus::*;
+use craven_control::mqtt::*;
+use craven_control::console::*;
+use craven_control::cli::*;
 
 /// This dictates, on average, how often the main loop runs 
 const INTER_LOOP_DELAY: Duration = Duration::from_millis(1000);
@@ -60,6 +96,8 @@ const CUT_OUT_ABOVE_TARGET_TEMP_C: f32 = 6.;
 const EXCESSIVE_HEAT_DELTA_C: f32 = 12.;
 /// Above this temperature the furnace heat is out of control
 const EXCESSIVE_HEAT_TEMP_C:f32 = ELECTROLYTE_TARGET_TEMP_C + EXCESSIVE_HEAT_DELTA_C;
+/// Highest set point an operator may request: keeps the heater cut-out below the excessive heat limit
+const MAX_OPERATOR_SETPOINT_C: f32 = EXCESSIVE_HEAT_TEMP_C - CUT_OUT_ABOVE_TARGET_TEMP_C;
 
 
 /// Below this resistance value we terminate the Cyclic phase
@@ -68,6 +106,24 @@ const CYCLIC_LOWV_TERMINATION_OHMS: f32 = 0.5;
 /// Limit of the current supply
 const MAX_DRIVE_CURRENT_MA: f32 = 1000.;
 
+/// Furthest the operator may jog the dipper in one command
+const MAX_DIPPER_JOG_MM: f32 = 25.;
+
+/// Longest a dipper move or homing may hold up the control loop
+const DIPPER_MOVE_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// How often to probe the melt level while Holding with the dipper monitor off
+const MELT_PROBE_INTERVAL_MS: i64 = 30*60*1000;
+
+/// Longest a melt level probe may hold up the electrodes (the furnace is still serviced)
+const MELT_PROBE_TIMEOUT: Duration = Duration::from_secs(180);
+
+/// Longest a per-anode check may hold up the electrodes
+const ANODE_CHECK_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// How often running charge totals are printed and recorded in the run log
+const CHARGE_LOG_INTERVAL_MS: i64 = 5*60*1000;
+
 /// Pre-estimated surface area of electrode probe (in this case, the area of the cathode)
 // const ELECTRODE_SURFACE_MM2:f32 = f32::consts::PI*(1.0)*30.; // Approximate area of twisted pair of 1 mm diameter, about 30 mm long
 // const ELECTRODE_SURFACE_MM2:f32 = f32::consts::PI*(2.0)*30.; // Approximate area of rod of 2 mm diameter, about 30 mm long
@@ -104,11 +160,8 @@ const CYCLIC_LOWV_DURATION_MS: u64 = 20*1000;
 const CYCLIC_PERIOD_MS: u64 = CYCLIC_LOWV_DURATION_MS + CYCLIC_HIGHV_DURATION_MS;
 
 
-/// Minimum time an anode should remain connected to current source during elongation phase
-const ANODE_ELONGATION_CONNECT_PERIOD_MS:usize = 1000;
-const ANODE_CONNECTION_CHANGE_MS:usize = ANODE_ELONGATION_CONNECT_PERIOD_MS / 3;
-const NUM_ANODE_PAIRS:usize = 4;
-// const FULL_ANODE_CYCLE_DURATION_MS: usize  = NUM_ANODE_PAIRS * ANODE_ELONGATION_CONNECT_PERIOD_MS;
+/// Default time each step of the elongation phase anode pattern is held
+const ANODE_ELONGATION_CONNECT_PERIOD_MS: u64 = 1000;
 
 
 
@@ -144,7 +197,7 @@ async fn enumerate_required_modules(ctx: &mut tokio_modbus::client::Context) ->
     ping_one_modbus_node_id(ctx,NODEID_YKPVCCS010_CURR_SRC, REG_NODEID_YKPVCCS010_CURR_SRC).await?;
 
     // controls furnace on/off
-    // controls 4-pair anode connection relays
+    // controls anode connection relays
     ping_one_modbus_node_id(ctx, NODEID_WAV_OCTO_RELAY, REG_NODEID_WAVESHARE_V2).await?;
 
     // controls dipping motion of cathode
@@ -154,7 +207,7 @@ async fn enumerate_required_modules(ctx: &mut tokio_modbus::client::Context) ->
 }
 
 #[repr(u8)]
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 enum DrivePhase {
     /// No prior state
     Fresh = 0,
@@ -176,13 +229,12 @@ enum DrivePhase {
 async fn toggle_furnace(ctx: &mut tokio_modbus::client::Context, active:bool)
 -> Result<(), Box<dyn std::error::Error>> 
 {
-    const FURNACE_RELAY_CHANNEL: u8 = 7;
     sleep(MODBUS_RW_DELAY).await;
     toggle_wav_octo_relay(ctx, FURNACE_RELAY_CHANNEL, active).await
 }
 
 /// Shut off the furnace heater, shut off any current drive.
-async fn zero_control_outputs(ctx: &mut tokio_modbus::client::Context)
+async fn zero_control_outputs(ctx: &mut tokio_modbus::client::Context, anode_relays: &[u8])
 -> Result<(), Box<dyn std::error::Error>> 
 {
     println!("zero_control_outputs...");
@@ -190,8 +242,7 @@ async fn zero_control_outputs(ctx: &mut tokio_modbus::client::Context)
     set_electrode_current_drive(ctx,0.).await?;
     stop_smc05_rotation(ctx).await?;
 
-    let anode_channels= [false; 4];
-    write_wav_octo_relays(ctx, &anode_channels).await?;
+    write_anode_relays(ctx, anode_relays, 0).await?;
 
     println!("Outputs disabled.");
     Ok(())
@@ -202,17 +253,31 @@ pub struct FurnaceState {
 
     /// Temperature set point
     pub setpoint_c: f32,
+    /// Operator-supplied set point that replaces the automatic schedule
+    pub setpoint_override_c: Option<f32>,
     /// Most recently measured temperature
     pub measured_temp_c: f32,
+    /// Individual thermocouple readings, None if a probe is disconnected
+    pub tk1_c: Option<f32>,
+    pub tk2_c: Option<f32>,
     /// Whether the furnace heater is turned on
     pub heater_on: bool,
+    /// Whether no thermocouple reported a valid temperature on the last read
+    pub probes_lost: bool,
+    /// Whether the operator has forced the heater off, regardless of set point
+    pub heater_forced_off: bool,
 
 }
 
 const INITIAL_FURNACE_STATE: FurnaceState = FurnaceState  { 
         setpoint_c: PROBE_CHECK_TEMP_C, 
+        setpoint_override_c: None,
         measured_temp_c: 0., 
-        heater_on: false 
+        tk1_c: None,
+        tk2_c: None,
+        heater_on: false,
+        probes_lost: false,
+        heater_forced_off: false,
     };
 
 ///
@@ -235,12 +300,15 @@ async fn control_furnace(ctx: &mut tokio_modbus::client::Context, state: &mut Fu
                 }
                 (tk1_c + tk2_c) / 2f32 
             }
-            else { tk1_c.into()}
+            else { tk1_c}
         }
-        else if ch2_tk_opt.is_some() { tk2_c.into() }
-        else { MAX_PROBE_TEMP_C as f32};
+        else if ch2_tk_opt.is_some() { tk2_c }
+        else { MAX_PROBE_TEMP_C};
     
     state.measured_temp_c = avg_core_tk_c;
+    state.tk1_c = ch1_tk_opt;
+    state.tk2_c = ch2_tk_opt;
+    state.probes_lost = ch1_tk_opt.is_none() && ch2_tk_opt.is_none();
 
     // update the temperature setpoint based on which phase of electrolyte melting we're at
     if state.measured_temp_c < ELECTROLYTE_TARGET_TEMP_C {
@@ -258,10 +326,22 @@ async fn control_furnace(ctx: &mut tokio_modbus::client::Context, state: &mut Fu
         new_temp_setpoint_c = ELECTROLYTE_TARGET_TEMP_C;
     }
 
+    // an operator override trumps the schedule
+    let new_temp_setpoint_c = state.setpoint_override_c.unwrap_or(new_temp_setpoint_c);
+
     if new_temp_setpoint_c != state.setpoint_c {
         println!("setpoint old {:.3} new {:.3}", state.setpoint_c, new_temp_setpoint_c);
     }
 
+    if state.heater_forced_off {
+        if state.heater_on {
+            toggle_furnace(ctx, false).await?;
+            state.heater_on = false;
+        }
+        state.setpoint_c = new_temp_setpoint_c;
+        return Ok(());
+    }
+
     // Dirt simple bangbang controller:
     // - Cut out the heater when temperature exceeds a cut out point above the target temperature
     // - Cut in the heater when temperature drops below a cut in point (above the target temperature)
@@ -320,14 +400,55 @@ pub struct ElectrodeState {
     /// The actual measured potential across the electrodes 
     measured_volts: f32,
 
-    /// The current primary active anode index
-    primary_anode_idx: usize,
-    /// Whether a given anode is connected to the current supply 
-    anode_connections: [bool; NUM_ANODE_PAIRS],
+    /// What the anode relays should do, as chosen by the drive phase (applied by the anode driver)
+    anode_drive: AnodeDrive,
+    /// Whether a given anode is connected to the current supply, as last switched by the anode driver
+    anode_connections: Vec<bool>,
+    /// Operator-chosen anode connections, replacing the drive phase pattern
+    anode_override: Option<AnodeMask>,
+    /// Operator-chosen Elongation anode pattern, in place of the default
+    elongation_anodes: Option<AnodeSequence>,
+    /// Octo relay channel switching each anode, in anode order: one per anode
+    anode_relays: Vec<u8>,
+
+    /// Operator-fixed drive current, replacing the drive phase's choice
+    drive_override_ma: Option<f32>,
+    /// Operator cap on the drive current
+    drive_limit_ma: Option<f32>,
+    /// UTC epoch milliseconds at which the operator paused the phase clock
+    phase_clock_paused_ms: Option<i64>,
 
     // stepper motor controller state used for inserting/withdrawing (dipping) cathode
     dipper_state: StepperDriverState,
-
+    /// Recipe the dipper follows when enabled, in place of the surface contact monitor
+    dip_profile: Option<DipProfileRun>,
+
+    /// Melt surface heights found by probing
+    melt_level: MeltLevelLog,
+    /// UTC epoch milliseconds after which the next scheduled melt probe runs
+    next_melt_probe_ms: i64,
+    /// Whether the operator asked for a melt probe
+    melt_probe_requested: bool,
+
+    /// The latest per-anode check
+    anode_check: Option<AnodeCheck>,
+    /// How often to check the anodes once the drive phases start, if at all
+    anode_check_interval_ms: Option<i64>,
+    /// UTC epoch milliseconds after which the next scheduled anode check runs
+    next_anode_check_ms: i64,
+    /// Whether the operator asked for an anode check
+    anode_check_requested: bool,
+    /// Leave anodes that failed the latest check out of the drive phase patterns
+    exclude_failed_anodes: bool,
+    /// Balance the Elongation pattern's dwells by the anode resistances from the latest check
+    balance_anodes: bool,
+
+    /// Charge and energy delivered, per drive phase and for the run
+    charge: ChargeIntegrator,
+    /// Charge in coulombs at which a drive phase ends, indexed by phase
+    phase_charge_targets: [Option<f64>; DrivePhase::Max as usize],
+    /// UTC epoch milliseconds after which the running charge totals are next logged
+    next_charge_log_ms: i64,
 
     /// Timestamp when each phase started
     phase_starts_utc_ms: [i64; DrivePhase::Max as usize],
@@ -350,8 +471,14 @@ const INITIAL_ELECTRODE_STATE: ElectrodeState =
             reported_drive_ma:0.,
             measured_ma:0.,
             measured_volts:0., 
-            primary_anode_idx: 0,
-            anode_connections: [false; NUM_ANODE_PAIRS],
+            anode_drive: AnodeDrive::Fixed(0),
+            anode_connections: Vec::new(),
+            anode_relays: Vec::new(),
+            anode_override: None,
+            elongation_anodes: None,
+            drive_override_ma: None,
+            drive_limit_ma: None,
+            phase_clock_paused_ms: None,
             dipper_state: StepperDriverState {  // TODO replace with Default when const Default is stable
                 dipper_enabled: false, 
                 dipper_last_status_check_ms: 0, 
@@ -359,7 +486,22 @@ const INITIAL_ELECTRODE_STATE: ElectrodeState =
                 dipper_prior_pulse_count: 0, 
                 dipper_prior_action_count: 0,
                 surface_contact_start_ms: 0,
+                axis: DipperAxis::new(DipperAxisConfig::DEFAULT),
+                contact: ContactDetector::new(ContactThresholds::DEFAULT),
             },
+            dip_profile: None,
+            melt_level: MeltLevelLog::new(CrucibleGeometry::DEFAULT),
+            next_melt_probe_ms: 0,
+            melt_probe_requested: false,
+            anode_check: None,
+            anode_check_interval_ms: None,
+            next_anode_check_ms: 0,
+            anode_check_requested: false,
+            exclude_failed_anodes: false,
+            balance_anodes: false,
+            charge: ChargeIntegrator::new(CARBONATE_REDUCTION),
+            phase_charge_targets: [None; DrivePhase::Max as usize],
+            next_charge_log_ms: 0,
             phase_starts_utc_ms: [0; DrivePhase::Max as usize],
         }; 
 
@@ -417,13 +559,23 @@ async fn drive_current_and_measure(ctx: &mut tokio_modbus::client::Context,
             INF_INTER_ELECTRODE_OHMS // arbitrary value based on previous experiments
         };
 
-    return Ok((measured_volts, measured_milliamps, measured_ohms))
+    Ok((measured_volts, measured_milliamps, measured_ohms))
+}
+
+/// Report the charge delivered in the phase being left, and start counting afresh for the next
+fn restart_phase_charge(state: &mut ElectrodeState, next_phase: DrivePhase, trans_utc_ms: i64)
+{
+    if state.drive_phase != DrivePhase::Fresh {
+        println!("{} {:?} phase delivered {}", trans_utc_ms, state.drive_phase, state.charge.phase(state.drive_phase as u8));
+    }
+    state.charge.restart_phase(next_phase as u8);
 }
 
 /// Transition to Warmup drive phase
 fn trans_warmup_phase(state: &mut ElectrodeState, trans_utc_ms: i64)
 -> f32
 {
+    restart_phase_charge(state, DrivePhase::Warmup, trans_utc_ms);
     state.drive_phase = DrivePhase::Warmup;
     state.phase_start_ms = trans_utc_ms;
     state.phase_starts_utc_ms[DrivePhase::Warmup as usize] = trans_utc_ms;
@@ -438,6 +590,7 @@ fn trans_warmup_phase(state: &mut ElectrodeState, trans_utc_ms: i64)
 fn trans_nucleation_phase(state: &mut ElectrodeState, trans_utc_ms: i64)
 -> f32
 {
+    restart_phase_charge(state, DrivePhase::Nucleation, trans_utc_ms);
     state.drive_phase = DrivePhase::Nucleation;
     state.phase_start_ms = trans_utc_ms;
     state.phase_starts_utc_ms[DrivePhase::Nucleation as usize] = trans_utc_ms;
@@ -453,6 +606,7 @@ fn trans_nucleation_phase(state: &mut ElectrodeState, trans_utc_ms: i64)
 fn trans_elongation_phase(state: &mut ElectrodeState, trans_utc_ms: i64, prior_duration_ms: u64)
 -> f32
 {
+    restart_phase_charge(state, DrivePhase::Elongation, trans_utc_ms);
     state.drive_phase = DrivePhase::Elongation;
     state.phase_start_ms = trans_utc_ms;
     state.phase_starts_utc_ms[DrivePhase::Elongation as usize] = trans_utc_ms;
@@ -468,6 +622,7 @@ fn trans_elongation_phase(state: &mut ElectrodeState, trans_utc_ms: i64, prior_d
 fn trans_holding_phase(state: &mut ElectrodeState, trans_utc_ms: i64, prior_duration_ms: u64)
 -> f32
 {
+    restart_phase_charge(state, DrivePhase::Holding, trans_utc_ms);
     state.drive_phase = DrivePhase::Holding;
     state.phase_start_ms = trans_utc_ms;
     state.phase_starts_utc_ms[DrivePhase::Holding as usize] = trans_utc_ms;
@@ -485,26 +640,121 @@ fn trans_holding_phase(state: &mut ElectrodeState, trans_utc_ms: i64, prior_dura
 /// Monitor the cathode dipping into the electrolyte.
 /// 
 pub async fn dipper_cycle_check(ctx: &mut tokio_modbus::client::Context, 
-    state: &mut ElectrodeState, current_utc_ms: i64, measured_ma: f32)
+    state: &mut ElectrodeState, current_utc_ms: i64, measured_ma: f32, measured_ohms: f32)
     -> Result<(), Box<dyn std::error::Error>> 
 {
     if !state.dipper_state.dipper_enabled {return Ok(()) };
 
+    if let Some(run) = state.dip_profile.as_mut() {
+        if state.dipper_state.dipper_last_status_check_ms == 0 {
+            run.restart();
+        }
+        state.dipper_state.dipper_last_status_check_ms = current_utc_ms;
+        match run.tick(ctx, &mut state.dipper_state.axis, current_utc_ms, measured_ma).await {
+            Ok(true) => {}
+            Ok(false) => disable_dipper_monitor(&mut state.dipper_state),
+            Err(e) => {
+                println!("{} Dip profile stopped: {e}", current_utc_ms);
+                disable_dipper_monitor(&mut state.dipper_state);
+                stop_smc05_rotation(ctx).await?;
+            }
+        }
+        return Ok(());
+    }
+
     if state.dipper_state.dipper_last_status_check_ms == 0 {
         println!("{} Fresh Dipper",current_utc_ms);
         setup_cathode_surface_probe(ctx).await?;
     }
 
-    let threshold_current_ma = state.target_drive_ma / 20.;
-
     // only check the status periodically, because there can be some pauses and delays between reversals and loops
     if (current_utc_ms - state.dipper_state.dipper_last_status_check_ms) > 1000 {
-        surface_contact_monitor(ctx, current_utc_ms, &mut state.dipper_state, threshold_current_ma, measured_ma).await?;
+        surface_contact_monitor(ctx, current_utc_ms, &mut state.dipper_state,
+            state.target_drive_ma, measured_ma, measured_ohms).await?;
     }
 
     Ok(())
 }
 
+/// Whether a melt level probe should run now: asked for, or due while Holding,
+/// with the dipper homed and its monitor off
+fn melt_probe_due(state: &ElectrodeState, current_utc_ms: i64) -> bool
+{
+    let dipper = &state.dipper_state;
+    let scheduled = state.drive_phase == DrivePhase::Holding && current_utc_ms >= state.next_melt_probe_ms;
+    dipper.axis.is_homed() && !dipper.dipper_enabled && (state.melt_probe_requested || scheduled)
+}
+
+///
+/// Probe the melt surface and record the level, warning when the melt no longer covers the anode ring
+///
+async fn probe_melt_level(ctx: &mut tokio_modbus::client::Context, state: &mut ElectrodeState, current_utc_ms: i64)
+{
+    state.melt_probe_requested = false;
+    state.next_melt_probe_ms = current_utc_ms + MELT_PROBE_INTERVAL_MS;
+    let geometry = state.melt_level.geometry;
+    let last_surface_mm = state.melt_level.latest().map(|sample| sample.surface_mm);
+    let probe = probe_melt_surface(ctx, &mut state.dipper_state.axis, &geometry, &MeltProbeConfig::DEFAULT, last_surface_mm);
+    match tokio::time::timeout(MELT_PROBE_TIMEOUT, probe).await {
+        Ok(Ok(surface_mm)) => {
+            let sample = state.melt_level.record(chrono::Utc::now().timestamp_millis(), surface_mm);
+            let trend = state.melt_level.trend_mm_per_hour().map_or(String::new(), |trend| format!(" trend {trend:+.2} mm/h"));
+            println!("{} Melt {sample}{trend}", sample.epoch_ms);
+            if state.melt_level.below_anode_ring() {
+                println!("{} Melt level {:.2} mm is below the top of the anode ring at {:.2} mm!", 
+                    sample.epoch_ms, sample.level_mm, geometry.anode_ring_top_mm);
+            }
+        }
+        Ok(Err(e)) => println!("{} Melt probe failed: {e}", current_utc_ms),
+        Err(_) => {
+            println!("{} Melt probe timed out", current_utc_ms);
+            // don't leave the dipper running
+            let _ = tokio::time::timeout(MODBUS_TRANSACTION_TIMEOUT, stop_smc05_rotation(ctx)).await;
+        }
+    }
+}
+
+/// Whether a per-anode check should run now: asked for, or due once the drive phases have started
+fn anode_check_due(state: &ElectrodeState, current_utc_ms: i64) -> bool
+{
+    let scheduled = state.anode_check_interval_ms.is_some()
+        && state.drive_phase != DrivePhase::Fresh 
+        && current_utc_ms >= state.next_anode_check_ms;
+    state.anode_check_requested || scheduled
+}
+
+///
+/// Take the anode relays from the anode driver and measure each anode on its own;
+/// the driver gets the relays back when the main loop next sets the anode drive
+///
+async fn check_anode_wires(ctx: &mut tokio_modbus::client::Context, anodes: &AnodeDriver, 
+    state: &mut ElectrodeState, current_utc_ms: i64)
+{
+    state.anode_check_requested = false;
+    state.next_anode_check_ms = current_utc_ms + state.anode_check_interval_ms.unwrap_or_default();
+    let anode_relays = state.anode_relays.clone();
+    let check = async {
+        anodes.release().await?;
+        check_anodes(ctx, &anode_relays, &AnodeCheckConfig::DEFAULT).await
+    };
+    match tokio::time::timeout(ANODE_CHECK_TIMEOUT, check).await {
+        Ok(Ok(check)) => {
+            print!("{check}");
+            if check.failed_mask() != 0 {
+                println!("{} Anodes {} failed the check{}", check.epoch_ms, format_anode_mask(check.failed_mask()),
+                    if state.exclude_failed_anodes { ", and are left out" } else { "" });
+            }
+            state.anode_check = Some(check);
+        }
+        Ok(Err(e)) => println!("{} Anode check failed: {e}", current_utc_ms),
+        Err(_) => {
+            println!("{} Anode check timed out", current_utc_ms);
+            // don't leave the probe current running
+            let _ = tokio::time::timeout(MODBUS_TRANSACTION_TIMEOUT, set_electrode_current_drive(ctx, 0.)).await;
+        }
+    }
+}
+
 /// 
 /// Adjust the electrode current based on melt condition and drive phase
 /// 
@@ -519,12 +769,16 @@ async fn control_electrodes(ctx: &mut tokio_modbus::client::Context,
 
     let after_drive_utc_dt = chrono::Utc::now();
     let after_drive_utc_ms = after_drive_utc_dt.timestamp_millis();
+    // credited to the phase that ordered this current
+    state.charge.record(after_drive_utc_ms, state.drive_phase as u8, measured_milliamps, measured_volts);
 
+    // a paused phase clock stands still at the moment it was paused
+    let phase_clock_ms = state.phase_clock_paused_ms.unwrap_or(after_drive_utc_ms);
     let phase_duration_ms = 
-        if state.phase_start_ms <  after_drive_utc_ms {  (after_drive_utc_ms - state.phase_start_ms) as u64 } 
+        if state.phase_start_ms <  phase_clock_ms {  (phase_clock_ms - state.phase_start_ms) as u64 } 
         else { 0 };
 
-    dipper_cycle_check(ctx, state, after_drive_utc_ms, measured_milliamps).await?;
+    dipper_cycle_check(ctx, state, after_drive_utc_ms, measured_milliamps, measured_ohms).await?;
     
 
     // reuse old drive current until instructed otherwise
@@ -550,7 +804,7 @@ async fn control_electrodes(ctx: &mut tokio_modbus::client::Context,
     
     match state.drive_phase {
         DrivePhase::Fresh => {
-            set_all_anode_connections(&mut state.anode_connections, false);
+            state.anode_drive = AnodeDrive::Fixed(0);
             state.phase_starts_utc_ms[DrivePhase::Fresh as usize] = state.phase_start_ms;
             // just transition to next phase
             new_drive_ma = trans_warmup_phase(state, after_drive_utc_ms);
@@ -558,7 +812,7 @@ async fn control_electrodes(ctx: &mut tokio_modbus::client::Context,
         DrivePhase::Warmup => {
             // while the melt is warming up, monitor the current throughput 
             new_drive_ma = WARMUP_CURRENT_MA;
-            set_all_anode_connections(&mut state.anode_connections, true);
+            state.anode_drive = AnodeDrive::Fixed(usable_anodes(state));
             if phase_duration_ms > WARMUP_PHASE_DUR_MS 
                 && state.measured_ma > (new_drive_ma / 2.)  
                 && ohms_ewma_valid 
@@ -571,14 +825,19 @@ async fn control_electrodes(ctx: &mut tokio_modbus::client::Context,
         }
         DrivePhase::Nucleation => {
             new_drive_ma = MAX_NUCLEATION_CURRENT_MA;
-            set_all_anode_connections(&mut state.anode_connections, true);
-
-            if phase_duration_ms > NUCLEATION_DURATION_MS  {
+            state.anode_drive = AnodeDrive::Fixed(usable_anodes(state));
+
+            // a charge target takes the place of the fixed duration
+            let nucleated = match phase_charge_target(state) {
+                Some(_) => phase_charge_target_reached(state),
+                None => phase_duration_ms > NUCLEATION_DURATION_MS,
+            };
+            if nucleated {
                 trans_elongation_phase(state, after_drive_utc_ms, phase_duration_ms);
             } 
         }
         DrivePhase::Elongation => {
-            anode_connections_at_time_ms(phase_duration_ms, state);
+            state.anode_drive = elongation_anode_drive(state);
 
             let goal_drive_volts = cyclic_voltage_at_time_ms(phase_duration_ms);
             // calculate current value for (nearly) constant voltage
@@ -626,9 +885,14 @@ async fn control_electrodes(ctx: &mut tokio_modbus::client::Context,
                 }
                 println!("{} Elongation fallback at {:.2} Ω : {:.1}", after_drive_utc_ms, state.ohms_ewma,new_drive_ma);
             }
+
+            if state.drive_phase == DrivePhase::Elongation && phase_charge_target_reached(state) {
+                println!("{} Elongation charge target reached", after_drive_utc_ms);
+                new_drive_ma = trans_holding_phase(state, after_drive_utc_ms, phase_duration_ms);
+            }
         }
         DrivePhase::Holding => {
-            set_all_anode_connections(&mut state.anode_connections, true);
+            state.anode_drive = AnodeDrive::Fixed(usable_anodes(state));
             new_drive_ma = HOLDING_PROBE_CURRENT_MA;
         }
         DrivePhase::Max => {
@@ -637,8 +901,18 @@ async fn control_electrodes(ctx: &mut tokio_modbus::client::Context,
     };
 
 
-    // ensure that anode drive outputs are set correctly
-    write_wav_octo_relays(ctx, &state.anode_connections).await?;
+    // operator overrides trump the drive phase
+    if let Some(mask) = state.anode_override {
+        state.anode_drive = AnodeDrive::Fixed(mask);
+    }
+    if let Some(override_ma) = state.drive_override_ma {
+        new_drive_ma = override_ma;
+    }
+    if let Some(limit_ma) = state.drive_limit_ma {
+        new_drive_ma = new_drive_ma.min(limit_ma);
+    }
+
+    // the anode driver switches the relays to match `state.anode_drive`, on its own time base
 
     // Now, update the drive current for the next main loop iteration
     // state.reported_drive_ma = set_electrode_current_drive(ctx, new_drive_ma).await?;
@@ -666,34 +940,454 @@ fn cyclic_voltage_at_time_ms(phase_duration_ms: u64) -> f32
 }
 
 ///
-/// Set all anode connections as either active (relay Normally Open lead) or inactive (relay Normally Closed lead)
-fn set_all_anode_connections(connections: &mut [bool], active: bool)
+/// The default Elongation anode pattern: adjacent pairs, a primary anode with a "trailing" secondary,
+/// which provides more drive current and smooths the electric field changes
+fn default_elongation_anodes(anode_count: usize) -> AnodeSequence
+{
+    AnodeSequence::new(AnodePattern::Pairs, anode_count, &[ANODE_ELONGATION_CONNECT_PERIOD_MS])
+        .expect("default anode pattern fits the wired anodes")
+}
+
+///
+/// Anodes that failed the latest check, if they're to be left out
+fn excluded_anodes(state: &ElectrodeState) -> AnodeMask
+{
+    match &state.anode_check {
+        Some(check) if state.exclude_failed_anodes => check.failed_mask(),
+        _ => 0,
+    }
+}
+
+///
+/// Every anode, less those excluded, unless that would leave none
+fn usable_anodes(state: &ElectrodeState) -> AnodeMask
+{
+    let usable = all_anodes_mask(state.anode_relays.len()) & !excluded_anodes(state);
+    if usable == 0 { ALL_ANODES } else { usable }
+}
+
+///
+/// The Elongation anode pattern, timed from the start of the phase, 
+/// less excluded anodes and balanced as configured
+fn elongation_anode_drive(state: &ElectrodeState) -> AnodeDrive
+{
+    let mut sequence = state.elongation_anodes.clone()
+        .unwrap_or_else(|| default_elongation_anodes(state.anode_relays.len()));
+    if let Ok(without_excluded) = sequence.excluding(excluded_anodes(state)) {
+        sequence = without_excluded;
+    }
+    if state.balance_anodes && let Some(check) = &state.anode_check {
+        sequence = sequence.balanced(&check.anode_ohms());
+    }
+    AnodeDrive::Sequence { sequence, start_ms: state.phase_start_ms }
+}
+
+///
+/// The charge at which the current drive phase ends, if any
+fn phase_charge_target(state: &ElectrodeState) -> Option<f64>
+{
+    state.phase_charge_targets[state.drive_phase as usize]
+}
+
+///
+/// Whether the current drive phase has passed its target charge.
+/// A paused phase clock holds the phase, whatever the charge.
+fn phase_charge_target_reached(state: &ElectrodeState) -> bool
+{
+    state.phase_clock_paused_ms.is_none()
+        && phase_charge_target(state).is_some_and(|target| state.charge.phase(state.drive_phase as u8).coulombs >= target)
+}
+
+///
+/// Print and record the running charge totals, at intervals once the drive phases start
+fn log_charge_totals(state: &mut ElectrodeState, telemetry: &TelemetryHub, current_utc_ms: i64)
+{
+    if state.drive_phase == DrivePhase::Fresh || current_utc_ms < state.next_charge_log_ms {
+        return;
+    }
+    state.next_charge_log_ms = current_utc_ms + CHARGE_LOG_INTERVAL_MS;
+    let summary = state.charge.summary(state.drive_phase as u8);
+    println!("{} Charge: {}", current_utc_ms, summary);
+    telemetry.emit(ControllerEvent::new(current_utc_ms, "charge", summary));
+}
+
+///
+/// Collect the fault flags (see `run_log::FAULT_*`) that apply to the current controller state
+fn current_faults(furnace: &FurnaceState, electrodes: &ElectrodeState) -> u8
+{
+    let mut faults = 0;
+    if furnace.probes_lost {
+        faults |= FAULT_TK_LOST;
+    }
+    if furnace.measured_temp_c > EXCESSIVE_HEAT_TEMP_C {
+        faults |= FAULT_OVER_TEMP;
+    }
+    if electrodes.drive_phase != DrivePhase::Fresh 
+        && electrodes.reported_drive_ma > REPORTED_CURRENT_THRESHOLD_MA 
+        && electrodes.measured_ohms == INF_INTER_ELECTRODE_OHMS 
+    {
+        faults |= FAULT_OPEN_CIRCUIT;
+    }
+    if electrodes.melt_level.below_anode_ring() {
+        faults |= FAULT_MELT_LOW;
+    }
+    if electrodes.anode_check.as_ref().is_some_and(|check| check.failed_mask() != 0) {
+        faults |= FAULT_ANODE_FAILED;
+    }
+    faults
+}
+
+///
+/// The latest melt level probe, for remote observers
+fn melt_snapshot(melt_level: &MeltLevelLog) -> MeltSnapshot
+{
+    let latest = melt_level.latest();
+    MeltSnapshot {
+        sampled_ms: latest.map(|sample| sample.epoch_ms),
+        surface_mm: latest.map(|sample| sample.surface_mm),
+        level_mm: latest.map(|sample| sample.level_mm),
+        volume_ml: latest.map(|sample| sample.volume_ml),
+        trend_mm_per_hour: melt_level.trend_mm_per_hour(),
+        below_anode_ring: melt_level.below_anode_ring(),
+    }
+}
+
+///
+/// Charge totals for remote observers
+fn charge_snapshot(state: &ElectrodeState) -> ChargeSnapshot
+{
+    let charge = &state.charge;
+    let phase = charge.phase(state.drive_phase as u8);
+    ChargeSnapshot {
+        reaction: charge.reaction().name.to_string(),
+        run: *charge.run(),
+        run_amp_hours: charge.run().amp_hours(),
+        run_carbon_mg: charge.run_carbon_mg(),
+        phase,
+        phase_carbon_mg: charge.reaction().carbon_mg(phase.coulombs),
+        phase_target_coulombs: phase_charge_target(state),
+        unaccounted_ms: charge.unaccounted_ms(),
+    }
+}
+
+///
+/// Capture the controller state for remote observers
+fn controller_snapshot(epoch_ms: i64, furnace: &FurnaceState, electrodes: &ElectrodeState, bus_stats: &BusStats) 
+-> ControllerSnapshot
+{
+    let faults = current_faults(furnace, electrodes);
+    let dipper = &electrodes.dipper_state;
+    ControllerSnapshot {
+        epoch_ms,
+        furnace: FurnaceSnapshot {
+            setpoint_c: furnace.setpoint_c,
+            setpoint_override_c: furnace.setpoint_override_c,
+            measured_temp_c: furnace.measured_temp_c,
+            tk1_c: furnace.tk1_c,
+            tk2_c: furnace.tk2_c,
+            heater_on: furnace.heater_on,
+            probes_lost: furnace.probes_lost,
+            heater_forced_off: furnace.heater_forced_off,
+        },
+        electrodes: ElectrodeSnapshot {
+            drive_phase: electrodes.drive_phase as u8,
+            drive_phase_name: drive_phase_name(electrodes.drive_phase as u8).to_string(),
+            phase_start_ms: electrodes.phase_start_ms,
+            target_drive_ma: electrodes.target_drive_ma,
+            reported_drive_ma: electrodes.reported_drive_ma,
+            measured_ma: electrodes.measured_ma,
+            measured_volts: electrodes.measured_volts,
+            measured_ohms: electrodes.measured_ohms,
+            ohms_ewma: electrodes.ohms_ewma,
+            max_ohms_ewma: electrodes.max_ohms_ewma,
+            lowv_minr_ohms: electrodes.lowv_minr_ohms,
+            highv_minr_ohms: electrodes.highv_minr_ohms,
+            drive_override_ma: electrodes.drive_override_ma,
+            drive_limit_ma: electrodes.drive_limit_ma,
+            phase_clock_paused: electrodes.phase_clock_paused_ms.is_some(),
+        },
+        dipper: DipperSnapshot {
+            enabled: dipper.dipper_enabled,
+            surface_contact: dipper.surface_contact_start_ms != 0,
+            contact: dipper.contact.state(),
+            surface_contact_start_ms: dipper.surface_contact_start_ms,
+            last_status_check_ms: dipper.dipper_last_status_check_ms,
+            motion_direction: dipper.dipper_prior_motion_direction,
+            pulse_count: dipper.dipper_prior_pulse_count,
+            action_count: dipper.dipper_prior_action_count,
+            position_mm: dipper.axis.position_mm(),
+        },
+        melt: melt_snapshot(&electrodes.melt_level),
+        charge: charge_snapshot(electrodes),
+        anode_check: electrodes.anode_check.clone(),
+        relays: RelaySnapshot {
+            heater: furnace.heater_on,
+            anodes: electrodes.anode_connections.clone(),
+            anode_override: electrodes.anode_override.is_some(),
+            anode_pattern: electrodes.anode_drive.to_string(),
+        },
+        faults,
+        fault_names: FAULT_NAMES.iter().filter(|(flag, _)| faults & flag != 0).map(|(_, name)| *name).collect(),
+        bus: bus_stats.snapshot(),
+    }
+}
+
+///
+/// Build the run log record for the current controller state
+fn run_log_sample(epoch_ms: i64, furnace: &FurnaceState, electrodes: &ElectrodeState) -> RunLogSample
 {
-    connections.fill(active);
+    RunLogSample {
+        epoch_ms,
+        heater_on: furnace.heater_on,
+        dipper_enabled: electrodes.dipper_state.dipper_enabled,
+        avg_temp_c: furnace.measured_temp_c,
+        target_drive_ma: electrodes.target_drive_ma,
+        measured_ma: electrodes.measured_ma,
+        measured_volts: electrodes.measured_volts,
+        measured_ohms: electrodes.measured_ohms,
+        ohms_ewma: electrodes.ohms_ewma,
+        highv_minr_ohms: electrodes.highv_minr_ohms,
+        lowv_minr_ohms: electrodes.lowv_minr_ohms,
+        setpoint_c: Some(furnace.setpoint_c),
+        drive_phase: Some(electrodes.drive_phase as u8),
+        faults: current_faults(furnace, electrodes),
+        charge_coulombs: Some(electrodes.charge.run().coulombs as f32),
+        energy_joules: Some(electrodes.charge.run().joules as f32),
+    }
 }
 
 ///
-/// Calculate which anodes drive wires are connected (via relay switch) to the current supply at the given time
-fn anode_connections_at_time_ms(phase_duration_ms: u64, state: &mut ElectrodeState) 
+/// Carry out an operator command, from whichever source it arrived.
+/// Commands that end the run (quit, emergency stop) are acted on by the caller after this returns.
+async fn apply_operator_command(ctx: &mut tokio_modbus::client::Context, 
+    command: &OperatorCommand, 
+    furnace: &mut FurnaceState, 
+    electrodes: &mut ElectrodeState, 
+    bus_stats: &BusStats,
+    telemetry: &TelemetryHub,
+    current_utc_ms: i64)
+-> CommandReply
 {
-    // we rate-limit how frequently the anode drive rotation is allowed to advance
-    let cycle_modulo_ms: usize = (phase_duration_ms as usize) % ANODE_ELONGATION_CONNECT_PERIOD_MS;
-    if cycle_modulo_ms < ANODE_CONNECTION_CHANGE_MS {
-        // We have a primary active anode and a "trailing" secondary active anode:
-        // this helps provide more drive current as well as smooth the electric field changes.
-        let secondary_active_idx =  state.primary_anode_idx;
-        if state.primary_anode_idx == 0 {
-            state.primary_anode_idx = 1;
+    match *command {
+        OperatorCommand::Hello => Ok("Hello!".to_string()),
+        OperatorCommand::Quit => Ok("Quitting...".to_string()),
+        OperatorCommand::Warmup => {
+            trans_warmup_phase(electrodes, current_utc_ms);
+            Ok("Warmup phase".to_string())
         }
-        else {
-            state.primary_anode_idx = (state.primary_anode_idx + 1) % NUM_ANODE_PAIRS;
+        OperatorCommand::Nucleate => {
+            trans_nucleation_phase(electrodes, current_utc_ms);
+            Ok("Nucleation phase".to_string())
+        }
+        OperatorCommand::Elongate => {
+            trans_elongation_phase(electrodes, current_utc_ms, 0);
+            Ok("Elongation phase".to_string())
+        }
+        OperatorCommand::Holding => {
+            trans_holding_phase(electrodes, current_utc_ms, 0);
+            Ok("Holding phase".to_string())
+        }
+        OperatorCommand::ToggleDipper => {
+            toggle_dipper_monitor(&mut electrodes.dipper_state);
+            Ok(format!("dipper_enabled {}", electrodes.dipper_state.dipper_enabled))
+        }
+        OperatorCommand::SetDipper(enable) => {
+            if electrodes.dipper_state.dipper_enabled != enable {
+                toggle_dipper_monitor(&mut electrodes.dipper_state);
+            }
+            Ok(format!("dipper_enabled {}", electrodes.dipper_state.dipper_enabled))
+        }
+        OperatorCommand::Setpoint(None) => {
+            furnace.setpoint_override_c = None;
+            Ok("setpoint automatic".to_string())
+        }
+        OperatorCommand::Setpoint(Some(setpoint_c)) => {
+            if !(0. ..=MAX_OPERATOR_SETPOINT_C).contains(&setpoint_c) {
+                return Err(format!("setpoint {setpoint_c:.1} °C outside 0..{MAX_OPERATOR_SETPOINT_C:.1} °C"));
+            }
+            furnace.setpoint_override_c = Some(setpoint_c);
+            Ok(format!("setpoint override {setpoint_c:.1} °C"))
+        }
+        OperatorCommand::FurnaceOff(true) => {
+            furnace.heater_forced_off = true;
+            match tokio::time::timeout(MODBUS_TRANSACTION_TIMEOUT, toggle_furnace(ctx, false)).await {
+                Ok(Ok(())) => {
+                    furnace.heater_on = false;
+                    Ok("furnace heater forced off".to_string())
+                }
+                Ok(Err(e)) => Err(format!("furnace heater forced off, but switching it failed ({e}): will retry")),
+                Err(_) => Err("furnace heater forced off, but switching it timed out: will retry".to_string()),
+            }
+        }
+        OperatorCommand::FurnaceOff(false) => {
+            furnace.heater_forced_off = false;
+            Ok("furnace heater follows set point".to_string())
+        }
+        OperatorCommand::DriveCurrent(None) => {
+            electrodes.drive_override_ma = None;
+            Ok("drive current automatic".to_string())
+        }
+        OperatorCommand::DriveCurrent(Some(milliamps)) => {
+            if !(0. ..=MAX_DRIVE_CURRENT_MA).contains(&milliamps) {
+                return Err(format!("drive current {milliamps:.1} mA outside 0..{MAX_DRIVE_CURRENT_MA:.0} mA"));
+            }
+            electrodes.drive_override_ma = Some(milliamps);
+            electrodes.target_drive_ma = electrodes.drive_limit_ma.map_or(milliamps, |limit_ma| milliamps.min(limit_ma));
+            Ok(format!("drive current fixed at {milliamps:.1} mA"))
+        }
+        OperatorCommand::DriveLimit(None) => {
+            electrodes.drive_limit_ma = None;
+            Ok("drive current limit removed".to_string())
+        }
+        OperatorCommand::DriveLimit(Some(milliamps)) => {
+            if !(0. ..=MAX_DRIVE_CURRENT_MA).contains(&milliamps) {
+                return Err(format!("drive current limit {milliamps:.1} mA outside 0..{MAX_DRIVE_CURRENT_MA:.0} mA"));
+            }
+            electrodes.drive_limit_ma = Some(milliamps);
+            electrodes.target_drive_ma = electrodes.target_drive_ma.min(milliamps);
+            Ok(format!("drive current limited to {milliamps:.1} mA"))
+        }
+        OperatorCommand::Anodes(None) => {
+            electrodes.anode_override = None;
+            Ok("anodes follow drive phase".to_string())
+        }
+        OperatorCommand::Anodes(Some(mask)) => {
+            let anode_count = electrodes.anode_relays.len();
+            if mask != ALL_ANODES && mask & !all_anodes_mask(anode_count) != 0 {
+                return Err(format!("only anodes 1..{anode_count} are wired"));
+            }
+            electrodes.anode_override = Some(mask);
+            electrodes.anode_drive = AnodeDrive::Fixed(mask);
+            Ok(format!("anodes fixed at {}", format_anode_mask(mask & all_anodes_mask(anode_count))))
+        }
+        OperatorCommand::AnodePattern(None) => {
+            electrodes.elongation_anodes = None;
+            Ok(format!("Elongation anodes: {}", default_elongation_anodes(electrodes.anode_relays.len())))
+        }
+        OperatorCommand::AnodePattern(Some((ref pattern, ref dwells_ms))) => {
+            let dwells_ms = if dwells_ms.is_empty() { &[ANODE_ELONGATION_CONNECT_PERIOD_MS][..] } else { dwells_ms };
+            let sequence = AnodeSequence::new(pattern.clone(), electrodes.anode_relays.len(), dwells_ms)?;
+            let reply = format!("Elongation anodes: {sequence}");
+            electrodes.elongation_anodes = Some(sequence);
+            if electrodes.drive_phase == DrivePhase::Elongation && electrodes.anode_override.is_none() {
+                electrodes.anode_drive = elongation_anode_drive(electrodes);
+            }
+            Ok(reply)
+        }
+        OperatorCommand::PausePhaseClock(true) => {
+            if electrodes.phase_clock_paused_ms.is_some() {
+                return Err("phase clock already paused".to_string());
+            }
+            electrodes.phase_clock_paused_ms = Some(current_utc_ms);
+            Ok(format!("{:?} phase clock paused", electrodes.drive_phase))
+        }
+        OperatorCommand::PausePhaseClock(false) => {
+            let Some(paused_ms) = electrodes.phase_clock_paused_ms.take() else {
+                return Err("phase clock is not paused".to_string());
+            };
+            // push the phase start back by however long the clock stood still in this phase
+            let paused_for_ms = (current_utc_ms - paused_ms.max(electrodes.phase_start_ms)).max(0);
+            electrodes.phase_start_ms += paused_for_ms;
+            Ok(format!("{:?} phase clock resumed after {} s", electrodes.drive_phase, paused_for_ms / 1000))
+        }
+        OperatorCommand::JogDipper(distance_mm) => {
+            if electrodes.dipper_state.dipper_enabled {
+                return Err("disable the dipper monitor before jogging".to_string());
+            }
+            if distance_mm.abs() > MAX_DIPPER_JOG_MM {
+                return Err(format!("jog {distance_mm:.1} mm exceeds {MAX_DIPPER_JOG_MM:.0} mm"));
+            }
+            let axis = &mut electrodes.dipper_state.axis;
+            if axis.is_homed() {
+                // a measured move, inside the soft limits
+                return match tokio::time::timeout(DIPPER_MOVE_TIMEOUT, axis.move_by(ctx, distance_mm)).await {
+                    Ok(Ok(position_mm)) => Ok(format!("dipper jogged to {position_mm:.2} mm")),
+                    Ok(Err(e)) => Err(format!("dipper jog failed: {e}")),
+                    Err(_) => Err("dipper jog timed out".to_string()),
+                };
+            }
+            match tokio::time::timeout(MODBUS_TRANSACTION_TIMEOUT, jog_smc05_distance(ctx, distance_mm)).await {
+                Ok(Ok(())) => Ok(format!("dipper jogged {distance_mm:.1} mm")),
+                Ok(Err(e)) => Err(format!("dipper jog failed: {e}")),
+                Err(_) => Err("dipper jog timed out".to_string()),
+            }
+        }
+        OperatorCommand::HomeDipper(reference_mm) => {
+            if electrodes.dipper_state.dipper_enabled {
+                return Err("disable the dipper monitor before homing".to_string());
+            }
+            let axis = &mut electrodes.dipper_state.axis;
+            let homing = async {
+                match reference_mm {
+                    Some(position_mm) => axis.set_reference(ctx, position_mm).await,
+                    None => axis.home(ctx).await,
+                }
+            };
+            match tokio::time::timeout(DIPPER_MOVE_TIMEOUT, homing).await {
+                Ok(Ok(())) => Ok(format!("dipper homed at {:.2} mm", axis.position_mm().unwrap_or_default())),
+                Ok(Err(e)) => Err(format!("dipper homing failed: {e}")),
+                Err(_) => Err("dipper homing timed out".to_string()),
+            }
+        }
+        OperatorCommand::MoveDipperTo(position_mm) => {
+            if electrodes.dipper_state.dipper_enabled {
+                return Err("disable the dipper monitor before moving the dipper".to_string());
+            }
+            let axis = &mut electrodes.dipper_state.axis;
+            match tokio::time::timeout(DIPPER_MOVE_TIMEOUT, axis.move_to(ctx, position_mm)).await {
+                Ok(Ok(stopped_mm)) => Ok(format!("dipper at {stopped_mm:.2} mm")),
+                Ok(Err(e)) => Err(format!("dipper move failed: {e}")),
+                Err(_) => Err("dipper move timed out".to_string()),
+            }
+        }
+        OperatorCommand::ProbeMeltLevel => {
+            if electrodes.dipper_state.dipper_enabled {
+                return Err("disable the dipper monitor before probing the melt level".to_string());
+            }
+            if !electrodes.dipper_state.axis.is_homed() {
+                return Err("home the dipper before probing the melt level".to_string());
+            }
+            electrodes.melt_probe_requested = true;
+            Ok("melt level probe at the next loop".to_string())
+        }
+        OperatorCommand::ChargeTarget(phase, target) => {
+            let phase_name = drive_phase_name(phase);
+            electrodes.phase_charge_targets[phase as usize] = target;
+            Ok(match target {
+                Some(coulombs) => format!("{phase_name} ends after {coulombs:.1} C"),
+                None => format!("{phase_name} charge target removed"),
+            })
+        }
+        OperatorCommand::CheckAnodes => {
+            electrodes.anode_check_requested = true;
+            Ok("anode check at the next loop".to_string())
         }
+        OperatorCommand::Annotate(ref text) => {
+            telemetry.emit(ControllerEvent::new(current_utc_ms, "note", text.clone()));
+            Ok("noted".to_string())
+        }
+        OperatorCommand::Query(ref path) => {
+            query_snapshot(&controller_snapshot(current_utc_ms, furnace, electrodes, bus_stats), path)
+        }
+        OperatorCommand::EmergencyStop => {
+            eprintln!("{} EMERGENCY STOP", current_utc_ms);
+            electrodes.anode_drive = AnodeDrive::Fixed(0);
+            match tokio::time::timeout(MODBUS_TRANSACTION_TIMEOUT, zero_control_outputs(ctx, &electrodes.anode_relays)).await {
+                Ok(Ok(())) => Ok("Emergency stop: outputs zeroed, exiting".to_string()),
+                Ok(Err(e)) => Err(format!("Emergency stop: zeroing outputs failed ({e}), exiting")),
+                Err(_) => Err("Emergency stop: zeroing outputs timed out, exiting".to_string()),
+            }
+        }
+    }
+}
 
-        state.anode_connections.fill(false);
-        state.anode_connections[state.primary_anode_idx] = true;
-        state.anode_connections[secondary_active_idx] = true;
+/// Next operator command line from stdin, or never if stdin isn't being used for commands
+async fn next_stdin_line(lines: &mut Option<Lines<BufReader<Stdin>>>) -> std::io::Result<Option<String>>
+{
+    match lines {
+        Some(lines) => lines.next_line().await,
+        None => std::future::pending().await,
     }
-    // println!("{} anodes mods {} conns {:?}", phase_duration_ms, cycle_modulo_ms, connections);
 }
 
 /**
@@ -701,15 +1395,122 @@ fn anode_connections_at_time_ms(phase_duration_ms: u64, state: &mut ElectrodeSta
  */
 #[tokio::main(flavor = "current_thread")]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    // Connect to Modbus apparatus via TCP server bridge (a WiFi bridge on our local network)
-    let socket_addr: std::net::SocketAddr = "10.0.1.151:502".parse()?;
+    // By default, connect to Modbus apparatus via TCP server bridge (a WiFi bridge on our local network)
+    const USAGE: &str = "usage: potslide [--console] [--dip-profile <file>] [--anode-relays <channel>,...] \
+        [--anode-pattern <pattern>] [--anode-dwell <ms>[,<ms>...]] [--anode-check <minutes>] [--anode-exclude] [--anode-balance] \
+        [--reaction <name>] [--charge-target <phase>=<C>] [bus options]";
+    let (bus_options, args) = match BusOptions::from_args(std::env::args().skip(1)) {
+        Ok(parsed) => parsed,
+        Err(e) => {
+            eprintln!("{e}\n{USAGE}\n{BUS_OPTIONS_USAGE}");
+            std::process::exit(1);
+        }
+    };
+    let mut use_console = false;
+    let mut dip_profile = None;
+    let mut anode_relays = DEFAULT_ANODE_RELAY_CHANNELS.to_vec();
+    let mut anode_pattern = None;
+    let mut anode_dwells_ms = vec![ANODE_ELONGATION_CONNECT_PERIOD_MS];
+    let mut anode_check_interval_ms = None;
+    let mut exclude_failed_anodes = false;
+    let mut balance_anodes = false;
+    let mut reaction = CARBONATE_REDUCTION;
+    let mut phase_charge_targets = [None; DrivePhase::Max as usize];
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--console" => use_console = true,
+            "--anode-exclude" => exclude_failed_anodes = true,
+            "--anode-balance" => balance_anodes = true,
+            "--anode-check" => {
+                let Some(minutes) = args.next().and_then(|minutes| minutes.parse::<u32>().ok()).filter(|minutes| *minutes > 0) else {
+                    eprintln!("--anode-check needs an interval in minutes\n{USAGE}");
+                    std::process::exit(1);
+                };
+                anode_check_interval_ms = Some(minutes as i64 * 60 * 1000);
+            }
+            "--reaction" | "--charge-target" => {
+                let parsed = match args.next() {
+                    None => Err(format!("{arg} needs a value")),
+                    Some(value) if arg == "--reaction" => value.parse().map(|parsed| reaction = parsed),
+                    Some(value) => parse_charge_target(value)
+                        .map(|(phase, coulombs)| phase_charge_targets[phase as usize] = Some(coulombs)),
+                };
+                if let Err(e) = parsed {
+                    eprintln!("{e}\n{USAGE}");
+                    std::process::exit(1);
+                }
+            }
+            "--dip-profile" => {
+                let Some(path) = args.next() else {
+                    eprintln!("--dip-profile needs a file\n{USAGE}");
+                    std::process::exit(1);
+                };
+                let profile = DipProfile::load(std::path::Path::new(path))?;
+                println!("Dip profile {:?}: {} steps, repeating from step {} for {} cycles",
+                    profile.name, profile.steps.len(), profile.repeat_from + 1,
+                    if profile.cycles == 0 { "unlimited".to_string() } else { profile.cycles.to_string() });
+                dip_profile = Some(DipProfileRun::new(profile));
+            }
+            "--anode-relays" => {
+                let parsed = args.next().ok_or_else(|| "--anode-relays needs a list of relay channels".to_string())
+                    .and_then(|list| parse_anode_relays(list));
+                match parsed {
+                    Ok(channels) => anode_relays = channels,
+                    Err(e) => {
+                        eprintln!("{e}\n{USAGE}");
+                        std::process::exit(1);
+                    }
+                }
+            }
+            "--anode-pattern" | "--anode-dwell" => {
+                let parsed = match args.next() {
+                    None => Err(format!("{arg} needs a value")),
+                    Some(value) if arg == "--anode-pattern" => value.parse().map(|pattern| anode_pattern = Some(pattern)),
+                    Some(value) => parse_anode_dwells(value).map(|dwells_ms| anode_dwells_ms = dwells_ms),
+                };
+                if let Err(e) = parsed {
+                    eprintln!("{e}\n{USAGE}");
+                    std::process::exit(1);
+                }
+            }
+            other => {
+                eprintln!("Unknown argument {other:?}\n{USAGE}\n{BUS_OPTIONS_USAGE}");
+                std::process::exit(1);
+            }
+        }
+    }
+
+    // the default pattern, unless another pattern or dwell was asked for
+    let electrode_anodes = match (anode_pattern, anode_dwells_ms.as_slice()) {
+        (None, [ANODE_ELONGATION_CONNECT_PERIOD_MS]) => None,
+        (pattern, dwells_ms) => Some(AnodeSequence::new(pattern.unwrap_or(AnodePattern::Pairs), anode_relays.len(), dwells_ms)?),
+    };
+
+    // operator commands arrive over this channel from any remote command sources
+    let (command_tx, mut command_rx) = tokio::sync::mpsc::channel::<OperatorRequest>(16);
+    let telemetry = TelemetryHub::new();
+    if let Some(http_config) = HttpApiConfig::from_env()? {
+        spawn_http_api(http_config, telemetry.clone(), command_tx.clone()).await?;
+    }
+    if let Some(mqtt_config) = MqttConfig::from_env()? {
+        spawn_mqtt(mqtt_config, telemetry.clone(), command_tx.clone()).await?;
+    }
 
-    println!("Connecting to: '{socket_addr:?}'");
-    let mut ctx: client::Context = tcp::connect(socket_addr).await?;
+    println!("Connecting to: {}", bus_options.describe());
+    // count Modbus traffic per node (with per-request timeouts and read retries only if asked for)
+    let bus_stats = BusStats::new();
+    let bus_router = bus_options.connect_router(bus_stats.clone()).await?;
+    let mut ctx: client::Context = bus_router.context();
+    // the furnace has its own handle so that it can be serviced alongside the electrodes:
+    // when the thermocouple reader lives on a separate bus, it no longer holds up the measurement loop
+    let mut furnace_ctx: client::Context = bus_router.context();
     
     enumerate_required_modules(&mut ctx).await?;
 
-    zero_control_outputs(&mut ctx).await?;
+    zero_control_outputs(&mut ctx, &anode_relays).await?;
+    // the anode relays are switched on their own time base, whatever the main loop is doing
+    let anode_driver = spawn_anode_driver(bus_router.context(), anode_relays.clone())?;
 
     let start_time_secs = chrono::Utc::now().timestamp();
     let log_out_filename = format!("{}_log.csv",start_time_secs);
@@ -721,25 +1522,48 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     println!("Warmup {} mA ; Holding {} mA", WARMUP_CURRENT_MA, HOLDING_PROBE_CURRENT_MA);
     println!("Nucleate {} minutes , {:.2} A/cm2, {:.2} mA max", 
         NUCLEATION_DURATION_MINUTES, NUCLEATION_CURRENT_DENSITY_AMPS_CM2, MAX_NUCLEATION_CURRENT_MA);
-    println!("Elongate: {:.2} A/cm2, {:.2} mA max, Vmax {:.2}, Rot {:.2} ms, Term {:.1} Ω ", 
-        ELONGATION_CURRENT_DENSITY_AMPS_CM2, MAX_ELONGATION_CURRENT_MA, CYCLIC_GROWTH_PEAK_V,  ANODE_ELONGATION_CONNECT_PERIOD_MS,
+    println!("Elongate: {:.2} A/cm2, {:.2} mA max, Vmax {:.2}, Anodes {}, Term {:.1} Ω ", 
+        ELONGATION_CURRENT_DENSITY_AMPS_CM2, MAX_ELONGATION_CURRENT_MA, CYCLIC_GROWTH_PEAK_V,
+        electrode_anodes.clone().unwrap_or_else(|| default_elongation_anodes(anode_relays.len())),
         CYCLIC_LOWV_TERMINATION_OHMS);
+    println!("Anode relays: {}", anode_relays.iter().map(u8::to_string).collect::<Vec<_>>().join(","));
+    let charge_targets: Vec<String> = phase_charge_targets.iter().enumerate()
+        .filter_map(|(phase, target)| target.map(|coulombs| format!("{} {:.1} C", drive_phase_name(phase as u8), coulombs)))
+        .collect();
+    println!("Deposition: {}, {:.3} mg C per C ; charge targets: {}", reaction, reaction.carbon_mg(1.),
+        if charge_targets.is_empty() { "none".to_string() } else { charge_targets.join(", ") });
+    // nothing on the bus tells the current source models apart, so show which one the drive currents are scaled for
+    let current_source = electrode_current_source()?;
+    println!("Current source: {} ({}) at node 0x{:02X}, 0-{} mA in {} mA steps (set {} to change)",
+        current_source.model, current_source.model.map().description, current_source.node_id,
+        current_source.model.max_milliamps(), current_source.model.resolution_milliamps(), ykpvccs::CURRENT_SOURCE_ENV_VAR);
 
 
     let logfile = File::create(format!("./data/{}",log_out_filename))?;
     let mut csv_writer = BufWriter::new(logfile);
 
-    const CSV_HEADER: &str =  "epoch_ms,heat,dip,avg_C,eleco_mA,elecm_mA,elecm_V,elec_R,Rew,hvMinR,lvMinR";
-    macro_rules! CSV_LINE_FORMAT { () => { "{},{},{},{:.2},{:.2},{:.2},{:.3},{:.3},{:.3},{:.3},{:.3}" } }
-    
-    println!("{}",CSV_HEADER);
-    writeln!(csv_writer, "{}", CSV_HEADER)?;
+    println!("{}",RUN_LOG_CSV_HEADER);
+    writeln!(csv_writer, "{}", RUN_LOG_CSV_HEADER)?;
 
-    // setup command handling
-    let mut lines = BufReader::new(tokio::io::stdin()).lines();
+    // setup command handling: either the full-screen console, or plain stdin lines
+    let console = 
+        if use_console {
+            let console_log_path = format!("./data/{}_console.log", start_time_secs);
+            Some(start_console(telemetry.clone(), command_tx.clone(), std::path::Path::new(&console_log_path))?)
+        }
+        else { None };
+    let mut lines = if console.is_none() { Some(BufReader::new(tokio::io::stdin()).lines()) } else { None };
 
     let mut furnace_state = INITIAL_FURNACE_STATE;
     let mut electrode_state =  INITIAL_ELECTRODE_STATE;
+    electrode_state.dip_profile = dip_profile;
+    electrode_state.elongation_anodes = electrode_anodes;
+    electrode_state.anode_relays = anode_relays;
+    electrode_state.anode_check_interval_ms = anode_check_interval_ms;
+    electrode_state.exclude_failed_anodes = exclude_failed_anodes;
+    electrode_state.balance_anodes = balance_anodes;
+    electrode_state.charge = ChargeIntegrator::new(reaction);
+    electrode_state.phase_charge_targets = phase_charge_targets;
 
     electrode_state.phase_start_ms =  chrono::Utc::now().timestamp_millis();
 
@@ -750,6 +1574,9 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let first_loop_instant = now_instant + INTER_LOOP_DELAY - Duration::from_millis(now_instant.elapsed().subsec_millis() as u64);
     sleep_until(first_loop_instant).await;
 
+    // commands, annotations and state changes are all recorded in the run log
+    let mut run_events = telemetry.subscribe_events();
+
     let ctrl_c_fut = tokio::signal::ctrl_c();
     tokio::pin!(ctrl_c_fut);
 
@@ -766,31 +1593,25 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                 eprintln!("\nCtrl-C: Shutting down...");
                 break;
             }
-            cmd_line =  lines.next_line() => {
+            cmd_line = next_stdin_line(&mut lines) => {
                 match cmd_line {
                     Ok(Some(cmd_line)) => {
-                        match cmd_line.trim() {
-                            "hello" => println!("Hello!"),
-                            "q" | "quit" => {
-                                println!("Quitting...");
-                                break;
+                        match cmd_line.parse::<OperatorCommand>() {
+                            Ok(command) => {
+                                let reply = apply_operator_command(&mut ctx, &command, 
+                                    &mut furnace_state, &mut electrode_state, &bus_stats, &telemetry, current_utc_ms).await;
+                                anode_driver.set(&electrode_state.anode_drive);
+                                telemetry.emit(command_event(current_utc_ms, "stdin", &command, &reply));
+                                match reply {
+                                    Ok(msg) => println!("{msg}"),
+                                    Err(msg) => println!("Command failed: {msg}"),
+                                }
+                                if command.ends_run() { break; }
                             }
-                            "w" | "warmup" => {
-                                trans_warmup_phase(&mut electrode_state, current_utc_ms);
-                            },
-                            "n" | "nucleate" => {
-                                trans_nucleation_phase(&mut electrode_state, current_utc_ms);
+                            Err(msg) => {
+                                println!("{msg}");
+                                telemetry.emit(ControllerEvent::new(current_utc_ms, "command", format!("stdin rejected: {msg}")));
                             }
-                            "e" | "elongate" => {
-                                trans_elongation_phase(&mut electrode_state, current_utc_ms, 0);
-                            },
-                            "h" | "holding" => {
-                                trans_holding_phase(&mut electrode_state,  current_utc_ms, 0);
-                            }
-                            "d" | "dip" => {
-                                toggle_dipper_monitor(&mut electrode_state.dipper_state);
-                            }
-                            other => println!("Unknown command: {other:?}"),
                         }
                     }
                     Ok(None) => {
@@ -807,45 +1628,89 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                     }
                 }
             }
+            Some(request) = command_rx.recv() => {
+                println!("{} {} command: {:?}", current_utc_ms, request.source, request.command);
+                let reply = apply_operator_command(&mut ctx, &request.command, 
+                    &mut furnace_state, &mut electrode_state, &bus_stats, &telemetry, current_utc_ms).await;
+                anode_driver.set(&electrode_state.anode_drive);
+                telemetry.emit(command_event(current_utc_ms, request.source, &request.command, &reply));
+                let _ = request.reply.send(reply);
+                if request.command.ends_run() { break; }
+            }
             _ = &mut sleep_timer  => {
                 // no commands received: continue the main loop
             }
         }
 
-        let furnace_res = 
-            tokio::time::timeout(MODBUS_TRANSACTION_TIMEOUT,control_furnace(&mut ctx, &mut furnace_state)).await;
-        if !furnace_res.is_ok() { 
+        // read the furnace first, so the electrodes are gated on the current temperature
+        let furnace_res = tokio::time::timeout(MODBUS_TRANSACTION_TIMEOUT, control_furnace(&mut furnace_ctx, &mut furnace_state)).await;
+        if furnace_res.is_err() { 
             eprintln!("control_furnace timeout: {:?}",furnace_res);
             break;
         }
-
-        if (furnace_state.measured_temp_c > MIN_ELECTRODE_CHECK_TEMP_C &&  furnace_state.measured_temp_c < EXCESSIVE_HEAT_TEMP_C) ||
-            electrode_state.drive_phase != DrivePhase::Fresh 
-        {
-            let elec_res = 
-                tokio::time::timeout(MODBUS_TRANSACTION_TIMEOUT, control_electrodes(&mut ctx, &mut electrode_state)).await;
-            if !elec_res.is_ok() { 
-                eprintln!("control_electrodes timeout: {:?}",elec_res);
+        let electrodes_active = 
+            (furnace_state.measured_temp_c > MIN_ELECTRODE_CHECK_TEMP_C &&  furnace_state.measured_temp_c < EXCESSIVE_HEAT_TEMP_C) ||
+            electrode_state.drive_phase != DrivePhase::Fresh;
+
+        let probing = electrodes_active && melt_probe_due(&electrode_state, current_utc_ms);
+        let checking_anodes = electrodes_active && !probing && anode_check_due(&electrode_state, current_utc_ms);
+        let electrodes_busy = std::cell::Cell::new(true);
+        let (furnace_res, elec_res) = tokio::join!(
+            async {
+                // keep servicing the furnace for as long as a melt probe or anode check holds up the electrodes
+                if !(probing || checking_anodes) { return Ok(Ok(())); }
+                loop {
+                    sleep(INTER_LOOP_DELAY).await;
+                    if !electrodes_busy.get() { break Ok(Ok(())); }
+                    let res = tokio::time::timeout(MODBUS_TRANSACTION_TIMEOUT, control_furnace(&mut furnace_ctx, &mut furnace_state)).await;
+                    if res.is_err() { break res; }
+                }
+            },
+            async {
+                let res = if probing {
+                    probe_melt_level(&mut ctx, &mut electrode_state, current_utc_ms).await;
+                    Some(Ok(Ok(())))
+                }
+                else if checking_anodes {
+                    check_anode_wires(&mut ctx, &anode_driver, &mut electrode_state, current_utc_ms).await;
+                    Some(Ok(Ok(())))
+                }
+                else if electrodes_active {
+                    Some(tokio::time::timeout(MODBUS_TRANSACTION_TIMEOUT, control_electrodes(&mut ctx, &mut electrode_state)).await)
+                }
+                else { None };
+                electrodes_busy.set(false);
+                res
+            }
+        );
+        if furnace_res.is_err() { 
+            eprintln!("control_furnace timeout: {:?}",furnace_res);
+            break;
+        }
+        match elec_res {
+            Some(Err(elapsed)) => {
+                eprintln!("control_electrodes timeout: {:?}",elapsed);
                 break;
             }
+            Some(Ok(_)) => {}
+            None => {
+                // println!("drive_phase: {:?} temp: {:.2}", electrode_state.drive_phase, furnace_state.measured_temp_c);
+                electrode_state.phase_start_ms = current_utc_dt.timestamp_millis();
+            }
         }
-        else {
-            // println!("drive_phase: {:?} temp: {:.2}", electrode_state.drive_phase, furnace_state.measured_temp_c);
-            electrode_state.phase_start_ms = current_utc_dt.timestamp_millis();
-        }
-
-        let log_line = format!( CSV_LINE_FORMAT!(),
-            current_utc_dt.timestamp_millis(),
-            furnace_state.heater_on as u8,
-            electrode_state.dipper_state.dipper_enabled as u8,
-            furnace_state.measured_temp_c,
-            electrode_state.target_drive_ma, electrode_state.measured_ma,
-            electrode_state.measured_volts, 
-            electrode_state.measured_ohms, electrode_state.ohms_ewma, 
-            electrode_state.highv_minr_ohms, electrode_state.lowv_minr_ohms,
-        );
+
+        anode_driver.set(&electrode_state.anode_drive);
+        electrode_state.anode_connections = anode_driver.connections();
+        log_charge_totals(&mut electrode_state, &telemetry, current_utc_ms);
+
+        let sample = run_log_sample(current_utc_ms, &furnace_state, &electrode_state);
+        let log_line = sample.to_csv_line();
+        telemetry.publish(controller_snapshot(current_utc_ms, &furnace_state, &electrode_state, &bus_stats), sample);
         println!("{}",log_line);
         writeln!(  csv_writer,"{}",  log_line)?;
+        while let Ok(event) = run_events.try_recv() {
+            writeln!(csv_writer, "{}", run_log_event_line(event.epoch_ms, event.kind, &event.message))?;
+        }
         loop_count = (loop_count + 1) % 5;
         if loop_count == 0 { let _ = csv_writer.flush(); }
         else { sleep(MAINLOOP_DELAY).await; }
@@ -853,14 +1718,26 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     }
 
 
+    // hand the terminal back before shutting down
+    drop(console);
+    // leave the anode relays alone for the shutdown to zero
+    anode_driver.stop();
+
+    // record the final charge totals, whenever they were last logged
+    electrode_state.next_charge_log_ms = 0;
+    log_charge_totals(&mut electrode_state, &telemetry, chrono::Utc::now().timestamp_millis());
+    // record the command (or signal) that ended the run
+    while let Ok(event) = run_events.try_recv() {
+        writeln!(csv_writer, "{}", run_log_event_line(event.epoch_ms, event.kind, &event.message))?;
+    }
     println!("Flushing log file...");
     csv_writer.flush()?;
 
     // Disconnect and then reconnect to shutdown outputs
     println!("Disconnecting...");
     ctx.disconnect().await?;
-    let shutdown_res = tokio::time::timeout(MODBUS_TRANSACTION_TIMEOUT,robust_shutdown(socket_addr)).await;
-    if !shutdown_res.is_ok() { 
+    let shutdown_res = tokio::time::timeout(MODBUS_TRANSACTION_TIMEOUT,robust_shutdown(&bus_options, &electrode_state.anode_relays)).await;
+    if shutdown_res.is_err() { 
         eprintln!("robust_shutdown timeout: {:?}",shutdown_res);
     }
     // dump logged timeline
@@ -875,15 +1752,15 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 /// Attempt to shut off all outputs before exiting.
 /// We reconnect to Modbus to flush any cruft buffered at the WiFi bridge.
 /// 
-async fn robust_shutdown(socket_addr: std::net::SocketAddr )
+async fn robust_shutdown(bus_options: &BusOptions, anode_relays: &[u8])
 -> Result<(), Box<dyn std::error::Error>> 
 {
     sleep(Duration::from_secs(2)).await;
-    println!("Reconnecting to: '{socket_addr:?}' ...");
-    let mut ctx = tcp::connect(socket_addr).await?;
+    println!("Reconnecting to: {} ...", bus_options.describe());
+    let mut ctx = bus_options.connect(BusStats::new()).await?;
 
     // Zeroing control outputs
-    zero_control_outputs(&mut ctx).await?;
+    zero_control_outputs(&mut ctx, anode_relays).await?;
 
     println!("Disconnecting again...");
     let disconnect_res = ctx.disconnect().await;
@@ -0,0 +1,216 @@
+//!
+//! Instrumented Modbus bus access: wraps any client transport so that traffic is counted per node ID.
+//! The wrapped client is still a plain `tokio_modbus::client::Context`, so existing
+//! device functions work unchanged.
+//!
+//! Counting alone leaves each request exactly as the caller issued it. A per-request timeout
+//! and read retries can be asked for (`instrument_with`), but they aren't applied by default:
+//! the control loops have their own transaction timeouts, and a retried read through a bridge
+//! that buffers replies may pick up a stale frame.
+//!
+//! A `BusRouter` spreads the devices over several physical buses (say, a fast serial
+//! adapter for the electrode measurements and the WiFi bridge for the relays and stepper),
+//! choosing the bus for each request from the addressed node ID.
+//!
+
+use std::collections::BTreeMap;
+use std::io;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use async_trait::async_trait;
+use serde::Serialize;
+use tokio_modbus::client::{Client, Context};
+use tokio_modbus::slave::SlaveContext;
+use tokio_modbus::{Request, Response, Slave};
+
+/// How long to wait for any single Modbus response, unless a timeout is asked for: the caller's own
+pub const DEFAULT_REQUEST_TIMEOUT: Option<Duration> = None;
+
+/// How many times a failed read is retried, unless retries are asked for.
+/// Writes are never retried: some (like the SMC05 start/stop command) are toggles.
+pub const DEFAULT_READ_RETRIES: u32 = 0;
+
+/// Traffic counters for one Modbus node ID
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+pub struct NodeCounters {
+    /// Requests issued (not counting retries)
+    pub requests: u64,
+    /// Extra attempts made after a failed read
+    pub retries: u64,
+    /// Attempts that got no response within the request timeout, or that the transport timed out
+    pub timeouts: u64,
+    /// Modbus exception responses
+    pub exceptions: u64,
+    /// Attempts that failed with an I/O or framing error
+    pub transport_errors: u64,
+}
+
+/// Counters for every node ID seen on a bus
+#[derive(Debug, Default)]
+pub struct BusStats {
+    nodes: Mutex<BTreeMap<u8, NodeCounters>>,
+}
+
+impl BusStats {
+    pub fn new() -> Arc<Self> {
+        Arc::new(Self::default())
+    }
+
+    /// Copy of the current counters, keyed by node ID
+    pub fn snapshot(&self) -> BTreeMap<u8, NodeCounters> {
+        self.nodes.lock().unwrap().clone()
+    }
+
+    fn count(&self, node_id: u8, update: impl FnOnce(&mut NodeCounters)) {
+        update(self.nodes.lock().unwrap().entry(node_id).or_default());
+    }
+}
+
+/// A Modbus client that records `BusStats`, applying a timeout and read retries if asked to
+pub struct InstrumentedClient {
+    inner: Context,
+    slave: Slave,
+    stats: Arc<BusStats>,
+    request_timeout: Option<Duration>,
+    read_retries: u32,
+}
+
+fn is_read(request: &Request<'_>) -> bool {
+    matches!(request,
+        Request::ReadCoils(..) | Request::ReadDiscreteInputs(..) |
+        Request::ReadInputRegisters(..) | Request::ReadHoldingRegisters(..))
+}
+
+impl SlaveContext for InstrumentedClient {
+    fn set_slave(&mut self, slave: Slave) {
+        self.slave = slave;
+        self.inner.set_slave(slave);
+    }
+}
+
+#[async_trait]
+impl Client for InstrumentedClient {
+    async fn call(&mut self, request: Request<'_>) -> tokio_modbus::Result<Response> {
+        let node_id = self.slave.0;
+        let max_attempts = if is_read(&request) { 1 + self.read_retries } else { 1 };
+        self.stats.count(node_id, |c| c.requests += 1);
+
+        let mut attempt = 1;
+        loop {
+            let result = match self.request_timeout {
+                Some(request_timeout) => tokio::time::timeout(request_timeout, self.inner.call(request.clone())).await
+                    .unwrap_or_else(|_elapsed| Err(io::Error::new(io::ErrorKind::TimedOut,
+                        format!("no response from node 0x{node_id:02X} within {request_timeout:?}")).into())),
+                None => self.inner.call(request.clone()).await,
+            };
+            let err = match result {
+                Ok(Ok(response)) => return Ok(Ok(response)),
+                Ok(Err(exception)) => {
+                    self.stats.count(node_id, |c| c.exceptions += 1);
+                    return Ok(Err(exception));
+                }
+                Err(err) => {
+                    let timed_out = matches!(&err, tokio_modbus::Error::Transport(e) if e.kind() == io::ErrorKind::TimedOut);
+                    self.stats.count(node_id, |c| if timed_out { c.timeouts += 1 } else { c.transport_errors += 1 });
+                    err
+                }
+            };
+            if attempt >= max_attempts {
+                return Err(err);
+            }
+            attempt += 1;
+            self.stats.count(node_id, |c| c.retries += 1);
+        }
+    }
+
+    async fn disconnect(&mut self) -> io::Result<()> {
+        self.inner.disconnect().await
+    }
+}
+
+/// Wrap a connected client so its traffic is counted in `stats`, with requests otherwise untouched
+pub fn instrument(ctx: Context, stats: Arc<BusStats>) -> Context {
+    instrument_with(ctx, stats, DEFAULT_REQUEST_TIMEOUT, DEFAULT_READ_RETRIES)
+}
+
+/// Like `instrument`, also timing out each attempt and retrying failed reads
+pub fn instrument_with(ctx: Context, stats: Arc<BusStats>, request_timeout: Option<Duration>, read_retries: u32) -> Context {
+    let client = InstrumentedClient {
+        inner: ctx,
+        slave: Slave(0),
+        stats,
+        request_timeout,
+        read_retries,
+    };
+    Context::from(Box::new(client) as Box<dyn Client>)
+}
+
+/// Several physical buses, and which bus each node ID lives on.
+/// Hands out any number of `Context`s that route every request to its node's bus,
+/// so control code never needs to know where a device is wired.
+/// Requests on one bus are serialized; requests on different buses proceed concurrently.
+#[derive(Clone)]
+pub struct BusRouter {
+    buses: Arc<Vec<tokio::sync::Mutex<Context>>>,
+    routes: Arc<BTreeMap<u8, usize>>,
+}
+
+impl BusRouter {
+    /// Route each node ID in `routes` to the bus at that index in `buses`; other node IDs use the first bus
+    pub fn new(buses: Vec<Context>, routes: BTreeMap<u8, usize>) -> Self {
+        assert!(!buses.is_empty(), "a router needs at least one bus");
+        assert!(routes.values().all(|idx| *idx < buses.len()), "route to a missing bus");
+        Self {
+            buses: Arc::new(buses.into_iter().map(tokio::sync::Mutex::new).collect()),
+            routes: Arc::new(routes),
+        }
+    }
+
+    /// Index of the bus that carries `node_id`
+    pub fn bus_index(&self, node_id: u8) -> usize {
+        self.routes.get(&node_id).copied().unwrap_or(0)
+    }
+
+    /// A new client handle that shares these buses
+    pub fn context(&self) -> Context {
+        let client = RoutedClient { router: self.clone(), slave: Slave(0) };
+        Context::from(Box::new(client) as Box<dyn Client>)
+    }
+
+    /// Disconnect every bus
+    pub async fn disconnect(&self) -> io::Result<()> {
+        let mut first_err = Ok(());
+        for bus in self.buses.iter() {
+            let res = bus.lock().await.disconnect().await;
+            if first_err.is_ok() { first_err = res; }
+        }
+        first_err
+    }
+}
+
+/// A client handle on a `BusRouter`, addressing one node at a time like any other `Context`
+struct RoutedClient {
+    router: BusRouter,
+    slave: Slave,
+}
+
+impl SlaveContext for RoutedClient {
+    fn set_slave(&mut self, slave: Slave) {
+        self.slave = slave;
+    }
+}
+
+#[async_trait]
+impl Client for RoutedClient {
+    async fn call(&mut self, request: Request<'_>) -> tokio_modbus::Result<Response> {
+        let mut bus = self.router.buses[self.router.bus_index(self.slave.0)].lock().await;
+        // other handles may have addressed a different node on this bus since our last request
+        bus.set_slave(self.slave);
+        bus.call(request).await
+    }
+
+    async fn disconnect(&mut self) -> io::Result<()> {
+        self.router.disconnect().await
+    }
+}
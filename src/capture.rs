@@ -0,0 +1,368 @@
+//!
+//! Modbus traffic capture and replay, for debugging problems that only show up in the field
+//! (such as the stale responses buffered at the WiFi bridge that `robust_shutdown` works around).
+//!
+//! A `Capture` taps a bus below any timeout and retry layer, so every attempt is recorded:
+//! node ID, function code, address, request and response data, latency, and any exception
+//! or transport error. Requests abandoned by the request timeout are recorded too.
+//! Captures are JSON Lines files, one `CaptureRecord` per line, appended to as traffic flows.
+//!
+//! A `Replay` answers requests from a capture file, so a field problem can be reproduced
+//! on a desk: each distinct request (node, function, address, quantity) gets its recorded
+//! responses back in order, including exceptions, and no response at all where the
+//! original request failed.
+//!
+
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::future::Future;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use tokio_modbus::client::{Client, Context};
+use tokio_modbus::slave::SlaveContext;
+use tokio_modbus::{ExceptionCode, Request, Response, Slave, SlaveRequest};
+
+use crate::cli::Transport;
+
+/// One request and its outcome
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CaptureRecord {
+    /// When the request was sent, in milliseconds since the Unix epoch
+    pub time_ms: i64,
+    /// Transport spec of the bus the request went out on
+    pub bus: String,
+    pub node: u8,
+    pub function: u8,
+    /// First register or coil addressed, if the function has one
+    pub address: Option<u16>,
+    /// Request data: the quantity for reads, the values (coils as 0 or 1) for writes
+    pub request: Vec<u16>,
+    /// Response data: the values read, or what a write echoed back
+    pub response: Vec<u16>,
+    /// Modbus exception code, if the device answered with one
+    pub exception: Option<u8>,
+    /// Transport error, timeout or abandoned request, if there was no answer
+    pub error: Option<String>,
+    pub latency_ms: f64,
+}
+
+/// What a replay matches requests on: node ID, function code, address and quantity
+pub type RequestKey = (u8, u8, u16, u16);
+
+impl CaptureRecord {
+    /// The request this record answers
+    pub fn key(&self) -> RequestKey {
+        let quantity = match self.function {
+            0x01..=0x04 => self.request.first().copied().unwrap_or(0),
+            _ => self.request.len() as u16,
+        };
+        (self.node, self.function, self.address.unwrap_or(0), quantity)
+    }
+
+    /// The recorded answer, as a server would send it: no response if the request failed
+    pub fn replay_response(&self) -> Result<Option<Response>, ExceptionCode> {
+        if let Some(code) = self.exception {
+            return Err(ExceptionCode::new(code));
+        }
+        if self.error.is_some() {
+            return Ok(None);
+        }
+        let address = self.address.unwrap_or(0);
+        let word = |idx: usize| self.response.get(idx).copied().unwrap_or(0);
+        let bits = || self.response.iter().map(|bit| *bit != 0).collect();
+        let response = match self.function {
+            0x01 => Response::ReadCoils(bits()),
+            0x02 => Response::ReadDiscreteInputs(bits()),
+            0x03 => Response::ReadHoldingRegisters(self.response.clone()),
+            0x04 => Response::ReadInputRegisters(self.response.clone()),
+            0x05 => Response::WriteSingleCoil(address, word(0) != 0),
+            0x06 => Response::WriteSingleRegister(address, word(0)),
+            0x0F => Response::WriteMultipleCoils(address, word(0)),
+            0x10 => Response::WriteMultipleRegisters(address, word(0)),
+            0x16 => Response::MaskWriteRegister(address, word(0), word(1)),
+            0x17 => Response::ReadWriteMultipleRegisters(self.response.clone()),
+            _ => return Err(ExceptionCode::IllegalFunction),
+        };
+        Ok(Some(response))
+    }
+}
+
+/// Address and data of a request, as recorded
+fn request_fields(request: &Request<'_>) -> (Option<u16>, Vec<u16>) {
+    match request {
+        Request::ReadCoils(address, quantity) |
+        Request::ReadDiscreteInputs(address, quantity) |
+        Request::ReadInputRegisters(address, quantity) |
+        Request::ReadHoldingRegisters(address, quantity) => (Some(*address), vec![*quantity]),
+        Request::WriteSingleCoil(address, coil) => (Some(*address), vec![u16::from(*coil)]),
+        Request::WriteMultipleCoils(address, coils) => (Some(*address), coils.iter().map(|coil| u16::from(*coil)).collect()),
+        Request::WriteSingleRegister(address, word) => (Some(*address), vec![*word]),
+        Request::WriteMultipleRegisters(address, words) => (Some(*address), words.to_vec()),
+        Request::MaskWriteRegister(address, and_mask, or_mask) => (Some(*address), vec![*and_mask, *or_mask]),
+        Request::ReadWriteMultipleRegisters(address, quantity, _, words) => {
+            (Some(*address), [&[*quantity][..], words].concat())
+        }
+        Request::Custom(_, bytes) => (None, bytes.iter().map(|byte| *byte as u16).collect()),
+        Request::ReportServerId | Request::ReadDeviceIdentification(..) => (None, Vec::new()),
+    }
+}
+
+/// Data of a response, as recorded
+fn response_data(response: &Response) -> Vec<u16> {
+    match response {
+        Response::ReadCoils(bits) | Response::ReadDiscreteInputs(bits) => bits.iter().map(|bit| u16::from(*bit)).collect(),
+        Response::ReadInputRegisters(words) | Response::ReadHoldingRegisters(words) |
+        Response::ReadWriteMultipleRegisters(words) => words.clone(),
+        Response::WriteSingleCoil(_, coil) => vec![u16::from(*coil)],
+        Response::WriteSingleRegister(_, word) => vec![*word],
+        Response::WriteMultipleCoils(_, quantity) | Response::WriteMultipleRegisters(_, quantity) => vec![*quantity],
+        Response::MaskWriteRegister(_, and_mask, or_mask) => vec![*and_mask, *or_mask],
+        Response::Custom(_, bytes) => bytes.iter().map(|byte| *byte as u16).collect(),
+        Response::ReportServerId(..) | Response::ReadDeviceIdentification(..) => Vec::new(),
+    }
+}
+
+/// A capture file being written
+#[derive(Debug)]
+pub struct Capture {
+    writer: Mutex<BufWriter<File>>,
+}
+
+impl Capture {
+    /// Append to the capture file at `path`, creating it if need be,
+    /// so that reconnecting to the bus continues the same capture
+    pub fn open(path: &Path) -> io::Result<Arc<Self>> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Arc::new(Self { writer: Mutex::new(BufWriter::new(file)) }))
+    }
+
+    /// Write one record, flushed at once so a crash loses nothing
+    pub fn record(&self, record: &CaptureRecord) {
+        let mut writer = self.writer.lock().unwrap();
+        let res = serde_json::to_writer(&mut *writer, record)
+            .map_err(io::Error::from)
+            .and_then(|()| writeln!(writer))
+            .and_then(|()| writer.flush());
+        if let Err(e) = res {
+            eprintln!("capture write failed: {e}");
+        }
+    }
+
+    /// Wrap a bare bus client so that all its traffic is recorded, labelled with the bus's transport spec
+    pub fn tap(self: &Arc<Self>, ctx: Context, bus: String) -> Context {
+        let client = CaptureClient { inner: ctx, slave: Slave(0), bus, capture: self.clone() };
+        Context::from(Box::new(client) as Box<dyn Client>)
+    }
+}
+
+/// Read every record of a capture file
+pub fn load_capture(path: &Path) -> Result<Vec<CaptureRecord>, Box<dyn std::error::Error>> {
+    let mut records = Vec::new();
+    for (idx, line) in BufReader::new(File::open(path)?).lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let record = serde_json::from_str(&line).map_err(|e| format!("{}:{}: {e}", path.display(), idx + 1))?;
+        records.push(record);
+    }
+    Ok(records)
+}
+
+/// Milliseconds since `sent`, to the microsecond
+fn elapsed_ms(sent: Instant) -> f64 {
+    (sent.elapsed().as_secs_f64() * 1e6).round() / 1e3
+}
+
+/// A request on its way to the device; recorded as abandoned if dropped before it completes
+struct PendingRecord {
+    capture: Arc<Capture>,
+    record: Option<CaptureRecord>,
+    sent: Instant,
+}
+
+impl PendingRecord {
+    fn finish(mut self, result: &tokio_modbus::Result<Response>) {
+        let Some(mut record) = self.record.take() else { return };
+        record.latency_ms = elapsed_ms(self.sent);
+        match result {
+            Ok(Ok(response)) => record.response = response_data(response),
+            Ok(Err(exception)) => record.exception = Some(u8::from(*exception)),
+            Err(e) => record.error = Some(e.to_string()),
+        }
+        self.capture.record(&record);
+    }
+}
+
+impl Drop for PendingRecord {
+    fn drop(&mut self) {
+        if let Some(mut record) = self.record.take() {
+            record.latency_ms = elapsed_ms(self.sent);
+            record.error = Some("abandoned without a response".to_string());
+            self.capture.record(&record);
+        }
+    }
+}
+
+/// A Modbus client that records every request and its outcome to a `Capture`
+struct CaptureClient {
+    inner: Context,
+    slave: Slave,
+    bus: String,
+    capture: Arc<Capture>,
+}
+
+impl SlaveContext for CaptureClient {
+    fn set_slave(&mut self, slave: Slave) {
+        self.slave = slave;
+        self.inner.set_slave(slave);
+    }
+}
+
+#[async_trait]
+impl Client for CaptureClient {
+    async fn call(&mut self, request: Request<'_>) -> tokio_modbus::Result<Response> {
+        let (address, data) = request_fields(&request);
+        let pending = PendingRecord {
+            capture: self.capture.clone(),
+            record: Some(CaptureRecord {
+                time_ms: chrono::Utc::now().timestamp_millis(),
+                bus: self.bus.clone(),
+                node: self.slave.0,
+                function: request.function_code().value(),
+                address,
+                request: data,
+                response: Vec::new(),
+                exception: None,
+                error: None,
+                latency_ms: 0.,
+            }),
+            sent: Instant::now(),
+        };
+        let result = self.inner.call(request).await;
+        pending.finish(&result);
+        result
+    }
+
+    async fn disconnect(&mut self) -> io::Result<()> {
+        self.inner.disconnect().await
+    }
+}
+
+/// Answers Modbus requests from a capture
+#[derive(Debug)]
+pub struct Replay {
+    responses: HashMap<RequestKey, Vec<CaptureRecord>>,
+    /// Index of the next record to play for each request
+    cursors: Mutex<HashMap<RequestKey, usize>>,
+    /// Answer after the recorded latency, rather than at once
+    pub realtime: bool,
+    /// Log each request and how it was answered
+    pub verbose: bool,
+}
+
+impl Replay {
+    pub fn new(records: Vec<CaptureRecord>) -> Self {
+        let mut responses: HashMap<RequestKey, Vec<CaptureRecord>> = HashMap::new();
+        for record in records {
+            responses.entry(record.key()).or_default().push(record);
+        }
+        Self { responses, cursors: Mutex::new(HashMap::new()), realtime: false, verbose: true }
+    }
+
+    /// Number of distinct requests the capture can answer
+    pub fn request_count(&self) -> usize {
+        self.responses.len()
+    }
+
+    /// The next recorded answer to `key`, starting over once they have all been played
+    pub fn next_record(&self, key: RequestKey) -> Option<(usize, usize, &CaptureRecord)> {
+        let records = self.responses.get(&key)?;
+        let mut cursors = self.cursors.lock().unwrap();
+        let cursor = cursors.entry(key).or_insert(0);
+        let idx = *cursor % records.len();
+        *cursor = idx + 1;
+        Some((idx, records.len(), &records[idx]))
+    }
+}
+
+impl tokio_modbus::server::Service for Replay {
+    type Request = SlaveRequest<'static>;
+    type Response = Option<Response>;
+    type Exception = ExceptionCode;
+    type Future = Pin<Box<dyn Future<Output = Result<Option<Response>, ExceptionCode>> + Send>>;
+
+    fn call(&self, req: Self::Request) -> Self::Future {
+        let (address, data) = request_fields(&req.request);
+        let probe = CaptureRecord {
+            time_ms: 0,
+            bus: String::new(),
+            node: req.slave,
+            function: req.request.function_code().value(),
+            address,
+            request: data,
+            response: Vec::new(),
+            exception: None,
+            error: None,
+            latency_ms: 0.,
+        };
+        let key = probe.key();
+        let (node, function, address, quantity) = key;
+        let Some((idx, count, record)) = self.next_record(key) else {
+            println!("node 0x{node:02X} fc 0x{function:02X} 0x{address:04X} x{quantity}: not in the capture");
+            return Box::pin(std::future::ready(Err(ExceptionCode::GatewayTargetDevice)));
+        };
+        let answer = record.replay_response();
+        if self.verbose {
+            let outcome = match (&answer, &record.error) {
+                (Ok(Some(_)), _) => format!("{:04X?}", record.response),
+                (Ok(None), Some(e)) => format!("no response ({e})"),
+                (Ok(None), None) => "no response".to_string(),
+                (Err(exception), _) => format!("exception {exception}"),
+            };
+            println!("node 0x{node:02X} fc 0x{function:02X} 0x{address:04X} x{quantity}: #{}/{count} {outcome}", idx + 1);
+        }
+        let delay = if self.realtime { Duration::from_secs_f64(record.latency_ms.max(0.) / 1000.) } else { Duration::ZERO };
+        Box::pin(async move {
+            if !delay.is_zero() {
+                tokio::time::sleep(delay).await;
+            }
+            answer
+        })
+    }
+}
+
+/// Serve a replay on `listen` (any transport spec: a TCP port, a serial port, or RTU over TCP)
+/// until the server fails
+pub async fn serve_replay(replay: Arc<Replay>, listen: &Transport) -> Result<(), Box<dyn std::error::Error>> {
+    use tokio_modbus::server::{rtu, rtu_over_tcp, tcp};
+
+    match listen {
+        Transport::Tcp(socket_addr) => {
+            let server = tcp::Server::new(tokio::net::TcpListener::bind(socket_addr).await?);
+            let on_connected = |stream, socket_addr| {
+                let replay = replay.clone();
+                async move { tcp::accept_tcp_connection(stream, socket_addr, |_| Ok(Some(replay.clone()))) }
+            };
+            server.serve(&on_connected, |e| eprintln!("replay connection failed: {e}")).await?;
+        }
+        Transport::RtuOverTcp(socket_addr) => {
+            let server = rtu_over_tcp::Server::new(tokio::net::TcpListener::bind(socket_addr).await?);
+            let on_connected = |stream, socket_addr| {
+                let replay = replay.clone();
+                async move { rtu_over_tcp::accept_tcp_connection(stream, socket_addr, |_| Ok(Some(replay.clone()))) }
+            };
+            server.serve(&on_connected, |e| eprintln!("replay connection failed: {e}")).await?;
+        }
+        Transport::Rtu { tty_path, baud_rate } => {
+            rtu::Server::new_from_path(tty_path, *baud_rate)?.serve_forever(replay).await?;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,321 @@
+//!
+//! Charge and energy accounting: integrating the measured drive current and electrode potential
+//! over time, per drive phase and for the whole run.
+//!
+//! The charge passed through the cathode is what sets how much carbon can be deposited. By
+//! Faraday's law, a reaction that takes `z` electrons per carbon atom deposits at most
+//! `Q · M / (z · F)` grams for a charge `Q`, with `M` the molar mass of carbon and `F` the
+//! Faraday constant. That is a theoretical ceiling: side reactions, back-reactions and carbon
+//! lost from the cathode all bring the real yield below it.
+//!
+//! Samples are integrated with the trapezoid rule. An interval longer than
+//! `MAX_INTEGRATION_GAP_MS` (a melt probe or anode check holding up the measurement loop,
+//! or the electrodes being idle) is left out and counted as unaccounted time instead.
+//!
+
+use std::str::FromStr;
+
+use serde::Serialize;
+
+use crate::run_log::{DRIVE_PHASE_NAMES, drive_phase_name};
+
+/// Faraday constant, coulombs per mole of electrons
+pub const FARADAY_C_PER_MOL: f64 = 96_485.332;
+
+/// Molar mass of carbon, grams per mole
+pub const CARBON_MOLAR_MASS_G_MOL: f64 = 12.011;
+
+/// Samples further apart than this aren't integrated across
+pub const MAX_INTEGRATION_GAP_MS: i64 = 10_000;
+
+/// Drive phases that can be ended by a charge target
+pub const CHARGE_TARGET_PHASE_NAMES: [&str; 2] = ["Nucleation", "Elongation"];
+
+/// A cathode reaction depositing carbon
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct DepositionReaction {
+    pub name: &'static str,
+    pub equation: &'static str,
+    /// Electrons transferred per carbon atom deposited
+    pub electrons_per_carbon: u8,
+}
+
+/// Reduction of carbonate in the melt, the usual route in molten carbonate electrolysis
+pub const CARBONATE_REDUCTION: DepositionReaction = DepositionReaction {
+    name: "carbonate",
+    equation: "CO3²⁻ + 4e⁻ → C + 3O²⁻",
+    electrons_per_carbon: 4,
+};
+
+/// Reduction of dissolved carbon monoxide
+pub const CO_REDUCTION: DepositionReaction = DepositionReaction {
+    name: "co",
+    equation: "CO + 2e⁻ → C + O²⁻",
+    electrons_per_carbon: 2,
+};
+
+/// Reactions that can be chosen by name
+pub const DEPOSITION_REACTIONS: [DepositionReaction; 2] = [CARBONATE_REDUCTION, CO_REDUCTION];
+
+impl DepositionReaction {
+    /// Theoretical carbon deposited by a charge, in milligrams
+    pub fn carbon_mg(&self, coulombs: f64) -> f64 {
+        1000. * coulombs * CARBON_MOLAR_MASS_G_MOL / (self.electrons_per_carbon as f64 * FARADAY_C_PER_MOL)
+    }
+}
+
+impl FromStr for DepositionReaction {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        DEPOSITION_REACTIONS.iter()
+            .find(|reaction| reaction.name.eq_ignore_ascii_case(name.trim()))
+            .copied()
+            .ok_or_else(|| format!("Unknown reaction {name:?}, expected one of: {}",
+                DEPOSITION_REACTIONS.map(|reaction| reaction.name).join(", ")))
+    }
+}
+
+impl std::fmt::Display for DepositionReaction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} ({})", self.name, self.equation)
+    }
+}
+
+/// Charge and energy delivered over some span of time
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+pub struct ChargeTotals {
+    pub coulombs: f64,
+    pub joules: f64,
+    /// Time integrated over
+    pub duration_ms: i64,
+}
+
+impl ChargeTotals {
+    pub const ZERO: ChargeTotals = ChargeTotals { coulombs: 0., joules: 0., duration_ms: 0 };
+
+    pub fn amp_hours(&self) -> f64 {
+        self.coulombs / 3600.
+    }
+
+    pub fn watt_hours(&self) -> f64 {
+        self.joules / 3600.
+    }
+}
+
+impl std::fmt::Display for ChargeTotals {
+    /// e.g. `123.4 C (34.28 mAh), 0.152 Wh`
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:.1} C ({:.2} mAh), {:.3} Wh", self.coulombs, 1000. * self.amp_hours(), self.watt_hours())
+    }
+}
+
+/// One measurement of the electrode drive
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct DriveSample {
+    epoch_ms: i64,
+    measured_ma: f32,
+    measured_volts: f32,
+}
+
+/// Running totals of the charge and energy delivered, for the run and per drive phase
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChargeIntegrator {
+    reaction: DepositionReaction,
+    run: ChargeTotals,
+    /// Indexed like `DRIVE_PHASE_NAMES`, since each phase was last entered
+    phases: [ChargeTotals; DRIVE_PHASE_NAMES.len()],
+    /// Time left out because samples were too far apart
+    unaccounted_ms: i64,
+    last: Option<DriveSample>,
+}
+
+impl ChargeIntegrator {
+    pub const fn new(reaction: DepositionReaction) -> Self {
+        Self {
+            reaction,
+            run: ChargeTotals::ZERO,
+            phases: [ChargeTotals::ZERO; DRIVE_PHASE_NAMES.len()],
+            unaccounted_ms: 0,
+            last: None,
+        }
+    }
+
+    pub fn reaction(&self) -> &DepositionReaction {
+        &self.reaction
+    }
+
+    /// Totals for the whole run
+    pub fn run(&self) -> &ChargeTotals {
+        &self.run
+    }
+
+    /// Totals for a drive phase since it was last entered
+    pub fn phase(&self, phase: u8) -> ChargeTotals {
+        self.phases.get(phase as usize).copied().unwrap_or_default()
+    }
+
+    pub fn unaccounted_ms(&self) -> i64 {
+        self.unaccounted_ms
+    }
+
+    /// Add a measurement taken while `phase` was driving the electrodes:
+    /// the interval since the previous measurement is credited to that phase
+    pub fn record(&mut self, epoch_ms: i64, phase: u8, measured_ma: f32, measured_volts: f32) {
+        let sample = DriveSample { epoch_ms, measured_ma, measured_volts };
+        let Some(last) = self.last.replace(sample) else { return };
+        let interval_ms = epoch_ms - last.epoch_ms;
+        if interval_ms <= 0 {
+            return;
+        }
+        if interval_ms > MAX_INTEGRATION_GAP_MS {
+            self.unaccounted_ms += interval_ms;
+            return;
+        }
+        let secs = interval_ms as f64 / 1000.;
+        let amps = |sample: &DriveSample| sample.measured_ma.max(0.) as f64 / 1000.;
+        let watts = |sample: &DriveSample| amps(sample) * sample.measured_volts.max(0.) as f64;
+        let coulombs = secs * (amps(&last) + amps(&sample)) / 2.;
+        let joules = secs * (watts(&last) + watts(&sample)) / 2.;
+        for totals in [Some(&mut self.run), self.phases.get_mut(phase as usize)].into_iter().flatten() {
+            totals.coulombs += coulombs;
+            totals.joules += joules;
+            totals.duration_ms += interval_ms;
+        }
+    }
+
+    /// Start a phase's totals over, as it is (re-)entered
+    pub fn restart_phase(&mut self, phase: u8) {
+        if let Some(totals) = self.phases.get_mut(phase as usize) {
+            *totals = ChargeTotals::ZERO;
+        }
+    }
+
+    /// Theoretical carbon deposited over the run, in milligrams
+    pub fn run_carbon_mg(&self) -> f64 {
+        self.reaction.carbon_mg(self.run.coulombs)
+    }
+
+    /// One line summary of the run and current phase totals
+    pub fn summary(&self, phase: u8) -> String {
+        format!("run {}, C {:.2} mg; {} {}",
+            self.run, self.run_carbon_mg(), drive_phase_name(phase), self.phase(phase))
+    }
+}
+
+/// Parse the drive phase a charge target applies to, by name, into its `DRIVE_PHASE_NAMES` index
+pub fn parse_charge_target_phase(name: &str) -> Result<u8, String> {
+    CHARGE_TARGET_PHASE_NAMES.iter()
+        .find(|phase_name| phase_name.eq_ignore_ascii_case(name))
+        .and_then(|phase_name| DRIVE_PHASE_NAMES.iter().position(|drive_phase| drive_phase == phase_name))
+        .map(|phase| phase as u8)
+        .ok_or_else(|| format!("No charge target for phase {name:?}, expected one of: {}",
+            CHARGE_TARGET_PHASE_NAMES.join(", ").to_lowercase()))
+}
+
+/// Parse a charge in coulombs, which must be positive
+pub fn parse_charge_coulombs(text: &str) -> Result<f64, String> {
+    text.parse::<f64>().ok()
+        .filter(|coulombs| coulombs.is_finite() && *coulombs > 0.)
+        .ok_or_else(|| format!("Bad charge in coulombs: {text:?}"))
+}
+
+/// Parse a phase charge target such as `nucleation=120` (coulombs)
+pub fn parse_charge_target(text: &str) -> Result<(u8, f64), String> {
+    let (phase, coulombs) = text.split_once('=')
+        .ok_or_else(|| format!("Bad charge target {text:?}, expected <phase>=<coulombs>"))?;
+    Ok((parse_charge_target_phase(phase.trim())?, parse_charge_coulombs(coulombs.trim())?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn nucleation() -> u8 {
+        parse_charge_target_phase("nucleation").unwrap()
+    }
+
+    fn elongation() -> u8 {
+        parse_charge_target_phase("elongation").unwrap()
+    }
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!((actual - expected).abs() < 1e-6, "{actual} != {expected}");
+    }
+
+    #[test]
+    fn integrates_with_the_trapezoid_rule() {
+        let mut charge = ChargeIntegrator::new(CARBONATE_REDUCTION);
+        charge.record(0, nucleation(), 100., 2.);
+        charge.record(1000, nucleation(), 300., 2.);
+        // 1 s at an average of 200 mA and 0.4 W
+        assert_close(charge.run().coulombs, 0.2);
+        assert_close(charge.run().joules, 0.4);
+        assert_eq!(charge.run().duration_ms, 1000);
+        assert_eq!(charge.phase(nucleation()), *charge.run());
+        assert_eq!(charge.phase(elongation()), ChargeTotals::ZERO);
+    }
+
+    #[test]
+    fn gaps_are_unaccounted() {
+        let mut charge = ChargeIntegrator::new(CARBONATE_REDUCTION);
+        charge.record(0, nucleation(), 100., 2.);
+        charge.record(1000, nucleation(), 100., 2.);
+        charge.record(1000 + MAX_INTEGRATION_GAP_MS + 1, nucleation(), 100., 2.);
+        assert_eq!(charge.unaccounted_ms(), MAX_INTEGRATION_GAP_MS + 1);
+        assert_close(charge.run().coulombs, 0.1);
+        assert_eq!(charge.run().duration_ms, 1000);
+
+        // integration picks up again from the sample after the gap
+        charge.record(2000 + MAX_INTEGRATION_GAP_MS + 1, nucleation(), 100., 2.);
+        assert_close(charge.run().coulombs, 0.2);
+        assert_eq!(charge.unaccounted_ms(), MAX_INTEGRATION_GAP_MS + 1);
+
+        // a gap of exactly the limit is still integrated
+        charge.record(2000 + 2 * MAX_INTEGRATION_GAP_MS + 1, nucleation(), 100., 2.);
+        assert_eq!(charge.unaccounted_ms(), MAX_INTEGRATION_GAP_MS + 1);
+        assert_close(charge.run().coulombs, 1.2);
+    }
+
+    #[test]
+    fn restarting_a_phase_keeps_the_run_totals() {
+        let mut charge = ChargeIntegrator::new(CARBONATE_REDUCTION);
+        charge.record(0, elongation(), 100., 2.);
+        charge.record(1000, elongation(), 100., 2.);
+        charge.restart_phase(elongation());
+        assert_eq!(charge.phase(elongation()), ChargeTotals::ZERO);
+        charge.record(2000, elongation(), 100., 2.);
+        assert_close(charge.phase(elongation()).coulombs, 0.1);
+        assert_eq!(charge.phase(elongation()).duration_ms, 1000);
+        assert_close(charge.run().coulombs, 0.2);
+    }
+
+    #[test]
+    fn carbon_from_faradays_law() {
+        // 100 mA for an hour is 360 C: 360 C × 12.011 g/mol / (4 × 96485.332 C/mol) = 11.20 mg
+        let mut charge = ChargeIntegrator::new(CARBONATE_REDUCTION);
+        for second in 0..=3600 {
+            charge.record(second * 1000, nucleation(), 100., 2.);
+        }
+        assert_close(charge.run().coulombs, 360.);
+        assert_close(charge.run().watt_hours(), 0.2);
+        assert!((charge.run_carbon_mg() - 11.2037).abs() < 1e-4, "{}", charge.run_carbon_mg());
+        // carbon monoxide takes half the electrons per carbon
+        assert_close(CO_REDUCTION.carbon_mg(360.), 2. * CARBONATE_REDUCTION.carbon_mg(360.));
+    }
+
+    #[test]
+    fn charge_targets_parse() {
+        assert_eq!(parse_charge_target("nucleation=120"), Ok((nucleation(), 120.)));
+        assert_eq!(parse_charge_target(" Elongation = 2.5 "), Ok((elongation(), 2.5)));
+        assert_ne!(nucleation(), elongation());
+    }
+
+    #[test]
+    fn bad_charge_targets_are_rejected() {
+        for text in ["nucleation", "nucleation:120", "holding=120", "=120",
+                     "nucleation=0", "nucleation=-5", "nucleation=NaN", "nucleation=inf", "nucleation="] {
+            assert!(parse_charge_target(text).is_err(), "{text}");
+        }
+    }
+}
@@ -0,0 +1,382 @@
+//!
+//! Command-line options shared by the tools: how to reach the Modbus bus
+//! (a TCP bridge or a serial RS-485 adapter), which node to address, and bus timing.
+//!
+//! Global options may appear anywhere on the command line; everything else is
+//! left in order for the tool's own arguments.
+//!
+//! The bus is named by a transport spec, given with `--transport` or in the
+//! `CRAVEN_TRANSPORT` environment variable:
+//!
+//! | Spec | Transport |
+//! |-------|----------|
+//! | `tcp://10.0.1.151:502` | Modbus TCP, e.g. to a bridge that speaks Modbus TCP on its network side |
+//! | `rtu:///dev/ttyUSB0?baud=115200` | Modbus RTU on a local serial port (default 9600 baud) |
+//! | `rtu-over-tcp://10.0.1.152:8899` | Raw RTU frames tunnelled through a TCP socket, as many cheap serial servers do |
+//!
+//! The port defaults to 502 when omitted. `--tcp <host:port>`, `--rtu <tty path>` and
+//! `--baud <rate>` remain as shorthands.
+//!
+//! Devices can be spread over several buses with `--route <devices>=<spec>`, naming devices
+//! by node ID or by role (`thermo`, `current`, `ivadc`, `relays`, `stepper`, `ai`, `ao`),
+//! e.g. `--route current,ivadc=rtu:///dev/ttyUSB0?baud=115200`.
+//! `CRAVEN_ROUTES` holds default routes, separated by `;`.
+//! Every other device stays on the `--transport` bus.
+//!
+//! `--capture <file>` records all bus traffic to a capture file (see `capture`)
+//! that `craven replay` can serve back.
+//!
+//! Requests are sent once, and wait as long as the caller lets them. `--timeout <ms>` gives each
+//! request its own response timeout and `--retries <n>` retries failed reads (never writes), for
+//! a noisy serial line where a lost frame is more likely than a stale one. Leave them off on the
+//! WiFi bridge, which can hand a retried read the reply buffered for the attempt before.
+//!
+
+use std::collections::BTreeMap;
+use std::net::{SocketAddr, ToSocketAddrs};
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio_modbus::client::Context;
+use tokio_modbus::prelude::*;
+
+use crate::*;
+use crate::bus::*;
+use crate::capture::Capture;
+
+/// The WiFi Modbus TCP bridge on our local network
+pub const DEFAULT_TCP_BRIDGE: &str = "10.0.1.151:502";
+/// Most of our RS-485 devices ship at this baud rate
+pub const DEFAULT_BAUD_RATE: u32 = 9600;
+/// Standard Modbus TCP port, used when a spec doesn't give one
+pub const DEFAULT_MODBUS_TCP_PORT: u16 = 502;
+/// Environment variable holding a default transport spec
+pub const TRANSPORT_ENV_VAR: &str = "CRAVEN_TRANSPORT";
+/// Environment variable holding default routes, separated by `;`
+pub const ROUTES_ENV_VAR: &str = "CRAVEN_ROUTES";
+
+/// Role names that `--route` accepts in place of node IDs
+pub const DEVICE_ROLES: [(&str, u8); 7] = [
+    ("thermo", NODEID_YKKTC1202_DUAL_TK),
+    ("current", NODEID_YKPVCCS010_CURR_SRC),
+    ("ivadc", NODEID_WDCU3003_IV_ADC),
+    ("relays", NODEID_WAV_OCTO_RELAY),
+    ("stepper", NODEID_SMC05_STEP_DRIVER),
+    ("ai", NODEID_WA8TAI_IV_ADC),
+    ("ao", NODEID_WA26419_8CH_DAC),
+];
+
+/// Help text for the options parsed by `BusOptions::from_args`
+pub const BUS_OPTIONS_USAGE: &str = "\
+Bus options:
+  --transport <spec>  tcp://<host:port>, rtu://<tty path>[?baud=<rate>]
+                      or rtu-over-tcp://<host:port> (default $CRAVEN_TRANSPORT,
+                      else tcp://10.0.1.151:502)
+  --tcp <host:port>   Same as --transport tcp://<host:port>
+  --rtu <tty path>    Same as --transport rtu://<tty path>
+  --baud <rate>       Serial baud rate for --rtu (default 9600)
+  --route <devices>=<spec>
+                      Put devices (node IDs, or thermo, current, ivadc, relays,
+                      stepper, ai, ao; comma separated) on another bus.
+                      Repeatable; default $CRAVEN_ROUTES
+  --capture <file>    Append every request and response to a capture file
+  --node <id>         Node ID to address, decimal or 0x hex
+  --timeout <ms>      Per-request response timeout (default none)
+  --retries <n>       Read retries after a failed read (default 0)";
+
+/// How to reach the bus
+#[derive(Debug, Clone, PartialEq)]
+pub enum Transport {
+    /// Modbus TCP, typically a bridge to the RS-485 bus
+    Tcp(SocketAddr),
+    /// Modbus RTU over a local serial port
+    Rtu { tty_path: String, baud_rate: u32 },
+    /// Modbus RTU frames passed verbatim through a TCP socket (a transparent serial server)
+    RtuOverTcp(SocketAddr),
+}
+
+/// Resolve `host[:port]`, defaulting to the Modbus TCP port
+fn resolve_socket_addr(text: &str) -> Result<SocketAddr, String> {
+    let resolved = match text.to_socket_addrs() {
+        Ok(addrs) => Ok(addrs),
+        Err(_) => (text.trim_start_matches('[').trim_end_matches(']'), DEFAULT_MODBUS_TCP_PORT).to_socket_addrs(),
+    };
+    resolved.ok().and_then(|mut addrs| addrs.next())
+        .ok_or_else(|| format!("can't resolve address {text:?}"))
+}
+
+impl FromStr for Transport {
+    type Err = String;
+
+    /// Parse a transport spec such as `tcp://10.0.1.151:502` or `rtu:///dev/ttyUSB0?baud=115200`
+    fn from_str(spec: &str) -> Result<Self, Self::Err> {
+        let (scheme, rest) = spec.split_once("://")
+            .ok_or_else(|| format!("transport spec {spec:?} needs a tcp://, rtu:// or rtu-over-tcp:// prefix"))?;
+        let (location, query) = rest.split_once('?').unwrap_or((rest, ""));
+        if location.is_empty() {
+            return Err(format!("transport spec {spec:?} has no address"));
+        }
+        let mut baud_rate = DEFAULT_BAUD_RATE;
+        for param in query.split('&').filter(|param| !param.is_empty()) {
+            match param.split_once('=') {
+                Some(("baud", rate)) if scheme == "rtu" =>
+                    baud_rate = rate.parse().map_err(|_| format!("bad baud rate in {spec:?}"))?,
+                _ => return Err(format!("unsupported parameter {param:?} in {spec:?}")),
+            }
+        }
+        match scheme {
+            "tcp" => Ok(Transport::Tcp(resolve_socket_addr(location)?)),
+            "rtu" => Ok(Transport::Rtu { tty_path: location.to_string(), baud_rate }),
+            "rtu-over-tcp" => Ok(Transport::RtuOverTcp(resolve_socket_addr(location)?)),
+            _ => Err(format!("unknown transport {scheme:?}: use tcp, rtu or rtu-over-tcp")),
+        }
+    }
+}
+
+/// Formats as the transport spec, so that it parses back to the same transport
+impl std::fmt::Display for Transport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Transport::Tcp(socket_addr) => write!(f, "tcp://{socket_addr}"),
+            Transport::Rtu { tty_path, baud_rate } => write!(f, "rtu://{tty_path}?baud={baud_rate}"),
+            Transport::RtuOverTcp(socket_addr) => write!(f, "rtu-over-tcp://{socket_addr}"),
+        }
+    }
+}
+
+impl Transport {
+    /// Open the transport, returning a bare Modbus context addressed to broadcast
+    pub async fn connect(&self) -> Result<Context, Box<dyn std::error::Error>> {
+        let ctx = match self {
+            Transport::Tcp(socket_addr) => tcp::connect(*socket_addr).await?,
+            Transport::Rtu { tty_path, baud_rate } => {
+                let builder = tokio_serial::new(tty_path, *baud_rate);
+                rtu::attach(tokio_serial::SerialStream::open(&builder)?)
+            }
+            Transport::RtuOverTcp(socket_addr) => {
+                let stream = tokio::net::TcpStream::connect(socket_addr).await?;
+                stream.set_nodelay(true)?;
+                rtu::attach(stream)
+            }
+        };
+        Ok(ctx)
+    }
+}
+
+/// Devices that live on a bus other than the default one
+#[derive(Debug, Clone, PartialEq)]
+pub struct BusRoute {
+    pub node_ids: Vec<u8>,
+    pub transport: Transport,
+}
+
+impl FromStr for BusRoute {
+    type Err = String;
+
+    /// Parse `<devices>=<spec>`, such as `current,0x13=rtu:///dev/ttyUSB0?baud=115200`
+    fn from_str(route: &str) -> Result<Self, Self::Err> {
+        let (devices, spec) = route.split_once('=')
+            .ok_or_else(|| format!("route {route:?} should look like <devices>=<transport spec>"))?;
+        let node_ids = devices.split(',')
+            .map(|device| match DEVICE_ROLES.iter().find(|(role, _)| *role == device.trim()) {
+                Some((_, node_id)) => Ok(*node_id),
+                None => parse_node_id(device.trim()).map_err(|_| format!(
+                    "unknown device {device:?} in route: give a node ID or one of {}",
+                    DEVICE_ROLES.map(|(role, _)| role).join(", "))),
+            })
+            .collect::<Result<Vec<u8>, String>>()?;
+        Ok(BusRoute { node_ids, transport: spec.parse()? })
+    }
+}
+
+impl std::fmt::Display for BusRoute {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let node_ids: Vec<String> = self.node_ids.iter().map(|node_id| format!("0x{node_id:02X}")).collect();
+        write!(f, "{}={}", node_ids.join(","), self.transport)
+    }
+}
+
+/// Bus access options common to every tool
+#[derive(Debug, Clone)]
+pub struct BusOptions {
+    /// The default bus
+    pub transport: Transport,
+    /// Devices on other buses
+    pub routes: Vec<BusRoute>,
+    /// Node ID to address, if given
+    pub node_id: Option<u8>,
+    /// Timeout for each Modbus request, if any beyond the caller's own
+    pub request_timeout: Option<Duration>,
+    /// How many times a failed read is retried
+    pub read_retries: u32,
+    /// Capture file to record bus traffic to, if given
+    pub capture: Option<PathBuf>,
+}
+
+impl Default for BusOptions {
+    fn default() -> Self {
+        Self {
+            transport: Transport::Tcp(DEFAULT_TCP_BRIDGE.parse().unwrap()),
+            routes: Vec::new(),
+            node_id: None,
+            request_timeout: DEFAULT_REQUEST_TIMEOUT,
+            read_retries: DEFAULT_READ_RETRIES,
+            capture: None,
+        }
+    }
+}
+
+/// Parse a node ID: decimal, or hex with a `0x` prefix
+pub fn parse_node_id(text: &str) -> Result<u8, String> {
+    let node_id = parse_u16(text)?;
+    u8::try_from(node_id).ok()
+        .filter(|id| (1..=NODEID_MAX).contains(id))
+        .ok_or_else(|| format!("node ID {text} outside 1..{NODEID_MAX}"))
+}
+
+/// Parse a register address or value: decimal, or hex with a `0x` prefix
+pub fn parse_u16(text: &str) -> Result<u16, String> {
+    let parsed = match text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        Some(hex) => u16::from_str_radix(hex, 16),
+        None => text.parse(),
+    };
+    parsed.map_err(|_| format!("bad number: {text:?}"))
+}
+
+impl BusOptions {
+    /// Pull the bus options out of a command line, returning them with the remaining arguments in order
+    pub fn from_args(args: impl IntoIterator<Item = String>) -> Result<(Self, Vec<String>), String> {
+        let mut options = Self::default();
+        if let Ok(spec) = std::env::var(TRANSPORT_ENV_VAR) {
+            options.transport = spec.parse().map_err(|e| format!("{TRANSPORT_ENV_VAR}: {e}"))?;
+        }
+        if let Ok(routes) = std::env::var(ROUTES_ENV_VAR) {
+            for route in routes.split(';').filter(|route| !route.trim().is_empty()) {
+                options.routes.push(route.trim().parse().map_err(|e| format!("{ROUTES_ENV_VAR}: {e}"))?);
+            }
+        }
+        let mut baud_rate: Option<u32> = None;
+        let mut rest = Vec::new();
+
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            let mut value = |name: &str| args.next().ok_or_else(|| format!("{name} needs a value"));
+            match arg.as_str() {
+                "--transport" => options.transport = value("--transport")?.parse()?,
+                "--tcp" => options.transport = Transport::Tcp(resolve_socket_addr(&value("--tcp")?)?),
+                "--rtu" => options.transport = Transport::Rtu { tty_path: value("--rtu")?, baud_rate: DEFAULT_BAUD_RATE },
+                "--baud" => {
+                    let rate = value("--baud")?;
+                    baud_rate = Some(rate.parse().map_err(|_| format!("bad --baud rate: {rate:?}"))?);
+                }
+                "--route" => options.routes.push(value("--route")?.parse()?),
+                "--capture" => options.capture = Some(PathBuf::from(value("--capture")?)),
+                "--node" => options.node_id = Some(parse_node_id(&value("--node")?)?),
+                "--timeout" => {
+                    let millis = value("--timeout")?;
+                    options.request_timeout = Some(Duration::from_millis(
+                        millis.parse().map_err(|_| format!("bad --timeout: {millis:?}"))?));
+                }
+                "--retries" => {
+                    let retries = value("--retries")?;
+                    options.read_retries = retries.parse().map_err(|_| format!("bad --retries: {retries:?}"))?;
+                }
+                _ => rest.push(arg),
+            }
+        }
+        if let Some(rate) = baud_rate {
+            let Transport::Rtu { baud_rate, .. } = &mut options.transport else {
+                return Err("--baud only applies to a serial (rtu://) transport".to_string());
+            };
+            *baud_rate = rate;
+        }
+        Ok((options, rest))
+    }
+
+    /// The same options as command-line arguments, to hand on to another tool
+    pub fn to_args(&self) -> Vec<String> {
+        let mut args = vec!["--transport".to_string(), self.transport.to_string()];
+        for route in &self.routes {
+            args.extend(["--route".to_string(), route.to_string()]);
+        }
+        if let Some(node_id) = self.node_id {
+            args.extend(["--node".to_string(), format!("0x{node_id:02X}")]);
+        }
+        if let Some(capture) = &self.capture {
+            args.extend(["--capture".to_string(), capture.display().to_string()]);
+        }
+        if let Some(request_timeout) = self.request_timeout {
+            args.extend(["--timeout".to_string(), request_timeout.as_millis().to_string()]);
+        }
+        args.extend(["--retries".to_string(), self.read_retries.to_string()]);
+        args
+    }
+
+    /// The addressed node ID, or a tool's default device
+    pub fn node_or(&self, default_node_id: u8) -> u8 {
+        self.node_id.unwrap_or(default_node_id)
+    }
+
+    /// The transport that carries `node_id`
+    pub fn transport_for(&self, node_id: u8) -> &Transport {
+        self.routes.iter().rev()
+            .find(|route| route.node_ids.contains(&node_id))
+            .map_or(&self.transport, |route| &route.transport)
+    }
+
+    /// Open every bus, each wrapped so that traffic is counted in `stats` (and timed out and retried if asked),
+    /// and recorded to the capture file if there is one
+    pub async fn connect_router(&self, stats: Arc<BusStats>) -> Result<BusRouter, Box<dyn std::error::Error>> {
+        let mut transports = vec![&self.transport];
+        let mut node_routes = BTreeMap::new();
+        for route in &self.routes {
+            // routes naming the same transport share one connection
+            let bus_idx = match transports.iter().position(|transport| **transport == route.transport) {
+                Some(bus_idx) => bus_idx,
+                None => {
+                    transports.push(&route.transport);
+                    transports.len() - 1
+                }
+            };
+            for node_id in &route.node_ids {
+                node_routes.insert(*node_id, bus_idx);
+            }
+        }
+        let capture = self.capture.as_deref()
+            .map(|path| Capture::open(path).map_err(|e| format!("capture file {}: {e}", path.display())))
+            .transpose()?;
+        let mut buses = Vec::with_capacity(transports.len());
+        for transport in transports {
+            let mut ctx = transport.connect().await.map_err(|e| format!("{transport}: {e}"))?;
+            if let Some(capture) = &capture {
+                // below the retries, so that every attempt is recorded
+                ctx = capture.tap(ctx, transport.to_string());
+            }
+            buses.push(instrument_with(ctx, stats.clone(), self.request_timeout, self.read_retries));
+        }
+        Ok(BusRouter::new(buses, node_routes))
+    }
+
+    /// Open the buses and return one client that reaches every device, addressed to `--node` if given
+    pub async fn connect(&self, stats: Arc<BusStats>) -> Result<Context, Box<dyn std::error::Error>> {
+        let mut ctx = self.connect_router(stats).await?.context();
+        if let Some(node_id) = self.node_id {
+            ctx.set_slave(Slave(node_id));
+        }
+        Ok(ctx)
+    }
+
+    /// Describe the buses, for logging
+    pub fn describe(&self) -> String {
+        let mut text = self.transport.to_string();
+        for route in &self.routes {
+            text.push_str(&format!(", {route}"));
+        }
+        if let Some(capture) = &self.capture {
+            text.push_str(&format!(" (capturing to {})", capture.display()));
+        }
+        text
+    }
+}
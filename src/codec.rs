@@ -0,0 +1,372 @@
+//!
+//! Register codec: converts between raw Modbus register words and engineering values.
+//!
+//! Devices disagree about almost everything past a single unsigned register:
+//! 32 bit values come high word first or low word first, some swap the bytes within
+//! each register, temperatures are two's complement, some counters are BCD, and status
+//! registers pack several flags. A `Codec` names the encoding, the byte order and the
+//! scale (engineering units per raw count) so a driver never does that arithmetic inline.
+//!
+//! The examples below decode frames quoted in the device documentation in `doc/`.
+//!
+//! ```
+//! use craven_control::codec::*;
+//!
+//! // N4AIA04 "Read Voltage": 01 03 02 01 4B F9 E3 is 331 counts of 0.01 V
+//! let words = read_response_words(&[0x01, 0x03, 0x02, 0x01, 0x4B, 0xF9, 0xE3]).unwrap();
+//! assert_eq!(words, [0x014B]);
+//! let volts = Codec::new(Encoding::U16).scaled(0.01).decode(&words).unwrap();
+//! assert!((volts - 3.31).abs() < 1e-9);
+//!
+//! // N4AIA04 "Read Current": 01 03 02 00 78 B8 66 is 120 counts of 0.1 mA
+//! let words = read_response_words(&[0x01, 0x03, 0x02, 0x00, 0x78, 0xB8, 0x66]).unwrap();
+//! let milliamps = Codec::new(Encoding::U16).scaled(0.1).decode(&words).unwrap();
+//! assert!((milliamps - 12.0).abs() < 1e-9);
+//!
+//! // LC 4 channel relay "Read baud rate": FF 03 02 00 04 90 53 is code 4, 19200 baud
+//! let words = read_response_words(&[0xFF, 0x03, 0x02, 0x00, 0x04, 0x90, 0x53]).unwrap();
+//! assert_eq!(Codec::new(Encoding::U16).decode(&words).unwrap(), 4.);
+//!
+//! // LC 4 channel relay "Read relay status": FF 01 01 01 A1 A0 has bit 0 (relay 1) on
+//! let payload = read_response_payload(&[0xFF, 0x01, 0x01, 0x01, 0xA1, 0xA0]).unwrap();
+//! let relay_1 = Codec::new(Encoding::Bits(0, 1)).decode(&[payload[0] as u16]).unwrap();
+//! let relay_2 = Codec::new(Encoding::Bits(1, 1)).decode(&[payload[0] as u16]).unwrap();
+//! assert_eq!((relay_1, relay_2), (1., 0.));
+//!
+//! // A corrupted frame is refused
+//! assert!(read_response_words(&[0x01, 0x03, 0x02, 0x01, 0x4B, 0xF8, 0xE3]).is_err());
+//! ```
+//!
+
+/// Where the bytes of a value land across its registers, in address order,
+/// naming the most significant byte `A` and the least significant `D`.
+/// Single register values only distinguish `AB` (big endian) from `BA` (byte swapped).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ByteOrder {
+    /// `ABCD`: high word first, high byte first (the Modbus convention)
+    BigEndian,
+    /// `CDAB`: low word first, high byte first
+    WordSwapped,
+    /// `BADC`: high word first, low byte first
+    ByteSwapped,
+    /// `DCBA`: low word first, low byte first
+    LittleEndian,
+}
+
+/// How a raw value is stored
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    /// A single coil or discrete input, or any nonzero register
+    Bool,
+    U16,
+    /// Two's complement
+    I16,
+    /// Two registers
+    U32,
+    /// Two registers, two's complement
+    I32,
+    /// Two registers, IEEE 754 single precision
+    F32,
+    /// Four binary coded decimal digits in one register
+    Bcd16,
+    /// Eight binary coded decimal digits in two registers
+    Bcd32,
+    /// An unsigned field of one register: (lowest bit, width in bits)
+    Bits(u8, u8),
+}
+
+/// A complete description of how to read and write one value
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Codec {
+    pub encoding: Encoding,
+    pub byte_order: ByteOrder,
+    /// Engineering units per raw count, so 0.1 for a register counting tenths
+    pub scale: f64,
+}
+
+impl Encoding {
+    /// Number of 16 bit registers (or coils) the value spans
+    pub const fn word_count(self) -> u16 {
+        match self {
+            Encoding::U32 | Encoding::I32 | Encoding::F32 | Encoding::Bcd32 => 2,
+            _ => 1,
+        }
+    }
+}
+
+/// Assemble two registers, in address order, into a 32 bit value
+///
+/// ```
+/// use craven_control::codec::*;
+///
+/// // 123.456 is 0x42F6E979 as an f32, stored four different ways
+/// assert_eq!(words_to_u32([0x42F6, 0xE979], ByteOrder::BigEndian), 0x42F6E979);
+/// assert_eq!(words_to_u32([0xE979, 0x42F6], ByteOrder::WordSwapped), 0x42F6E979);
+/// assert_eq!(words_to_u32([0xF642, 0x79E9], ByteOrder::ByteSwapped), 0x42F6E979);
+/// assert_eq!(words_to_u32([0x79E9, 0xF642], ByteOrder::LittleEndian), 0x42F6E979);
+/// ```
+pub fn words_to_u32(words: [u16; 2], byte_order: ByteOrder) -> u32 {
+    let (high, low) = match byte_order {
+        ByteOrder::BigEndian => (words[0], words[1]),
+        ByteOrder::WordSwapped => (words[1], words[0]),
+        ByteOrder::ByteSwapped => (words[0].swap_bytes(), words[1].swap_bytes()),
+        ByteOrder::LittleEndian => (words[1].swap_bytes(), words[0].swap_bytes()),
+    };
+    ((high as u32) << 16) | low as u32
+}
+
+/// Split a 32 bit value into two registers, in address order
+///
+/// ```
+/// use craven_control::codec::*;
+///
+/// for order in [ByteOrder::BigEndian, ByteOrder::WordSwapped, ByteOrder::ByteSwapped, ByteOrder::LittleEndian] {
+///     assert_eq!(words_to_u32(u32_to_words(0x12345678, order), order), 0x12345678);
+/// }
+/// assert_eq!(u32_to_words(0x12345678, ByteOrder::WordSwapped), [0x5678, 0x1234]);
+/// ```
+pub fn u32_to_words(bits: u32, byte_order: ByteOrder) -> [u16; 2] {
+    let (high, low) = ((bits >> 16) as u16, bits as u16);
+    match byte_order {
+        ByteOrder::BigEndian => [high, low],
+        ByteOrder::WordSwapped => [low, high],
+        ByteOrder::ByteSwapped => [high.swap_bytes(), low.swap_bytes()],
+        ByteOrder::LittleEndian => [low.swap_bytes(), high.swap_bytes()],
+    }
+}
+
+/// A single register in big endian order, whatever order it arrived in
+fn word_to_u16(word: u16, byte_order: ByteOrder) -> u16 {
+    match byte_order {
+        ByteOrder::BigEndian | ByteOrder::WordSwapped => word,
+        ByteOrder::ByteSwapped | ByteOrder::LittleEndian => word.swap_bytes(),
+    }
+}
+
+/// Decode packed BCD digits, refusing nibbles above 9
+fn bcd_to_u32(bcd: u32, digits: u32) -> Result<u32, String> {
+    let mut value = 0;
+    for digit_idx in (0..digits).rev() {
+        let digit = (bcd >> (4 * digit_idx)) & 0xF;
+        if digit > 9 {
+            return Err(format!("0x{bcd:X} is not BCD"));
+        }
+        value = 10 * value + digit;
+    }
+    Ok(value)
+}
+
+/// Pack a value into BCD digits
+fn u32_to_bcd(mut value: u32, digits: u32) -> u32 {
+    let mut bcd = 0;
+    for digit_idx in 0..digits {
+        bcd |= (value % 10) << (4 * digit_idx);
+        value /= 10;
+    }
+    bcd
+}
+
+impl Codec {
+    /// An unscaled value in the Modbus (big endian) byte order
+    pub const fn new(encoding: Encoding) -> Self {
+        Self { encoding, byte_order: ByteOrder::BigEndian, scale: 1. }
+    }
+
+    pub const fn with_order(self, byte_order: ByteOrder) -> Self {
+        Self { byte_order, ..self }
+    }
+
+    /// Fixed point: engineering units per raw count
+    pub const fn scaled(self, scale: f64) -> Self {
+        Self { scale, ..self }
+    }
+
+    pub const fn word_count(&self) -> u16 {
+        self.encoding.word_count()
+    }
+
+    /// Mask of a bit field, in place, refusing fields that don't fit a register
+    fn field_mask(lsb: u8, width: u8) -> Result<u16, String> {
+        if lsb as u32 + width as u32 > 16 || width == 0 {
+            return Err(format!("bit field {lsb}+{width} doesn't fit a register"));
+        }
+        Ok((((1u32 << width) - 1) << lsb) as u16)
+    }
+
+    /// The raw (unscaled) value held in `words`
+    pub fn decode_raw(&self, words: &[u16]) -> Result<f64, String> {
+        if words.len() < self.word_count() as usize {
+            return Err(format!("{:?} needs {} registers, got {}", self.encoding, self.word_count(), words.len()));
+        }
+        let word = word_to_u16(words[0], self.byte_order);
+        let raw = match self.encoding {
+            Encoding::Bool => (words[0] != 0) as u8 as f64,
+            Encoding::U16 => word as f64,
+            Encoding::I16 => word as i16 as f64,
+            Encoding::Bcd16 => bcd_to_u32(word as u32, 4)? as f64,
+            Encoding::Bits(lsb, width) => ((word & Self::field_mask(lsb, width)?) >> lsb) as f64,
+            Encoding::U32 | Encoding::I32 | Encoding::F32 | Encoding::Bcd32 => {
+                let bits = words_to_u32([words[0], words[1]], self.byte_order);
+                match self.encoding {
+                    Encoding::U32 => bits as f64,
+                    Encoding::I32 => bits as i32 as f64,
+                    Encoding::F32 => f32::from_bits(bits) as f64,
+                    _ => bcd_to_u32(bits, 8)? as f64,
+                }
+            }
+        };
+        Ok(raw)
+    }
+
+    /// The engineering value held in `words`
+    ///
+    /// ```
+    /// use craven_control::codec::*;
+    ///
+    /// // The YK-KTC1202 reports tenths of a degree in two's complement: 0xFF83 is -12.5 °C
+    /// let temperature = Codec::new(Encoding::I16).scaled(0.1);
+    /// assert!((temperature.decode(&[0xFF83]).unwrap() + 12.5).abs() < 1e-9);
+    /// assert!((temperature.decode(&[0x2710]).unwrap() - 1000.).abs() < 1e-9);
+    ///
+    /// let f32_word_swapped = Codec::new(Encoding::F32).with_order(ByteOrder::WordSwapped);
+    /// assert_eq!(f32_word_swapped.decode(&[0x0000, 0xC148]).unwrap(), -12.5);
+    ///
+    /// assert_eq!(Codec::new(Encoding::I32).decode(&[0xFFFF, 0xFFFE]).unwrap(), -2.);
+    /// assert_eq!(Codec::new(Encoding::Bcd16).decode(&[0x1234]).unwrap(), 1234.);
+    /// assert_eq!(Codec::new(Encoding::Bcd32).decode(&[0x0012, 0x3456]).unwrap(), 123456.);
+    /// assert!(Codec::new(Encoding::Bcd16).decode(&[0x12A4]).is_err());
+    /// assert_eq!(Codec::new(Encoding::Bits(4, 3)).decode(&[0b0101_0000]).unwrap(), 5.);
+    /// assert!(Codec::new(Encoding::Bits(16, 1)).decode(&[0xFFFF]).is_err());
+    /// assert!(Codec::new(Encoding::Bits(0, 0)).decode(&[0xFFFF]).is_err());
+    /// assert_eq!(Codec::new(Encoding::U16).with_order(ByteOrder::ByteSwapped).decode(&[0x3412]).unwrap(), 4660.);
+    /// ```
+    pub fn decode(&self, words: &[u16]) -> Result<f64, String> {
+        Ok(self.decode_raw(words)? * self.scale)
+    }
+
+    /// The registers holding an engineering value, refusing values the encoding can't represent
+    ///
+    /// ```
+    /// use craven_control::codec::*;
+    ///
+    /// let temperature = Codec::new(Encoding::I16).scaled(0.1);
+    /// assert_eq!(temperature.encode(-12.5).unwrap(), [0xFF83]);
+    /// assert!(temperature.encode(4000.).is_err());
+    ///
+    /// let microamps = Codec::new(Encoding::U16).scaled(0.001);
+    /// assert_eq!(microamps.encode(20.).unwrap(), [20000]);
+    /// assert!(microamps.encode(-1.).is_err());
+    ///
+    /// let float = Codec::new(Encoding::F32).with_order(ByteOrder::LittleEndian);
+    /// assert_eq!(float.encode(123.456).unwrap(), [0x79E9, 0xF642]);
+    /// assert_eq!(Codec::new(Encoding::Bcd32).encode(123456.).unwrap(), [0x0012, 0x3456]);
+    ///
+    /// // bit fields must fit the register: no shifting bits out of it
+    /// assert_eq!(Codec::new(Encoding::Bits(12, 4)).encode(15.).unwrap(), [0xF000]);
+    /// assert!(Codec::new(Encoding::Bits(12, 8)).encode(1.).is_err());
+    /// assert!(Codec::new(Encoding::Bits(16, 1)).encode(1.).is_err());
+    /// assert!(Codec::new(Encoding::Bits(4, 3)).encode(8.).is_err());
+    /// ```
+    pub fn encode(&self, value: f64) -> Result<Vec<u16>, String> {
+        if let Encoding::Bits(lsb, width) = self.encoding {
+            Self::field_mask(lsb, width)?;
+        }
+        let raw = value / self.scale;
+        let rounded = raw.round();
+        let (min, max) = match self.encoding {
+            Encoding::Bool => (0., 1.),
+            Encoding::U16 => (0., u16::MAX as f64),
+            Encoding::I16 => (i16::MIN as f64, i16::MAX as f64),
+            Encoding::U32 => (0., u32::MAX as f64),
+            Encoding::I32 => (i32::MIN as f64, i32::MAX as f64),
+            Encoding::F32 => (f32::MIN as f64, f32::MAX as f64),
+            Encoding::Bcd16 => (0., 9999.),
+            Encoding::Bcd32 => (0., 99_999_999.),
+            Encoding::Bits(_, width) => (0., ((1u32 << width) - 1) as f64),
+        };
+        if !raw.is_finite() || rounded < min || rounded > max {
+            return Err(format!("{value} is outside the range of a {:?} with scale {}", self.encoding, self.scale));
+        }
+        let single = |word: u16| vec![word_to_u16(word, self.byte_order)];
+        let words = match self.encoding {
+            Encoding::Bool => vec![(rounded != 0.) as u16],
+            Encoding::U16 => single(rounded as u16),
+            Encoding::I16 => single(rounded as i16 as u16),
+            Encoding::Bcd16 => single(u32_to_bcd(rounded as u32, 4) as u16),
+            Encoding::Bits(lsb, _) => single((rounded as u16) << lsb),
+            Encoding::U32 => u32_to_words(rounded as u32, self.byte_order).to_vec(),
+            Encoding::I32 => u32_to_words(rounded as i32 as u32, self.byte_order).to_vec(),
+            Encoding::F32 => u32_to_words((raw as f32).to_bits(), self.byte_order).to_vec(),
+            Encoding::Bcd32 => u32_to_words(u32_to_bcd(rounded as u32, 8), self.byte_order).to_vec(),
+        };
+        Ok(words)
+    }
+
+    /// Replace a bit field within the register's current contents, leaving the other bits alone.
+    /// Other encodings simply replace the register.
+    ///
+    /// ```
+    /// use craven_control::codec::*;
+    ///
+    /// let mode = Codec::new(Encoding::Bits(4, 3));
+    /// assert_eq!(mode.merge(0xFF0F, 5.).unwrap(), 0xFF5F);
+    /// assert!(Codec::new(Encoding::Bits(12, 8)).merge(0xFFFF, 1.).is_err());
+    /// ```
+    pub fn merge(&self, current: u16, value: f64) -> Result<u16, String> {
+        let encoded = self.encode(value)?[0];
+        Ok(match self.encoding {
+            Encoding::Bits(lsb, width) => {
+                let mask = word_to_u16(Self::field_mask(lsb, width)?, self.byte_order);
+                (current & !mask) | encoded
+            }
+            _ => encoded,
+        })
+    }
+}
+
+/// The Modbus RTU CRC-16 of a frame, as transmitted (low byte first)
+///
+/// ```
+/// use craven_control::codec::*;
+///
+/// // LC relay "Turn ON CH_1 Relay": FF 05 00 00 FF 00 99 E4
+/// assert_eq!(modbus_crc16(&[0xFF, 0x05, 0x00, 0x00, 0xFF, 0x00]).to_le_bytes(), [0x99, 0xE4]);
+/// ```
+pub fn modbus_crc16(bytes: &[u8]) -> u16 {
+    let mut crc: u16 = 0xFFFF;
+    for byte in bytes {
+        crc ^= *byte as u16;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0xA001 } else { crc >> 1 };
+        }
+    }
+    crc
+}
+
+/// The data bytes of an RTU read response (function 0x01-0x04), after checking its length and CRC
+pub fn read_response_payload(frame: &[u8]) -> Result<&[u8], String> {
+    let [_node_id, function, byte_count, ..] = frame else {
+        return Err(format!("frame too short: {frame:02X?}"));
+    };
+    if !(0x01..=0x04).contains(function) {
+        return Err(format!("function 0x{function:02X} is not a read"));
+    }
+    let data_end = 3 + *byte_count as usize;
+    if frame.len() != data_end + 2 {
+        return Err(format!("frame length {} doesn't match byte count {byte_count}", frame.len()));
+    }
+    let crc = u16::from_le_bytes([frame[data_end], frame[data_end + 1]]);
+    if crc != modbus_crc16(&frame[..data_end]) {
+        return Err(format!("bad CRC in frame {frame:02X?}"));
+    }
+    Ok(&frame[3..data_end])
+}
+
+/// The registers of an RTU read holding/input registers response (function 0x03 or 0x04)
+pub fn read_response_words(frame: &[u8]) -> Result<Vec<u16>, String> {
+    let payload = read_response_payload(frame)?;
+    if !matches!(frame[1], 0x03 | 0x04) || payload.len() % 2 != 0 {
+        return Err(format!("frame {frame:02X?} doesn't hold registers"));
+    }
+    Ok(payload.chunks(2).map(|pair| u16::from_be_bytes([pair[0], pair[1]])).collect())
+}
@@ -0,0 +1,424 @@
+//!
+//! Full-screen terminal operator console for the controller.
+//!
+//! The console draws on the controlling terminal (`/dev/tty`) from its own thread.
+//! While it runs, the process's stdout and stderr are redirected to a console log file,
+//! so the controller's regular progress output is kept without scribbling over the screen.
+//! Commands typed in the command bar go through the same `OperatorCommand` channel as
+//! every other command source; dangerous ones must be confirmed first.
+//!
+
+use std::collections::VecDeque;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::os::fd::AsRawFd;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use ratatui::crossterm::terminal::{EnterAlternateScreen, LeaveAlternateScreen, disable_raw_mode, enable_raw_mode};
+use ratatui::crossterm::{cursor, execute};
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, List, ListItem, Paragraph, Sparkline};
+use ratatui::{Frame, Terminal, backend::CrosstermBackend};
+use tokio::sync::broadcast::error::TryRecvError;
+use tokio::sync::oneshot;
+
+use crate::INF_INTER_ELECTRODE_OHMS;
+use crate::operator::*;
+use crate::telemetry::{ChargeSnapshot, ControllerEvent, ControllerSnapshot, ElectrodeSnapshot, TelemetryHub};
+
+/// How often the screen is redrawn (and keys are polled)
+const CONSOLE_TICK: Duration = Duration::from_millis(250);
+/// How many events are kept for the event pane
+const MAX_EVENT_LINES: usize = 500;
+
+/// A running console. Dropping it restores the terminal and the process output.
+pub struct ConsoleHandle {
+    stop: Arc<AtomicBool>,
+    thread: Option<std::thread::JoinHandle<()>>,
+    saved_output: Option<SavedOutput>,
+    log_path: PathBuf,
+}
+
+impl Drop for ConsoleHandle {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::SeqCst);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+        if let Some(saved) = self.saved_output.take() {
+            saved.restore();
+            println!("Console output was saved to {:?}", self.log_path);
+        }
+    }
+}
+
+/// Original stdout/stderr, kept while they're redirected to the console log
+struct SavedOutput {
+    stdout_fd: i32,
+    stderr_fd: i32,
+}
+
+impl SavedOutput {
+    /// Point stdout and stderr at the given file, remembering where they went before
+    fn redirect_to(log: &File) -> io::Result<Self> {
+        let _ = io::stdout().flush();
+        let _ = io::stderr().flush();
+        // SAFETY: plain descriptor duplication; the descriptors involved stay open for the process lifetime
+        unsafe {
+            let stdout_fd = libc::dup(libc::STDOUT_FILENO);
+            let stderr_fd = libc::dup(libc::STDERR_FILENO);
+            if stdout_fd < 0 || stderr_fd < 0
+                || libc::dup2(log.as_raw_fd(), libc::STDOUT_FILENO) < 0
+                || libc::dup2(log.as_raw_fd(), libc::STDERR_FILENO) < 0
+            {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(Self { stdout_fd, stderr_fd })
+        }
+    }
+
+    fn restore(self) {
+        let _ = io::stdout().flush();
+        let _ = io::stderr().flush();
+        // SAFETY: restores the descriptors saved by redirect_to
+        unsafe {
+            libc::dup2(self.stdout_fd, libc::STDOUT_FILENO);
+            libc::dup2(self.stderr_fd, libc::STDERR_FILENO);
+            libc::close(self.stdout_fd);
+            libc::close(self.stderr_fd);
+        }
+    }
+}
+
+/// Everything the console thread needs to draw a frame and run commands
+struct ConsoleApp {
+    hub: Arc<TelemetryHub>,
+    commands: CommandSender,
+    events: VecDeque<ControllerEvent>,
+    input: String,
+    /// Command (and the text it came from) waiting for a y/n confirmation
+    confirming: Option<(OperatorCommand, String)>,
+    /// Result of the last command, or other feedback for the operator
+    status: String,
+    /// Commands sent but not yet answered
+    pending: Vec<(String, oneshot::Receiver<CommandReply>)>,
+}
+
+impl ConsoleApp {
+    fn send(&mut self, command: OperatorCommand, text: String) {
+        let (reply, reply_rx) = oneshot::channel();
+        match self.commands.try_send(OperatorRequest { command, source: "console", reply }) {
+            Ok(()) => {
+                self.status = format!("sent: {text}");
+                self.pending.push((text, reply_rx));
+            }
+            Err(e) => self.status = format!("could not send {text:?}: {e}"),
+        }
+    }
+
+    fn poll_replies(&mut self) {
+        let mut still_pending = Vec::new();
+        for (text, mut reply_rx) in self.pending.drain(..) {
+            match reply_rx.try_recv() {
+                Ok(Ok(msg)) => self.status = format!("{text}: {msg}"),
+                Ok(Err(msg)) => self.status = format!("{text} failed: {msg}"),
+                Err(oneshot::error::TryRecvError::Empty) => still_pending.push((text, reply_rx)),
+                Err(oneshot::error::TryRecvError::Closed) => self.status = format!("{text}: no reply"),
+            }
+        }
+        self.pending = still_pending;
+    }
+
+    fn submit_input(&mut self) {
+        let text = std::mem::take(&mut self.input).trim().to_string();
+        if text.is_empty() {
+            return;
+        }
+        match text.parse::<OperatorCommand>() {
+            Ok(command) if command.needs_confirmation() => {
+                self.status = String::new();
+                self.confirming = Some((command, text));
+            }
+            Ok(command) => self.send(command, text),
+            Err(msg) => self.status = msg,
+        }
+    }
+
+    fn complete_input(&mut self) {
+        let prefix = self.input.trim_start();
+        if prefix.contains(' ') {
+            return;
+        }
+        let matches: Vec<&str> = COMMAND_WORDS.iter().copied().filter(|word| word.starts_with(prefix)).collect();
+        match matches.as_slice() {
+            [] => self.status = format!("no command starts with {prefix:?}"),
+            [word] => self.input = word.to_string(),
+            _ => self.status = matches.join(" "),
+        }
+    }
+
+    fn handle_key(&mut self, code: KeyCode, modifiers: KeyModifiers) {
+        if let Some((command, text)) = self.confirming.take() {
+            if matches!(code, KeyCode::Char('y') | KeyCode::Char('Y')) {
+                self.send(command, text);
+            }
+            else {
+                self.status = format!("cancelled: {text}");
+            }
+            return;
+        }
+        match code {
+            KeyCode::Char('c') if modifiers.contains(KeyModifiers::CONTROL) => {
+                self.input = "quit".to_string();
+                self.submit_input();
+            }
+            KeyCode::Char(c) => self.input.push(c),
+            KeyCode::Backspace => { self.input.pop(); }
+            KeyCode::Esc => self.input.clear(),
+            KeyCode::Tab => self.complete_input(),
+            KeyCode::Enter => self.submit_input(),
+            _ => {}
+        }
+    }
+}
+
+fn local_time(epoch_ms: i64) -> String {
+    chrono::DateTime::from_timestamp_millis(epoch_ms)
+        .map(|utc| utc.with_timezone(&chrono::Local).format("%H:%M:%S").to_string())
+        .unwrap_or_default()
+}
+
+fn on_off(value: bool) -> Span<'static> {
+    if value { Span::styled("ON ", Style::default().fg(Color::Green).add_modifier(Modifier::BOLD)) }
+    else { Span::styled("off", Style::default().fg(Color::DarkGray)) }
+}
+
+fn ohms_text(ohms: f32) -> String {
+    if ohms >= INF_INTER_ELECTRODE_OHMS { "open".to_string() } else { format!("{ohms:.3} Ω") }
+}
+
+fn label(text: &'static str) -> Span<'static> {
+    Span::styled(format!("{text:<9}"), Style::default().fg(Color::Cyan))
+}
+
+fn furnace_panel(snapshot: &ControllerSnapshot) -> Paragraph<'static> {
+    let furnace = &snapshot.furnace;
+    let tk = |temp_c: Option<f32>| temp_c.map(|t| format!("{t:.1}")).unwrap_or_else(|| "--".to_string());
+    let lines = vec![
+        Line::from(vec![label("Melt"), Span::raw(format!("{:.1} °C", furnace.measured_temp_c))]),
+        Line::from(vec![label("Probes"), Span::raw(format!("TK1 {}  TK2 {}", tk(furnace.tk1_c), tk(furnace.tk2_c)))]),
+        Line::from(vec![label("Setpoint"), Span::raw(format!("{:.1} °C", furnace.setpoint_c)),
+            Span::styled(if furnace.setpoint_override_c.is_some() { "  override" } else { "" }, Style::default().fg(Color::Yellow))]),
+        Line::from(vec![label("Heater"), on_off(furnace.heater_on),
+            Span::styled(if furnace.heater_forced_off { "  forced off" } else { "" }, Style::default().fg(Color::Yellow))]),
+    ];
+    Paragraph::new(lines).block(Block::bordered().title(" Furnace "))
+}
+
+/// Operator overrides of the drive current, if any
+fn drive_override_text(e: &ElectrodeSnapshot) -> String {
+    let mut text = String::new();
+    if let Some(override_ma) = e.drive_override_ma {
+        text += &format!("  fixed {override_ma:.1}");
+    }
+    if let Some(limit_ma) = e.drive_limit_ma {
+        text += &format!("  limit {limit_ma:.1}");
+    }
+    text
+}
+
+/// Charge delivered in this phase (against its target, if any) and over the run, with the carbon estimate
+fn charge_text(charge: &ChargeSnapshot) -> String {
+    let target = charge.phase_target_coulombs.map_or(String::new(), |target| format!("/{target:.0}"));
+    format!("{:.1}{target} C  run {:.1} C  {:.1} mg C", charge.phase.coulombs, charge.run.coulombs, charge.run_carbon_mg)
+}
+
+fn electrode_panel(snapshot: &ControllerSnapshot) -> Paragraph<'static> {
+    let e = &snapshot.electrodes;
+    let phase_secs = (snapshot.epoch_ms - e.phase_start_ms).max(0) / 1000;
+    let lines = vec![
+        Line::from(vec![label("Phase"), Span::styled(e.drive_phase_name.clone(), Style::default().add_modifier(Modifier::BOLD)),
+            Span::raw(format!("  {}m {:02}s", phase_secs / 60, phase_secs % 60)),
+            Span::styled(if e.phase_clock_paused { "  paused" } else { "" }, Style::default().fg(Color::Yellow))]),
+        Line::from(vec![label("Ordered"), Span::raw(format!("{:.1} mA  (reported {:.1} mA)", e.target_drive_ma, e.reported_drive_ma)),
+            Span::styled(drive_override_text(e), Style::default().fg(Color::Yellow))]),
+        Line::from(vec![label("Measured"), Span::raw(format!("{:.1} mA  {:.3} V", e.measured_ma, e.measured_volts))]),
+        Line::from(vec![label("R"), Span::raw(format!("{}  EWMA {}", ohms_text(e.measured_ohms), ohms_text(e.ohms_ewma)))]),
+        Line::from(vec![label("MinR"), Span::raw(format!("LV {}  HV {}", ohms_text(e.lowv_minr_ohms), ohms_text(e.highv_minr_ohms)))]),
+        Line::from(vec![label("Charge"), Span::raw(charge_text(&snapshot.charge))]),
+    ];
+    Paragraph::new(lines).block(Block::bordered().title(" Electrode drive "))
+}
+
+fn dipper_relay_panel(snapshot: &ControllerSnapshot) -> Paragraph<'static> {
+    let dipper = &snapshot.dipper;
+    let mut anodes: Vec<Span> = vec![label("Anodes")];
+    let failed = snapshot.anode_check.as_ref().map_or(0, |check| check.failed_mask());
+    for (idx, connected) in snapshot.relays.anodes.iter().enumerate() {
+        let style = 
+            if *connected { Style::default().fg(Color::Black).bg(Color::Green) } 
+            else if failed & (1 << idx) != 0 { Style::default().fg(Color::Red) }
+            else { Style::default().fg(Color::DarkGray) };
+        anodes.push(Span::styled(format!("{} ", idx + 1), style));
+        anodes.push(Span::raw(" "));
+    }
+    if snapshot.relays.anode_override {
+        anodes.push(Span::styled(" override", Style::default().fg(Color::Yellow)));
+    }
+    else if !snapshot.relays.anode_pattern.is_empty() {
+        anodes.push(Span::styled(format!(" {}", snapshot.relays.anode_pattern), Style::default().fg(Color::DarkGray)));
+    }
+    let faults = if snapshot.fault_names.is_empty() {
+        Span::styled("none", Style::default().fg(Color::Green))
+    }
+    else {
+        Span::styled(snapshot.fault_names.join(" "), Style::default().fg(Color::White).bg(Color::Red))
+    };
+    let (requests, failures) = snapshot.bus.values()
+        .fold((0, 0), |(req, fail), c| (req + c.requests, fail + c.timeouts + c.transport_errors + c.exceptions));
+    let lines = vec![
+        Line::from(vec![label("Dipper"), on_off(dipper.enabled),
+            Span::raw(dipper.position_mm.map_or("  not homed".to_string(), |position_mm| format!("  {position_mm:.1} mm"))),
+            Span::raw(if dipper.contact.is_wetted() { format!("  {}", dipper.contact) } else { String::new() })]),
+        Line::from(vec![label("Stepper"), Span::raw(format!("dir {} pulses {} actions {}",
+            if dipper.motion_direction == 0 { "fwd" } else { "rev" }, dipper.pulse_count, dipper.action_count))]),
+        Line::from(anodes),
+        Line::from(vec![label("Faults"), faults]),
+        Line::from(vec![label("Modbus"), Span::raw(format!("{requests} requests, {failures} failures"))]),
+    ];
+    Paragraph::new(lines).block(Block::bordered().title(" Dipper & relays "))
+}
+
+/// Sparkline bars for values, offset so the smallest visible value sits at the baseline
+fn sparkline_bars(values: &[f32], scale: f32) -> (Vec<u64>, f32, f32) {
+    let lo = values.iter().copied().fold(f32::MAX, f32::min);
+    let hi = values.iter().copied().fold(f32::MIN, f32::max);
+    if values.is_empty() {
+        return (vec![], 0., 0.);
+    }
+    let bars = values.iter().map(|v| ((v - lo) * scale) as u64 + 1).collect();
+    (bars, lo, hi)
+}
+
+fn draw_sparkline(frame: &mut Frame, area: Rect, title: &str, unit: &str, values: &[f32], scale: f32, color: Color) {
+    let (bars, lo, hi) = sparkline_bars(values, scale);
+    let title = if bars.is_empty() { format!(" {title} ") } else { format!(" {title} {lo:.2}..{hi:.2} {unit} ") };
+    let sparkline = Sparkline::default()
+        .block(Block::bordered().title(title))
+        .data(&bars)
+        .style(Style::default().fg(color));
+    frame.render_widget(sparkline, area);
+}
+
+fn draw(frame: &mut Frame, app: &ConsoleApp) {
+    let [panels_area, charts_area, events_area, command_area] = Layout::vertical([
+        Constraint::Length(8), Constraint::Length(7), Constraint::Min(4), Constraint::Length(4),
+    ]).areas(frame.area());
+
+    let snapshot = app.hub.latest().unwrap_or_default();
+    let [furnace_area, electrode_area, dipper_area] = Layout::horizontal([
+        Constraint::Ratio(1, 3), Constraint::Ratio(1, 3), Constraint::Ratio(1, 3),
+    ]).areas(panels_area);
+    frame.render_widget(furnace_panel(&snapshot), furnace_area);
+    frame.render_widget(electrode_panel(&snapshot), electrode_area);
+    frame.render_widget(dipper_relay_panel(&snapshot), dipper_area);
+
+    let [temp_area, ohms_area] = Layout::horizontal([Constraint::Ratio(1, 2), Constraint::Ratio(1, 2)]).areas(charts_area);
+    let samples = app.hub.recent_samples(None, temp_area.width.saturating_sub(2) as usize);
+    let temps: Vec<f32> = samples.iter().map(|s| s.avg_temp_c).collect();
+    let ohms: Vec<f32> = samples.iter().map(|s| s.ohms_ewma).filter(|r| *r < INF_INTER_ELECTRODE_OHMS).collect();
+    draw_sparkline(frame, temp_area, "Temperature", "°C", &temps, 10., Color::Red);
+    draw_sparkline(frame, ohms_area, "Resistance EWMA", "Ω", &ohms, 1000., Color::Magenta);
+
+    let visible = events_area.height.saturating_sub(2) as usize;
+    let items: Vec<ListItem> = app.events.iter().rev().take(visible).rev().map(|event| {
+        let color = match event.kind {
+            "fault" => Color::Red,
+            "phase" => Color::Yellow,
+            "command" => Color::Cyan,
+            "note" => Color::Magenta,
+            _ => Color::Reset,
+        };
+        ListItem::new(Line::from(vec![
+            Span::raw(format!("{} ", local_time(event.epoch_ms))),
+            Span::styled(format!("{:<9}", event.kind), Style::default().fg(color)),
+            Span::raw(event.message.clone()),
+        ]))
+    }).collect();
+    frame.render_widget(List::new(items).block(Block::bordered().title(" Events ")), events_area);
+
+    let prompt = match &app.confirming {
+        Some((_, text)) => Line::from(Span::styled(format!("Really send {text:?}? (y/n)"),
+            Style::default().fg(Color::Black).bg(Color::Yellow))),
+        None => Line::from(vec![Span::raw("> "), Span::raw(app.input.clone()), Span::styled("_", Style::default().add_modifier(Modifier::SLOW_BLINK))]),
+    };
+    let command_bar = Paragraph::new(vec![prompt, Line::from(Span::styled(app.status.clone(), Style::default().fg(Color::Gray)))])
+        .block(Block::bordered().title(" Command: setpoint furnace current limit anodes pause resume jog note get dip warmup ... quit  (Tab lists and completes) "));
+    frame.render_widget(command_bar, command_area);
+}
+
+fn run_console(terminal: &mut Terminal<CrosstermBackend<File>>, app: &mut ConsoleApp, stop: &AtomicBool) -> io::Result<()> {
+    let mut events = app.hub.subscribe_events();
+    while !stop.load(Ordering::SeqCst) {
+        loop {
+            match events.try_recv() {
+                Ok(event) => {
+                    if app.events.len() == MAX_EVENT_LINES {
+                        app.events.pop_front();
+                    }
+                    app.events.push_back(event);
+                }
+                Err(TryRecvError::Lagged(_)) => continue,
+                Err(_) => break,
+            }
+        }
+        app.poll_replies();
+        terminal.draw(|frame| draw(frame, app))?;
+
+        if event::poll(CONSOLE_TICK)? && let Event::Key(key) = event::read()? && key.kind == KeyEventKind::Press {
+            app.handle_key(key.code, key.modifiers);
+        }
+    }
+    Ok(())
+}
+
+/// Take over the terminal with the operator console.
+/// stdout and stderr are appended to `log_path` until the returned handle is dropped.
+pub fn start_console(hub: Arc<TelemetryHub>, commands: CommandSender, log_path: &Path)
+-> Result<ConsoleHandle, Box<dyn std::error::Error>>
+{
+    let tty = OpenOptions::new().read(true).write(true).open("/dev/tty")
+        .map_err(|e| format!("console needs a terminal: {e}"))?;
+    let log = OpenOptions::new().create(true).append(true).open(log_path)?;
+
+    let mut terminal = Terminal::new(CrosstermBackend::new(tty))?;
+    enable_raw_mode()?;
+    execute!(terminal.backend_mut(), EnterAlternateScreen, cursor::Hide)?;
+    let saved_output = SavedOutput::redirect_to(&log)?;
+
+    let stop = Arc::new(AtomicBool::new(false));
+    let thread_stop = stop.clone();
+    let thread = std::thread::Builder::new().name("console".to_string()).spawn(move || {
+        let mut app = ConsoleApp {
+            hub, commands,
+            events: VecDeque::new(),
+            input: String::new(),
+            confirming: None,
+            status: String::new(),
+            pending: Vec::new(),
+        };
+        if let Err(e) = run_console(&mut terminal, &mut app, &thread_stop) {
+            eprintln!("console failed: {e}");
+        }
+        let _ = disable_raw_mode();
+        let _ = execute!(terminal.backend_mut(), LeaveAlternateScreen, cursor::Show);
+    })?;
+
+    Ok(ConsoleHandle { stop, thread: Some(thread), saved_output: Some(saved_output), log_path: log_path.to_path_buf() })
+}
@@ -0,0 +1,269 @@
+//!
+//! Detecting contact between the cathode tip and the melt surface.
+//!
+//! A single current threshold chatters as the tip hovers at the surface, and can't tell
+//! a meniscus (melt wetting the tip and clinging to it, a thin high-resistance neck) from
+//! the tip actually being in the melt. `ContactDetector` classifies each electrode
+//! measurement as open, meniscus or contact:
+//! - current must rise above the make threshold to leave open, and fall below the (lower)
+//!   break threshold to return to it, both as fractions of the drive current
+//! - while conducting, resistance decides between meniscus and contact, again with a gap
+//!   between the thresholds for entering and leaving contact
+//! - measurements must differ from the state for the dwell time before it changes
+//!
+//! The detector only keeps timestamps: it is fed one measurement per control loop and never waits.
+//!
+
+use serde::Serialize;
+
+/// Whether, and how, the cathode tip touches the melt
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ContactState {
+    /// No current path through the melt
+    #[default]
+    Open,
+    /// Current flows through a thin wetting neck of melt: the tip is at the surface, not in it
+    Meniscus,
+    /// The tip is in the melt
+    Contact,
+}
+
+impl ContactState {
+    pub fn name(&self) -> &'static str {
+        match self {
+            ContactState::Open => "open",
+            ContactState::Meniscus => "meniscus",
+            ContactState::Contact => "contact",
+        }
+    }
+
+    /// Whether melt touches the tip at all
+    pub fn is_wetted(&self) -> bool {
+        *self != ContactState::Open
+    }
+}
+
+impl std::fmt::Display for ContactState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.pad(self.name())
+    }
+}
+
+/// Thresholds and timing for contact detection
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ContactThresholds {
+    /// Fraction of the drive current that must flow to make contact
+    pub make_fraction: f32,
+    /// Fraction of the drive current below which contact is broken
+    pub break_fraction: f32,
+    /// Highest resistance counted as the tip entering the melt
+    pub contact_max_ohms: f32,
+    /// Lowest resistance counted as the tip leaving the melt for a meniscus
+    pub meniscus_min_ohms: f32,
+    /// How long a new classification must persist before the state changes
+    pub dwell_ms: i64,
+}
+
+impl Default for ContactThresholds {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+impl ContactThresholds {
+    /// Starting points, to be tuned against the rig: the make threshold matches the
+    /// old single threshold of 1/20 of the drive current
+    pub const DEFAULT: ContactThresholds = ContactThresholds {
+        make_fraction: 0.05,
+        break_fraction: 0.025,
+        contact_max_ohms: 20.,
+        meniscus_min_ohms: 40.,
+        dwell_ms: 2000,
+    };
+}
+
+/// A change of contact state, and the measurement that confirmed it
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ContactTransition {
+    pub from: ContactState,
+    pub to: ContactState,
+    pub epoch_ms: i64,
+    pub measured_ma: f32,
+    pub measured_ohms: f32,
+}
+
+impl std::fmt::Display for ContactTransition {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} -> {} at {:.3} mA", self.from, self.to, self.measured_ma)?;
+        if self.to.is_wetted() {
+            write!(f, ", {:.2} Ω", self.measured_ohms)?;
+        }
+        Ok(())
+    }
+}
+
+/// Debounced, hysteretic contact state from a stream of electrode measurements
+#[derive(Debug, Clone, PartialEq)]
+pub struct ContactDetector {
+    pub thresholds: ContactThresholds,
+    state: ContactState,
+    /// When the current state was entered
+    since_ms: i64,
+    /// The latest classification differing from the state, and when measurements first differed
+    pending: Option<(ContactState, i64)>,
+}
+
+impl Default for ContactDetector {
+    fn default() -> Self {
+        Self::new(ContactThresholds::DEFAULT)
+    }
+}
+
+impl ContactDetector {
+    pub const fn new(thresholds: ContactThresholds) -> Self {
+        Self { thresholds, state: ContactState::Open, since_ms: 0, pending: None }
+    }
+
+    pub fn state(&self) -> ContactState {
+        self.state
+    }
+
+    /// When the current state was entered
+    pub fn since_ms(&self) -> i64 {
+        self.since_ms
+    }
+
+    /// A different state seen but not yet held for the dwell time
+    pub fn pending(&self) -> Option<ContactState> {
+        self.pending.map(|(state, _)| state)
+    }
+
+    /// Forget the state, e.g. when the dipper monitor restarts
+    pub fn reset(&mut self) {
+        *self = Self::new(self.thresholds);
+    }
+
+    /// What a single measurement looks like from the current state, before any dwell
+    pub fn classify(&self, drive_ma: f32, measured_ma: f32, measured_ohms: f32) -> ContactState {
+        let limits = &self.thresholds;
+        let conducting = match self.state {
+            ContactState::Open => measured_ma > drive_ma * limits.make_fraction,
+            _ => measured_ma >= drive_ma * limits.break_fraction,
+        };
+        if !conducting || drive_ma <= 0. {
+            return ContactState::Open;
+        }
+        let in_melt = match self.state {
+            ContactState::Contact => measured_ohms < limits.meniscus_min_ohms,
+            _ => measured_ohms <= limits.contact_max_ohms,
+        };
+        if in_melt { ContactState::Contact } else { ContactState::Meniscus }
+    }
+
+    /// Take a measurement, returning the state change it confirms, if any
+    pub fn update(&mut self, epoch_ms: i64, drive_ma: f32, measured_ma: f32, measured_ohms: f32)
+    -> Option<ContactTransition>
+    {
+        let candidate = self.classify(drive_ma, measured_ma, measured_ohms);
+        if candidate == self.state {
+            self.pending = None;
+            return None;
+        }
+        // the dwell runs for as long as measurements differ from the state, and the latest wins:
+        // a tip wetting while it settles from meniscus to contact still changes state on time
+        let first_seen_ms = self.pending.map_or(epoch_ms, |(_, first_seen_ms)| first_seen_ms);
+        self.pending = Some((candidate, first_seen_ms));
+        if epoch_ms - first_seen_ms < self.thresholds.dwell_ms {
+            return None;
+        }
+        let transition = ContactTransition { from: self.state, to: candidate, epoch_ms, measured_ma, measured_ohms };
+        self.state = candidate;
+        self.since_ms = epoch_ms;
+        self.pending = None;
+        Some(transition)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DRIVE_MA: f32 = 100.;
+    const DWELL_MS: i64 = ContactThresholds::DEFAULT.dwell_ms;
+
+    /// A detector that has held a measurement for the dwell time, from open
+    fn settled(measured_ma: f32, measured_ohms: f32) -> ContactDetector {
+        let mut detector = ContactDetector::default();
+        detector.update(0, DRIVE_MA, measured_ma, measured_ohms);
+        detector.update(DWELL_MS, DRIVE_MA, measured_ma, measured_ohms);
+        detector
+    }
+
+    #[test]
+    fn no_transition_before_dwell() {
+        let mut detector = ContactDetector::default();
+        assert_eq!(detector.update(1000, DRIVE_MA, 50., 10.), None);
+        assert_eq!(detector.pending(), Some(ContactState::Contact));
+        assert_eq!(detector.update(1000 + DWELL_MS - 1, DRIVE_MA, 50., 10.), None);
+        assert_eq!(detector.state(), ContactState::Open);
+
+        let transition = detector.update(1000 + DWELL_MS, DRIVE_MA, 50., 10.).unwrap();
+        assert_eq!((transition.from, transition.to), (ContactState::Open, ContactState::Contact));
+        assert_eq!(detector.state(), ContactState::Contact);
+        assert_eq!(detector.since_ms(), 1000 + DWELL_MS);
+        assert_eq!(detector.pending(), None);
+    }
+
+    #[test]
+    fn measurement_matching_the_state_restarts_the_dwell() {
+        let mut detector = ContactDetector::default();
+        detector.update(0, DRIVE_MA, 50., 10.);
+        detector.update(DWELL_MS - 1, DRIVE_MA, 0., f32::INFINITY);
+        assert_eq!(detector.pending(), None);
+        assert_eq!(detector.update(DWELL_MS, DRIVE_MA, 50., 10.), None);
+        assert_eq!(detector.state(), ContactState::Open);
+    }
+
+    #[test]
+    fn chatter_between_break_and_make_stays_open() {
+        // 5 mA makes contact and 2.5 mA breaks it at 100 mA drive: anything between leaves an open tip open
+        let mut detector = ContactDetector::default();
+        for step in 0..20 {
+            let measured_ma = if step % 2 == 0 { 2.6 } else { 5. };
+            assert_eq!(detector.update(step * 1000, DRIVE_MA, measured_ma, 30.), None);
+        }
+        assert_eq!(detector.state(), ContactState::Open);
+        assert_eq!(detector.pending(), None);
+
+        // ... and a wetted tip wetted, down to the break threshold
+        let mut detector = settled(50., 30.);
+        assert_eq!(detector.state(), ContactState::Meniscus);
+        for step in 1..20 {
+            assert_eq!(detector.update(DWELL_MS + step * 1000, DRIVE_MA, 2.5, 30.), None);
+        }
+        assert_eq!(detector.state(), ContactState::Meniscus);
+    }
+
+    #[test]
+    fn meniscus_and_contact_cross_their_own_thresholds() {
+        let limits = ContactThresholds::DEFAULT;
+        let meniscus = settled(50., 30.);
+        assert_eq!(meniscus.state(), ContactState::Meniscus);
+        assert_eq!(meniscus.classify(DRIVE_MA, 50., limits.contact_max_ohms + 0.1), ContactState::Meniscus);
+        assert_eq!(meniscus.classify(DRIVE_MA, 50., limits.contact_max_ohms), ContactState::Contact);
+
+        let contact = settled(50., 10.);
+        assert_eq!(contact.state(), ContactState::Contact);
+        assert_eq!(contact.classify(DRIVE_MA, 50., limits.meniscus_min_ohms - 0.1), ContactState::Contact);
+        assert_eq!(contact.classify(DRIVE_MA, 50., limits.meniscus_min_ohms), ContactState::Meniscus);
+    }
+
+    #[test]
+    fn no_drive_is_open() {
+        let contact = settled(50., 10.);
+        assert_eq!(contact.classify(0., 50., 10.), ContactState::Open);
+        assert_eq!(contact.classify(-1., 50., 10.), ContactState::Open);
+        assert_eq!(ContactDetector::default().classify(0., 50., 10.), ContactState::Open);
+    }
+}
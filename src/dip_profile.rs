@@ -0,0 +1,403 @@
+//!
+//! Recipe-defined dip profiles: cathode immersion depth over time.
+//!
+//! A profile is a list of steps, each run until a condition keyed on position, time or
+//! measured current: find the melt surface by descending until current flows, immerse
+//! to a depth below that surface, hold, withdraw at a controlled rate, and repeat.
+//! Withdrawal rates can be far below the slowest speed the motor turns smoothly
+//! (deposit growth is a fraction of a mm per minute), so moving steps track a setpoint
+//! that advances at the profile rate, and the dipper is stepped to it in short measured moves.
+//!
+//! Profiles are JSON files, for example:
+//! ```json
+//! { "name": "slow pull",
+//!   "steps": [
+//!     { "find_surface": { "mm_per_min": 30, "contact_ma": 5, "max_travel_mm": 40 } },
+//!     { "move_to": { "depth_mm": 3 } },
+//!     { "hold": { "until": { "time_secs": 120 } } },
+//!     { "travel": { "mm_per_min": -0.5, "until": { "current_below_ma": 5 } } }
+//!   ],
+//!   "repeat_from": 1, "cycles": 0 }
+//! ```
+//! Depths are in mm below the surface found by the last `find_surface` step; positions in mm below home.
+//! Travel rates are positive down into the melt. `cycles` 0 repeats until the dipper is disabled.
+//!
+//! The runner keeps the control loop going: `tick` is called once per control loop with the latest
+//! measured current, and makes at most one move, of no longer than `DIP_TICK_MOVE_SECS` at the
+//! axis move speed, before it returns. Longer moves, including `move_to` at full speed, carry on
+//! over later ticks.
+//!
+
+use std::path::Path;
+
+use serde::Deserialize;
+
+use crate::dipper::*;
+use crate::smc05::*;
+
+/// Longest a single tick's move may take at the axis move speed, as the control loop waits on it
+pub const DIP_TICK_MOVE_SECS: f32 = 1.;
+
+/// When a step ends
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DipCondition {
+    /// Seconds since the step started
+    TimeSecs(f32),
+    /// Reaching a position, in mm below home
+    PositionMm(f32),
+    /// Reaching a depth, in mm below the melt surface
+    DepthMm(f32),
+    /// Measured electrode current rising above this
+    CurrentAboveMa(f32),
+    /// Measured electrode current falling below this (e.g. the cathode leaving the melt)
+    CurrentBelowMa(f32),
+}
+
+impl std::fmt::Display for DipCondition {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DipCondition::TimeSecs(secs) => write!(f, "{secs} s"),
+            DipCondition::PositionMm(mm) => write!(f, "position {mm:.2} mm"),
+            DipCondition::DepthMm(mm) => write!(f, "depth {mm:.2} mm"),
+            DipCondition::CurrentAboveMa(ma) => write!(f, "current > {ma:.2} mA"),
+            DipCondition::CurrentBelowMa(ma) => write!(f, "current < {ma:.2} mA"),
+        }
+    }
+}
+
+/// One step of a dip profile
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DipStep {
+    /// Descend until the measured current shows contact, and take that as the melt surface
+    FindSurface { mm_per_min: f32, contact_ma: f32, max_travel_mm: f32 },
+    /// Move to a depth below the surface (negative is above it), at a rate or at full move speed
+    MoveTo { depth_mm: f32, mm_per_min: Option<f32> },
+    /// Stay still
+    Hold { until: DipCondition },
+    /// Move at a steady rate, positive down, negative withdrawing
+    Travel { mm_per_min: f32, until: DipCondition },
+}
+
+impl std::fmt::Display for DipStep {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DipStep::FindSurface { mm_per_min, contact_ma, .. } =>
+                write!(f, "find surface at {mm_per_min} mm/min until current > {contact_ma:.2} mA"),
+            DipStep::MoveTo { depth_mm, mm_per_min: Some(rate) } => write!(f, "move to depth {depth_mm:.2} mm at {rate} mm/min"),
+            DipStep::MoveTo { depth_mm, mm_per_min: None } => write!(f, "move to depth {depth_mm:.2} mm"),
+            DipStep::Hold { until } => write!(f, "hold until {until}"),
+            DipStep::Travel { mm_per_min, until } => write!(f, "travel {mm_per_min} mm/min until {until}"),
+        }
+    }
+}
+
+/// A named sequence of dip steps, optionally repeated
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct DipProfile {
+    #[serde(default)]
+    pub name: String,
+    pub steps: Vec<DipStep>,
+    /// First step of the repeated part: steps before it run once
+    #[serde(default)]
+    pub repeat_from: usize,
+    /// How many times the repeated part runs; 0 runs it until the dipper is disabled
+    #[serde(default = "one_cycle")]
+    pub cycles: u32,
+}
+
+fn one_cycle() -> u32 { 1 }
+
+impl DipProfile {
+    /// Read and check a profile from a JSON file
+    pub fn load(path: &Path) -> Result<Self, Box<dyn std::error::Error>> {
+        let text = std::fs::read_to_string(path)?;
+        let profile: DipProfile = serde_json::from_str(&text).map_err(|e| format!("{}: {e}", path.display()))?;
+        profile.validate().map_err(|e| format!("{}: {e}", path.display()))?;
+        Ok(profile)
+    }
+
+    /// Reject profiles that can't run: empty, bad rates, or depths with no surface to measure them from
+    pub fn validate(&self) -> Result<(), String> {
+        if self.steps.is_empty() {
+            return Err("dip profile has no steps".into());
+        }
+        if self.repeat_from >= self.steps.len() {
+            return Err(format!("repeat_from {} is past the last of {} steps", self.repeat_from, self.steps.len()));
+        }
+        let mut surface_found = false;
+        for (idx, step) in self.steps.iter().enumerate() {
+            let step_err = |reason: &str| format!("step {} ({step}): {reason}", idx + 1);
+            let (rate, until) = match *step {
+                DipStep::FindSurface { mm_per_min, max_travel_mm, .. } => {
+                    if !(max_travel_mm.is_finite() && max_travel_mm > 0.) {
+                        return Err(step_err("max_travel_mm must be positive"));
+                    }
+                    if !(mm_per_min.is_finite() && mm_per_min > 0.) {
+                        return Err(step_err("the surface is found moving down: mm_per_min must be positive"));
+                    }
+                    surface_found = true;
+                    (Some(mm_per_min), None)
+                }
+                DipStep::MoveTo { mm_per_min, .. } => {
+                    if !surface_found {
+                        return Err(step_err("depth before any find_surface step"));
+                    }
+                    (mm_per_min, None)
+                }
+                DipStep::Hold { until } => (None, Some(until)),
+                DipStep::Travel { mm_per_min, until } => (Some(mm_per_min), Some(until)),
+            };
+            if let Some(rate) = rate && !(rate.is_finite() && rate != 0.) {
+                return Err(step_err("rate must be finite and non-zero"));
+            }
+            match until {
+                Some(DipCondition::DepthMm(_)) if !surface_found =>
+                    return Err(step_err("depth before any find_surface step")),
+                Some(DipCondition::TimeSecs(secs)) if !(secs.is_finite() && secs >= 0.) =>
+                    return Err(step_err("time must not be negative")),
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Progress through a dip profile
+#[derive(Debug, Clone, PartialEq)]
+pub struct DipProfileRun {
+    pub profile: DipProfile,
+    step_idx: usize,
+    /// Completed passes through the repeated part
+    cycles_done: u32,
+    /// When the current step started, and where the dipper was; `None` until it starts
+    step_start: Option<(i64, f32)>,
+    /// Position of the melt surface found by the last `find_surface` step
+    surface_mm: Option<f32>,
+    finished: bool,
+}
+
+impl DipProfileRun {
+    pub fn new(profile: DipProfile) -> Self {
+        Self { profile, step_idx: 0, cycles_done: 0, step_start: None, surface_mm: None, finished: false }
+    }
+
+    /// Start again from the first step
+    pub fn restart(&mut self) {
+        *self = Self::new(self.profile.clone());
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    /// The step now running, numbered from 1, and the step itself
+    pub fn current_step(&self) -> Option<(usize, DipStep)> {
+        if self.finished { None } else { Some((self.step_idx + 1, self.profile.steps[self.step_idx])) }
+    }
+
+    pub fn surface_mm(&self) -> Option<f32> {
+        self.surface_mm
+    }
+
+    fn depth_to_position(&self, depth_mm: f32) -> Result<f32, Box<dyn std::error::Error>> {
+        let surface_mm = self.surface_mm.ok_or("no melt surface found yet")?;
+        Ok(surface_mm + depth_mm)
+    }
+
+    /// Whether `until` holds, for a step that started at `start_mm` and has run `elapsed_secs`
+    fn condition_met(&self, until: DipCondition, tolerance_mm: f32, start_mm: f32, position_mm: f32,
+        elapsed_secs: f32, measured_ma: f32)
+    -> Result<bool, Box<dyn std::error::Error>>
+    {
+        let reached = |target_mm: f32| {
+            // reached from whichever side the step started
+            let heading = if target_mm >= start_mm { 1. } else { -1. };
+            (position_mm - target_mm) * heading >= -tolerance_mm
+        };
+        Ok(match until {
+            DipCondition::TimeSecs(secs) => elapsed_secs >= secs,
+            DipCondition::PositionMm(target_mm) => reached(target_mm),
+            DipCondition::DepthMm(depth_mm) => reached(self.depth_to_position(depth_mm)?),
+            DipCondition::CurrentAboveMa(ma) => measured_ma > ma,
+            DipCondition::CurrentBelowMa(ma) => measured_ma < ma,
+        })
+    }
+
+    /// Step the dipper toward a setpoint if it has fallen behind, by no more than the axis covers
+    /// in `DIP_TICK_MOVE_SECS`: the rest of a longer move is left for later ticks
+    async fn track(ctx: &mut tokio_modbus::client::Context, axis: &mut DipperAxis,
+        setpoint_mm: f32, position_mm: f32)
+    -> Result<(), Box<dyn std::error::Error>>
+    {
+        let tolerance_mm = axis.config.tolerance_mm;
+        let max_step_mm = (axis.config.mm_per_sec(axis.config.move_rpm) * DIP_TICK_MOVE_SECS).max(2. * tolerance_mm);
+        let step_mm = (setpoint_mm - position_mm).clamp(-max_step_mm, max_step_mm);
+        if step_mm.abs() > tolerance_mm {
+            axis.move_to(ctx, position_mm + step_mm).await?;
+        }
+        Ok(())
+    }
+
+    /// Advance the profile: check the running step's end condition and make at most one bounded move.
+    /// Returns false once the profile has finished.
+    pub async fn tick(&mut self, ctx: &mut tokio_modbus::client::Context, axis: &mut DipperAxis,
+        now_ms: i64, measured_ma: f32)
+    -> Result<bool, Box<dyn std::error::Error>>
+    {
+        if self.finished {
+            return Ok(false);
+        }
+        axis.sync(ctx).await?;
+        let position_mm = axis.position_mm().ok_or("home the dipper before running a dip profile")?;
+        let step = self.profile.steps[self.step_idx];
+        let (start_ms, start_mm) = match self.step_start {
+            Some(start) => start,
+            None => {
+                println!("{} Dip profile {:?} step {}/{}: {step} (from {position_mm:.2} mm)",
+                    now_ms, self.profile.name, self.step_idx + 1, self.profile.steps.len());
+                enable_sport_mode03(ctx).await?;
+                stop_smc05_rotation(ctx).await?;
+                *self.step_start.insert((now_ms, position_mm))
+            }
+        };
+        let elapsed_secs = (now_ms - start_ms).max(0) as f32 / 1000.;
+        let tolerance_mm = axis.config.tolerance_mm;
+        let rate_setpoint = |mm_per_min: f32| start_mm + mm_per_min * elapsed_secs / 60.;
+
+        let step_done = match step {
+            DipStep::FindSurface { mm_per_min, contact_ma, max_travel_mm } => {
+                if measured_ma > contact_ma {
+                    println!("{now_ms} Dip profile found the surface at {position_mm:.2} mm ({measured_ma:.2} mA)");
+                    self.surface_mm = Some(position_mm);
+                    true
+                }
+                else if position_mm - start_mm >= max_travel_mm {
+                    return Err(format!("no surface contact within {max_travel_mm:.1} mm of {start_mm:.2} mm").into());
+                }
+                else {
+                    let setpoint_mm = rate_setpoint(mm_per_min).min(start_mm + max_travel_mm);
+                    Self::track(ctx, axis, setpoint_mm, position_mm).await?;
+                    false
+                }
+            }
+            DipStep::MoveTo { depth_mm, mm_per_min: None } => {
+                let target_mm = self.depth_to_position(depth_mm)?;
+                if self.condition_met(DipCondition::PositionMm(target_mm), tolerance_mm, start_mm, position_mm, 0., measured_ma)? {
+                    true
+                }
+                else {
+                    Self::track(ctx, axis, target_mm, position_mm).await?;
+                    false
+                }
+            }
+            DipStep::MoveTo { depth_mm, mm_per_min: Some(mm_per_min) } => {
+                let target_mm = self.depth_to_position(depth_mm)?;
+                if self.condition_met(DipCondition::PositionMm(target_mm), tolerance_mm, start_mm, position_mm, 0., measured_ma)? {
+                    true
+                }
+                else {
+                    // the rate's sign comes from the direction of the target
+                    let heading = (target_mm - start_mm).signum();
+                    let setpoint_mm = rate_setpoint(mm_per_min.abs() * heading);
+                    let setpoint_mm = if heading > 0. { setpoint_mm.min(target_mm) } else { setpoint_mm.max(target_mm) };
+                    Self::track(ctx, axis, setpoint_mm, position_mm).await?;
+                    false
+                }
+            }
+            DipStep::Hold { until } =>
+                self.condition_met(until, tolerance_mm, start_mm, position_mm, elapsed_secs, measured_ma)?,
+            DipStep::Travel { mm_per_min, until } => {
+                if self.condition_met(until, tolerance_mm, start_mm, position_mm, elapsed_secs, measured_ma)? {
+                    true
+                }
+                else {
+                    Self::track(ctx, axis, rate_setpoint(mm_per_min), position_mm).await?;
+                    false
+                }
+            }
+        };
+
+        if step_done {
+            self.step_start = None;
+            self.step_idx += 1;
+            if self.step_idx == self.profile.steps.len() {
+                self.cycles_done += 1;
+                if self.profile.cycles != 0 && self.cycles_done >= self.profile.cycles {
+                    println!("{now_ms} Dip profile {:?} finished after {} cycles", self.profile.name, self.cycles_done);
+                    stop_smc05_rotation(ctx).await?;
+                    self.finished = true;
+                    return Ok(false);
+                }
+                self.step_idx = self.profile.repeat_from;
+                println!("{now_ms} Dip profile {:?} cycle {} done", self.profile.name, self.cycles_done);
+            }
+        }
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn profile(steps: &str, repeat_from: usize) -> DipProfile {
+        serde_json::from_str(&format!(r#"{{ "steps": [{steps}], "repeat_from": {repeat_from} }}"#)).unwrap()
+    }
+
+    const FIND_SURFACE: &str = r#"{ "find_surface": { "mm_per_min": 30, "contact_ma": 5, "max_travel_mm": 40 } }"#;
+
+    #[test]
+    fn module_doc_example_parses() {
+        // the JSON block of the module doc, as written there
+        let source = include_str!("dip_profile.rs");
+        let example: String = source.lines()
+            .skip_while(|line| *line != "//! ```json")
+            .skip(1)
+            .take_while(|line| *line != "//! ```")
+            .map(|line| line.trim_start_matches("//!"))
+            .collect::<Vec<_>>()
+            .join("\n");
+        let profile: DipProfile = serde_json::from_str(&example).unwrap();
+        profile.validate().unwrap();
+        assert_eq!(profile.name, "slow pull");
+        assert_eq!(profile.steps.len(), 4);
+        assert_eq!(profile.steps[1], DipStep::MoveTo { depth_mm: 3., mm_per_min: None });
+        assert_eq!(profile.steps[3], DipStep::Travel { mm_per_min: -0.5, until: DipCondition::CurrentBelowMa(5.) });
+        assert_eq!((profile.repeat_from, profile.cycles), (1, 0));
+    }
+
+    #[test]
+    fn cycles_default_to_one() {
+        assert_eq!(profile(FIND_SURFACE, 0).cycles, 1);
+    }
+
+    #[test]
+    fn depth_needs_a_surface_first() {
+        let move_first = profile(&format!(r#"{{ "move_to": {{ "depth_mm": 3 }} }}, {FIND_SURFACE}"#), 0);
+        assert!(move_first.validate().unwrap_err().contains("depth before any find_surface"));
+        let until_depth = profile(r#"{ "travel": { "mm_per_min": 1, "until": { "depth_mm": 2 } } }"#, 0);
+        assert!(until_depth.validate().unwrap_err().contains("depth before any find_surface"));
+        let after = profile(&format!(r#"{FIND_SURFACE}, {{ "travel": {{ "mm_per_min": 1, "until": {{ "depth_mm": 2 }} }} }}"#), 0);
+        assert_eq!(after.validate(), Ok(()));
+    }
+
+    #[test]
+    fn rates_must_be_non_zero() {
+        let zero_travel = profile(r#"{ "travel": { "mm_per_min": 0, "until": { "time_secs": 5 } } }"#, 0);
+        assert!(zero_travel.validate().unwrap_err().contains("non-zero"));
+        let zero_move = profile(&format!(r#"{FIND_SURFACE}, {{ "move_to": {{ "depth_mm": 3, "mm_per_min": 0 }} }}"#), 0);
+        assert!(zero_move.validate().unwrap_err().contains("non-zero"));
+        let zero_find = profile(r#"{ "find_surface": { "mm_per_min": 0, "contact_ma": 5, "max_travel_mm": 40 } }"#, 0);
+        assert!(zero_find.validate().unwrap_err().contains("must be positive"));
+    }
+
+    #[test]
+    fn repeat_from_must_be_a_step() {
+        let two_steps = format!(r#"{FIND_SURFACE}, {{ "hold": {{ "until": {{ "time_secs": 5 }} }} }}"#);
+        assert_eq!(profile(&two_steps, 1).validate(), Ok(()));
+        assert!(profile(&two_steps, 2).validate().unwrap_err().contains("repeat_from 2"));
+        assert!(profile("", 0).validate().unwrap_err().contains("no steps"));
+    }
+}
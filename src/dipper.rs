@@ -0,0 +1,333 @@
+//!
+//! Absolute position of the cathode dipper, integrated from the SMC05 pulse counter.
+//!
+//! The SMC05 has no position register: it counts the pulses it sends (in a 16 bit register that wraps)
+//! and reports which way it is turning. `DipperAxis` integrates the change in that count, signed by
+//! the direction, into pulses travelled since a reference, and so into millimetres below home.
+//! Positive positions are down, toward the melt, matching the controller's forward direction.
+//!
+//! The axis must be polled (`sync`) at least once per 32768 pulses for wraps to be told apart
+//! from the counter restarting, and before every change of direction, since the counter
+//! doesn't say which direction its pulses went.
+//!
+//! Homing drives up slowly until the controller's limit input stops the motor, or takes
+//! a known reference (the tip touching a gauge block, say) from the operator. Until the axis is
+//! homed there is no absolute position, and so no soft limits and no absolute moves.
+//!
+
+use crate::*;
+use crate::smc05::*;
+
+/// Full steps per revolution of the dipper motor (1.8° per step), without microstepping
+pub const DIPPER_DEFAULT_PULSES_PER_REV: u16 = 200;
+
+/// Lowest the dipper may be sent below home, to keep the carriage off the crucible
+pub const DIPPER_DEFAULT_MAX_TRAVEL_MM: f32 = 100.;
+
+/// Pulse count changes above this between polls are counter restarts, not wraps
+const MAX_PULSES_BETWEEN_POLLS: i64 = 32768;
+
+/// Time between position checks during moves and homing
+const DIPPER_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Homing gives up when the limit hasn't stopped the motor after this much travel
+const HOMING_TRAVEL_MARGIN: f32 = 1.5;
+
+/// How many polls without new pulses count as the motor having stopped at the limit
+const HOMING_STALL_POLLS: u32 = 4;
+
+/// Polls a homing motor gets to start before a stop counts as the limit, unless it has already moved:
+/// the first polls may land before the controller has started the motor
+const HOMING_START_POLLS: u32 = 10;
+
+/// Geometry, limits and speeds of the dipper axis
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DipperAxisConfig {
+    /// Pulses per motor revolution, as set on the controller (`PULSES_PER_REV`)
+    pub pulses_per_rev: u16,
+    /// Travel per motor revolution: the lead screw pitch
+    pub mm_per_rev: f32,
+    /// Soft travel limits, in mm below home
+    pub min_mm: f32,
+    pub max_mm: f32,
+    /// Position of the home reference
+    pub home_mm: f32,
+    pub move_rpm: f32,
+    pub homing_rpm: f32,
+    /// How close to its target a move must end
+    pub tolerance_mm: f32,
+}
+
+impl Default for DipperAxisConfig {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+impl DipperAxisConfig {
+    /// Our rail and motor, homed at the top of travel
+    pub const DEFAULT: DipperAxisConfig = DipperAxisConfig {
+        pulses_per_rev: DIPPER_DEFAULT_PULSES_PER_REV,
+        mm_per_rev: SMC05_RAIL_MM_PER_REV,
+        min_mm: 0.,
+        max_mm: DIPPER_DEFAULT_MAX_TRAVEL_MM,
+        home_mm: 0.,
+        move_rpm: SMC05_JOG_RATE_RPM,
+        homing_rpm: SMC05_SLOW_MOVE_RATE_RPM / 2.,
+        tolerance_mm: 0.1,
+    };
+
+    pub fn pulses_per_mm(&self) -> f32 {
+        self.pulses_per_rev as f32 / self.mm_per_rev
+    }
+
+    /// Linear speed of the carriage at `rpm`
+    pub fn mm_per_sec(&self, rpm: f32) -> f32 {
+        rpm * self.mm_per_rev / 60.
+    }
+
+    /// Take the pulses per revolution from the controller's configuration
+    pub async fn with_controller_pulses_per_rev(self, ctx: &mut tokio_modbus::client::Context)
+    -> Result<Self, Box<dyn std::error::Error>>
+    {
+        let config = read_smc05_config(ctx).await?;
+        if config.pulses_per_rev == 0 {
+            return Err("SMC05 reports 0 pulses per revolution".into());
+        }
+        Ok(Self { pulses_per_rev: config.pulses_per_rev, ..self })
+    }
+}
+
+/// Absolute dipper position, integrated from the controller's pulse counter
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DipperAxis {
+    pub config: DipperAxisConfig,
+    /// Signed pulses travelled since the reference; `None` until homed
+    pulses_from_home: Option<i64>,
+    /// The pulse counter at the last poll
+    prior_count: Option<u16>,
+}
+
+impl DipperAxis {
+    pub const fn new(config: DipperAxisConfig) -> Self {
+        Self { config, pulses_from_home: None, prior_count: None }
+    }
+
+    pub fn is_homed(&self) -> bool {
+        self.pulses_from_home.is_some()
+    }
+
+    /// Millimetres below home, once homed
+    pub fn position_mm(&self) -> Option<f32> {
+        self.pulses_from_home.map(|pulses| self.config.home_mm + pulses as f32 / self.config.pulses_per_mm())
+    }
+
+    /// Add the pulses sent since the last poll, returning the signed change
+    pub fn integrate(&mut self, status: &Smc05Status) -> i64 {
+        let Some(prior) = self.prior_count.replace(status.pulse_count) else { return 0 };
+        let mut delta = status.pulse_count.wrapping_sub(prior) as i64;
+        if delta >= MAX_PULSES_BETWEEN_POLLS {
+            // the counter restarted (a new action), rather than wrapping
+            delta = status.pulse_count as i64;
+        }
+        let signed = match status.direction {
+            Direction::Forward => delta,
+            Direction::Reverse => -delta,
+        };
+        if let Some(pulses) = self.pulses_from_home.as_mut() {
+            *pulses += signed;
+        }
+        signed
+    }
+
+    /// Poll the controller and bring the position up to date
+    pub async fn sync(&mut self, ctx: &mut tokio_modbus::client::Context)
+    -> Result<Smc05Status, Box<dyn std::error::Error>>
+    {
+        let status = read_smc05_status(ctx).await?;
+        self.integrate(&status);
+        Ok(status)
+    }
+
+    /// Declare the current position, e.g. with the tip resting on a reference of known height
+    pub async fn set_reference(&mut self, ctx: &mut tokio_modbus::client::Context, position_mm: f32)
+    -> Result<(), Box<dyn std::error::Error>>
+    {
+        self.sync(ctx).await?;
+        let pulses = ((position_mm - self.config.home_mm) * self.config.pulses_per_mm()).round() as i64;
+        self.pulses_from_home = Some(pulses);
+        println!("Dipper reference set at {position_mm:.2} mm");
+        Ok(())
+    }
+
+    /// Drive up slowly until the controller's limit input stops the motor, and call that home
+    pub async fn home(&mut self, ctx: &mut tokio_modbus::client::Context) -> Result<(), Box<dyn std::error::Error>> {
+        let travel_mm = (self.config.max_mm - self.config.min_mm).abs() * HOMING_TRAVEL_MARGIN;
+        let max_pulses = (travel_mm * self.config.pulses_per_mm()) as i64;
+        println!("Dipper homing at {} rpm ...", self.config.homing_rpm);
+        self.pulses_from_home = None;
+        enable_sport_mode03(ctx).await?;
+        stop_smc05_rotation(ctx).await?;
+        self.sync(ctx).await?;
+        set_rev_speed(ctx, self.config.homing_rpm).await?;
+        start_smc05_rev_rotation(ctx).await?;
+
+        let mut travelled: i64 = 0;
+        let mut stalled_polls = 0;
+        let mut polls = 0;
+        loop {
+            sleep(DIPPER_POLL_INTERVAL).await;
+            let status = read_smc05_status(ctx).await?;
+            let moved = self.integrate(&status);
+            travelled += moved.abs();
+            polls += 1;
+            stalled_polls = if moved == 0 { stalled_polls + 1 } else { 0 };
+            // a motor that never moves was already at the limit
+            let started = travelled > 0 || polls >= HOMING_START_POLLS;
+            if started && (!status.is_moving() || stalled_polls >= HOMING_STALL_POLLS) {
+                break;
+            }
+            if travelled > max_pulses {
+                stop_smc05_rotation(ctx).await?;
+                return Err(format!("dipper homing travelled {travel_mm:.0} mm without reaching the limit").into());
+            }
+        }
+        stop_smc05_rotation(ctx).await?;
+        self.set_reference(ctx, self.config.home_mm).await
+    }
+
+    /// Whether `target_mm` is inside the soft limits
+    pub fn check_target(&self, target_mm: f32) -> Result<(), String> {
+        if !self.is_homed() {
+            return Err("the dipper is not homed".into());
+        }
+        if !(self.config.min_mm..=self.config.max_mm).contains(&target_mm) {
+            return Err(format!("dipper target {target_mm:.2} mm outside the soft limits {}..{} mm",
+                self.config.min_mm, self.config.max_mm));
+        }
+        Ok(())
+    }
+
+    /// Whether moving in `direction` keeps inside the soft limits; always true before homing
+    pub fn can_move(&self, direction: Direction) -> bool {
+        match (self.position_mm(), direction) {
+            (None, _) => true,
+            (Some(position), Direction::Forward) => position < self.config.max_mm,
+            (Some(position), Direction::Reverse) => position > self.config.min_mm,
+        }
+    }
+
+    /// Move to `target_mm` below home, returning where the dipper stopped
+    pub async fn move_to(&mut self, ctx: &mut tokio_modbus::client::Context, target_mm: f32)
+    -> Result<f32, Box<dyn std::error::Error>>
+    {
+        self.check_target(target_mm)?;
+        enable_sport_mode03(ctx).await?;
+        stop_smc05_rotation(ctx).await?;
+        self.sync(ctx).await?;
+        let start_mm = self.position_mm().ok_or("the dipper is not homed")?;
+        let distance_mm = target_mm - start_mm;
+        if distance_mm.abs() <= self.config.tolerance_mm {
+            return Ok(start_mm);
+        }
+        let expected = Duration::from_secs_f32(distance_mm.abs() / self.config.mm_per_sec(self.config.move_rpm));
+        let timeout = expected * 2 + Duration::from_secs(5);
+        let started = std::time::Instant::now();
+        if distance_mm > 0. {
+            set_fwd_speed(ctx, self.config.move_rpm).await?;
+            start_smc05_fwd_rotation(ctx).await?;
+        }
+        else {
+            set_rev_speed(ctx, self.config.move_rpm).await?;
+            start_smc05_rev_rotation(ctx).await?;
+        }
+
+        loop {
+            sleep(DIPPER_POLL_INTERVAL).await;
+            self.sync(ctx).await?;
+            let remaining_mm = (target_mm - self.position_mm().unwrap_or(target_mm)) * distance_mm.signum();
+            if remaining_mm <= self.config.tolerance_mm {
+                break;
+            }
+            if started.elapsed() > timeout {
+                stop_smc05_rotation(ctx).await?;
+                return Err(format!("dipper move to {target_mm:.2} mm timed out after {timeout:?}").into());
+            }
+        }
+        stop_smc05_rotation(ctx).await?;
+        self.sync(ctx).await?;
+        let stopped_mm = self.position_mm().ok_or("the dipper is not homed")?;
+        if (stopped_mm - target_mm).abs() > self.config.tolerance_mm {
+            println!("Dipper stopped at {stopped_mm:.2} mm, {:.2} mm past the {target_mm:.2} mm target",
+                (stopped_mm - target_mm).abs());
+        }
+        Ok(stopped_mm)
+    }
+
+    /// Move by `distance_mm`, positive down toward the melt, returning where the dipper stopped
+    pub async fn move_by(&mut self, ctx: &mut tokio_modbus::client::Context, distance_mm: f32)
+    -> Result<f32, Box<dyn std::error::Error>>
+    {
+        self.sync(ctx).await?;
+        let position = self.position_mm().ok_or("the dipper is not homed")?;
+        self.move_to(ctx, position + distance_mm).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn status(direction: Direction, pulse_count: u16) -> Smc05Status {
+        Smc05Status { status: OperatingStatus::Uniform, direction, speed_rpm: 60., pulse_count, action_count: 0 }
+    }
+
+    /// An axis homed at the pulse count `count`
+    fn homed_at(count: u16) -> DipperAxis {
+        let mut axis = DipperAxis::new(DipperAxisConfig::DEFAULT);
+        axis.integrate(&status(Direction::Forward, count));
+        axis.pulses_from_home = Some(0);
+        axis
+    }
+
+    #[test]
+    fn first_poll_only_sets_the_count() {
+        let mut axis = DipperAxis::new(DipperAxisConfig::DEFAULT);
+        assert_eq!(axis.integrate(&status(Direction::Forward, 1234)), 0);
+    }
+
+    #[test]
+    fn counter_wrap_continues_the_count() {
+        let mut axis = homed_at(65530);
+        assert_eq!(axis.integrate(&status(Direction::Forward, 10)), 16);
+        assert_eq!(axis.pulses_from_home, Some(16));
+    }
+
+    #[test]
+    fn counter_restart_counts_from_zero() {
+        let mut axis = homed_at(5000);
+        // a new action restarts the count: the 100 pulses are all new
+        assert_eq!(axis.integrate(&status(Direction::Forward, 100)), 100);
+        assert_eq!(axis.pulses_from_home, Some(100));
+    }
+
+    #[test]
+    fn reverse_pulses_move_toward_home() {
+        let mut axis = homed_at(0);
+        axis.integrate(&status(Direction::Forward, 400));
+        assert_eq!(axis.integrate(&status(Direction::Reverse, 600)), -200);
+        assert_eq!(axis.pulses_from_home, Some(200));
+        let config = DipperAxisConfig::DEFAULT;
+        assert_eq!(axis.position_mm(), Some(config.home_mm + 200. / config.pulses_per_mm()));
+    }
+
+    #[test]
+    fn integration_before_homing_has_no_position() {
+        let mut axis = DipperAxis::new(DipperAxisConfig::DEFAULT);
+        axis.integrate(&status(Direction::Forward, 0));
+        assert_eq!(axis.integrate(&status(Direction::Forward, 300)), 300);
+        assert!(!axis.is_homed());
+        assert_eq!(axis.position_mm(), None);
+        assert!(axis.check_target(1.).is_err());
+    }
+}
@@ -0,0 +1,228 @@
+//!
+//! Optional embedded HTTP server exposing controller state as JSON and accepting operator commands.
+//!
+//! | Method | Path | Description |
+//! |-------|----------|----------|
+//! | GET  | `/` | Live dashboard page (self-contained, no external resources) |
+//! | GET  | `/ws` | WebSocket stream: a `history` message, then one `snapshot` message per control loop |
+//! | GET  | `/metrics` | Prometheus metrics |
+//! | GET  | `/api/state` | Latest `ControllerSnapshot` |
+//! | GET  | `/api/samples?since_ms=<ms>&limit=<n>` | Recent run log samples, oldest first |
+//! | POST | `/api/command` | Run an operator command, body `{"command": "setpoint 760"}` |
+//!
+//! Commands require an `Authorization: Bearer <token>` header matching the configured token.
+//! If no token is configured, the API is read-only.
+//!
+
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use axum::extract::ws::{Message, WebSocket, WebSocketUpgrade};
+use axum::extract::{Query, State};
+use axum::http::{HeaderMap, StatusCode, header};
+use axum::response::{Html, IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use serde::{Deserialize, Serialize};
+
+use crate::operator::*;
+use crate::run_log::RunLogSample;
+use crate::telemetry::{ControllerSnapshot, TelemetryHub};
+
+/// Environment variable holding the address to serve on, e.g. `0.0.0.0:8080`
+pub const ENV_HTTP_ADDR: &str = "CRAVEN_HTTP_ADDR";
+/// Environment variable holding the bearer token required for commands
+pub const ENV_HTTP_TOKEN: &str = "CRAVEN_HTTP_TOKEN";
+
+/// Default and maximum number of samples returned by `/api/samples`
+const DEFAULT_SAMPLES_LIMIT: usize = 300;
+const MAX_SAMPLES_LIMIT: usize = crate::telemetry::TELEMETRY_HISTORY_LEN;
+
+/// How many past samples a new dashboard connection is primed with (at roughly one per second)
+const DASHBOARD_HISTORY_LEN: usize = 1800;
+
+/// The dashboard page: scripts and styles are inline so it works on an isolated network
+const DASHBOARD_HTML: &str = include_str!("dashboard.html");
+
+/// How long to wait for the control loop to act on a command
+const COMMAND_REPLY_TIMEOUT: Duration = Duration::from_secs(15);
+
+#[derive(Debug, Clone)]
+pub struct HttpApiConfig {
+    /// Address and port to listen on
+    pub bind_addr: SocketAddr,
+    /// Bearer token required for commands; commands are refused if unset
+    pub auth_token: Option<String>,
+}
+
+impl HttpApiConfig {
+    /// Build a config from `CRAVEN_HTTP_ADDR` / `CRAVEN_HTTP_TOKEN`.
+    /// Returns None if no address is set, meaning the server is disabled.
+    pub fn from_env() -> Result<Option<Self>, Box<dyn std::error::Error>> {
+        let Ok(addr) = std::env::var(ENV_HTTP_ADDR) else { return Ok(None) };
+        let bind_addr = addr.parse().map_err(|e| format!("{ENV_HTTP_ADDR}={addr:?}: {e}"))?;
+        let auth_token = std::env::var(ENV_HTTP_TOKEN).ok().filter(|token| !token.is_empty());
+        Ok(Some(Self { bind_addr, auth_token }))
+    }
+}
+
+#[derive(Clone)]
+struct ApiState {
+    hub: Arc<TelemetryHub>,
+    commands: CommandSender,
+    auth_token: Option<Arc<str>>,
+}
+
+#[derive(Deserialize)]
+struct SamplesQuery {
+    since_ms: Option<i64>,
+    limit: Option<usize>,
+}
+
+#[derive(Deserialize)]
+struct CommandBody {
+    command: String,
+}
+
+#[derive(Serialize)]
+struct CommandResult {
+    ok: bool,
+    message: String,
+}
+
+fn command_result(status: StatusCode, ok: bool, message: String) -> Response {
+    (status, Json(CommandResult { ok, message })).into_response()
+}
+
+async fn get_state(State(api): State<ApiState>) -> Response {
+    match api.hub.latest() {
+        Some(snapshot) => Json(snapshot).into_response(),
+        None => (StatusCode::SERVICE_UNAVAILABLE, "no data yet").into_response(),
+    }
+}
+
+async fn get_metrics(State(api): State<ApiState>) -> Response {
+    match api.hub.latest() {
+        Some(snapshot) => (
+            [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+            crate::metrics::render_prometheus(&snapshot),
+        ).into_response(),
+        None => (StatusCode::SERVICE_UNAVAILABLE, "no data yet").into_response(),
+    }
+}
+
+async fn get_samples(State(api): State<ApiState>, Query(query): Query<SamplesQuery>) -> Response {
+    let limit = query.limit.unwrap_or(DEFAULT_SAMPLES_LIMIT).min(MAX_SAMPLES_LIMIT);
+    Json(api.hub.recent_samples(query.since_ms, limit)).into_response()
+}
+
+/// Messages pushed to dashboard WebSocket clients
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum StreamMessage<'a> {
+    History { samples: Vec<RunLogSample> },
+    Snapshot { snapshot: &'a ControllerSnapshot },
+}
+
+async fn get_dashboard() -> Html<&'static str> {
+    Html(DASHBOARD_HTML)
+}
+
+async fn ws_upgrade(State(api): State<ApiState>, ws: WebSocketUpgrade) -> Response {
+    ws.on_upgrade(move |socket| stream_telemetry(socket, api.hub))
+}
+
+async fn send_json(socket: &mut WebSocket, message: &StreamMessage<'_>) -> Result<(), Box<dyn std::error::Error>> {
+    let text = serde_json::to_string(message)?;
+    socket.send(Message::Text(text.into())).await?;
+    Ok(())
+}
+
+/// Push recent history, then every new snapshot, until the client goes away
+async fn stream_telemetry(mut socket: WebSocket, hub: Arc<TelemetryHub>) {
+    // subscribe first so nothing falls between the history and the live updates
+    let mut updates = hub.subscribe();
+    let history = StreamMessage::History { samples: hub.recent_samples(None, DASHBOARD_HISTORY_LEN) };
+    if send_json(&mut socket, &history).await.is_err() {
+        return;
+    }
+
+    loop {
+        tokio::select! {
+            update = updates.recv() => {
+                match update {
+                    Ok(snapshot) => {
+                        if send_json(&mut socket, &StreamMessage::Snapshot { snapshot: &snapshot }).await.is_err() {
+                            break;
+                        }
+                    }
+                    // a slow client just misses some points
+                    Err(tokio::sync::broadcast::error::RecvError::Lagged(_)) => continue,
+                    Err(tokio::sync::broadcast::error::RecvError::Closed) => break,
+                }
+            }
+            incoming = socket.recv() => {
+                match incoming {
+                    Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                    // the stream is one-way: ignore anything else the client sends
+                    Some(Ok(_)) => {}
+                }
+            }
+        }
+    }
+}
+
+/// Compare tokens without an early exit on the first differing byte
+fn tokens_match(expected: &str, offered: &str) -> bool {
+    expected.len() == offered.len()
+        && expected.bytes().zip(offered.bytes()).fold(0u8, |diff, (a, b)| diff | (a ^ b)) == 0
+}
+
+async fn post_command(State(api): State<ApiState>, headers: HeaderMap, Json(body): Json<CommandBody>) -> Response {
+    let Some(expected) = api.auth_token.as_deref() else {
+        return command_result(StatusCode::FORBIDDEN, false, format!("commands disabled: set {ENV_HTTP_TOKEN}"));
+    };
+    let offered = headers.get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "));
+    if !offered.is_some_and(|offered| tokens_match(expected, offered.trim())) {
+        return command_result(StatusCode::UNAUTHORIZED, false, "bad or missing bearer token".to_string());
+    }
+
+    let command: OperatorCommand = match body.command.parse() {
+        Ok(command) => command,
+        Err(e) => return command_result(StatusCode::BAD_REQUEST, false, e),
+    };
+    match tokio::time::timeout(COMMAND_REPLY_TIMEOUT, submit_command(&api.commands, command, "http")).await {
+        Ok(Ok(message)) => command_result(StatusCode::OK, true, message),
+        Ok(Err(message)) => command_result(StatusCode::CONFLICT, false, message),
+        Err(_) => command_result(StatusCode::GATEWAY_TIMEOUT, false, "controller did not respond".to_string()),
+    }
+}
+
+/// Bind the HTTP API and serve it in a background task
+pub async fn spawn_http_api(config: HttpApiConfig, hub: Arc<TelemetryHub>, commands: CommandSender)
+-> Result<tokio::task::JoinHandle<()>, Box<dyn std::error::Error>>
+{
+    let api = ApiState { hub, commands, auth_token: config.auth_token.map(Arc::from) };
+    let commands_enabled = api.auth_token.is_some();
+    let router = Router::new()
+        .route("/", get(get_dashboard))
+        .route("/ws", get(ws_upgrade))
+        .route("/metrics", get(get_metrics))
+        .route("/api/state", get(get_state))
+        .route("/api/samples", get(get_samples))
+        .route("/api/command", post(post_command))
+        .with_state(api);
+
+    let listener = tokio::net::TcpListener::bind(config.bind_addr).await?;
+    println!("HTTP API and dashboard on http://{} (commands {})",
+        listener.local_addr()?, if commands_enabled { "enabled" } else { "disabled" });
+
+    Ok(tokio::spawn(async move {
+        if let Err(e) = axum::serve(listener, router).await {
+            eprintln!("HTTP API stopped: {e:?}");
+        }
+    }))
+}
@@ -1,27 +1,50 @@
-
+//! # Modbus node address assignments
+//!
+//! | Address | Description |
+//! |-------|----------|
+//! | 0x01  | Reserved for Modbus default node ID |
+//! | 0x1A  | Current-Voltage 2 channel ADC   |
+//! | 0x1F  | Current-Voltage 2 channel ADC   |
+//! | 0x2A  | Precision current source (0-1000 mA)   |
+//! | 0x2F  | Precision current source (0-100 mA)   |
+//! | 0x3F  | Dual Type-K Thermocouple Reader |
+//! | 0x4A  | 4-20mA current loop source |
+//! | 0x4F  | Pyrometer simulator (4-20mA source) |
+//! | 0x5F  | Octo relay control |
+//! | 0x6A  | Dipper stepper motor driver |
 
 use tokio_modbus::prelude::*;
 use tokio::time::sleep;
 use std::{time::Duration};
 
-pub mod smc05;
+use codec::{ByteOrder, words_to_u32};
+use register_map::*;
 
-/// # Modbus node address assignments
-///
-/// | Address | Description |
-/// |-------|----------|
-/// | 0x01  | Reserved for Modbus default node ID |
-/// | 0x1A  | Current-Voltage 2 channel ADC   |
-/// | 0x1F  | Current-Voltage 2 channel ADC   |
-/// | 0x2A  | Precision current source (0-1000 mA)   |
-/// | 0x2F  | Precision current source (0-100 mA)   |
-/// | 0x3F  | Dual Type-K Thermocouple Reader |
-/// | 0x4A  | 4-20mA current loop source |
-/// | 0x4F  | Pyrometer simulator (4-20mA source) |
-/// | 0x5F  | Octo relay control |
-/// | 0x6A  | Dipper stepper motor driver |
-/// 
-///
+pub mod smc05;
+pub mod dipper;
+pub mod contact;
+pub mod melt_level;
+pub mod dip_profile;
+pub mod anode_pattern;
+pub mod anode_check;
+pub mod charge;
+pub mod run_log;
+pub mod telemetry;
+pub mod operator;
+pub mod http_api;
+pub mod bus;
+pub mod metrics;
+pub mod mqtt;
+pub mod console;
+pub mod cli;
+pub mod codec;
+pub mod register_map;
+pub mod peek;
+pub mod capture;
+pub mod ykpvccs;
+pub mod units;
+pub mod wa8tai;
+pub mod wa26419;
 
 /// Modbus node IDs
 pub const NODEID_BROADCAST_0: u8 = 0x00;
@@ -34,12 +57,15 @@ pub const NODEID_YKDAQ1402_IV_ADC: u8 = 0x1F;
 pub const NODEID_YKPVCCS010_CURR_SRC: u8 = 0x2F;
 pub const NODEID_YKKTC1202_DUAL_TK: u8 = 0x3F;
 pub const NODEID_N4IOA01_CURR_GEN: u8 = 0x4A; // 4-20 mA current loop source (signal generator)
-pub const NODEID_WA26419_8CH_DAC: u8 = 0x4F; // TODO Waveshare 8CH analog output (0-20 mA)
+pub const NODEID_WA26419_8CH_DAC: u8 = 0x4F; // Waveshare 8CH analog output (0-20 mA)
 pub const NODEID_R4DVI04_QRELAY_ADC: u8 = 0x5A; // Eletechsup quad relay plus ADC
 pub const NODEID_WAV_OCTO_RELAY: u8 = 0x5F; // Waveshare 8-relay board v3, SKU 17658
 pub const NODEID_SMC05_STEP_DRIVER: u8 = 0x6A; // SMC05 stepper motor controler
 pub const NODEID_MAX: u8 = 0x7F;
 
+/// Octo relay channel switching the furnace heater
+pub const FURNACE_RELAY_CHANNEL: u8 = 7;
+
 /// Register addresses
 pub const REG_NODEID_YKKTC1202_DUAL_TK:u16 = 0x20; // dual Type-K thermocouple reader
 pub const REG_NODEID_YKDAQ1402_IV_ADC:u16 = 64; // ELECDEMO YK-DAQ1402 0-10 Volt, 0-5 Amp IV ADC
@@ -81,12 +107,9 @@ pub const INF_INTER_ELECTRODE_OHMS: f32 = 666.;
 /// We only recognize current values reported by the current source above this threshold
 pub const REPORTED_CURRENT_THRESHOLD_MA: f32 = MIN_DRIVE_CURRENT_INCR_MA;
 
-/// Combine two u16 registers into an i32
+/// Combine two u16 registers (high word first) into an i32
 pub fn registers_to_i32(registers: &[u16], offset: usize) -> i32 {
-    let high = registers[offset] as i32;
-    let low = registers[offset + 1] as i32;
-    let combined = (high << 16) | low;
-    combined
+    words_to_u32([registers[offset], registers[offset + 1]], ByteOrder::BigEndian) as i32
 }
 
 /// Ensure that we can connect with the given Modbus node ID.
@@ -129,18 +152,14 @@ pub async fn ping_one_modbus_node_register(ctx: &mut tokio_modbus::client::Conte
 pub async fn read_ykdaq1402_iv_adc(ctx: &mut tokio_modbus::client::Context)
 -> Result<(f32, f32), Box<dyn std::error::Error>> 
 {
-    ctx.set_slave(Slave(NODEID_YKDAQ1402_IV_ADC));
-    // let read_rsp: Vec<u16> = ctx.read_holding_registers(REG_NODEID_YKDAQ1402_IV_ADC, 3).await??;
-    // println!(" YKDAQ1402 CFG ({REG_NODEID_YKDAQ1402_IV_ADC:?})[3]: {read_rsp:?}");
-    let iv_adc_vals: Vec<u16> = ctx.read_holding_registers(REG_IV_ADC_2CH_VALS, 4).await??;
-    println!(" YKDAQ1402 VALS ({REG_IV_ADC_2CH_VALS:?})[4]: {iv_adc_vals:?}");
-    let ch1_value = registers_to_i32(&iv_adc_vals, 0);
-    let verified_volts = (ch1_value as f32) / 10000.0; // resolution is 0.1 mV for 10V range
-    let ch2_value = registers_to_i32(&iv_adc_vals, 2);
-    let verified_milliamps: f32 = (ch2_value as f32)/ 10.0; // resolution is 0.1 mA for 5A range
-
-    println!(" YKDAQ1402 ch1_value: {ch1_value:?} = {verified_volts:?} V");
-    println!(" YKDAQ1402 ch2_value: {ch2_value:?} = {verified_milliamps:?} mA");
+    use register_map::ykdaq1402::*;
+    let (start, iv_adc_vals) = read_block(ctx, NODE_ID, &[&VOLTAGE.spec, &CURRENT.spec]).await?;
+    println!(" YKDAQ1402 VALS ({start:?})[4]: {iv_adc_vals:?}");
+    let verified_volts = VOLTAGE.decode_in(start, &iv_adc_vals)?; // resolution is 0.1 mV for 10V range
+    let verified_milliamps = CURRENT.decode_in(start, &iv_adc_vals)?; // resolution is 0.1 mA for 5A range
+
+    println!(" YKDAQ1402 = {verified_volts:?} V");
+    println!(" YKDAQ1402 = {verified_milliamps:?} mA");
     Ok((verified_volts, verified_milliamps))
 }
 
@@ -151,84 +170,61 @@ pub async fn read_ykdaq1402_iv_adc(ctx: &mut tokio_modbus::client::Context)
 pub async fn read_wdcu3003_iv_adc(ctx: &mut tokio_modbus::client::Context)
 -> Result<(f32, f32), Box<dyn std::error::Error>> 
 {
-    ctx.set_slave(Slave(NODEID_WDCU3003_IV_ADC));
-    let iv_adc_vals: Vec<u16> = ctx.read_holding_registers(0, 4).await??;
+    use register_map::wdcu3003::*;
+    let (start, iv_adc_vals) = read_block(ctx, NODE_ID, &[&VOLTAGE.spec, &HIGH_RANGE.spec, &CURRENT.spec, &POWER.spec]).await?;
     // println!("WDCU3003 vals: {:?}",iv_adc_vals);
-    let raw_potential_val = iv_adc_vals[0] as f32;
-    let high_range = iv_adc_vals[1] != 0;
-    let raw_current_val: f32 = iv_adc_vals[2] as f32;
-    let milliwatts_val = iv_adc_vals[3] as f32;
+    let volt_val = VOLTAGE.decode_in(start, &iv_adc_vals)?;
+    let high_range = HIGH_RANGE.decode_in(start, &iv_adc_vals)?;
+    let milliwatts_val = POWER.decode_in(start, &iv_adc_vals)?;
 
-    let volt_val = raw_potential_val / 1000.;
     let milliamps_val = 
         if high_range { milliwatts_val / volt_val }
-        else {raw_current_val / 1000. };
+        else { CURRENT.decode_in(start, &iv_adc_vals)? };
 
     Ok((volt_val, milliamps_val))
 }
 
 /**
- * Set the output drive current of the YK-PVCCS0100 precision current source 
- * @return The reading of current
+ * Set the output drive current of the YK-PVCCS0100 precision current source,
+ * clamped to 0-100 mA in 0.1 mA steps and verified by reading back the setpoint
  */
 pub async fn set_ykpvccs0100_current_drive(ctx: &mut tokio_modbus::client::Context, milliamps: f32) 
 -> Result<(), Box<dyn std::error::Error>> 
 {
-    ctx.set_slave(Slave(NODEID_YKPVCCS010_CURR_SRC)); 
-
-    // precision is 0.1 mA
-    let out_ma_setting: u16 = (10.0 * milliamps).round() as u16;
-    // println!("writing val of : {}", out_ma_setting);
-    ctx.write_single_register(REG_YKPVCCS_DRIVE_MILLIAMPS, out_ma_setting).await??;
-
+    ykpvccs::CurrentSource::new(ykpvccs::CurrentSourceModel::Ykpvccs0100).set_drive(ctx, milliamps).await?;
     Ok(())
 }    
 
 pub async fn set_ykpvccs1000_current_drive(ctx: &mut tokio_modbus::client::Context, milliamps: f32) 
 -> Result<(), Box<dyn std::error::Error>> 
 {
-    ctx.set_slave(Slave(NODEID_YKPVCCS010_CURR_SRC)); 
-
     // precision is 1 mA, range is 0...1000
-    let out_ma_setting: u16 = ((milliamps).round()) as u16;
-    // println!("writing val of : {}", out_ma_setting);
-    ctx.write_single_register(REG_YKPVCCS_DRIVE_MILLIAMPS, out_ma_setting).await??;
-
+    ykpvccs::CurrentSource::new(ykpvccs::CurrentSourceModel::Ykpvccs1000).set_drive(ctx, milliamps).await?;
     Ok(())
 } 
 
 pub async fn read_ykpvccs0100_current_drive(ctx: &mut tokio_modbus::client::Context)
 -> Result<f32, Box<dyn std::error::Error>> 
 {
-    let read_rsp: Vec<u16> = ctx.read_holding_registers(REG_YKPVCCS_MONITOR_MILLIAMPS, 1).await??;
-    let actual_ma = (read_rsp[0] as f32)/10.0;// precision is 0.1 mA
-    Ok(actual_ma)
+    ykpvccs0100::MONITOR_CURRENT.read(ctx).await // precision is 0.1 mA
 }
 
 pub async fn read_ykpvccs1000_current_drive(ctx: &mut tokio_modbus::client::Context)
 -> Result<f32, Box<dyn std::error::Error>> 
 {
-    let read_rsp: Vec<u16> = ctx.read_holding_registers(REG_YKPVCCS_MONITOR_MILLIAMPS, 1).await??;
-    let actual_ma = read_rsp[0] as f32;// precision is 1 mA
-    Ok(actual_ma)
+    ykpvccs1000::MONITOR_CURRENT.read(ctx).await // precision is 1 mA
 }
 
 
 pub async fn read_n4via02_multimeter(ctx: &mut tokio_modbus::client::Context)
 -> Result<((f32, f32), (f64, f64)), Box<dyn std::error::Error>> 
 {
-    const CURRENT_CONVERSION_FACTOR: f64 = 0.5;
+    use register_map::n4via02::*;
     // println!("Check N4VIA02_IV... ");
-    ctx.set_slave(Slave(NODEID_N4VIA02_IV_ADC));
-    // let mut ctx_iv_adc: client::Context = rtu::attach_slave(SerialStream::open(&builder).unwrap(), Slave(NODEID_IV_ADC));
-    // let read_rsp: Vec<u16> = ctx.read_holding_registers(REG_CFG_N4VIA02, 6).await??;
-    // println!(" N4VIA02 CFG ({REG_NODEID_N4VIA02:?})[6]: {read_rsp:?}");
-    // let read_rsp: Vec<u16> = ctx.read_holding_registers(REG_NODEID_N4VIA02, 1).await??;
-    // println!(" N4VIA02 NODE ID ({REG_NODEID_N4VIA02:?})[1]: {read_rsp:?}");
-    let milliamp_vals: Vec<u16> = ctx.read_holding_registers(REG_N4VIA02_CURR_VALS, 2).await??;
-    println!(" N4VIA02 mA VALS ({REG_N4VIA02_CURR_VALS:?})[2]: {milliamp_vals:?}");
-    let ch0_ma = (milliamp_vals[0] as f64) * CURRENT_CONVERSION_FACTOR;
-    let ch1_ma = (milliamp_vals[1] as f64) * CURRENT_CONVERSION_FACTOR;
+    let (start, milliamp_vals) = read_block(ctx, NODE_ID, &[&CURRENT_1.spec, &CURRENT_2.spec]).await?;
+    println!(" N4VIA02 mA VALS ({start:?})[2]: {milliamp_vals:?}");
+    let ch0_ma = CURRENT_1.spec.decode_in(start, &milliamp_vals)?;
+    let ch1_ma = CURRENT_2.spec.decode_in(start, &milliamp_vals)?;
     // let voltage_vals: Vec<u16> = ctx.read_holding_registers(REG_N4VIA02_VOLT_VALS, 2).await??;
     // println!(" N4VIA02 V VALS ({REG_N4VIA02_VOLT_VALS:?})[2]: {voltage_vals:?}");
 
@@ -238,56 +234,33 @@ pub async fn read_n4via02_multimeter(ctx: &mut tokio_modbus::client::Context)
 pub async fn read_n4aia04_420_iv_adc(ctx: &mut tokio_modbus::client::Context)
 -> Result<(f32, f32), Box<dyn std::error::Error>> 
 {
-    // println!("Check N4VIA02_IV... ");
-    ctx.set_slave(Slave(NODEID_N4AIA04_IV_ADC));
-    // let mut ctx_iv_adc: client::Context = rtu::attach_slave(SerialStream::open(&builder).unwrap(), Slave(NODEID_IV_ADC));
-    // let read_rsp: Vec<u16> = ctx.read_holding_registers(REG_CFG_N4VIA02, 6).await??;
-    // println!(" N4VIA02 CFG ({REG_NODEID_N4VIA02:?})[6]: {read_rsp:?}");
-    // let read_rsp: Vec<u16> = ctx.read_holding_registers(REG_NODEID_N4VIA02, 1).await??;
-    // println!(" N4VIA02 NODE ID ({REG_NODEID_N4VIA02:?})[1]: {read_rsp:?}");
-    let milliamp_vals: Vec<u16> = ctx.read_holding_registers(REG_N4AIA04_CH1_CURR, 2).await??;
-    // println!(" N4AIA04  mA VALS ({REG_N4VIA02_CURR_VALS:?})[2]: {milliamp_vals:?}");
-    let ch1_milliamps = (milliamp_vals[0] as f32)/10.0;
-    // let ch2_milliamps = (milliamp_vals[1] as f32)/10.0;
+    let ch1_milliamps = n4aia04::CURRENT_1.read(ctx).await?;
     //TODO extract mA and voltage?
-    // let voltage_vals: Vec<u16> = ctx.read_holding_registers(REG_N4VIA02_VOLT_VALS, 2).await??;
-    // println!(" N4VIA02 V VALS ({REG_N4VIA02_VOLT_VALS:?})[2]: {voltage_vals:?}");
     Ok((0f32, ch1_milliamps))
 }
 
 
 
 /**
- * Read Waveshare WA8TAI 8CH analog IV ADC.
- * Returns a value that is either milliamps or volts, depending on how the channel was configured
+ * Read one channel (1-8) of the Waveshare WA8TAI 8CH analog IV ADC,
+ * as volts or milliamps depending on how the channel is configured
  */
 pub async fn read_wa8tai_one_channel(ctx: &mut tokio_modbus::client::Context, channel: u8)
--> Result<f32, Box<dyn std::error::Error>> 
+-> Result<wa8tai::AnalogReading, Box<dyn std::error::Error>> 
 {
-    let chan_offset = (channel as u16) - 1; 
-    ctx.set_slave(Slave(NODEID_WA8TAI_IV_ADC)); 
-    let resp: Vec<u16> = ctx.read_input_registers(chan_offset, 1).await??; // read just one channel
-    // println!("AIN resp: {resp:?}");
-    let val = resp[0];
-    // println!("AIN ch {channel:?} val: {val:?}");
-    //output range 4000~20000, unit uA;
-    let converted_val = (val as f32) / 1E3; // either milliamps or volts
-    Ok(converted_val)
+    wa8tai::Wa8tai::new().read_channel(ctx, channel).await
 }
 
 /**
- * Read Waveshare WA8TAI 8CH analog IV ADC.
- * Returns a value that is either milliamps or volts, depending on how the channel was configured
+ * Read Waveshare WA8TAI 8CH analog IV ADC:
+ * channel 1 must be configured for volts, and channel 2 for milliamps
  */
 pub async fn read_wa8tai_volts_milliamps(ctx: &mut tokio_modbus::client::Context)
--> Result<(f32, f32), Box<dyn std::error::Error>> 
+-> Result<(units::Volts, units::Milliamps), Box<dyn std::error::Error>> 
 {
-    ctx.set_slave(Slave(NODEID_WA8TAI_IV_ADC)); 
-    let resp: Vec<u16> = ctx.read_input_registers(0x0000, 2).await??; //read all 8 at once
-    // println!("AIN resp: {resp:?}");
-    let volts = (resp[0] as f32) / 1E3; // original value is millivolts
-    let milliamps =  (resp[1] as f32) / 1E3; //output range 4000~20000, unit uA;
-
+    let readings = wa8tai::Wa8tai::new().read_all(ctx).await?;
+    let volts = readings[0].volts().ok_or_else(|| format!("WA8TAI channel 1 reads {}, not volts", readings[0]))?;
+    let milliamps = readings[1].milliamps().ok_or_else(|| format!("WA8TAI channel 2 reads {}, not milliamps", readings[1]))?;
     Ok((volts, milliamps))
 }
 
@@ -300,20 +273,8 @@ pub async fn read_wa8tai_volts_milliamps(ctx: &mut tokio_modbus::client::Context
 pub async fn set_wa26419_0420_current_loop_drive(ctx: &mut tokio_modbus::client::Context, channel: u8, milliamps: f32) 
 -> Result<(), Box<dyn std::error::Error>> 
 {
-    let chan_address: u16 = 0x0000 + (channel - 1) as u16;
-    ctx.set_slave(Slave(NODEID_WA26419_8CH_DAC)); 
-
-    // this module accepts settings in microamps (mA * 1000)
-    let desired_microamps = (milliamps * 1000.0).round() as u16;
-    // println!("setting microamps: {desired_microamps:?}");
-    ctx.write_single_register(chan_address, desired_microamps).await??;
-
-    let resp = ctx.read_holding_registers(chan_address, 1).await??;
-    let verified_microamps = resp[0];
-    if verified_microamps != desired_microamps {
-        println!("desired {desired_microamps:?} verfied {verified_microamps:?}");
-    }
-
+    // clamped to the channel's range, and verified by reading back
+    wa26419::Wa26419::new().set_output(ctx, channel, units::Milliamps(milliamps)).await?;
     Ok(())
 }
 
@@ -324,23 +285,8 @@ pub async fn set_wa26419_0420_current_loop_drive(ctx: &mut tokio_modbus::client:
 pub async fn set_n4ioa01_0420_current_loop_drive(ctx: &mut tokio_modbus::client::Context,  milliamps: f32) 
 -> Result<(), Box<dyn std::error::Error>> 
 {
-    ctx.set_slave(Slave(NODEID_N4IOA01_CURR_GEN)); 
-
-    // println!("Reading current loop value");
-    // let old_set: Vec<u16> = ctx.read_holding_registers(REG_N4IOA01_CURR_VAL, 1).await??;
-    // // println!("read_rsp: {read_rsp:?}");
-    // sleep(Duration::from_millis(250)).await;
-
-    let out_ma_setting: u16 = (milliamps * 100.0).round() as u16;
-    // println!("writing val of : {milliamps:?} mA  -> {out_ma_setting:?}");
-    ctx.write_single_register(REG_N4IOA01_CURR_VAL, out_ma_setting).await??;
-
-    // sleep(Duration::from_millis(500)).await;
-    // println!("Reading new current loop value");
-    // let new_set: Vec<u16> = ctx.read_holding_registers(REG_N4IOA01_CURR_VAL, 1).await??;
-    // println!("420 src values: {read_rsp:?}");
-    // println!(" 420src old: {old_set:?} new: {out_ma_setting:?} ");
-    Ok(())
+    // resolution is 0.01 mA
+    n4ioa01::CURRENT.write(ctx, milliamps).await
 }
 /**
  * Read the dual thermocouple reader
@@ -348,22 +294,21 @@ pub async fn set_n4ioa01_0420_current_loop_drive(ctx: &mut tokio_modbus::client:
 pub async fn read_ykktc1202_dual_tk_temps(ctx: &mut tokio_modbus::client::Context)
 -> Result<(Option<f32>, Option<f32>), Box<dyn std::error::Error>> 
 {
-    ctx.set_slave(Slave(NODEID_YKKTC1202_DUAL_TK));
+    use register_map::ykktc1202::*;
     // move RTK check into a separate function
     // let cfg_rsp: Vec<u16> = ctx.read_holding_registers(0x20, 3).await??;
     // println!(" 0x20 cfg_rsp: {:?}", cfg_rsp);
 
-    let tk_valid_resp: Vec<u16> = ctx.read_holding_registers(REG_YKKTC1202_VALIDITY, 2).await??;
+    let (start, tk_valid_resp) = read_block(ctx, NODE_ID, &[&PROBE_OPEN_1.spec, &PROBE_OPEN_2.spec]).await?;
     // println!(" REG_TK_VALIDITY: {:?}", tk_valid_resp);
-    let mut ch1_tk_conn: bool = tk_valid_resp[0] == 0; // 0: The thermocouple is connected, 1: The thermocouple is not connected
-    let mut ch2_tk_conn: bool = tk_valid_resp[1] == 0;
+    let mut ch1_tk_conn: bool = !PROBE_OPEN_1.decode_in(start, &tk_valid_resp)?;
+    let mut ch2_tk_conn: bool = !PROBE_OPEN_2.decode_in(start, &tk_valid_resp)?;
 
-    // TODO use reg address consts
-    // example of reading all the dual TK registers:
-    let tk_resp: Vec<u16> = ctx.read_holding_registers(REG_YKKTC1202_TEMP_VALS, 2).await??;
+    let (start, tk_resp) = read_block(ctx, NODE_ID, &[&TEMPERATURE_1.spec, &TEMPERATURE_2.spec]).await?;
     // println!(" REG_TK_TEMP_VALS: {:?}", tk_resp);
-    let ch1_tk_val: f32 = (tk_resp[0] as f32) / 10.0; // resolution is 0.1 °C
-    let ch2_tk_val: f32 = (tk_resp[1] as f32) / 10.0; // resolution is 0.1 °C
+    // resolution is 0.1 °C, and temperatures below zero are two's complement
+    let ch1_tk_val: f32 = TEMPERATURE_1.decode_in(start, &tk_resp)?;
+    let ch2_tk_val: f32 = TEMPERATURE_2.decode_in(start, &tk_resp)?;
 
     // the TK reader will sometimes report a thermocouple is disconnected when it's not
     if !ch1_tk_conn && ch1_tk_val < 1000.0 { //1000 C
@@ -382,27 +327,21 @@ pub async fn read_ykktc1202_dual_tk_temps(ctx: &mut tokio_modbus::client::Contex
 pub async fn toggle_r4dvi04_relay(ctx: &mut tokio_modbus::client::Context, channel: u8, active: bool)
 -> Result<(), Box<dyn std::error::Error>> 
 {
-    let relay_coil_address: u16 = (channel -1) as u16;
-    ctx.set_slave(Slave(NODEID_R4DVI04_QRELAY_ADC));
-    ctx.write_single_coil(relay_coil_address, active).await??;
-    Ok(())
+    channel_register(&R4DVI04_RELAYS, channel)?.write(ctx, active).await
 }
 
 pub async fn toggle_wav_octo_relay(ctx: &mut tokio_modbus::client::Context, channel: u8, active: bool)
 -> Result<(), Box<dyn std::error::Error>> 
 {
-    ctx.set_slave(Slave(NODEID_WAV_OCTO_RELAY));
-    let relay_coil_address: u16 = (channel -1) as u16;
-    // println!("set relay channel {}  (idx {}) to {}", channel, relay_coil_address, active);
-    ctx.write_single_coil(relay_coil_address, active).await??;
-    Ok(())
+    // println!("set relay channel {}  to {}", channel, active);
+    channel_register(&OCTO_RELAYS, channel)?.write(ctx, active).await
 }
 
 pub async fn write_wav_octo_relays(ctx: &mut tokio_modbus::client::Context, channel_vals: &[bool])
 -> Result<(), Box<dyn std::error::Error>> 
 {
-    ctx.set_slave(Slave(NODEID_WAV_OCTO_RELAY));
-    ctx.write_multiple_coils(0x0000, &channel_vals).await??;
+    ctx.set_slave(Slave(wav_octo_relay::NODE_ID));
+    ctx.write_multiple_coils(wav_octo_relay::RELAY_1.spec.address, channel_vals).await??;
     Ok(())
 }
 
@@ -414,18 +353,23 @@ pub async fn read_dual_tk_temps(ctx: &mut tokio_modbus::client::Context)
     read_ykktc1202_dual_tk_temps(ctx).await
 }
 
+/// The current source driving the test electrodes, as configured by `CRAVEN_CURRENT_SOURCE`
+pub fn electrode_current_source() -> Result<ykpvccs::CurrentSource, String> {
+    static CONFIGURED: std::sync::OnceLock<Result<ykpvccs::CurrentSource, String>> = std::sync::OnceLock::new();
+    CONFIGURED.get_or_init(ykpvccs::CurrentSource::from_env).clone()
+}
+
  /// Set the output drive current of the test electrodes 
 pub async fn set_electrode_current_drive(ctx: &mut tokio_modbus::client::Context, milliamps: f32) -> Result<(), Box<dyn std::error::Error>> 
 {
-    // set_ykpvccs0100_current_drive(ctx, milliamps).await
-    set_ykpvccs1000_current_drive(ctx, milliamps).await
+    electrode_current_source()?.set_drive(ctx, milliamps).await?;
+    Ok(())
 }
 
 /// Read the reported current from the current source
 pub async fn read_electrode_current_drive(ctx: &mut tokio_modbus::client::Context) -> Result<f32, Box<dyn std::error::Error>> 
 {
-    //read_ykpvccs0100_current_drive(ctx).await
-    read_ykpvccs1000_current_drive(ctx).await
+    electrode_current_source()?.read_monitor(ctx).await
 }
 
 // pub async fn read_stable_electrode_iv(ctx: &mut tokio_modbus::client::Context) 
@@ -498,5 +442,5 @@ pub async fn drive_current_and_measure(ctx: &mut tokio_modbus::client::Context,
             INF_INTER_ELECTRODE_OHMS // arbitrary value based on previous experiments
         };
 
-    return Ok((measured_volts, measured_milliamps, measured_ohms))
+    Ok((measured_volts, measured_milliamps, measured_ohms))
 }
\ No newline at end of file
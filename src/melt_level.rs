@@ -0,0 +1,254 @@
+//!
+//! Melt level mapping: probing the height of the melt surface with the cathode dipper.
+//!
+//! A probe drives a small current, steps the homed dipper down from above the last known
+//! surface until the contact detector sees the tip wetted on consecutive readings, records
+//! that position, and returns the dipper to where it started. The descent only counts once the
+//! tip has read open: a tip already wetted where the probe starts (the melt, or the deposit on
+//! the tip, having risen since the last probe) is backed off further first. The level above the crucible
+//! floor follows from the floor's position on the dipper axis, and the melt volume from the
+//! crucible geometry, less the tubular anode ring standing in the melt.
+//!
+//! `doc/test-stand-volumes.jpg` is a rendering of the test stand without dimensions, so
+//! `CrucibleGeometry::DEFAULT` holds placeholder figures: measure the crucible and anode ring
+//! before trusting absolute volumes. Level changes and trends only depend on the dipper axis.
+//!
+//! The position at contact is that of whatever is on the cathode tip, so a growing deposit
+//! reads as a rising melt: compare probes taken with the same tip.
+//!
+
+use std::collections::VecDeque;
+
+use crate::*;
+use crate::contact::*;
+use crate::dipper::*;
+
+/// Samples kept for the trend
+pub const MELT_LEVEL_HISTORY: usize = 64;
+
+/// Samples older than this are left out of the trend
+pub const MELT_TREND_WINDOW_MS: i64 = 6 * 60 * 60 * 1000;
+
+/// Crucible and anode ring dimensions, with the floor located on the dipper axis
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CrucibleGeometry {
+    /// Dipper position (mm below home) at which the cathode tip would touch the crucible floor
+    pub floor_mm: f32,
+    pub inner_diameter_mm: f32,
+    /// The tubular anode ring standing on the crucible floor
+    pub anode_ring_inner_diameter_mm: f32,
+    pub anode_ring_outer_diameter_mm: f32,
+    /// Height of the top of the anode ring above the floor: the melt should cover it
+    pub anode_ring_top_mm: f32,
+}
+
+impl Default for CrucibleGeometry {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+impl CrucibleGeometry {
+    /// Placeholder dimensions, pending measurement of the test stand crucible
+    pub const DEFAULT: CrucibleGeometry = CrucibleGeometry {
+        floor_mm: 90.,
+        inner_diameter_mm: 40.,
+        anode_ring_inner_diameter_mm: 30.,
+        anode_ring_outer_diameter_mm: 34.,
+        anode_ring_top_mm: 25.,
+    };
+
+    /// Melt depth above the floor, for a surface found at `surface_mm` on the dipper axis
+    pub fn level_mm(&self, surface_mm: f32) -> f32 {
+        self.floor_mm - surface_mm
+    }
+
+    /// Melt volume in ml (cm³) for a melt depth above the floor
+    pub fn volume_ml(&self, level_mm: f32) -> f32 {
+        let level_mm = level_mm.max(0.);
+        let area = |diameter_mm: f32| std::f32::consts::PI * diameter_mm * diameter_mm / 4.;
+        let ring_area_mm2 = area(self.anode_ring_outer_diameter_mm) - area(self.anode_ring_inner_diameter_mm);
+        let volume_mm3 = area(self.inner_diameter_mm) * level_mm - ring_area_mm2 * level_mm.min(self.anode_ring_top_mm);
+        volume_mm3 / 1000.
+    }
+
+    /// Whether a melt depth leaves the top of the anode ring uncovered
+    pub fn below_anode_ring(&self, level_mm: f32) -> bool {
+        level_mm < self.anode_ring_top_mm
+    }
+}
+
+/// One probe of the melt surface
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MeltLevelSample {
+    pub epoch_ms: i64,
+    /// Dipper position at contact, in mm below home
+    pub surface_mm: f32,
+    /// Melt depth above the crucible floor
+    pub level_mm: f32,
+    pub volume_ml: f32,
+}
+
+impl std::fmt::Display for MeltLevelSample {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "surface at {:.2} mm, level {:.2} mm, {:.1} ml", self.surface_mm, self.level_mm, self.volume_ml)
+    }
+}
+
+/// Recent melt level samples, and the trend through them
+#[derive(Debug, Clone, PartialEq)]
+pub struct MeltLevelLog {
+    pub geometry: CrucibleGeometry,
+    samples: VecDeque<MeltLevelSample>,
+}
+
+impl MeltLevelLog {
+    pub const fn new(geometry: CrucibleGeometry) -> Self {
+        Self { geometry, samples: VecDeque::new() }
+    }
+
+    /// Record a surface found at `surface_mm`
+    pub fn record(&mut self, epoch_ms: i64, surface_mm: f32) -> MeltLevelSample {
+        let level_mm = self.geometry.level_mm(surface_mm);
+        let sample = MeltLevelSample { epoch_ms, surface_mm, level_mm, volume_ml: self.geometry.volume_ml(level_mm) };
+        if self.samples.len() == MELT_LEVEL_HISTORY {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(sample);
+        sample
+    }
+
+    pub fn latest(&self) -> Option<&MeltLevelSample> {
+        self.samples.back()
+    }
+
+    pub fn samples(&self) -> impl Iterator<Item = &MeltLevelSample> {
+        self.samples.iter()
+    }
+
+    /// Whether the latest sample leaves the anode ring uncovered
+    pub fn below_anode_ring(&self) -> bool {
+        self.latest().is_some_and(|sample| self.geometry.below_anode_ring(sample.level_mm))
+    }
+
+    /// Least-squares slope of the level over the trend window, in mm per hour (negative is falling)
+    pub fn trend_mm_per_hour(&self) -> Option<f32> {
+        let latest_ms = self.latest()?.epoch_ms;
+        let window: Vec<(f64, f64)> = self.samples.iter()
+            .filter(|sample| latest_ms - sample.epoch_ms <= MELT_TREND_WINDOW_MS)
+            .map(|sample| ((sample.epoch_ms - latest_ms) as f64 / 3_600_000., sample.level_mm as f64))
+            .collect();
+        if window.len() < 2 {
+            return None;
+        }
+        let count = window.len() as f64;
+        let mean_hours = window.iter().map(|(hours, _)| hours).sum::<f64>() / count;
+        let mean_level = window.iter().map(|(_, level)| level).sum::<f64>() / count;
+        let spread: f64 = window.iter().map(|(hours, _)| (hours - mean_hours).powi(2)).sum();
+        if spread == 0. {
+            return None;
+        }
+        let covariance: f64 = window.iter().map(|(hours, level)| (hours - mean_hours) * (level - mean_level)).sum();
+        Some((covariance / spread) as f32)
+    }
+}
+
+/// How a melt level probe is run
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MeltProbeConfig {
+    /// Drive current while probing
+    pub probe_ma: f32,
+    /// Dipper travel between readings
+    pub step_mm: f32,
+    /// Where to start above the last known surface
+    pub approach_mm: f32,
+    /// Where to start with no surface known yet, in mm below home
+    pub first_start_mm: f32,
+    /// Closest the tip may come to the crucible floor
+    pub floor_clearance_mm: f32,
+    /// Consecutive wetted readings that confirm the surface
+    pub confirm_readings: u32,
+    pub thresholds: ContactThresholds,
+}
+
+impl Default for MeltProbeConfig {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+impl MeltProbeConfig {
+    pub const DEFAULT: MeltProbeConfig = MeltProbeConfig {
+        probe_ma: 4. * MIN_DRIVE_CURRENT_INCR_MA,
+        step_mm: 0.25,
+        approach_mm: 5.,
+        first_start_mm: 20.,
+        floor_clearance_mm: 5.,
+        confirm_readings: 2,
+        thresholds: ContactThresholds::DEFAULT,
+    };
+}
+
+/// Find the melt surface: step the homed dipper down from above the last known surface until
+/// the tip is wetted, then return the dipper to where it started and the drive current to zero.
+/// Returns the dipper position at contact, in mm below home.
+pub async fn probe_melt_surface(ctx: &mut tokio_modbus::client::Context, axis: &mut DipperAxis,
+    geometry: &CrucibleGeometry, config: &MeltProbeConfig, last_surface_mm: Option<f32>)
+-> Result<f32, Box<dyn std::error::Error>>
+{
+    axis.sync(ctx).await?;
+    let parked_mm = axis.position_mm().ok_or("home the dipper before probing the melt level")?;
+    let lowest_mm = (geometry.floor_mm - config.floor_clearance_mm).min(axis.config.max_mm);
+    let start_mm = last_surface_mm.map_or(config.first_start_mm, |surface_mm| surface_mm - config.approach_mm)
+        .max(axis.config.min_mm);
+    println!("Melt probe from {start_mm:.2} mm at {:.1} mA ...", config.probe_ma);
+
+    let found = async {
+        let mut position_mm = axis.move_to(ctx, start_mm).await?;
+        let detector = ContactDetector::new(config.thresholds);
+        let mut wetted_readings = 0;
+        // contact only counts once the tip has been seen out of the melt
+        let mut seen_open = false;
+        loop {
+            let (_volts, measured_ma, measured_ohms) = drive_current_and_measure(ctx, config.probe_ma).await?;
+            let wetted = detector.classify(config.probe_ma, measured_ma, measured_ohms).is_wetted();
+            if wetted && !seen_open {
+                if position_mm <= axis.config.min_mm + axis.config.tolerance_mm {
+                    return Err(format!("tip still wetted at {position_mm:.2} mm: no open reading above the surface").into());
+                }
+                let backoff_mm = (position_mm - config.approach_mm).max(axis.config.min_mm);
+                println!("Melt probe tip wetted at {position_mm:.2} mm, backing off to {backoff_mm:.2} mm");
+                position_mm = axis.move_to(ctx, backoff_mm).await?;
+                continue;
+            }
+            if wetted {
+                wetted_readings += 1;
+                if wetted_readings >= config.confirm_readings {
+                    return Ok::<f32, Box<dyn std::error::Error>>(position_mm);
+                }
+                continue;
+            }
+            wetted_readings = 0;
+            seen_open = true;
+            if position_mm + config.step_mm > lowest_mm {
+                return Err(format!("no melt surface above {lowest_mm:.2} mm").into());
+            }
+            position_mm = axis.move_to(ctx, position_mm + config.step_mm).await?;
+        }
+    }.await;
+
+    // whatever the outcome, stop driving current and put the dipper back, then report every failure
+    let drive_off = set_electrode_current_drive(ctx, 0.).await;
+    let parked = axis.move_to(ctx, parked_mm).await;
+    let errors: Vec<String> = [
+        found.as_ref().err().map(|e| e.to_string()),
+        drive_off.err().map(|e| format!("zeroing the probe current failed: {e}")),
+        parked.err().map(|e| format!("returning the dipper to {parked_mm:.2} mm failed: {e}")),
+    ].into_iter().flatten().collect();
+    if !errors.is_empty() {
+        return Err(errors.join("; ").into());
+    }
+    let surface_mm = found?;
+    println!("Melt surface at {surface_mm:.2} mm");
+    Ok(surface_mm)
+}
@@ -0,0 +1,158 @@
+//!
+//! Prometheus text exposition of controller state, served at `/metrics`.
+//! All metric names are prefixed with `craven_`.
+//!
+
+use std::fmt::Write;
+
+use crate::INF_INTER_ELECTRODE_OHMS;
+use crate::bus::NodeCounters;
+use crate::run_log::{DRIVE_PHASE_NAMES, FAULT_NAMES};
+use crate::telemetry::ControllerSnapshot;
+
+/// Picks one counter out of a node's Modbus traffic counters
+type CounterFn = fn(&NodeCounters) -> u64;
+
+/// Accumulates metric families in the text exposition format
+struct Exposition {
+    text: String,
+}
+
+impl Exposition {
+    /// Start a metric family
+    fn family(&mut self, name: &str, kind: &str, help: &str) {
+        let _ = writeln!(self.text, "# HELP craven_{name} {help}");
+        let _ = writeln!(self.text, "# TYPE craven_{name} {kind}");
+    }
+
+    fn sample(&mut self, name: &str, labels: &[(&str, &str)], value: f64) {
+        let _ = write!(self.text, "craven_{name}");
+        if !labels.is_empty() {
+            let labels: Vec<String> = labels.iter().map(|(k, v)| format!("{k}=\"{v}\"")).collect();
+            let _ = write!(self.text, "{{{}}}", labels.join(","));
+        }
+        let value = if value == f64::INFINITY { "+Inf".to_string() } else { value.to_string() };
+        let _ = writeln!(self.text, " {value}");
+    }
+
+    /// A family with a single unlabeled gauge
+    fn gauge(&mut self, name: &str, help: &str, value: f64) {
+        self.family(name, "gauge", help);
+        self.sample(name, &[], value);
+    }
+}
+
+/// Open-circuit resistance is tracked internally as an arbitrary large value: export it as +Inf
+fn ohms_value(ohms: f32) -> f64 {
+    if ohms >= INF_INTER_ELECTRODE_OHMS { f64::INFINITY } else { ohms as f64 }
+}
+
+fn flag(value: bool) -> f64 {
+    value as u8 as f64
+}
+
+/// Render a snapshot in the Prometheus text exposition format
+pub fn render_prometheus(snapshot: &ControllerSnapshot) -> String {
+    let mut out = Exposition { text: String::new() };
+    let furnace = &snapshot.furnace;
+    let electrodes = &snapshot.electrodes;
+    let dipper = &snapshot.dipper;
+
+    out.gauge("snapshot_timestamp_seconds", "Time the controller state was sampled",
+        snapshot.epoch_ms as f64 / 1000.);
+
+    out.family("melt_temperature_celsius", "gauge", "Melt temperature per thermocouple, and their average");
+    out.sample("melt_temperature_celsius", &[("probe", "avg")], furnace.measured_temp_c as f64);
+    for (probe, temp_c) in [("tk1", furnace.tk1_c), ("tk2", furnace.tk2_c)] {
+        if let Some(temp_c) = temp_c {
+            out.sample("melt_temperature_celsius", &[("probe", probe)], temp_c as f64);
+        }
+    }
+    out.gauge("furnace_setpoint_celsius", "Furnace temperature set point in force", furnace.setpoint_c as f64);
+    out.gauge("furnace_setpoint_override", "Whether an operator set point override is active",
+        flag(furnace.setpoint_override_c.is_some()));
+    out.gauge("furnace_heater_on", "Whether the furnace heater relay is on", flag(furnace.heater_on));
+    out.gauge("furnace_heater_forced_off", "Whether the operator has forced the heater off", flag(furnace.heater_forced_off));
+    out.gauge("furnace_probes_lost", "Whether no thermocouple reports a valid temperature", flag(furnace.probes_lost));
+
+    out.family("electrode_current_milliamps", "gauge", "Electrode drive current");
+    out.sample("electrode_current_milliamps", &[("kind", "commanded")], electrodes.target_drive_ma as f64);
+    out.sample("electrode_current_milliamps", &[("kind", "reported")], electrodes.reported_drive_ma as f64);
+    out.sample("electrode_current_milliamps", &[("kind", "measured")], electrodes.measured_ma as f64);
+    out.gauge("electrode_volts", "Measured potential across the electrodes", electrodes.measured_volts as f64);
+    out.family("electrode_ohms", "gauge", "Inter-electrode resistance (+Inf when open circuit)");
+    out.sample("electrode_ohms", &[("kind", "raw")], ohms_value(electrodes.measured_ohms));
+    out.sample("electrode_ohms", &[("kind", "ewma")], ohms_value(electrodes.ohms_ewma));
+    out.sample("electrode_ohms", &[("kind", "lowv_min")], ohms_value(electrodes.lowv_minr_ohms));
+    out.sample("electrode_ohms", &[("kind", "highv_min")], ohms_value(electrodes.highv_minr_ohms));
+
+    out.gauge("drive_phase", "Electrode drive phase index", electrodes.drive_phase as f64);
+    out.gauge("drive_phase_clock_paused", "Whether the operator has paused the drive phase clock",
+        flag(electrodes.phase_clock_paused));
+    out.family("drive_phase_active", "gauge", "1 for the active electrode drive phase");
+    for (idx, name) in DRIVE_PHASE_NAMES.iter().enumerate() {
+        out.sample("drive_phase_active", &[("phase", name)], flag(idx == electrodes.drive_phase as usize));
+    }
+
+    out.family("anode_connected", "gauge", "Whether each anode relay connects its anode to the current source");
+    for (idx, connected) in snapshot.relays.anodes.iter().enumerate() {
+        out.sample("anode_connected", &[("anode", &(idx + 1).to_string())], flag(*connected));
+    }
+    let pattern = snapshot.relays.anodes.iter().enumerate()
+        .fold(0u32, |bits, (idx, connected)| bits | ((*connected as u32) << idx));
+    out.gauge("anode_pattern", "Anode relay pattern as a bitmask (bit 0 = anode 1)", pattern as f64);
+    if let Some(check) = &snapshot.anode_check {
+        out.family("anode_resistance_ohms", "gauge", "Resistance through each anode on its own, from the latest anode check");
+        for reading in &check.readings {
+            out.sample("anode_resistance_ohms", &[("anode", &reading.anode.to_string())], reading.ohms as f64);
+        }
+        out.family("anode_failed", "gauge", "Whether each anode was open or high resistance in the latest anode check");
+        for reading in &check.readings {
+            out.sample("anode_failed", &[("anode", &reading.anode.to_string())], flag(reading.health.is_failed()));
+        }
+    }
+
+    out.gauge("dipper_enabled", "Whether the cathode dipper monitor is enabled", flag(dipper.enabled));
+    out.gauge("dipper_surface_contact", "Whether the cathode is touching the melt", flag(dipper.surface_contact));
+    out.gauge("dipper_contact_state", "Cathode contact state (0 open, 1 meniscus, 2 contact)", dipper.contact as u8 as f64);
+    out.gauge("stepper_motion_direction", "Stepper driver motion direction (0 fwd, 1 rev)", dipper.motion_direction as f64);
+    out.gauge("stepper_pulse_count", "Stepper driver pulse count", dipper.pulse_count as f64);
+    out.gauge("stepper_action_count", "Stepper driver action count", dipper.action_count as f64);
+    if let Some(position_mm) = dipper.position_mm {
+        out.gauge("dipper_position_mm", "Cathode dipper position below home", position_mm as f64);
+    }
+    if let Some(level_mm) = snapshot.melt.level_mm {
+        out.gauge("melt_level_mm", "Melt depth above the crucible floor, from the latest probe", level_mm as f64);
+    }
+    if let Some(volume_ml) = snapshot.melt.volume_ml {
+        out.gauge("melt_volume_ml", "Melt volume estimated from the crucible geometry", volume_ml as f64);
+    }
+
+    let charge = &snapshot.charge;
+    out.gauge("charge_coulombs", "Charge delivered through the electrodes since the run started", charge.run.coulombs);
+    out.gauge("energy_joules", "Electrical energy delivered to the electrodes since the run started", charge.run.joules);
+    out.gauge("phase_charge_coulombs", "Charge delivered since the current drive phase was entered", charge.phase.coulombs);
+    out.gauge("carbon_theoretical_mg", "Carbon deposited over the run by Faraday's law, at full current efficiency", charge.run_carbon_mg);
+
+    out.gauge("faults", "Active fault flags as a bitmask", snapshot.faults as f64);
+    out.family("fault_active", "gauge", "1 for each active fault");
+    for (fault_flag, name) in FAULT_NAMES {
+        out.sample("fault_active", &[("fault", name)], flag(snapshot.faults & fault_flag != 0));
+    }
+
+    let counter_families: [(&str, &str, CounterFn); 5] = [
+        ("modbus_requests_total", "Modbus requests issued, per node", |c| c.requests),
+        ("modbus_retries_total", "Modbus read retries, per node", |c| c.retries),
+        ("modbus_timeouts_total", "Modbus requests that timed out, per node", |c| c.timeouts),
+        ("modbus_exceptions_total", "Modbus exception responses, per node", |c| c.exceptions),
+        ("modbus_transport_errors_total", "Modbus I/O or framing errors, per node", |c| c.transport_errors),
+    ];
+    for (name, help, value) in counter_families {
+        out.family(name, "counter", help);
+        for (node_id, counters) in &snapshot.bus {
+            out.sample(name, &[("node", &format!("0x{node_id:02X}"))], value(counters) as f64);
+        }
+    }
+
+    out.text
+}
@@ -0,0 +1,260 @@
+//!
+//! Optional MQTT telemetry publisher and command subscriber.
+//!
+//! Topics, under a prefix (default `craven/<client id>`):
+//!
+//! | Topic | Retained | Payload |
+//! |-------|----------|----------|
+//! | `<prefix>/status` | yes | `online`, or `offline` (also set by the broker as Last Will if the process dies) |
+//! | `<prefix>/furnace` | yes | Furnace state JSON, once per control loop |
+//! | `<prefix>/electrodes` | yes | Electrode drive and anode relay state JSON |
+//! | `<prefix>/dipper` | yes | Dipper state JSON |
+//! | `<prefix>/charge` | yes | Charge and energy delivered, per run and drive phase, with the carbon estimate |
+//! | `<prefix>/bus` | yes | Modbus traffic counters per node ID |
+//! | `<prefix>/faults` | yes | Active faults |
+//! | `<prefix>/events` | no | One JSON message per controller event |
+//! | `<prefix>/cmd` | - | Commands in: plain text (`setpoint 760`) or `{"id": "...", "command": "..."}` |
+//! | `<prefix>/cmd/ack` | no | `{"id": ..., "command": ..., "ok": bool, "message": ...}` for each command |
+//!
+//! Commands are only accepted if explicitly enabled; restrict the command topic with broker ACLs.
+//!
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+use rumqttc::{AsyncClient, Event, LastWill, MqttOptions, Packet, QoS};
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast::error::RecvError;
+
+use crate::operator::*;
+use crate::telemetry::{ControllerSnapshot, TelemetryHub};
+
+/// Environment variable holding the broker address, `host` or `host:port`. MQTT is disabled if unset.
+pub const ENV_MQTT_BROKER: &str = "CRAVEN_MQTT_BROKER";
+/// Environment variable holding the MQTT client ID
+pub const ENV_MQTT_CLIENT_ID: &str = "CRAVEN_MQTT_CLIENT_ID";
+/// Environment variable holding the topic prefix
+pub const ENV_MQTT_PREFIX: &str = "CRAVEN_MQTT_PREFIX";
+/// Environment variables holding optional broker credentials
+pub const ENV_MQTT_USERNAME: &str = "CRAVEN_MQTT_USERNAME";
+pub const ENV_MQTT_PASSWORD: &str = "CRAVEN_MQTT_PASSWORD";
+/// Environment variable that enables the command topic when set to `1` or `true`
+pub const ENV_MQTT_COMMANDS: &str = "CRAVEN_MQTT_COMMANDS";
+
+pub const DEFAULT_MQTT_PORT: u16 = 1883;
+pub const DEFAULT_MQTT_CLIENT_ID: &str = "potslide";
+
+const MQTT_KEEP_ALIVE: Duration = Duration::from_secs(10);
+/// Pause before reconnecting after losing the broker
+const MQTT_RECONNECT_DELAY: Duration = Duration::from_secs(5);
+/// How long to wait for the control loop to act on a command
+const COMMAND_REPLY_TIMEOUT: Duration = Duration::from_secs(15);
+
+#[derive(Debug, Clone)]
+pub struct MqttConfig {
+    pub host: String,
+    pub port: u16,
+    pub client_id: String,
+    pub topic_prefix: String,
+    /// Optional (username, password)
+    pub credentials: Option<(String, String)>,
+    /// Whether to accept commands on `<prefix>/cmd`
+    pub commands_enabled: bool,
+}
+
+impl MqttConfig {
+    /// Build a config from the `CRAVEN_MQTT_*` environment variables.
+    /// Returns None if no broker is set, meaning MQTT is disabled.
+    pub fn from_env() -> Result<Option<Self>, Box<dyn std::error::Error>> {
+        let Ok(broker) = std::env::var(ENV_MQTT_BROKER) else { return Ok(None) };
+        let (host, port) = match broker.rsplit_once(':') {
+            Some((host, port)) => (host.to_string(), port.parse().map_err(|e| format!("{ENV_MQTT_BROKER}={broker:?}: {e}"))?),
+            None => (broker.clone(), DEFAULT_MQTT_PORT),
+        };
+        let client_id = std::env::var(ENV_MQTT_CLIENT_ID).unwrap_or_else(|_| DEFAULT_MQTT_CLIENT_ID.to_string());
+        let topic_prefix = std::env::var(ENV_MQTT_PREFIX).unwrap_or_else(|_| format!("craven/{client_id}"));
+        let credentials = match (std::env::var(ENV_MQTT_USERNAME), std::env::var(ENV_MQTT_PASSWORD)) {
+            (Ok(username), Ok(password)) => Some((username, password)),
+            (Ok(username), Err(_)) => Some((username, String::new())),
+            _ => None,
+        };
+        let commands_enabled = std::env::var(ENV_MQTT_COMMANDS)
+            .is_ok_and(|value| value == "1" || value.eq_ignore_ascii_case("true"));
+        Ok(Some(Self { host, port, client_id, topic_prefix, credentials, commands_enabled }))
+    }
+
+    fn topic(&self, leaf: &str) -> String {
+        format!("{}/{leaf}", self.topic_prefix)
+    }
+}
+
+/// Subsystem state with the time it was sampled
+#[derive(Serialize)]
+struct Stamped<'a, T: Serialize> {
+    epoch_ms: i64,
+    #[serde(flatten)]
+    data: &'a T,
+}
+
+#[derive(Serialize)]
+struct ElectrodesPayload<'a> {
+    #[serde(flatten)]
+    electrodes: &'a crate::telemetry::ElectrodeSnapshot,
+    anodes: &'a [bool],
+    anode_pattern: &'a str,
+}
+
+#[derive(Serialize)]
+struct FaultsPayload<'a> {
+    faults: u8,
+    fault_names: &'a [&'static str],
+}
+
+#[derive(Deserialize)]
+struct CommandMessage {
+    id: Option<serde_json::Value>,
+    command: String,
+}
+
+#[derive(Serialize)]
+struct CommandAck {
+    id: Option<serde_json::Value>,
+    command: String,
+    ok: bool,
+    message: String,
+}
+
+/// Per-subsystem (leaf topic, JSON payload) pairs for one snapshot
+fn snapshot_payloads(snapshot: &ControllerSnapshot) -> Result<Vec<(&'static str, String)>, serde_json::Error> {
+    let epoch_ms = snapshot.epoch_ms;
+    Ok(vec![
+        ("furnace", serde_json::to_string(&Stamped { epoch_ms, data: &snapshot.furnace })?),
+        ("electrodes", serde_json::to_string(&Stamped { epoch_ms, data: &ElectrodesPayload {
+            electrodes: &snapshot.electrodes,
+            anodes: &snapshot.relays.anodes,
+            anode_pattern: &snapshot.relays.anode_pattern,
+        }})?),
+        ("dipper", serde_json::to_string(&Stamped { epoch_ms, data: &snapshot.dipper })?),
+        ("charge", serde_json::to_string(&Stamped { epoch_ms, data: &snapshot.charge })?),
+        ("bus", serde_json::to_string(&Stamped { epoch_ms, data: &snapshot.bus })?),
+        ("faults", serde_json::to_string(&Stamped { epoch_ms, data: &FaultsPayload {
+            faults: snapshot.faults,
+            fault_names: &snapshot.fault_names,
+        }})?),
+    ])
+}
+
+/// Run one command received on the command topic and publish its acknowledgement
+async fn handle_command(config: &MqttConfig, client: &AsyncClient, commands: &CommandSender, payload: &[u8]) {
+    let text = String::from_utf8_lossy(payload);
+    let (id, command_text) = match serde_json::from_str::<CommandMessage>(&text) {
+        Ok(msg) => (msg.id, msg.command),
+        Err(_) => (None, text.trim().to_string()),
+    };
+    let reply = match command_text.parse::<OperatorCommand>() {
+        Ok(command) => tokio::time::timeout(COMMAND_REPLY_TIMEOUT, submit_command(commands, command, "mqtt")).await
+            .unwrap_or_else(|_| Err("controller did not respond".to_string())),
+        Err(e) => Err(e),
+    };
+    let (ok, message) = match reply {
+        Ok(message) => (true, message),
+        Err(message) => (false, message),
+    };
+    let ack = CommandAck { id, command: command_text, ok, message };
+    if let Ok(ack) = serde_json::to_string(&ack) {
+        let _ = client.publish(config.topic("cmd/ack"), QoS::AtLeastOnce, false, ack).await;
+    }
+}
+
+/// Connect to the broker and publish telemetry (and optionally accept commands) in background tasks
+pub async fn spawn_mqtt(config: MqttConfig, hub: Arc<TelemetryHub>, commands: CommandSender)
+-> Result<tokio::task::JoinHandle<()>, Box<dyn std::error::Error>>
+{
+    let status_topic = config.topic("status");
+    let mut options = MqttOptions::new(&config.client_id, &config.host, config.port);
+    options.set_keep_alive(MQTT_KEEP_ALIVE);
+    options.set_last_will(LastWill::new(&status_topic, "offline", QoS::AtLeastOnce, true));
+    if let Some((username, password)) = &config.credentials {
+        options.set_credentials(username, password);
+    }
+    let (client, mut event_loop) = AsyncClient::new(options, 64);
+    println!("MQTT publishing to {}:{} under {}/ (commands {})", config.host, config.port, config.topic_prefix,
+        if config.commands_enabled { "enabled" } else { "disabled" });
+
+    // the request queue only drains while the event loop is polled and connected:
+    // telemetry isn't queued while the broker is away, and is brought up to date by the next snapshot
+    let connected = Arc::new(AtomicBool::new(false));
+
+    // telemetry publisher
+    let publisher = client.clone();
+    let pub_config = config.clone();
+    let pub_connected = connected.clone();
+    let mut updates = hub.subscribe();
+    let mut events = hub.subscribe_events();
+    tokio::spawn(async move {
+        loop {
+            tokio::select! {
+                update = updates.recv() => match update {
+                    Ok(_) if !pub_connected.load(Ordering::Relaxed) => continue,
+                    Ok(snapshot) => {
+                        let Ok(payloads) = snapshot_payloads(&snapshot) else { continue };
+                        for (leaf, payload) in payloads {
+                            let _ = publisher.publish(pub_config.topic(leaf), QoS::AtMostOnce, true, payload).await;
+                        }
+                    }
+                    Err(RecvError::Lagged(_)) => continue,
+                    Err(RecvError::Closed) => break,
+                },
+                event = events.recv() => match event {
+                    Ok(_) if !pub_connected.load(Ordering::Relaxed) => continue,
+                    Ok(event) => {
+                        let Ok(payload) = serde_json::to_string(&event) else { continue };
+                        let _ = publisher.publish(pub_config.topic("events"), QoS::AtLeastOnce, false, payload).await;
+                    }
+                    Err(RecvError::Lagged(_)) => continue,
+                    Err(RecvError::Closed) => break,
+                },
+            }
+        }
+    });
+
+    // connection driver: (re)subscribes on every connect and dispatches incoming commands.
+    // It alone drains the request queue, so it must never wait on the queue itself.
+    let cmd_topic = config.topic("cmd");
+    Ok(tokio::spawn(async move {
+        let mut warned_unreachable = false;
+        loop {
+            match event_loop.poll().await {
+                Ok(Event::Incoming(Packet::ConnAck(_))) => {
+                    connected.store(true, Ordering::Relaxed);
+                    warned_unreachable = false;
+                    println!("MQTT connected to {}:{}", config.host, config.port);
+                    if let Err(e) = client.try_publish(&status_topic, QoS::AtLeastOnce, true, "online") {
+                        eprintln!("MQTT status not published: {e}");
+                    }
+                    if config.commands_enabled && let Err(e) = client.try_subscribe(&cmd_topic, QoS::AtLeastOnce) {
+                        eprintln!("MQTT command subscription failed: {e}");
+                    }
+                }
+                Ok(Event::Incoming(Packet::Publish(publish))) if publish.topic == cmd_topic => {
+                    let (config, client, commands) = (config.clone(), client.clone(), commands.clone());
+                    tokio::spawn(async move {
+                        handle_command(&config, &client, &commands, &publish.payload).await;
+                    });
+                }
+                Ok(_) => {}
+                Err(e) => {
+                    if connected.swap(false, Ordering::Relaxed) {
+                        eprintln!("MQTT connection lost: {e}");
+                    }
+                    else if !warned_unreachable {
+                        eprintln!("MQTT broker unreachable: {e}");
+                        warned_unreachable = true;
+                    }
+                    tokio::time::sleep(MQTT_RECONNECT_DELAY).await;
+                }
+            }
+        }
+    }))
+}
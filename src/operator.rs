@@ -0,0 +1,235 @@
+//!
+//! Operator commands for the controller, shared by every command source
+//! (stdin, the HTTP API, ...) so they all speak the same command language.
+//!
+//! | Command | Effect |
+//! |-------|----------|
+//! | `hello` | Liveness check |
+//! | `q`, `quit` | Shut down outputs and exit |
+//! | `w`, `warmup` | Switch to Warmup drive phase |
+//! | `n`, `nucleate` | Switch to Nucleation drive phase |
+//! | `e`, `elongate` | Switch to Elongation drive phase |
+//! | `h`, `holding` | Switch to Holding drive phase |
+//! | `d`, `dip` | Toggle the cathode dipper monitor |
+//! | `dip on`, `dip off` | Enable or disable the cathode dipper monitor |
+//! | `setpoint <°C>` | Override the furnace temperature set point |
+//! | `setpoint auto` | Return to the automatic set point schedule |
+//! | `furnace off` | Force the furnace heater off, whatever the set point |
+//! | `furnace auto` | Return the heater to set point control |
+//! | `current <mA>` | Hold the electrode drive current at a fixed value |
+//! | `current auto` | Return to the drive current computed by the drive phase |
+//! | `limit <mA>` | Cap the electrode drive current |
+//! | `limit off` | Remove the drive current cap |
+//! | `anodes <n>[,<n>...]` | Connect only the listed anodes (numbered from 1) |
+//! | `anodes all`, `anodes none` | Connect every anode, or none |
+//! | `anodes auto` | Return to the anode pattern chosen by the drive phase |
+//! | `anodes check` | Measure each anode on its own at a low probe current, at the next loop |
+//! | `pattern <pattern> [<ms>[,<ms>...]]` | Elongation anode pattern (see `anode_pattern`), with step dwell times |
+//! | `pattern auto` | Return to the default Elongation anode pattern |
+//! | `charge <phase> <C>` | End Nucleation or Elongation once that much charge has passed in the phase |
+//! | `charge <phase> off` | Remove a phase charge target |
+//! | `pause`, `resume` | Stop or restart the drive phase clock |
+//! | `jog <mm>` | Move the cathode dipper by a distance: positive is down into the melt |
+//! | `home` | Home the cathode dipper against its upper limit |
+//! | `home <mm>` | Declare the dipper's current position, in mm below home |
+//! | `moveto <mm>` | Move the homed cathode dipper to a position, in mm below home |
+//! | `probe` | Probe the melt level with the homed cathode dipper at the next loop |
+//! | `note <text>` | Write an annotation to the run log |
+//! | `get [<field>]` | Query a state field by dotted path, e.g. `get furnace.setpoint_c` |
+//! | `estop`, `stop` | Emergency stop: zero all outputs immediately and exit |
+//!
+
+use std::str::FromStr;
+
+use tokio::sync::{mpsc, oneshot};
+
+use crate::anode_pattern::{AnodePattern, parse_anode_dwells};
+use crate::charge::{parse_charge_coulombs, parse_charge_target_phase};
+use crate::telemetry::ControllerEvent;
+
+/// Leading words of every command, e.g. for completion
+pub const COMMAND_WORDS: [&str; 24] = [
+    "hello", "warmup", "nucleate", "elongate", "holding", "dip", "setpoint", "furnace", "current", "limit",
+    "anodes", "pattern", "charge", "pause", "resume", "jog", "home", "moveto", "probe", "note", "get", "estop", "stop", "quit",
+];
+
+/// Anode selection meaning "every anode", however many there are
+pub const ALL_ANODES: u8 = u8::MAX;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum OperatorCommand {
+    Hello,
+    Quit,
+    Warmup,
+    Nucleate,
+    Elongate,
+    Holding,
+    ToggleDipper,
+    /// Explicitly enable or disable the dipper monitor
+    SetDipper(bool),
+    /// Override the furnace set point, or `None` to return to the automatic schedule
+    Setpoint(Option<f32>),
+    /// Force the furnace heater off (true), or return it to set point control (false)
+    FurnaceOff(bool),
+    /// Fixed electrode drive current in mA, or `None` to follow the drive phase
+    DriveCurrent(Option<f32>),
+    /// Cap on the electrode drive current in mA, or `None` for no cap
+    DriveLimit(Option<f32>),
+    /// Bitmask of anodes to connect (bit 0 = anode 1, `ALL_ANODES` for all),
+    /// or `None` to follow the drive phase
+    Anodes(Option<u8>),
+    /// Anode pattern for the Elongation phase, with step dwell times in ms repeated across the steps
+    /// (empty for the default dwell), or `None` for the default pattern
+    AnodePattern(Option<(AnodePattern, Vec<u64>)>),
+    /// Measure each anode on its own
+    CheckAnodes,
+    /// Charge in coulombs at which a drive phase (a `run_log::DRIVE_PHASE_NAMES` index) ends,
+    /// or `None` to remove the target
+    ChargeTarget(u8, Option<f64>),
+    /// Stop (true) or restart (false) the drive phase clock
+    PausePhaseClock(bool),
+    /// Move the dipper by this many millimeters, positive toward the melt
+    JogDipper(f32),
+    /// Home the dipper against its limit, or take the given position (mm below home) as a reference
+    HomeDipper(Option<f32>),
+    /// Move the homed dipper to a position in mm below home
+    MoveDipperTo(f32),
+    /// Probe the melt surface height with the dipper
+    ProbeMeltLevel,
+    /// Free text to record in the run log
+    Annotate(String),
+    /// Dotted path of a state field to report, or empty for the top-level fields
+    Query(String),
+    EmergencyStop,
+}
+
+impl OperatorCommand {
+    /// Whether the controller exits after running this command
+    pub fn ends_run(&self) -> bool {
+        matches!(self, OperatorCommand::Quit | OperatorCommand::EmergencyStop)
+    }
+
+    /// Whether an interactive operator should confirm this command before it is sent:
+    /// anything that ends the run, jumps the drive phase, overrides the furnace set point
+    /// or drive outputs, or moves the dipper
+    pub fn needs_confirmation(&self) -> bool {
+        matches!(self,
+            OperatorCommand::Quit | OperatorCommand::EmergencyStop |
+            OperatorCommand::Warmup | OperatorCommand::Nucleate |
+            OperatorCommand::Elongate | OperatorCommand::Holding |
+            OperatorCommand::Setpoint(Some(_)) | OperatorCommand::DriveCurrent(Some(_)) |
+            OperatorCommand::Anodes(Some(_)) | OperatorCommand::AnodePattern(Some(_)) | OperatorCommand::CheckAnodes |
+            OperatorCommand::ChargeTarget(_, Some(_)) |
+            OperatorCommand::JogDipper(_) |
+            OperatorCommand::HomeDipper(_) | OperatorCommand::MoveDipperTo(_) |
+            OperatorCommand::ProbeMeltLevel)
+    }
+}
+
+/// Parse a finite number, naming what it is in any error
+fn parse_number(text: &str, what: &str) -> Result<f32, String> {
+    text.parse::<f32>().ok()
+        .filter(|value| value.is_finite())
+        .ok_or_else(|| format!("Bad {what}: {text:?}"))
+}
+
+/// Parse an anode list such as `1,3` (or `1 3`) into a bitmask
+fn parse_anodes(list: &[&str]) -> Result<u8, String> {
+    let mut mask = 0u8;
+    for item in list.iter().flat_map(|word| word.split(',')).filter(|item| !item.is_empty()) {
+        let anode: u32 = item.parse().map_err(|_| format!("Bad anode number: {item:?}"))?;
+        if !(1..=8).contains(&anode) {
+            return Err(format!("Anode number {anode} outside 1..8"));
+        }
+        mask |= 1 << (anode - 1);
+    }
+    Ok(mask)
+}
+
+impl FromStr for OperatorCommand {
+    type Err = String;
+
+    fn from_str(line: &str) -> Result<Self, Self::Err> {
+        // free text is taken verbatim, apart from surrounding whitespace
+        if let Some(("note", text)) = line.trim().split_once(char::is_whitespace) {
+            return Ok(OperatorCommand::Annotate(text.trim().to_string()));
+        }
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let cmd = match words.as_slice() {
+            ["hello"] => OperatorCommand::Hello,
+            ["q"] | ["quit"] => OperatorCommand::Quit,
+            ["w"] | ["warmup"] => OperatorCommand::Warmup,
+            ["n"] | ["nucleate"] => OperatorCommand::Nucleate,
+            ["e"] | ["elongate"] => OperatorCommand::Elongate,
+            ["h"] | ["holding"] => OperatorCommand::Holding,
+            ["d"] | ["dip"] => OperatorCommand::ToggleDipper,
+            ["d" | "dip", "on"] => OperatorCommand::SetDipper(true),
+            ["d" | "dip", "off"] => OperatorCommand::SetDipper(false),
+            ["setpoint", "auto"] => OperatorCommand::Setpoint(None),
+            ["setpoint", temp] => OperatorCommand::Setpoint(Some(parse_number(temp, "setpoint temperature")?)),
+            ["furnace", "off"] => OperatorCommand::FurnaceOff(true),
+            ["furnace", "auto" | "on"] => OperatorCommand::FurnaceOff(false),
+            ["current", "auto"] => OperatorCommand::DriveCurrent(None),
+            ["current", milliamps] => OperatorCommand::DriveCurrent(Some(parse_number(milliamps, "drive current")?)),
+            ["limit", "off"] => OperatorCommand::DriveLimit(None),
+            ["limit", milliamps] => OperatorCommand::DriveLimit(Some(parse_number(milliamps, "current limit")?)),
+            ["anodes", "auto"] => OperatorCommand::Anodes(None),
+            ["anodes", "all"] => OperatorCommand::Anodes(Some(ALL_ANODES)),
+            ["anodes", "none"] => OperatorCommand::Anodes(Some(0)),
+            ["anodes", "check"] => OperatorCommand::CheckAnodes,
+            ["anodes", list @ ..] if !list.is_empty() => OperatorCommand::Anodes(Some(parse_anodes(list)?)),
+            ["pattern", "auto"] => OperatorCommand::AnodePattern(None),
+            ["pattern", pattern] => OperatorCommand::AnodePattern(Some((pattern.parse()?, Vec::new()))),
+            ["pattern", pattern, dwells] => OperatorCommand::AnodePattern(Some((pattern.parse()?, parse_anode_dwells(dwells)?))),
+            ["charge", phase, "off"] => OperatorCommand::ChargeTarget(parse_charge_target_phase(phase)?, None),
+            ["charge", phase, coulombs] =>
+                OperatorCommand::ChargeTarget(parse_charge_target_phase(phase)?, Some(parse_charge_coulombs(coulombs)?)),
+            ["pause"] => OperatorCommand::PausePhaseClock(true),
+            ["resume"] => OperatorCommand::PausePhaseClock(false),
+            ["jog", distance] => OperatorCommand::JogDipper(parse_number(distance, "jog distance")?),
+            ["home"] => OperatorCommand::HomeDipper(None),
+            ["home", position] => OperatorCommand::HomeDipper(Some(parse_number(position, "dipper position")?)),
+            ["moveto", position] => OperatorCommand::MoveDipperTo(parse_number(position, "dipper position")?),
+            ["probe"] => OperatorCommand::ProbeMeltLevel,
+            ["get"] => OperatorCommand::Query(String::new()),
+            ["get", path] => OperatorCommand::Query(path.to_string()),
+            ["estop"] | ["stop"] => OperatorCommand::EmergencyStop,
+            [word, ..] if COMMAND_WORDS.contains(word) =>
+                return Err(format!("Bad arguments for {word:?}: {:?}", line.trim())),
+            _ => return Err(format!("Unknown command: {:?}", line.trim())),
+        };
+        Ok(cmd)
+    }
+}
+
+/// Result of running a command: a message for the operator either way
+pub type CommandReply = Result<String, String>;
+
+/// A command submitted to the control loop, with a way to tell the submitter how it went
+#[derive(Debug)]
+pub struct OperatorRequest {
+    pub command: OperatorCommand,
+    /// Where the command came from, for logging
+    pub source: &'static str,
+    pub reply: oneshot::Sender<CommandReply>,
+}
+
+/// Channel on which command sources submit requests to the control loop
+pub type CommandSender = mpsc::Sender<OperatorRequest>;
+
+/// Record of a command having been run, for event subscribers
+pub fn command_event(epoch_ms: i64, source: &str, command: &OperatorCommand, reply: &CommandReply) -> ControllerEvent {
+    let outcome = match reply {
+        Ok(msg) => format!("ok: {msg}"),
+        Err(msg) => format!("failed: {msg}"),
+    };
+    ControllerEvent::new(epoch_ms, "command", format!("{source} {command:?} {outcome}"))
+}
+
+/// Submit a command to the control loop and wait for its reply
+pub async fn submit_command(commands: &CommandSender, command: OperatorCommand, source: &'static str) -> CommandReply {
+    let (reply, reply_rx) = oneshot::channel();
+    commands.send(OperatorRequest { command, source, reply }).await
+        .map_err(|_| "controller is not accepting commands".to_string())?;
+    reply_rx.await.map_err(|_| "controller dropped the command".to_string())?
+}
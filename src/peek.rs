@@ -0,0 +1,331 @@
+//!
+//! Register peek, poke and watch: the raw register access behind
+//! `craven read`, `craven write` and `craven watch`.
+//!
+//! A `Peek` names a run of values on one node, either by table and address or as
+//! `<model>.<register>` from the device maps. A `ValueFormat` says how the words are shown:
+//! each register in decimal, hex or binary, or decoded through a `Codec`
+//! as signed, 32 bit, float or BCD values with a byte order and scale.
+//! A `Watch` keeps the values from one poll to the next and counts how often each one changes.
+//!
+
+use tokio_modbus::prelude::*;
+
+use crate::cli::parse_u16;
+use crate::codec::*;
+use crate::register_map::*;
+
+/// Most registers one read request can return
+pub const MAX_READ_REGISTERS: u32 = 125;
+
+/// Most coils or discrete inputs one read request can return
+pub const MAX_READ_BITS: u32 = 2000;
+
+/// How register words are shown, and how typed values are turned back into words
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ValueFormat {
+    /// Each register in decimal and hex
+    Raw,
+    /// Each register in hex
+    Hex,
+    /// Each register in binary
+    Binary,
+    /// Values decoded by a codec, each spanning the codec's word count
+    Decoded(Codec),
+}
+
+/// Names accepted by `--format`
+pub const FORMAT_NAMES: &str = "dec, hex, bin, u16, i16, u32, i32, f32, bcd16, bcd32";
+
+/// Names accepted by `--order`
+pub const ORDER_NAMES: &str = "abcd, cdab, badc, dcba";
+
+impl ValueFormat {
+    /// Build a format from the `--format`, `--order` and `--scale` option values.
+    /// Giving only an order or a scale decodes unsigned 16 bit values.
+    pub fn parse(format: Option<&str>, order: Option<&str>, scale: Option<&str>) -> Result<Self, String> {
+        let byte_order = order.map(parse_byte_order).transpose()?;
+        let scale = scale.map(|text| text.parse::<f64>().ok()
+                .filter(|scale| scale.is_normal())
+                .ok_or_else(|| format!("bad scale: {text:?}")))
+            .transpose()?;
+        let encoding = match format {
+            None if byte_order.is_none() && scale.is_none() => return Ok(ValueFormat::Raw),
+            None | Some("u16") => Encoding::U16,
+            Some("dec") | Some("hex") | Some("bin") if byte_order.is_some() || scale.is_some() => {
+                return Err("--order and --scale need a typed --format, such as u16 or i32".into());
+            }
+            Some("dec") => return Ok(ValueFormat::Raw),
+            Some("hex") => return Ok(ValueFormat::Hex),
+            Some("bin") => return Ok(ValueFormat::Binary),
+            Some("i16") => Encoding::I16,
+            Some("u32") => Encoding::U32,
+            Some("i32") => Encoding::I32,
+            Some("f32") => Encoding::F32,
+            Some("bcd16") | Some("bcd") => Encoding::Bcd16,
+            Some("bcd32") => Encoding::Bcd32,
+            Some(other) => return Err(format!("unknown format {other:?}, expected one of {FORMAT_NAMES}")),
+        };
+        let codec = Codec::new(encoding)
+            .with_order(byte_order.unwrap_or(ByteOrder::BigEndian))
+            .scaled(scale.unwrap_or(1.0));
+        Ok(ValueFormat::Decoded(codec))
+    }
+
+    /// Number of registers each value spans
+    pub fn word_count(&self) -> u16 {
+        match self {
+            ValueFormat::Decoded(codec) => codec.word_count(),
+            _ => 1,
+        }
+    }
+
+    /// Show one value from its words
+    pub fn format_value(&self, words: &[u16]) -> Result<String, String> {
+        let word = *words.first().ok_or("no registers to format")?;
+        match self {
+            ValueFormat::Raw => Ok(format!("{word:>5}  0x{word:04X}")),
+            ValueFormat::Hex => Ok(format!("0x{word:04X}")),
+            ValueFormat::Binary => Ok(format!("0b{word:016b}")),
+            ValueFormat::Decoded(codec) => codec.decode(words).map(|value| format_decoded(codec, value)),
+        }
+    }
+
+    /// Turn one command line value into the words it is written as
+    pub fn parse_value(&self, text: &str) -> Result<Vec<u16>, String> {
+        match self {
+            ValueFormat::Decoded(codec) => {
+                let value = text.parse::<f64>().map_err(|_| format!("bad value: {text:?}"))?;
+                codec.encode(value)
+            }
+            _ => parse_u16(text).map(|word| vec![word]),
+        }
+    }
+}
+
+/// Show a decoded value with as many decimals as its scale resolves,
+/// and floats at their own precision rather than widened to f64
+fn format_decoded(codec: &Codec, value: f64) -> String {
+    if codec.encoding == Encoding::F32 {
+        return ((value / codec.scale) as f32 * codec.scale as f32).to_string();
+    }
+    let decimals = (-codec.scale.abs().log10()).ceil().max(0.) as usize;
+    format!("{value:.decimals$}")
+}
+
+fn parse_byte_order(text: &str) -> Result<ByteOrder, String> {
+    match text.to_ascii_lowercase().as_str() {
+        "abcd" | "big" => Ok(ByteOrder::BigEndian),
+        "cdab" | "word-swapped" => Ok(ByteOrder::WordSwapped),
+        "badc" | "byte-swapped" => Ok(ByteOrder::ByteSwapped),
+        "dcba" | "little" => Ok(ByteOrder::LittleEndian),
+        _ => Err(format!("unknown byte order {text:?}, expected one of {ORDER_NAMES}")),
+    }
+}
+
+/// Parse a coil value: on, off, 1 or 0
+pub fn parse_coil(text: &str) -> Result<bool, String> {
+    match text {
+        "on" | "1" => Ok(true),
+        "off" | "0" => Ok(false),
+        _ => Err(format!("expected on or off, not {text:?}")),
+    }
+}
+
+/// A run of values on one node
+#[derive(Debug, Clone, PartialEq)]
+pub struct Peek {
+    pub node_id: u8,
+    pub table: Table,
+    pub address: u16,
+    /// Number of values, each spanning `format.word_count()` registers
+    pub count: u16,
+    pub format: ValueFormat,
+    /// The device map register this peek was named by
+    pub spec: Option<&'static RegisterSpec>,
+}
+
+impl Peek {
+    /// `count` values from `address` in `table`
+    pub fn at(node_id: u8, table: Table, address: u16, count: u16, format: ValueFormat) -> Result<Self, String> {
+        let peek = Peek { node_id, table, address, count, format, spec: None };
+        if count == 0 {
+            return Err("count must be at least 1".into());
+        }
+        if matches!(table, Table::Coil | Table::Discrete) && format != ValueFormat::Raw {
+            return Err("coils and discrete inputs are shown as on/off, without a --format".into());
+        }
+        let word_count = peek.word_count();
+        let (max_read, what) = match table {
+            Table::Coil | Table::Discrete => (MAX_READ_BITS, "bits"),
+            Table::Holding | Table::Input => (MAX_READ_REGISTERS, "registers"),
+        };
+        if word_count > max_read {
+            return Err(format!("{word_count} {what} is more than one read can return (at most {max_read})"));
+        }
+        if address as u32 + word_count > 0x1_0000 {
+            return Err(format!("{word_count} registers from 0x{address:04X} run past 0xFFFF"));
+        }
+        Ok(peek)
+    }
+
+    /// A register named `<model>.<register>` in the device maps, on the model's
+    /// configured node unless `node_id` is given
+    pub fn named(name: &str, node_id: Option<u8>) -> Result<Self, String> {
+        let (model, register) = name.split_once('.')
+            .ok_or_else(|| format!("expected <model>.<register>, not {name:?}"))?;
+        let map = device_map(model).ok_or_else(|| format!("unknown model {model:?} (see `craven map`)"))?;
+        let spec = map.register(register)
+            .ok_or_else(|| format!("{} has no register {register:?} (see `craven map {}`)", map.model, map.model))?;
+        Ok(Peek {
+            node_id: node_id.unwrap_or(map.node_id),
+            table: spec.table,
+            address: spec.address,
+            count: 1,
+            format: ValueFormat::Decoded(spec.codec),
+            spec: Some(spec),
+        })
+    }
+
+    /// Whether an argument names a device map register rather than an address
+    pub fn is_register_name(arg: &str) -> bool {
+        arg.contains('.') && !arg.starts_with(|c: char| c.is_ascii_digit())
+    }
+
+    /// Total number of registers (or coils) covered
+    pub fn word_count(&self) -> u32 {
+        self.count as u32 * self.format.word_count() as u32
+    }
+
+    /// Label each value by its register name, or by its address
+    pub fn labels(&self) -> Vec<String> {
+        match self.spec {
+            Some(spec) => vec![spec.name.to_string()],
+            None => (0..self.count)
+                .map(|idx| format!("0x{:04X}", self.address as u32 + idx as u32 * self.format.word_count() as u32))
+                .collect(),
+        }
+    }
+
+    /// The unit of the values, if they are named
+    pub fn unit(&self) -> &'static str {
+        self.spec.map(|spec| spec.unit).unwrap_or("")
+    }
+
+    /// Read every word (coils and discrete inputs as 0 or 1)
+    pub async fn read_words(&self, ctx: &mut tokio_modbus::client::Context) -> Result<Vec<u16>, Box<dyn std::error::Error>> {
+        if let Some(spec) = self.spec.filter(|spec| !spec.is_readable()) {
+            return Err(format!("{} is write-only", spec.name).into());
+        }
+        ctx.set_slave(Slave(self.node_id));
+        let count = u16::try_from(self.word_count()).map_err(|_| format!("{} registers is too many to read", self.word_count()))?;
+        let words = match self.table {
+            Table::Holding => ctx.read_holding_registers(self.address, count).await??,
+            Table::Input => ctx.read_input_registers(self.address, count).await??,
+            Table::Coil => ctx.read_coils(self.address, count).await??.into_iter().map(u16::from).collect(),
+            Table::Discrete => ctx.read_discrete_inputs(self.address, count).await??.into_iter().map(u16::from).collect(),
+        };
+        Ok(words)
+    }
+
+    /// Show each value of a read, in address order
+    pub fn format_words(&self, words: &[u16]) -> Vec<String> {
+        let width = self.format.word_count() as usize;
+        words.chunks(width)
+            .map(|value_words| match self.table {
+                _ if value_words.len() < width => "(short read)".to_string(),
+                Table::Coil | Table::Discrete if self.spec.is_none() => {
+                    if value_words[0] != 0 { "on" } else { "off" }.to_string()
+                }
+                _ => self.format.format_value(value_words).unwrap_or_else(|e| format!("({e})")),
+            })
+            .collect()
+    }
+
+    /// Read the values, formatted
+    pub async fn read(&self, ctx: &mut tokio_modbus::client::Context) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+        let words = self.read_words(ctx).await?;
+        Ok(self.format_words(&words))
+    }
+
+    /// Write values given as text from `address` on, in the peek's format.
+    /// Named registers take one engineering value; bit fields keep the other bits of their register.
+    pub async fn write(&self, ctx: &mut tokio_modbus::client::Context, values: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+        if values.is_empty() {
+            return Err("nothing to write".into());
+        }
+        if let Some(spec) = self.spec {
+            let [value] = values else {
+                return Err(format!("{} takes a single value", spec.name).into());
+            };
+            let value = match spec.table {
+                Table::Coil => parse_coil(value)? as u16 as f64,
+                _ => value.parse::<f64>().map_err(|_| format!("bad value: {value:?}"))?,
+            };
+            return spec.write_value(ctx, self.node_id, value).await;
+        }
+        ctx.set_slave(Slave(self.node_id));
+        match self.table {
+            Table::Holding => {
+                let words = values.iter()
+                    .map(|value| self.format.parse_value(value))
+                    .collect::<Result<Vec<Vec<u16>>, String>>()?
+                    .concat();
+                match words.as_slice() {
+                    [word] => ctx.write_single_register(self.address, *word).await??,
+                    _ => ctx.write_multiple_registers(self.address, &words).await??,
+                }
+            }
+            Table::Coil => {
+                let bits = values.iter().map(|value| parse_coil(value)).collect::<Result<Vec<bool>, String>>()?;
+                match bits.as_slice() {
+                    [bit] => ctx.write_single_coil(self.address, *bit).await??,
+                    _ => ctx.write_multiple_coils(self.address, &bits).await??,
+                }
+            }
+            Table::Input | Table::Discrete => return Err(format!("{:?} table is read-only", self.table).into()),
+        }
+        Ok(())
+    }
+}
+
+/// Values from successive polls of a watch, remembering the last of each and counting changes
+#[derive(Debug, Clone, Default)]
+pub struct Watch {
+    previous: Vec<Option<String>>,
+    /// How many times each value has changed
+    pub changes: Vec<u32>,
+    pub polls: u32,
+    /// Polls that failed to read
+    pub errors: u32,
+}
+
+impl Watch {
+    pub fn new(value_count: usize) -> Self {
+        Watch { previous: vec![None; value_count], changes: vec![0; value_count], ..Default::default() }
+    }
+
+    /// Record one poll, returning which values differ from the previous poll.
+    /// Nothing counts as changed on the first poll.
+    pub fn update(&mut self, values: &[String]) -> Vec<bool> {
+        self.polls += 1;
+        values.iter().enumerate().map(|(idx, value)| {
+            if idx >= self.previous.len() {
+                self.previous.resize(idx + 1, None);
+                self.changes.resize(idx + 1, 0);
+            }
+            let changed = self.previous[idx].as_ref().is_some_and(|previous| previous != value);
+            if changed {
+                self.changes[idx] += 1;
+            }
+            self.previous[idx] = Some(value.clone());
+            changed
+        }).collect()
+    }
+
+    /// Record a poll that failed
+    pub fn record_error(&mut self) {
+        self.polls += 1;
+        self.errors += 1;
+    }
+}
@@ -0,0 +1,678 @@
+//!
+//! Declarative register maps, one per device model.
+//!
+//! Each map lists every register we use on a model: its table (holding, input, coil or discrete),
+//! address, encoding and byte order, scale, unit and access. `register_map!` turns that
+//! description into typed `Register<T>` constants with `read` / `write` accessors, plus a
+//! `DeviceMap` that tools can search by name and print as documentation (`craven map`).
+//!
+//! Values are converted by the register's `Codec`: raw values are multiplied by the scale on read,
+//! and divided by it on write, so a temperature register counting tenths of a degree has a scale
+//! of 0.1 and unit "°C".
+//!
+
+use std::marker::PhantomData;
+
+use tokio_modbus::prelude::*;
+
+use crate::*;
+use crate::codec::*;
+
+/// Which Modbus table a register lives in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Table {
+    Holding,
+    Input,
+    Coil,
+    Discrete,
+}
+
+/// How a register may be used
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Access {
+    Read,
+    Write,
+    ReadWrite,
+}
+
+/// The description of one register
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RegisterSpec {
+    pub name: &'static str,
+    pub table: Table,
+    pub address: u16,
+    pub codec: Codec,
+    pub unit: &'static str,
+    pub access: Access,
+}
+
+impl RegisterSpec {
+    pub fn is_readable(&self) -> bool {
+        self.access != Access::Write
+    }
+
+    pub fn is_writable(&self) -> bool {
+        self.access != Access::Read && matches!(self.table, Table::Holding | Table::Coil)
+    }
+
+    /// Number of registers (or coils) the value spans
+    pub fn word_count(&self) -> u16 {
+        self.codec.word_count()
+    }
+
+    /// Convert raw register words into the scaled engineering value
+    pub fn decode(&self, words: &[u16]) -> Result<f64, String> {
+        self.codec.decode(words).map_err(|e| format!("{}: {e}", self.name))
+    }
+
+    /// Convert a scaled engineering value into raw register words, refusing values the register can't hold
+    pub fn encode(&self, value: f64) -> Result<Vec<u16>, String> {
+        self.codec.encode(value).map_err(|e| format!("{}: {e} {}", self.name, self.unit))
+    }
+
+    /// Decode this register out of a block of registers read starting at `block_start`
+    pub fn decode_in(&self, block_start: u16, block: &[u16]) -> Result<f64, String> {
+        let offset = self.address.checked_sub(block_start)
+            .filter(|offset| *offset as usize + self.word_count() as usize <= block.len())
+            .ok_or_else(|| format!("{} (0x{:04X}) is outside the block read at 0x{block_start:04X}", self.name, self.address))?;
+        self.decode(&block[offset as usize..])
+    }
+
+    /// Read the register from `node_id`, returning the scaled value
+    pub async fn read_value(&self, ctx: &mut tokio_modbus::client::Context, node_id: u8)
+    -> Result<f64, Box<dyn std::error::Error>>
+    {
+        if !self.is_readable() {
+            return Err(format!("{} is write-only", self.name).into());
+        }
+        ctx.set_slave(Slave(node_id));
+        let count = self.word_count();
+        let words: Vec<u16> = match self.table {
+            Table::Holding => ctx.read_holding_registers(self.address, count).await??,
+            Table::Input => ctx.read_input_registers(self.address, count).await??,
+            Table::Coil => ctx.read_coils(self.address, count).await??.into_iter().map(u16::from).collect(),
+            Table::Discrete => ctx.read_discrete_inputs(self.address, count).await??.into_iter().map(u16::from).collect(),
+        };
+        Ok(self.decode(&words)?)
+    }
+
+    /// Write a scaled value to the register on `node_id`
+    pub async fn write_value(&self, ctx: &mut tokio_modbus::client::Context, node_id: u8, value: f64)
+    -> Result<(), Box<dyn std::error::Error>>
+    {
+        if !self.is_writable() {
+            return Err(format!("{} is read-only", self.name).into());
+        }
+        ctx.set_slave(Slave(node_id));
+        let words = match self.codec.encoding {
+            // bit fields share their register, so keep the other bits
+            Encoding::Bits(..) => {
+                let current = ctx.read_holding_registers(self.address, 1).await??[0];
+                vec![self.codec.merge(current, value).map_err(|e| format!("{}: {e}", self.name))?]
+            }
+            _ => self.encode(value)?,
+        };
+        match (self.table, words.as_slice()) {
+            (Table::Coil, [word]) => ctx.write_single_coil(self.address, *word != 0).await??,
+            (_, [word]) => ctx.write_single_register(self.address, *word).await??,
+            (_, words) => ctx.write_multiple_registers(self.address, words).await??,
+        }
+        Ok(())
+    }
+}
+
+/// Engineering value types that registers can be read as, or written from
+pub trait RegisterValue: Copy {
+    fn from_f64(value: f64) -> Self;
+    fn to_f64(self) -> f64;
+}
+
+impl RegisterValue for f32 {
+    fn from_f64(value: f64) -> Self { value as f32 }
+    fn to_f64(self) -> f64 { self as f64 }
+}
+
+impl RegisterValue for f64 {
+    fn from_f64(value: f64) -> Self { value }
+    fn to_f64(self) -> f64 { self }
+}
+
+impl RegisterValue for bool {
+    fn from_f64(value: f64) -> Self { value != 0. }
+    fn to_f64(self) -> f64 { self as u8 as f64 }
+}
+
+macro_rules! integer_register_value {
+    ($($int:ty),*) => {
+        $(impl RegisterValue for $int {
+            fn from_f64(value: f64) -> Self { value.round() as $int }
+            fn to_f64(self) -> f64 { self as f64 }
+        })*
+    };
+}
+integer_register_value!(u16, i16, u32, i32);
+
+/// A register on a particular model, read and written as `T`
+#[derive(Debug, Clone, Copy)]
+pub struct Register<T: RegisterValue> {
+    pub spec: RegisterSpec,
+    /// The node ID the model is configured with on our bus
+    pub node_id: u8,
+    value_type: PhantomData<T>,
+}
+
+impl<T: RegisterValue> Register<T> {
+    pub const fn new(spec: RegisterSpec, node_id: u8) -> Self {
+        Self { spec, node_id, value_type: PhantomData }
+    }
+
+    /// Read from the model's usual node ID
+    pub async fn read(&self, ctx: &mut tokio_modbus::client::Context) -> Result<T, Box<dyn std::error::Error>> {
+        self.read_from(ctx, self.node_id).await
+    }
+
+    /// Read from a specific node ID, for a second device of the same model
+    pub async fn read_from(&self, ctx: &mut tokio_modbus::client::Context, node_id: u8)
+    -> Result<T, Box<dyn std::error::Error>>
+    {
+        Ok(T::from_f64(self.spec.read_value(ctx, node_id).await?))
+    }
+
+    /// Decode this register out of a block read starting at `block_start`
+    pub fn decode_in(&self, block_start: u16, block: &[u16]) -> Result<T, String> {
+        Ok(T::from_f64(self.spec.decode_in(block_start, block)?))
+    }
+
+    /// Write to the model's usual node ID
+    pub async fn write(&self, ctx: &mut tokio_modbus::client::Context, value: T) -> Result<(), Box<dyn std::error::Error>> {
+        self.write_to(ctx, self.node_id, value).await
+    }
+
+    /// Write to a specific node ID
+    pub async fn write_to(&self, ctx: &mut tokio_modbus::client::Context, node_id: u8, value: T)
+    -> Result<(), Box<dyn std::error::Error>>
+    {
+        self.spec.write_value(ctx, node_id, value.to_f64()).await
+    }
+}
+
+/// Read the span of registers covering `specs` (all in the same table) in a single request,
+/// returning the block start address and the raw words, for decoding with `decode_in`
+pub async fn read_block(ctx: &mut tokio_modbus::client::Context, node_id: u8, specs: &[&RegisterSpec])
+-> Result<(u16, Vec<u16>), Box<dyn std::error::Error>>
+{
+    let Some((table, first, count)) = block_span(specs)? else {
+        return Ok((0, Vec::new()));
+    };
+    ctx.set_slave(Slave(node_id));
+    let block: Vec<u16> = match table {
+        Table::Holding => ctx.read_holding_registers(first, count).await??,
+        Table::Input => ctx.read_input_registers(first, count).await??,
+        Table::Coil => ctx.read_coils(first, count).await??.into_iter().map(u16::from).collect(),
+        Table::Discrete => ctx.read_discrete_inputs(first, count).await??.into_iter().map(u16::from).collect(),
+    };
+    Ok((first, block))
+}
+
+/// The table, start address and register count a block read of `specs` covers,
+/// or `None` for no registers
+pub fn block_span(specs: &[&RegisterSpec]) -> Result<Option<(Table, u16, u16)>, String> {
+    let (Some(first), Some(table)) = (specs.iter().map(|spec| spec.address).min(), specs.first().map(|spec| spec.table)) else {
+        return Ok(None);
+    };
+    if specs.iter().any(|spec| spec.table != table) {
+        return Err("a block read needs registers from a single table".into());
+    }
+    // a register at the top of the address space ends past u16
+    let end = specs.iter().map(|spec| spec.address as u32 + spec.word_count() as u32).max().unwrap_or(first as u32);
+    let count = u16::try_from(end - first as u32).ok()
+        .filter(|_| end <= u16::MAX as u32 + 1)
+        .ok_or_else(|| format!("a block read from 0x{first:04X} runs past the end of the address space"))?;
+    Ok(Some((table, first, count)))
+}
+
+/// Every register of one device model
+#[derive(Debug, Clone, Copy)]
+pub struct DeviceMap {
+    /// Short model name, as used on the command line
+    pub model: &'static str,
+    pub description: &'static str,
+    /// The node ID the model is configured with on our bus
+    pub node_id: u8,
+    pub registers: &'static [RegisterSpec],
+}
+
+impl DeviceMap {
+    /// Find a register by name, ignoring case
+    pub fn register(&self, name: &str) -> Option<&'static RegisterSpec> {
+        self.registers.iter().find(|spec| spec.name.eq_ignore_ascii_case(name))
+    }
+
+    /// The map as a Markdown table
+    pub fn to_markdown(&self) -> String {
+        let mut text = format!("{} ({}), node 0x{:02X}\n\n", self.model, self.description, self.node_id);
+        text.push_str("| Register | Table | Address | Encoding | Scale | Unit | Access |\n");
+        text.push_str("|-------|-------|-------|-------|-------|-------|-------|\n");
+        for spec in self.registers {
+            let encoding = match spec.codec.byte_order {
+                ByteOrder::BigEndian => format!("{:?}", spec.codec.encoding),
+                byte_order => format!("{:?} {:?}", spec.codec.encoding, byte_order),
+            };
+            text.push_str(&format!("| {} | {:?} | 0x{:04X} | {} | {} | {} | {:?} |\n",
+                spec.name, spec.table, spec.address, encoding, spec.codec.scale, spec.unit, spec.access));
+        }
+        text
+    }
+}
+
+/// Declare a device model's register map as a module of typed `Register` constants:
+///
+/// ```text
+/// register_map! {
+///     /// Model description
+///     pub mod model_name ("Model description", NODE_ID_CONST) {
+///         /// What the register holds
+///         NAME: f32 = holding 0x0010 I16 * 0.1 "°C" read;
+///         WIDE: u32 = input 0x0020 U32 [WordSwapped] "" read;
+///         FLAGS: u16 = holding 0x0030 Bits(4, 3) "" read_write;
+///     }
+/// }
+/// ```
+///
+/// Tables are `holding`, `input`, `coil` or `discrete`; access is `read`, `write` or `read_write`.
+/// The encoding is a `codec::Encoding` variant; the byte order defaults to `BigEndian`, and the scale to 1.
+macro_rules! register_map {
+    (
+        $(#[$mod_meta:meta])*
+        $vis:vis mod $model:ident ($description:literal, $node:ident) {
+            $(
+                $(#[$meta:meta])*
+                $name:ident : $ty:ty = $table:ident $address:literal $encoding:ident $(($($field:literal),+))?
+                    $([$byte_order:ident])? $(* $scale:literal)? $unit:literal $access:ident;
+            )*
+        }
+    ) => {
+        $(#[$mod_meta])*
+        $vis mod $model {
+            use super::*;
+
+            /// The node ID this model is configured with on our bus
+            pub const NODE_ID: u8 = $node;
+
+            $(
+                $(#[$meta])*
+                #[doc = ""]
+                #[doc = concat!("`", stringify!($table), " ", stringify!($address), "` ",
+                    stringify!($encoding $(($($field),+))?), $(" ", stringify!($byte_order),)? $(", × ", stringify!($scale),)?
+                    ", ", $unit, " (", stringify!($access), ")")]
+                pub const $name: Register<$ty> = Register::new(RegisterSpec {
+                    name: stringify!($name),
+                    table: register_map!(@table $table),
+                    address: $address,
+                    codec: Codec {
+                        encoding: Encoding::$encoding $(($($field),+))?,
+                        byte_order: register_map!(@byte_order $($byte_order)?),
+                        scale: register_map!(@scale $($scale)?),
+                    },
+                    unit: $unit,
+                    access: register_map!(@access $access),
+                }, NODE_ID);
+            )*
+
+            /// This model's complete register map
+            pub const MAP: DeviceMap = DeviceMap {
+                model: stringify!($model),
+                description: $description,
+                node_id: NODE_ID,
+                registers: &[$($name.spec),*],
+            };
+        }
+    };
+    (@table holding) => { Table::Holding };
+    (@table input) => { Table::Input };
+    (@table coil) => { Table::Coil };
+    (@table discrete) => { Table::Discrete };
+    (@access read) => { Access::Read };
+    (@access write) => { Access::Write };
+    (@access read_write) => { Access::ReadWrite };
+    (@byte_order) => { ByteOrder::BigEndian };
+    (@byte_order $byte_order:ident) => { ByteOrder::$byte_order };
+    (@scale) => { 1.0 };
+    (@scale $scale:literal) => { $scale };
+}
+
+register_map! {
+    /// YK-KTC1202 dual Type-K thermocouple reader
+    pub mod ykktc1202 ("dual Type-K thermocouple reader", NODEID_YKKTC1202_DUAL_TK) {
+        /// Channel 1 temperature
+        TEMPERATURE_1: f32 = holding 0x0000 I16 * 0.1 "°C" read;
+        /// Channel 2 temperature
+        TEMPERATURE_2: f32 = holding 0x0001 I16 * 0.1 "°C" read;
+        /// Channel 1 thermocouple state: 0 connected, 1 not connected
+        PROBE_OPEN_1: bool = holding 0x0010 U16 "" read;
+        /// Channel 2 thermocouple state: 0 connected, 1 not connected
+        PROBE_OPEN_2: bool = holding 0x0011 U16 "" read;
+        NODE_ADDRESS: u16 = holding 0x0020 U16 "" read_write;
+        /// Baud rate code 0-6: ... 19200, 38400, 57600, 115200
+        BAUD: u16 = holding 0x0021 U16 "" read_write;
+    }
+}
+
+register_map! {
+    /// YK-PVCCS0100 precision current source, 0-100 mA
+    pub mod ykpvccs0100 ("precision current source, 0-100 mA", NODEID_YKPVCCS010_CURR_SRC) {
+        NODE_ADDRESS: u16 = holding 0x0000 U16 "" read_write;
+        /// Baud rate code 0-6: ... 19200, 38400, 57600, 115200
+        BAUD: u16 = holding 0x0001 U16 "" read_write;
+        /// Write 1 to persist the configuration
+        SAVE_CONFIG: u16 = holding 0x0002 U16 "" write;
+        /// Ordered output current
+        DRIVE_CURRENT: f32 = holding 0x0010 U16 * 0.1 "mA" read_write;
+        /// Output current measured by the source's own ammeter
+        MONITOR_CURRENT: f32 = holding 0x0011 U16 * 0.1 "mA" read;
+    }
+}
+
+register_map! {
+    /// YK-PVCC1000 precision current source, 0-1000 mA
+    pub mod ykpvccs1000 ("precision current source, 0-1000 mA", NODEID_YKPVCCS010_CURR_SRC) {
+        NODE_ADDRESS: u16 = holding 0x0000 U16 "" read_write;
+        /// Baud rate code 0-6: ... 19200, 38400, 57600, 115200
+        BAUD: u16 = holding 0x0001 U16 "" read_write;
+        /// Write 1 to persist the configuration
+        SAVE_CONFIG: u16 = holding 0x0002 U16 "" write;
+        /// Ordered output current
+        DRIVE_CURRENT: f32 = holding 0x0010 U16 "mA" read_write;
+        /// Output current measured by the source's own ammeter
+        MONITOR_CURRENT: f32 = holding 0x0011 U16 "mA" read;
+    }
+}
+
+register_map! {
+    /// WDCU3003 electrode voltage and current meter
+    pub mod wdcu3003 ("electrode voltage and current meter", NODEID_WDCU3003_IV_ADC) {
+        VOLTAGE: f32 = holding 0x0000 U16 * 0.001 "V" read;
+        /// Nonzero when the current reading is out of its low range: derive current from power instead
+        HIGH_RANGE: bool = holding 0x0001 U16 "" read;
+        CURRENT: f32 = holding 0x0002 U16 * 0.001 "mA" read;
+        POWER: f32 = holding 0x0003 U16 "mW" read;
+    }
+}
+
+register_map! {
+    /// ELECDEMO YK-DAQ1402 0-10 V, 0-5 A meter
+    pub mod ykdaq1402 ("0-10 V, 0-5 A meter", NODEID_YKDAQ1402_IV_ADC) {
+        VOLTAGE: f32 = holding 0x0000 I32 * 0.0001 "V" read;
+        CURRENT: f32 = holding 0x0002 I32 * 0.1 "mA" read;
+        NODE_ADDRESS: u16 = holding 0x0040 U16 "" read_write;
+    }
+}
+
+register_map! {
+    /// N4VIA02 dual channel 0-1 A meter
+    pub mod n4via02 ("dual channel 0-1 A meter", NODEID_N4VIA02_IV_ADC) {
+        CURRENT_1: f32 = holding 0x0000 U16 * 0.5 "mA" read;
+        CURRENT_2: f32 = holding 0x0001 U16 * 0.5 "mA" read;
+        CONFIG: u16 = holding 0x00FA U16 "" read_write;
+        NODE_ADDRESS: u16 = holding 0x00FD U16 "" read_write;
+    }
+}
+
+register_map! {
+    /// N4AIA04 4-20 mA meter
+    pub mod n4aia04 ("4-20 mA meter", NODEID_N4AIA04_IV_ADC) {
+        VOLTAGE_1: f32 = holding 0x0000 U16 * 0.01 "V" read;
+        VOLTAGE_2: f32 = holding 0x0001 U16 * 0.01 "V" read;
+        CURRENT_1: f32 = holding 0x0002 U16 * 0.1 "mA" read;
+        CURRENT_2: f32 = holding 0x0003 U16 * 0.1 "mA" read;
+        NODE_ADDRESS: u16 = holding 0x000E U16 "" read_write;
+        /// Baud rate code 0-4: 1200, 2400, 4800, 9600, 19200
+        BAUD: u16 = holding 0x000F U16 "" read_write;
+    }
+}
+
+register_map! {
+    /// N4IOA01 4-20 mA current loop source
+    pub mod n4ioa01 ("4-20 mA current loop source", NODEID_N4IOA01_CURR_GEN) {
+        CURRENT: f32 = holding 0x0000 U16 * 0.01 "mA" read_write;
+        NODE_ADDRESS: u16 = holding 0x000E U16 "" read_write;
+    }
+}
+
+register_map! {
+    /// Waveshare WA8TAI 8 channel analog input
+    pub mod wa8tai ("8 channel analog input", NODEID_WA8TAI_IV_ADC) {
+        /// Channel reading: volts or milliamps, depending on the channel's data mode
+        CHANNEL_1: f32 = input 0x0000 U16 * 0.001 "V or mA" read;
+        CHANNEL_2: f32 = input 0x0001 U16 * 0.001 "V or mA" read;
+        CHANNEL_3: f32 = input 0x0002 U16 * 0.001 "V or mA" read;
+        CHANNEL_4: f32 = input 0x0003 U16 * 0.001 "V or mA" read;
+        CHANNEL_5: f32 = input 0x0004 U16 * 0.001 "V or mA" read;
+        CHANNEL_6: f32 = input 0x0005 U16 * 0.001 "V or mA" read;
+        CHANNEL_7: f32 = input 0x0006 U16 * 0.001 "V or mA" read;
+        CHANNEL_8: f32 = input 0x0007 U16 * 0.001 "V or mA" read;
+        /// Channel data mode code
+        MODE_1: u16 = holding 0x1000 U16 "" read_write;
+        MODE_2: u16 = holding 0x1001 U16 "" read_write;
+        MODE_3: u16 = holding 0x1002 U16 "" read_write;
+        MODE_4: u16 = holding 0x1003 U16 "" read_write;
+        MODE_5: u16 = holding 0x1004 U16 "" read_write;
+        MODE_6: u16 = holding 0x1005 U16 "" read_write;
+        MODE_7: u16 = holding 0x1006 U16 "" read_write;
+        MODE_8: u16 = holding 0x1007 U16 "" read_write;
+        /// Baud rate code 0-7: ... 19200, 38400, 57600, 115200, 128000, 256000
+        BAUD: u16 = holding 0x2000 U16 "" read_write;
+        NODE_ADDRESS: u16 = holding 0x4000 U16 "" read_write;
+    }
+}
+
+register_map! {
+    /// Waveshare WA26419 8 channel 0-20 mA analog output
+    pub mod wa26419 ("8 channel 0-20 mA analog output", NODEID_WA26419_8CH_DAC) {
+        OUTPUT_1: f32 = holding 0x0000 U16 * 0.001 "mA" read_write;
+        OUTPUT_2: f32 = holding 0x0001 U16 * 0.001 "mA" read_write;
+        OUTPUT_3: f32 = holding 0x0002 U16 * 0.001 "mA" read_write;
+        OUTPUT_4: f32 = holding 0x0003 U16 * 0.001 "mA" read_write;
+        OUTPUT_5: f32 = holding 0x0004 U16 * 0.001 "mA" read_write;
+        OUTPUT_6: f32 = holding 0x0005 U16 * 0.001 "mA" read_write;
+        OUTPUT_7: f32 = holding 0x0006 U16 * 0.001 "mA" read_write;
+        OUTPUT_8: f32 = holding 0x0007 U16 * 0.001 "mA" read_write;
+        /// Channel output mode code: 0 = 0-20 mA, 1 = 4-20 mA, 2 = raw DAC code
+        MODE_1: u16 = holding 0x1000 U16 "" read_write;
+        MODE_2: u16 = holding 0x1001 U16 "" read_write;
+        MODE_3: u16 = holding 0x1002 U16 "" read_write;
+        MODE_4: u16 = holding 0x1003 U16 "" read_write;
+        MODE_5: u16 = holding 0x1004 U16 "" read_write;
+        MODE_6: u16 = holding 0x1005 U16 "" read_write;
+        MODE_7: u16 = holding 0x1006 U16 "" read_write;
+        MODE_8: u16 = holding 0x1007 U16 "" read_write;
+        BAUD: u16 = holding 0x2000 U16 "" read_write;
+        NODE_ADDRESS: u16 = holding 0x4000 U16 "" read_write;
+    }
+}
+
+register_map! {
+    /// Waveshare 8 relay board v3 (SKU 17658)
+    pub mod wav_octo_relay ("8 relay board", NODEID_WAV_OCTO_RELAY) {
+        RELAY_1: bool = coil 0x0000 Bool "" read_write;
+        RELAY_2: bool = coil 0x0001 Bool "" read_write;
+        RELAY_3: bool = coil 0x0002 Bool "" read_write;
+        RELAY_4: bool = coil 0x0003 Bool "" read_write;
+        RELAY_5: bool = coil 0x0004 Bool "" read_write;
+        RELAY_6: bool = coil 0x0005 Bool "" read_write;
+        /// Furnace heater relay
+        RELAY_7: bool = coil 0x0006 Bool "" read_write;
+        RELAY_8: bool = coil 0x0007 Bool "" read_write;
+        /// Baud rate code 0-7: ... 19200, 38400, 57600, 115200, 128000, 256000
+        BAUD: u16 = holding 0x2000 U16 "" read_write;
+        NODE_ADDRESS: u16 = holding 0x4000 U16 "" read_write;
+    }
+}
+
+register_map! {
+    /// Eletechsup R4DVI04 quad relay with analog inputs
+    pub mod r4dvi04 ("quad relay with analog inputs", NODEID_R4DVI04_QRELAY_ADC) {
+        RELAY_1: bool = coil 0x0000 Bool "" read_write;
+        RELAY_2: bool = coil 0x0001 Bool "" read_write;
+        RELAY_3: bool = coil 0x0002 Bool "" read_write;
+        RELAY_4: bool = coil 0x0003 Bool "" read_write;
+        NODE_ADDRESS: u16 = holding 0x00FD U16 "" read_write;
+        /// Baud rate code 0-7: ... 19200, 38400, 57600, 115200
+        BAUD: u16 = holding 0x00FE U16 "" read_write;
+    }
+}
+
+register_map! {
+    /// SMC05 stepper motor driver, moving the dipper
+    pub mod smc05 ("dipper stepper motor driver", NODEID_SMC05_STEP_DRIVER) {
+        /// Action mode, such as run-until-stopped (3) or a forward/reverse loop (6)
+        SPORT_MODE: u16 = holding 0x0000 U16 "" read_write;
+        /// Forward acceleration (unverified)
+        FWD_ACCELERATION: u16 = holding 0x0001 U16 "rpm/s" read_write;
+        /// Pulses per forward action, in the pulse-count sport modes (unverified)
+        FWD_PULSES: u16 = holding 0x0002 U16 "pulses" read_write;
+        FWD_SPEED: f32 = holding 0x0003 U16 * 0.1 "rpm" read_write;
+        /// Reverse acceleration (unverified)
+        REV_ACCELERATION: u16 = holding 0x0004 U16 "rpm/s" read_write;
+        /// Pulses per reverse action, in the pulse-count sport modes (unverified)
+        REV_PULSES: u16 = holding 0x0005 U16 "pulses" read_write;
+        REV_SPEED: f32 = holding 0x0006 U16 * 0.1 "rpm" read_write;
+        /// Pause after each forward action, in the loop sport modes (unverified)
+        FWD_DELAY: f32 = holding 0x0007 U16 * 0.1 "s" read_write;
+        /// Pause after each reverse action, in the loop sport modes (unverified)
+        REV_DELAY: f32 = holding 0x0008 U16 * 0.1 "s" read_write;
+        /// Actions per run in the loop sport modes; 0 loops until stopped (unverified)
+        LOOP_COUNT: u16 = holding 0x0009 U16 "" read_write;
+        /// Pulses per motor revolution, matching the driver's microstep setting (unverified)
+        PULSES_PER_REV: u16 = holding 0x000A U16 "pulses" read_write;
+        /// 1 runs the sport mode at power on (unverified)
+        POWER_ON_START: u16 = holding 0x000B U16 "" read_write;
+        NODE_ADDRESS: u16 = holding 0x0018 U16 "" read_write;
+        /// Baud rate code (unverified)
+        BAUD: u16 = holding 0x0019 U16 "" read_write;
+        /// 0 stop, 1 acceleration, 2 deceleration, 3 uniform speed
+        MOTOR_STATUS: u16 = holding 0x001A U16 "" read;
+        /// 0 forward, 1 reverse
+        MOTION_DIRECTION: u16 = holding 0x001B U16 "" read;
+        /// Speed right now, through the acceleration ramps (unverified)
+        CURRENT_SPEED: f32 = holding 0x001C U16 * 0.1 "rpm" read;
+        PULSE_COUNT: u16 = holding 0x001E U16 "" read;
+        ACTION_COUNT: u16 = holding 0x0022 U16 "" read;
+        /// Serial command: 1 forward, 2 reverse, 3 start/stop
+        OPERATION: u16 = holding 0x0030 U16 "" write;
+    }
+}
+
+/// Register maps of every model we know
+pub static DEVICE_MAPS: [DeviceMap; 13] = [
+    ykktc1202::MAP,
+    ykpvccs1000::MAP,
+    ykpvccs0100::MAP,
+    wdcu3003::MAP,
+    ykdaq1402::MAP,
+    n4via02::MAP,
+    n4aia04::MAP,
+    n4ioa01::MAP,
+    wa8tai::MAP,
+    wa26419::MAP,
+    wav_octo_relay::MAP,
+    r4dvi04::MAP,
+    smc05::MAP,
+];
+
+/// Look up a model's map by name, ignoring case
+pub fn device_map(model: &str) -> Option<&'static DeviceMap> {
+    DEVICE_MAPS.iter().find(|map| map.model.eq_ignore_ascii_case(model))
+}
+
+/// The map of the model configured at `node_id` on our bus
+pub fn device_map_for_node(node_id: u8) -> Option<&'static DeviceMap> {
+    DEVICE_MAPS.iter().find(|map| map.node_id == node_id)
+}
+
+/// WA8TAI channel readings, in channel order
+pub const WA8TAI_CHANNELS: [Register<f32>; 8] = [
+    wa8tai::CHANNEL_1, wa8tai::CHANNEL_2, wa8tai::CHANNEL_3, wa8tai::CHANNEL_4,
+    wa8tai::CHANNEL_5, wa8tai::CHANNEL_6, wa8tai::CHANNEL_7, wa8tai::CHANNEL_8,
+];
+
+/// WA8TAI channel data modes, in channel order
+pub const WA8TAI_MODES: [Register<u16>; 8] = [
+    wa8tai::MODE_1, wa8tai::MODE_2, wa8tai::MODE_3, wa8tai::MODE_4,
+    wa8tai::MODE_5, wa8tai::MODE_6, wa8tai::MODE_7, wa8tai::MODE_8,
+];
+
+/// WA26419 output currents, in channel order
+pub const WA26419_OUTPUTS: [Register<f32>; 8] = [
+    wa26419::OUTPUT_1, wa26419::OUTPUT_2, wa26419::OUTPUT_3, wa26419::OUTPUT_4,
+    wa26419::OUTPUT_5, wa26419::OUTPUT_6, wa26419::OUTPUT_7, wa26419::OUTPUT_8,
+];
+
+/// WA26419 channel output modes, in channel order
+pub const WA26419_MODES: [Register<u16>; 8] = [
+    wa26419::MODE_1, wa26419::MODE_2, wa26419::MODE_3, wa26419::MODE_4,
+    wa26419::MODE_5, wa26419::MODE_6, wa26419::MODE_7, wa26419::MODE_8,
+];
+
+/// Octo relay board relays, in channel order
+pub const OCTO_RELAYS: [Register<bool>; 8] = [
+    wav_octo_relay::RELAY_1, wav_octo_relay::RELAY_2, wav_octo_relay::RELAY_3, wav_octo_relay::RELAY_4,
+    wav_octo_relay::RELAY_5, wav_octo_relay::RELAY_6, wav_octo_relay::RELAY_7, wav_octo_relay::RELAY_8,
+];
+
+/// R4DVI04 relays, in channel order
+pub const R4DVI04_RELAYS: [Register<bool>; 4] = [
+    r4dvi04::RELAY_1, r4dvi04::RELAY_2, r4dvi04::RELAY_3, r4dvi04::RELAY_4,
+];
+
+/// Look up a 1-based channel in one of the channel tables above
+pub fn channel_register<T: RegisterValue>(channels: &[Register<T>], channel: u8) -> Result<&Register<T>, String> {
+    (channel as usize).checked_sub(1).and_then(|idx| channels.get(idx))
+        .ok_or_else(|| format!("channel {channel} outside 1..{}", channels.len()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spec(table: Table, address: u16, encoding: Encoding) -> RegisterSpec {
+        RegisterSpec { name: "TEST", table, address, codec: Codec::new(encoding), unit: "", access: Access::Read }
+    }
+
+    #[test]
+    fn decode_in_finds_the_register_at_its_offset() {
+        let block = [0x0001, 0x0002, 0x0003, 0x0004];
+        assert_eq!(spec(Table::Holding, 0x10, Encoding::U16).decode_in(0x10, &block), Ok(1.));
+        assert_eq!(spec(Table::Holding, 0x13, Encoding::U16).decode_in(0x10, &block), Ok(4.));
+        assert_eq!(spec(Table::Holding, 0x12, Encoding::U32).decode_in(0x10, &block), Ok(0x0003_0004 as f64));
+    }
+
+    #[test]
+    fn decode_in_rejects_registers_outside_the_block() {
+        let block = [0x0001, 0x0002];
+        // before the block, past it, and straddling its end
+        assert!(spec(Table::Holding, 0x0F, Encoding::U16).decode_in(0x10, &block).is_err());
+        assert!(spec(Table::Holding, 0x12, Encoding::U16).decode_in(0x10, &block).is_err());
+        assert!(spec(Table::Holding, 0x11, Encoding::U32).decode_in(0x10, &block).is_err());
+        // at the top of the address space
+        assert!(spec(Table::Holding, 0xFFFF, Encoding::U32).decode_in(0xFFFF, &[0]).is_err());
+        assert_eq!(spec(Table::Holding, 0xFFFF, Encoding::U16).decode_in(0xFFFF, &[7]), Ok(7.));
+    }
+
+    #[test]
+    fn block_span_covers_every_register() {
+        assert_eq!(block_span(&[]), Ok(None));
+        let (low, wide) = (spec(Table::Input, 0x20, Encoding::U16), spec(Table::Input, 0x24, Encoding::F32));
+        assert_eq!(block_span(&[&wide, &low]), Ok(Some((Table::Input, 0x20, 6))));
+        let holding = spec(Table::Holding, 0x21, Encoding::U16);
+        assert!(block_span(&[&low, &holding]).is_err());
+    }
+
+    #[test]
+    fn block_span_stops_at_the_end_of_the_address_space() {
+        let last = spec(Table::Holding, 0xFFFF, Encoding::U16);
+        assert_eq!(block_span(&[&last]), Ok(Some((Table::Holding, 0xFFFF, 1))));
+        let straddling = spec(Table::Holding, 0xFFFF, Encoding::U32);
+        assert!(block_span(&[&straddling]).is_err());
+    }
+}
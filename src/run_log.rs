@@ -0,0 +1,193 @@
+//!
+//! Run logs: the per-loop CSV records the controller writes to `./data/<epoch_secs>_log.csv`,
+//! and a reader so the records can be re-plotted or analyzed after a run.
+//! Operator commands, annotations and other controller events are interleaved as
+//! comment lines starting with `#`, which readers skip.
+//!
+
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+
+use serde::Serialize;
+
+/// Column header written at the top of every run log.
+/// Columns after `lvMinR` were added later; older logs simply lack them.
+pub const RUN_LOG_CSV_HEADER: &str =
+    "epoch_ms,heat,dip,avg_C,eleco_mA,elecm_mA,elecm_V,elec_R,Rew,hvMinR,lvMinR,set_C,phase,faults,charge_C,energy_J";
+
+/// Names of the electrode drive phases, indexed by the `phase` column value
+pub const DRIVE_PHASE_NAMES: [&str; 5] = ["Fresh", "Warmup", "Nucleation", "Elongation", "Holding"];
+
+/// Fault flag: no thermocouple reported a valid temperature
+pub const FAULT_TK_LOST: u8 = 0x01;
+/// Fault flag: melt temperature exceeded the excessive heat limit
+pub const FAULT_OVER_TEMP: u8 = 0x02;
+/// Fault flag: drive current was requested, but the electrodes look like an open circuit
+pub const FAULT_OPEN_CIRCUIT: u8 = 0x04;
+/// Fault flag: the last melt level probe found the melt below the top of the anode ring
+pub const FAULT_MELT_LOW: u8 = 0x08;
+/// Fault flag: the last per-anode check found an open or high resistance anode
+pub const FAULT_ANODE_FAILED: u8 = 0x10;
+
+/// Short names for each fault flag bit, in bit order
+pub const FAULT_NAMES: [(u8, &str); 5] = [
+    (FAULT_TK_LOST, "tk_lost"),
+    (FAULT_OVER_TEMP, "over_temp"),
+    (FAULT_OPEN_CIRCUIT, "open_circuit"),
+    (FAULT_MELT_LOW, "melt_low"),
+    (FAULT_ANODE_FAILED, "anode_failed"),
+];
+
+/// One row of a run log
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct RunLogSample {
+    /// UTC epoch milliseconds at which the sample was taken
+    pub epoch_ms: i64,
+    /// Whether the furnace heater was on
+    pub heater_on: bool,
+    /// Whether the cathode dipper monitor was enabled
+    pub dipper_enabled: bool,
+    /// Average melt temperature
+    pub avg_temp_c: f32,
+    /// Drive current requested from the current source
+    pub target_drive_ma: f32,
+    /// Measured drive current
+    pub measured_ma: f32,
+    /// Measured potential across the electrodes
+    pub measured_volts: f32,
+    /// Measured inter-electrode resistance
+    pub measured_ohms: f32,
+    /// Exponential moving average of inter-electrode resistance
+    pub ohms_ewma: f32,
+    /// Minimum resistance measured during High-voltage drive
+    pub highv_minr_ohms: f32,
+    /// Minimum resistance measured during Low-voltage drive
+    pub lowv_minr_ohms: f32,
+    /// Furnace temperature setpoint (not present in older logs)
+    pub setpoint_c: Option<f32>,
+    /// Electrode drive phase, see `DRIVE_PHASE_NAMES` (not present in older logs)
+    pub drive_phase: Option<u8>,
+    /// Bitwise OR of `FAULT_*` flags
+    pub faults: u8,
+    /// Charge delivered through the electrodes since the run started (not present in older logs)
+    pub charge_coulombs: Option<f32>,
+    /// Electrical energy delivered since the run started (not present in older logs)
+    pub energy_joules: Option<f32>,
+}
+
+impl RunLogSample {
+    /// Format this sample as a run log line, in `RUN_LOG_CSV_HEADER` column order
+    pub fn to_csv_line(&self) -> String {
+        format!("{},{},{},{:.2},{:.2},{:.2},{:.3},{:.3},{:.3},{:.3},{:.3},{:.1},{},{},{:.3},{:.3}",
+            self.epoch_ms,
+            self.heater_on as u8,
+            self.dipper_enabled as u8,
+            self.avg_temp_c,
+            self.target_drive_ma, self.measured_ma,
+            self.measured_volts,
+            self.measured_ohms, self.ohms_ewma,
+            self.highv_minr_ohms, self.lowv_minr_ohms,
+            self.setpoint_c.unwrap_or(0.),
+            self.drive_phase.unwrap_or(0),
+            self.faults,
+            self.charge_coulombs.unwrap_or(0.),
+            self.energy_joules.unwrap_or(0.),
+        )
+    }
+}
+
+/// Marks a run log line as a comment (an event record) rather than a sample
+pub const RUN_LOG_COMMENT_PREFIX: &str = "#";
+
+/// Format an event as a run log comment line: `# <epoch_ms> <kind> <message>`
+pub fn run_log_event_line(epoch_ms: i64, kind: &str, message: &str) -> String {
+    // keep each event on one line
+    let message = message.replace(['\r', '\n'], " ");
+    format!("{RUN_LOG_COMMENT_PREFIX} {epoch_ms} {kind} {message}")
+}
+
+/// Name of a drive phase, as recorded in the `phase` column
+pub fn drive_phase_name(phase: u8) -> &'static str {
+    DRIVE_PHASE_NAMES.get(phase as usize).copied().unwrap_or("Unknown")
+}
+
+/// Human-readable list of the faults set in a `faults` column value
+pub fn describe_faults(faults: u8) -> String {
+    let names: Vec<&str> = FAULT_NAMES.iter()
+        .filter(|(flag, _)| faults & flag != 0)
+        .map(|(_, name)| *name)
+        .collect();
+    names.join("+")
+}
+
+/// Read all samples from a run log CSV file.
+/// Columns are located by header name, so logs written before a column existed can still be read.
+/// A truncated final line (e.g. the controller was killed mid-write) is skipped.
+pub fn read_run_log(path: &Path) -> Result<Vec<RunLogSample>, Box<dyn std::error::Error>> {
+    let reader = BufReader::new(File::open(path)?);
+    let mut lines = reader.lines();
+
+    let header_line = lines.next().ok_or("run log is empty")??;
+    let header: Vec<&str> = header_line.trim().split(',').collect();
+    let column = |name: &str| header.iter().position(|col| *col == name);
+    let required = |name: &str| column(name).ok_or_else(|| format!("run log has no '{name}' column"));
+
+    let epoch_col = required("epoch_ms")?;
+    let heat_col = required("heat")?;
+    let temp_col = required("avg_C")?;
+    let ordered_col = required("eleco_mA")?;
+    let measured_ma_col = required("elecm_mA")?;
+    let volts_col = required("elecm_V")?;
+    let ohms_col = required("elec_R")?;
+    let ewma_col = required("Rew")?;
+    let hv_minr_col = required("hvMinR")?;
+    let lv_minr_col = required("lvMinR")?;
+    let dip_col = column("dip");
+    let setpoint_col = column("set_C");
+    let phase_col = column("phase");
+    let faults_col = column("faults");
+    let charge_col = column("charge_C");
+    let energy_col = column("energy_J");
+
+    let mut samples = Vec::new();
+    for (idx, line) in lines.enumerate() {
+        let line = line?;
+        if line.starts_with(RUN_LOG_COMMENT_PREFIX) {
+            continue;
+        }
+        let fields: Vec<&str> = line.trim().split(',').collect();
+        if fields.len() != header.len() {
+            // partial line, most likely the last one
+            continue;
+        }
+        let line_num = idx + 2;
+        let float = |col: usize| -> Result<f32, Box<dyn std::error::Error>> {
+            fields[col].parse::<f32>().map_err(|e| format!("line {line_num}: bad value {:?}: {e}", fields[col]).into())
+        };
+        let int = |col: usize| -> Result<i64, Box<dyn std::error::Error>> {
+            fields[col].parse::<i64>().map_err(|e| format!("line {line_num}: bad value {:?}: {e}", fields[col]).into())
+        };
+
+        samples.push(RunLogSample {
+            epoch_ms: int(epoch_col)?,
+            heater_on: int(heat_col)? != 0,
+            dipper_enabled: match dip_col { Some(col) => int(col)? != 0, None => false },
+            avg_temp_c: float(temp_col)?,
+            target_drive_ma: float(ordered_col)?,
+            measured_ma: float(measured_ma_col)?,
+            measured_volts: float(volts_col)?,
+            measured_ohms: float(ohms_col)?,
+            ohms_ewma: float(ewma_col)?,
+            highv_minr_ohms: float(hv_minr_col)?,
+            lowv_minr_ohms: float(lv_minr_col)?,
+            setpoint_c: setpoint_col.map(float).transpose()?,
+            drive_phase: phase_col.map(int).transpose()?.map(|phase| phase as u8),
+            faults: match faults_col { Some(col) => int(col)? as u8, None => 0 },
+            charge_coulombs: charge_col.map(float).transpose()?,
+            energy_joules: energy_col.map(float).transpose()?,
+        });
+    }
+
+    Ok(samples)
+}
@@ -1,5 +1,19 @@
+//!
+//! Driver for the SMC05 stepper motor controller that moves the dipper.
+//!
+//! The controller has a system configuration block (`0x0000..0x000B`: sport mode, and per direction
+//! the speed, acceleration, pulse count and loop delay), a status block (`0x001A..0x0022`), and a serial
+//! command register (`0x0030`). `Smc05Config` and `Smc05Status` read those blocks whole, as typed values;
+//! configuration is validated before anything is written, and written configuration is read back.
+//! The registers marked unverified in the register map have not been exercised on our unit yet.
+//!
+
 use tokio_modbus::{Slave, client::{Reader, Writer}, slave::SlaveContext};
 
+use register_map::smc05;
+
+use crate::contact::*;
+
 use crate::*;
 
 /// Register holding node ID (address) for SMC05
@@ -18,13 +32,6 @@ pub const REG_SMC05_CUR_MOTOR_STATUS:u16  = 0x001A;
 /// SMC05 fwd/rev/start/stop operations
 pub const REG_SMC05_OPERATION_MODE: u16 = 0x0030; 
 
-/// In this "sport mode", run either fwd or rev on command: stop on same command or using start/stop command
-const SMC05_SPORT_MODE_03_FWD_REV_RUNTIL: u16 = 3;
-
-/// In this "sport mode" run fwd,rev, with delays at direction changes
-const SMC05_SPORT_MODE_06_FWD_REV_LOOP: u16 = 6;
-
-
 /// Move the linear stepper motor in the Forward direction ("forward rotation" serial command)
 const ROTATION_DIR_FWD_CMD: u16 = 1;
 /// Move the linear stepper motor in the Reverse direction ("reverse rotation" serial command)
@@ -33,7 +40,7 @@ const ROTATION_DIR_REV_CMD: u16 = 2;
 const START_STOP_OP_COMMAND: u16 = 3;
 
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default)]
 pub struct StepperDriverState {
     /// Whether or not the SMC05 dipper is enabled
     pub dipper_enabled: bool, 
@@ -47,35 +54,319 @@ pub struct StepperDriverState {
     pub dipper_prior_action_count: u16,
     /// The time at which the cathode made contact with the surface
     pub surface_contact_start_ms: i64,
+    /// Absolute position, once homed
+    pub axis: dipper::DipperAxis,
+    /// Debounced surface contact state
+    pub contact: ContactDetector,
+}
+
+/// How long the tip rests in the melt after contact before the monitor starts pulling it back
+pub const SURFACE_CONTACT_SETTLE_MS: i64 = 1000;
+
+
+/// Fastest speed we ask of the dipper motor
+pub const SMC05_MAX_SPEED_RPM: f32 = 1000.;
+
+/// Largest acceleration we configure
+pub const SMC05_MAX_ACCELERATION: u16 = 5000;
+
+/// The action modes the controller runs on a start command
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SportMode {
+    /// Run forward or reverse on command, until the same command or a start/stop command
+    RunUntilStopped,
+    /// Run forward then reverse, pausing at the direction changes, for the configured loop count
+    ForwardReverseLoop,
+    /// A mode we don't drive the dipper with
+    Other(u16),
+}
+
+impl SportMode {
+    pub fn code(self) -> u16 {
+        match self {
+            SportMode::RunUntilStopped => 3,
+            SportMode::ForwardReverseLoop => 6,
+            SportMode::Other(code) => code,
+        }
+    }
+
+    pub fn from_code(code: u16) -> Self {
+        match code {
+            3 => SportMode::RunUntilStopped,
+            6 => SportMode::ForwardReverseLoop,
+            code => SportMode::Other(code),
+        }
+    }
+}
+
+impl std::str::FromStr for SportMode {
+    type Err = String;
+
+    /// Parse `run` (run until stopped), `loop` (forward/reverse loop) or a mode code
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        match text {
+            "run" => Ok(SportMode::RunUntilStopped),
+            "loop" => Ok(SportMode::ForwardReverseLoop),
+            code => code.parse().map(SportMode::from_code)
+                .map_err(|_| format!("unknown sport mode {code:?}: use run, loop or a mode code")),
+        }
+    }
+}
+
+impl std::fmt::Display for SportMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SportMode::RunUntilStopped => f.write_str("run (3)"),
+            SportMode::ForwardReverseLoop => f.write_str("loop (6)"),
+            SportMode::Other(code) => write!(f, "mode {code}"),
+        }
+    }
+}
+
+/// What the motor is doing
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OperatingStatus {
+    Stopped,
+    Accelerating,
+    Decelerating,
+    /// Running at the configured speed
+    Uniform,
+}
+
+impl OperatingStatus {
+    pub fn from_code(code: u16) -> Result<Self, String> {
+        match code {
+            0 => Ok(OperatingStatus::Stopped),
+            1 => Ok(OperatingStatus::Accelerating),
+            2 => Ok(OperatingStatus::Decelerating),
+            3 => Ok(OperatingStatus::Uniform),
+            code => Err(format!("unknown SMC05 motor status {code}")),
+        }
+    }
 }
 
-impl Default for StepperDriverState {
-    fn default() -> Self { Self { 
-            dipper_enabled: false,
-            dipper_last_status_check_ms: 0, 
-            dipper_prior_motion_direction: 0, 
-            dipper_prior_pulse_count: 0, 
-            dipper_prior_action_count: 0, 
-            surface_contact_start_ms: 0,
+impl std::fmt::Display for OperatingStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.pad(match self {
+            OperatingStatus::Stopped => "stopped",
+            OperatingStatus::Accelerating => "accelerating",
+            OperatingStatus::Decelerating => "decelerating",
+            OperatingStatus::Uniform => "uniform",
+        })
+    }
+}
+
+/// Direction of rotation: forward moves the dipper down into the crucible
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Forward,
+    Reverse,
+}
+
+impl Direction {
+    /// The `MOTION_DIRECTION` register code
+    pub fn code(self) -> u16 {
+        match self {
+            Direction::Forward => 0,
+            Direction::Reverse => 1,
+        }
+    }
+
+    pub fn from_code(code: u16) -> Result<Self, String> {
+        match code {
+            0 => Ok(Direction::Forward),
+            1 => Ok(Direction::Reverse),
+            code => Err(format!("unknown SMC05 motion direction {code}")),
         }
     }
 }
 
+impl std::fmt::Display for Direction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.pad(match self {
+            Direction::Forward => "fwd",
+            Direction::Reverse => "rev",
+        })
+    }
+}
+
+/// The controller's status block
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Smc05Status {
+    pub status: OperatingStatus,
+    pub direction: Direction,
+    pub speed_rpm: f32,
+    /// Pulses sent in the current action (wraps at 65536)
+    pub pulse_count: u16,
+    /// Actions run since the sport mode started (wraps at 65536)
+    pub action_count: u16,
+}
 
+impl Smc05Status {
+    pub fn is_moving(&self) -> bool {
+        self.status != OperatingStatus::Stopped
+    }
+}
+
+/// How the motor moves in one direction
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MoveProfile {
+    pub speed_rpm: f32,
+    pub acceleration: u16,
+    /// Pulses per action, in the pulse-count sport modes
+    pub pulses: u16,
+    /// Pause after each action, in the loop sport modes
+    pub delay_secs: f32,
+}
 
-/// # Returns 
-/// (motion_direction, pulse_count, action_count) 
-pub async fn read_stepper_driver_status(ctx: &mut tokio_modbus::client::Context)
--> Result<(u16, u16, u16, u16), Box<dyn std::error::Error>> 
+impl MoveProfile {
+    pub fn validate(&self) -> Result<(), String> {
+        validate_speed(self.speed_rpm)?;
+        if !(1..=SMC05_MAX_ACCELERATION).contains(&self.acceleration) {
+            return Err(format!("acceleration {} outside 1..{SMC05_MAX_ACCELERATION}", self.acceleration));
+        }
+        if self.pulses == 0 {
+            return Err("a move needs at least one pulse".into());
+        }
+        if !(0. ..=6553.5).contains(&self.delay_secs) {
+            return Err(format!("loop delay {} s outside 0..6553.5 s", self.delay_secs));
+        }
+        Ok(())
+    }
+}
+
+/// The controller's system configuration block
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Smc05Config {
+    pub sport_mode: SportMode,
+    pub forward: MoveProfile,
+    pub reverse: MoveProfile,
+    /// Actions per run in the loop sport modes; 0 loops until stopped
+    pub loop_count: u16,
+    pub pulses_per_rev: u16,
+    pub power_on_start: bool,
+}
+
+impl Smc05Config {
+    /// Check every parameter before any of them is written
+    pub fn validate(&self) -> Result<(), String> {
+        if let SportMode::Other(code) = self.sport_mode {
+            return Err(format!("sport mode {code} is not one we drive the dipper with: use run (3) or loop (6)"));
+        }
+        self.forward.validate().map_err(|e| format!("forward: {e}"))?;
+        self.reverse.validate().map_err(|e| format!("reverse: {e}"))?;
+        if self.pulses_per_rev == 0 {
+            return Err("pulses per revolution must be above zero".into());
+        }
+        Ok(())
+    }
+
+    /// Every configuration register with its value, in address order
+    fn values(&self) -> [(&'static RegisterSpec, f64); 12] {
+        [
+            (&smc05::SPORT_MODE.spec, self.sport_mode.code() as f64),
+            (&smc05::FWD_ACCELERATION.spec, self.forward.acceleration as f64),
+            (&smc05::FWD_PULSES.spec, self.forward.pulses as f64),
+            (&smc05::FWD_SPEED.spec, self.forward.speed_rpm as f64),
+            (&smc05::REV_ACCELERATION.spec, self.reverse.acceleration as f64),
+            (&smc05::REV_PULSES.spec, self.reverse.pulses as f64),
+            (&smc05::REV_SPEED.spec, self.reverse.speed_rpm as f64),
+            (&smc05::FWD_DELAY.spec, self.forward.delay_secs as f64),
+            (&smc05::REV_DELAY.spec, self.reverse.delay_secs as f64),
+            (&smc05::LOOP_COUNT.spec, self.loop_count as f64),
+            (&smc05::PULSES_PER_REV.spec, self.pulses_per_rev as f64),
+            (&smc05::POWER_ON_START.spec, u16::from(self.power_on_start) as f64),
+        ]
+    }
+}
+
+impl std::fmt::Display for Smc05Config {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "sport {}, loops {}, {} pulses/rev, power-on start {}",
+            self.sport_mode, self.loop_count, self.pulses_per_rev, if self.power_on_start { "on" } else { "off" })?;
+        for (name, profile) in [("fwd", &self.forward), ("rev", &self.reverse)] {
+            write!(f, "; {name} {} rpm, accel {}, {} pulses, delay {} s",
+                profile.speed_rpm, profile.acceleration, profile.pulses, profile.delay_secs)?;
+        }
+        Ok(())
+    }
+}
+
+fn validate_speed(rpm: f32) -> Result<(), String> {
+    if !(SMC05_MIN_MOVE_RATE_RPM..=SMC05_MAX_SPEED_RPM).contains(&rpm) {
+        return Err(format!("speed {rpm} RPM outside {SMC05_MIN_MOVE_RATE_RPM}..{SMC05_MAX_SPEED_RPM} RPM"));
+    }
+    Ok(())
+}
+
+/// Read the status block in one request
+pub async fn read_smc05_status(ctx: &mut tokio_modbus::client::Context)
+-> Result<Smc05Status, Box<dyn std::error::Error>> 
 {
-    ctx.set_slave(Slave(NODEID_SMC05_STEP_DRIVER));
-    let status_rsp: Vec<u16> = ctx.read_holding_registers(REG_SMC05_CUR_MOTOR_STATUS, 9).await??;
-    // println!("> SMC05 status: {:?}", status_rsp);
-    let op_status = status_rsp[0];
-    let motion_direction = status_rsp[1];
-    let pulse_count = status_rsp[4];
-    let action_count = status_rsp[8];
-    Ok((op_status, motion_direction, pulse_count, action_count))
+    use smc05::*;
+    let (start, block) = read_block(ctx, NODE_ID,
+        &[&MOTOR_STATUS.spec, &MOTION_DIRECTION.spec, &CURRENT_SPEED.spec, &PULSE_COUNT.spec, &ACTION_COUNT.spec]).await?;
+    Ok(Smc05Status {
+        status: OperatingStatus::from_code(MOTOR_STATUS.decode_in(start, &block)?)?,
+        direction: Direction::from_code(MOTION_DIRECTION.decode_in(start, &block)?)?,
+        speed_rpm: CURRENT_SPEED.decode_in(start, &block)?,
+        pulse_count: PULSE_COUNT.decode_in(start, &block)?,
+        action_count: ACTION_COUNT.decode_in(start, &block)?,
+    })
+}
+
+/// Read the system configuration block in one request
+pub async fn read_smc05_config(ctx: &mut tokio_modbus::client::Context)
+-> Result<Smc05Config, Box<dyn std::error::Error>> 
+{
+    use smc05::*;
+    let specs: Vec<&RegisterSpec> = smc05::MAP.registers.iter().filter(|spec| spec.address <= POWER_ON_START.spec.address).collect();
+    let (start, block) = read_block(ctx, NODE_ID, &specs).await?;
+    let profile = |speed: Register<f32>, acceleration: Register<u16>, pulses: Register<u16>, delay: Register<f32>|
+    -> Result<MoveProfile, String> {
+        Ok(MoveProfile {
+            speed_rpm: speed.decode_in(start, &block)?,
+            acceleration: acceleration.decode_in(start, &block)?,
+            pulses: pulses.decode_in(start, &block)?,
+            delay_secs: delay.decode_in(start, &block)?,
+        })
+    };
+    Ok(Smc05Config {
+        sport_mode: SportMode::from_code(SPORT_MODE.decode_in(start, &block)?),
+        forward: profile(FWD_SPEED, FWD_ACCELERATION, FWD_PULSES, FWD_DELAY)?,
+        reverse: profile(REV_SPEED, REV_ACCELERATION, REV_PULSES, REV_DELAY)?,
+        loop_count: LOOP_COUNT.decode_in(start, &block)?,
+        pulses_per_rev: PULSES_PER_REV.decode_in(start, &block)?,
+        power_on_start: POWER_ON_START.decode_in(start, &block)? != 0,
+    })
+}
+
+/// Validate the configuration, write the whole block in one request, and verify it took
+pub async fn write_smc05_config(ctx: &mut tokio_modbus::client::Context, config: &Smc05Config)
+-> Result<(), Box<dyn std::error::Error>> 
+{
+    config.validate()?;
+    let mut words = Vec::new();
+    for (spec, value) in config.values() {
+        words.extend(spec.encode(value)?);
+    }
+    ctx.set_slave(Slave(smc05::NODE_ID));
+    ctx.write_multiple_registers(smc05::SPORT_MODE.spec.address, &words).await??;
+    let readback: Vec<u16> = ctx.read_holding_registers(smc05::SPORT_MODE.spec.address, words.len() as u16).await??;
+    if readback != words {
+        return Err(format!("SMC05 configuration read back {readback:?} after writing {words:?}").into());
+    }
+    Ok(())
+}
+
+/// Read the configuration, change it, and write it back if it is still valid
+pub async fn update_smc05_config(ctx: &mut tokio_modbus::client::Context, change: impl FnOnce(&mut Smc05Config))
+-> Result<Smc05Config, Box<dyn std::error::Error>> 
+{
+    let mut config = read_smc05_config(ctx).await?;
+    change(&mut config);
+    write_smc05_config(ctx, &config).await?;
+    Ok(config)
 }
 
 pub async fn start_sport_mode06_sequence(ctx: &mut tokio_modbus::client::Context) 
@@ -85,19 +376,17 @@ pub async fn start_sport_mode06_sequence(ctx: &mut tokio_modbus::client::Context
 }
 
 pub async fn report_smc05_motor_status(ctx: &mut tokio_modbus::client::Context) 
--> Result<(u16, u16), Box<dyn std::error::Error>>
+-> Result<Smc05Status, Box<dyn std::error::Error>>
 {
-    let (op_status, motor_direction, pulse_count, action_count) = read_stepper_driver_status(ctx).await?;
-    println!("{} SMC05 > op {} dir {} pulse {} action {}", 
-        chrono::Utc::now().timestamp_millis(), op_status, motor_direction, pulse_count, action_count);
-    Ok((op_status, motor_direction))
+    let status = read_smc05_status(ctx).await?;
+    println!("{} SMC05 > op {} dir {} speed {} pulse {} action {}", 
+        chrono::Utc::now().timestamp_millis(), status.status, status.direction, status.speed_rpm,
+        status.pulse_count, status.action_count);
+    Ok(status)
 }
 
 const SMC05_CHECK_ACCEL_TIME_MS: u64 = 250;
 
-pub const SMC05_ROTATION_DIR_FWD: u16 = 0;
-pub const SMC05_ROTATION_DIR_REV: u16 = 1;
-
 /// Minimum rate at which the motor can move (without stopping)
 pub const SMC05_MIN_MOVE_RATE_RPM: f32 = 1.;
 
@@ -108,15 +397,20 @@ pub const SMC05_PROBE_DESCENT_RATE_RPM: f32 = SMC05_MEDIUM_MOVE_RATE_RPM;
 /// Very slow rate at which a cathode can be extracted with precision
 pub const SMC05_PULLBACK_RATE_RPM: f32 = SMC05_MIN_MOVE_RATE_RPM;
 
+/// Linear travel of the dipper rail per motor revolution (T8 lead screw)
+pub const SMC05_RAIL_MM_PER_REV: f32 = 8.;
+/// Rate at which operator jogs move the dipper
+pub const SMC05_JOG_RATE_RPM: f32 = SMC05_SLOW_MOVE_RATE_RPM;
+
 pub async fn start_smc05_fwd_rotation(ctx: &mut tokio_modbus::client::Context) 
 -> Result<(), Box<dyn std::error::Error>>
 {
-    let (op_status, motor_direction) = report_smc05_motor_status(ctx).await?;
-    if motor_direction != SMC05_ROTATION_DIR_FWD {
+    let status = report_smc05_motor_status(ctx).await?;
+    if status.direction != Direction::Forward {
         println!("FLIP -> Fwd");
         send_smc05_fwd_rotation_cmd(ctx).await?;
     }
-    else if op_status == 0 { //still stopped?
+    else if !status.is_moving() { //still stopped?
         println!("restart Fwd ");
         send_smc05_start_stop_cmd(ctx).await?;
     }
@@ -127,12 +421,12 @@ pub async fn start_smc05_fwd_rotation(ctx: &mut tokio_modbus::client::Context)
 pub async fn start_smc05_rev_rotation(ctx: &mut tokio_modbus::client::Context) 
 -> Result<(), Box<dyn std::error::Error>>
 {
-    let (op_status, motor_direction) = report_smc05_motor_status(ctx).await?;
-    if motor_direction != SMC05_ROTATION_DIR_REV {
+    let status = report_smc05_motor_status(ctx).await?;
+    if status.direction != Direction::Reverse {
         println!("FLIP -> Rev");
         send_smc05_rev_rotation_cmd(ctx).await?;
     }
-    else if op_status == 0 { //stopped
+    else if !status.is_moving() { //stopped
         println!("restart Rev");
         send_smc05_start_stop_cmd(ctx).await?;
     }
@@ -144,9 +438,9 @@ pub async fn stop_smc05_rotation(ctx: &mut tokio_modbus::client::Context)
 -> Result<(), Box<dyn std::error::Error>>
 {
     loop {
-        let (op_status, motion_direction) = report_smc05_motor_status(ctx).await?; 
-        if 0 != op_status {
-            println!("STOP dir {}", motion_direction);
+        let status = report_smc05_motor_status(ctx).await?; 
+        if status.is_moving() {
+            println!("STOP dir {}", status.direction);
             send_smc05_start_stop_cmd(ctx).await?;
             sleep(Duration::from_millis(SMC05_CHECK_ACCEL_TIME_MS)).await;
         }
@@ -158,10 +452,8 @@ pub async fn stop_smc05_rotation(ctx: &mut tokio_modbus::client::Context)
 pub async fn send_smc05_serial_op_cmd(ctx: &mut tokio_modbus::client::Context, op_cmd: u16) 
 -> Result<(), Box<dyn std::error::Error>> 
 {
-    ctx.set_slave(Slave(NODEID_SMC05_STEP_DRIVER));
     // println!("0x0030 -> opcmd: {}", op_cmd);
-    ctx.write_single_register(REG_SMC05_OPERATION_MODE, op_cmd).await??;
-    Ok(())
+    smc05::OPERATION.write(ctx, op_cmd).await
 }
 
 pub async fn send_smc05_fwd_rotation_cmd(ctx: &mut tokio_modbus::client::Context) 
@@ -182,10 +474,35 @@ pub async fn send_smc05_start_stop_cmd(ctx: &mut tokio_modbus::client::Context)
     send_smc05_serial_op_cmd(ctx, START_STOP_OP_COMMAND).await
 }
 
+///
+/// Move the dipper a given distance at the jog rate: positive distances move forward (down into the crucible).
+/// The driver has no position feedback here, so the distance is covered by running for a computed time.
+pub async fn jog_smc05_distance(ctx: &mut tokio_modbus::client::Context, distance_mm: f32)
+-> Result<(), Box<dyn std::error::Error>>
+{
+    let revs = distance_mm.abs() / SMC05_RAIL_MM_PER_REV;
+    let run_time = Duration::from_secs_f32(60. * revs / SMC05_JOG_RATE_RPM);
+
+    enable_sport_mode03(ctx).await?;
+    stop_smc05_rotation(ctx).await?;
+    if distance_mm > 0. {
+        set_fwd_speed(ctx, SMC05_JOG_RATE_RPM).await?;
+        start_smc05_fwd_rotation(ctx).await?;
+    }
+    else {
+        set_rev_speed(ctx, SMC05_JOG_RATE_RPM).await?;
+        start_smc05_rev_rotation(ctx).await?;
+    }
+    sleep(run_time).await;
+    stop_smc05_rotation(ctx).await?;
+    Ok(())
+}
+
 /// Disable the dipper monitor
 pub fn disable_dipper_monitor(state: &mut StepperDriverState) {
     state.dipper_enabled = false;
     state.dipper_last_status_check_ms = 0;
+    state.contact.reset();
     println!("Dipper monitor canceling...");
 }
 
@@ -194,6 +511,7 @@ pub fn toggle_dipper_monitor(state: &mut StepperDriverState) {
     let old_enabled = state.dipper_enabled ;
     state.dipper_enabled = !old_enabled;
     state.dipper_last_status_check_ms = 0;
+    state.contact.reset();
     println!("Toggled dipper_enabled {} -> {}", old_enabled, state.dipper_enabled);
 }
 
@@ -220,89 +538,115 @@ pub async fn setup_cathode_surface_probe(ctx: &mut tokio_modbus::client::Context
 }
 
 ///
-/// Try to maintain contact between the cathode and the surface of the electrolyte.
-/// If contact is lost, move the cathode down/forward to regain contact.
-/// If we have contact
+/// Try to keep the cathode tip at the surface of the electrolyte, using the contact detector:
+/// descend while open, hold still at a meniscus, and slowly pull back once the tip has settled into the melt.
+/// While the detector is confirming a change of state, the dipper holds still rather than overshoot.
 pub async fn surface_contact_monitor(
     ctx: &mut tokio_modbus::client::Context, 
     cur_time_utc_ms: i64, 
     state: &mut StepperDriverState, 
-    threshold_ma: f32,
-    measured_ma: f32) 
--> Result<(), Box<dyn std::error::Error>> 
+    drive_ma: f32,
+    measured_ma: f32,
+    measured_ohms: f32) 
+-> Result<Option<ContactTransition>, Box<dyn std::error::Error>> 
 {
-    if measured_ma > threshold_ma {
-        if state.surface_contact_start_ms == 0 {
-            println!("{} Dipper touchdown!", cur_time_utc_ms);
-            stop_smc05_rotation(ctx).await?;
+    let status = state.axis.sync(ctx).await?;
+    state.dipper_prior_motion_direction = status.direction.code();
+    state.dipper_prior_pulse_count = status.pulse_count;
+    state.dipper_prior_action_count = status.action_count;
+    state.dipper_last_status_check_ms = cur_time_utc_ms;
+
+    let transition = state.contact.update(cur_time_utc_ms, drive_ma, measured_ma, measured_ohms);
+    if let Some(transition) = transition {
+        println!("{} Dipper {transition}", cur_time_utc_ms);
+        if transition.to.is_wetted() && state.surface_contact_start_ms == 0 {
             state.surface_contact_start_ms = cur_time_utc_ms;
-            sleep(Duration::from_millis(1000)).await;
         }
-        else {
-            // start very slowly pulling the cathode out of the electrolyte
-            start_smc05_rev_rotation(ctx).await?;
+        else if !transition.to.is_wetted() {
+            state.surface_contact_start_ms = 0;
         }
     }
-    else {
-        // not in contact
-        if state.surface_contact_start_ms != 0 {
-            println!("{} Dipper lost contact!", cur_time_utc_ms);
-            state.surface_contact_start_ms = 0;
+
+    if state.contact.pending().is_some() {
+        stop_smc05_rotation(ctx).await?;
+        return Ok(transition);
+    }
+    match state.contact.state() {
+        ContactState::Open => {
+            if state.axis.can_move(Direction::Forward) {
+                // Move some increment FWD / down into the crucible
+                start_smc05_fwd_rotation(ctx).await?;
+            }
+            else {
+                println!("{} Dipper at the lower soft limit without contact", cur_time_utc_ms);
+                stop_smc05_rotation(ctx).await?;
+            }
+        }
+        ContactState::Meniscus => {
+            stop_smc05_rotation(ctx).await?;
+        }
+        ContactState::Contact => {
+            if cur_time_utc_ms - state.contact.since_ms() < SURFACE_CONTACT_SETTLE_MS {
+                stop_smc05_rotation(ctx).await?;
+            }
+            else if state.axis.can_move(Direction::Reverse) {
+                // start very slowly pulling the cathode out of the electrolyte
+                start_smc05_rev_rotation(ctx).await?;
+            }
+            else {
+                println!("{} Dipper at the upper soft limit", cur_time_utc_ms);
+                stop_smc05_rotation(ctx).await?;
+            }
         }
-        // Move some increment FWD / down into the crucible
-        start_smc05_fwd_rotation(ctx).await?;
     }
-    state.dipper_last_status_check_ms = cur_time_utc_ms;
 
-    Ok(())
+    Ok(transition)
 }
 
 
-/// 
+/// Print the SMC05 system configuration block
 pub async fn report_smc05_system_config(ctx: &mut tokio_modbus::client::Context)
-    -> Result<(), Box<dyn std::error::Error>> 
+    -> Result<Smc05Config, Box<dyn std::error::Error>> 
 {
-    ctx.set_slave(Slave(NODEID_SMC05_STEP_DRIVER));
-    let status_resp: Vec<u16> = ctx.read_holding_registers(REG_SMC05_SPORT_MODE, 12).await??;
-    println!("SMC05 sysconfig: {:?}", status_resp);
-    Ok(())
+    let config = read_smc05_config(ctx).await?;
+    println!("SMC05 sysconfig: {config}");
+    Ok(config)
 }
 
 pub async fn set_fwd_speed(ctx: &mut tokio_modbus::client::Context, rpm: f32)
     -> Result<(), Box<dyn std::error::Error>> 
 {
-    let fxp_rpm: u16 = (10. * rpm).round() as u16;
-    ctx.set_slave(Slave(NODEID_SMC05_STEP_DRIVER));
-    ctx.write_single_register(REG_SMC05_FWD_RPM, fxp_rpm).await??;
-    Ok(())
+    // resolution is 0.1 rpm
+    validate_speed(rpm)?;
+    smc05::FWD_SPEED.write(ctx, rpm).await
 }
 
 pub async fn set_rev_speed(ctx: &mut tokio_modbus::client::Context, rpm: f32)
     -> Result<(), Box<dyn std::error::Error>> 
 {
-    let fxp_rpm = (10. * rpm).round() as u16;
-    ctx.set_slave(Slave(NODEID_SMC05_STEP_DRIVER));
-    ctx.write_single_register(REG_SMC05_REV_RPM, fxp_rpm).await??;
-    Ok(())
+    // resolution is 0.1 rpm
+    validate_speed(rpm)?;
+    smc05::REV_SPEED.write(ctx, rpm).await
 }
 
 ///
 /// Set the sport mode of the SMC05 stepper driver
 /// 
-pub async fn set_smc05_sport_mode(ctx: &mut tokio_modbus::client::Context,  mode: u16)
+pub async fn set_smc05_sport_mode(ctx: &mut tokio_modbus::client::Context, mode: SportMode)
     -> Result<(), Box<dyn std::error::Error>> 
 {
+    if let SportMode::Other(code) = mode {
+        return Err(format!("sport mode {code} is not one we drive the dipper with").into());
+    }
     println!("Set SMC05 Sport Mode {}...", mode);
-    ctx.set_slave(Slave(NODEID_SMC05_STEP_DRIVER));
-    ctx.write_single_register(REG_SMC05_SPORT_MODE, mode).await??;
-    Ok(())
+    smc05::SPORT_MODE.write(ctx, mode.code()).await
 }
 
 
 pub async fn enable_sport_mode03(ctx: &mut tokio_modbus::client::Context)
     -> Result<(), Box<dyn std::error::Error>> 
 {
-    set_smc05_sport_mode(ctx, SMC05_SPORT_MODE_03_FWD_REV_RUNTIL).await
+    set_smc05_sport_mode(ctx, SportMode::RunUntilStopped).await
 }
 
 /// Setup stepper driver to run repeated dip cycle
@@ -311,11 +655,11 @@ pub async fn enable_sport_mode06(ctx: &mut tokio_modbus::client::Context,
     -> Result<(), Box<dyn std::error::Error>> 
 {
     ctx.set_slave(Slave(NODEID_SMC05_STEP_DRIVER));
-    let (_op_status, motor_direction, pulse_count, action_count) = read_stepper_driver_status(ctx).await?;            
-    set_smc05_sport_mode(ctx, SMC05_SPORT_MODE_06_FWD_REV_LOOP).await?;
-    state.dipper_prior_motion_direction = motor_direction;
-    state.dipper_prior_action_count = action_count;
-    state.dipper_prior_pulse_count = pulse_count;
+    let status = read_smc05_status(ctx).await?;
+    set_smc05_sport_mode(ctx, SportMode::ForwardReverseLoop).await?;
+    state.dipper_prior_motion_direction = status.direction.code();
+    state.dipper_prior_action_count = status.action_count;
+    state.dipper_prior_pulse_count = status.pulse_count;
 
     Ok(())
 }
@@ -0,0 +1,321 @@
+//!
+//! Live controller telemetry: the most recent state snapshot plus a short history of
+//! run log samples, shared between the control loop and any remote observers.
+//! Notable state changes (phase, faults, dipper, melt, anodes, set point), running charge totals
+//! and operator commands are also broadcast as discrete events.
+//!
+
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::{Arc, Mutex};
+
+use serde::Serialize;
+use tokio::sync::broadcast;
+
+use crate::anode_check::AnodeCheck;
+use crate::bus::NodeCounters;
+use crate::charge::ChargeTotals;
+use crate::contact::ContactState;
+use crate::run_log::RunLogSample;
+
+/// How many recent samples are retained (at roughly one per second, about an hour)
+pub const TELEMETRY_HISTORY_LEN: usize = 3600;
+
+/// How many snapshots a slow subscriber may fall behind before it starts missing some
+const TELEMETRY_BROADCAST_CAPACITY: usize = 64;
+
+/// How many events a slow subscriber may fall behind before it starts missing some
+const EVENT_BROADCAST_CAPACITY: usize = 256;
+
+/// Furnace heater and temperature state
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct FurnaceSnapshot {
+    /// Temperature set point currently in force
+    pub setpoint_c: f32,
+    /// Operator-supplied set point, if any, which replaces the automatic schedule
+    pub setpoint_override_c: Option<f32>,
+    /// Average melt temperature
+    pub measured_temp_c: f32,
+    /// Thermocouple readings, None if a probe is disconnected
+    pub tk1_c: Option<f32>,
+    pub tk2_c: Option<f32>,
+    /// Whether the furnace heater is turned on
+    pub heater_on: bool,
+    /// Whether no thermocouple reported a valid temperature
+    pub probes_lost: bool,
+    /// Whether the operator has forced the heater off
+    pub heater_forced_off: bool,
+}
+
+/// Electrode drive state
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ElectrodeSnapshot {
+    /// Drive phase index, see `run_log::DRIVE_PHASE_NAMES`
+    pub drive_phase: u8,
+    pub drive_phase_name: String,
+    /// UTC epoch milliseconds at which this drive phase started
+    pub phase_start_ms: i64,
+    /// The drive current requested from the current source
+    pub target_drive_ma: f32,
+    /// The drive current reported by the current source
+    pub reported_drive_ma: f32,
+    /// The measured drive current
+    pub measured_ma: f32,
+    /// The measured potential across the electrodes
+    pub measured_volts: f32,
+    /// Measured inter-electrode resistance
+    pub measured_ohms: f32,
+    /// Exponential moving average of inter-electrode resistance
+    pub ohms_ewma: f32,
+    /// Maximum value of the resistance EWMA
+    pub max_ohms_ewma: f32,
+    /// Minimum resistance measured during Low-voltage drive
+    pub lowv_minr_ohms: f32,
+    /// Minimum resistance measured during High-voltage drive
+    pub highv_minr_ohms: f32,
+    /// Operator-fixed drive current, if any, which replaces the drive phase's choice
+    pub drive_override_ma: Option<f32>,
+    /// Operator cap on the drive current, if any
+    pub drive_limit_ma: Option<f32>,
+    /// Whether the drive phase clock is paused
+    pub phase_clock_paused: bool,
+}
+
+/// Cathode dipper (stepper driver) state
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct DipperSnapshot {
+    /// Whether the dipper monitor is enabled
+    pub enabled: bool,
+    /// Whether the cathode is believed to be touching the melt
+    pub surface_contact: bool,
+    /// Debounced contact state: open, meniscus or contact
+    pub contact: ContactState,
+    /// The time at which the cathode made contact with the surface (0 if not in contact)
+    pub surface_contact_start_ms: i64,
+    /// Last time the driver status was checked
+    pub last_status_check_ms: i64,
+    /// Last known motion direction
+    pub motion_direction: u16,
+    /// Last known pulse count
+    pub pulse_count: u16,
+    /// Last known action count
+    pub action_count: u16,
+    /// Millimetres below home, once the dipper has been homed
+    pub position_mm: Option<f32>,
+}
+
+/// Melt level from the latest probe of the surface
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct MeltSnapshot {
+    /// When the surface was last probed, if ever
+    pub sampled_ms: Option<i64>,
+    /// Dipper position at contact, in mm below home
+    pub surface_mm: Option<f32>,
+    /// Melt depth above the crucible floor
+    pub level_mm: Option<f32>,
+    /// Estimated melt volume from the crucible geometry
+    pub volume_ml: Option<f32>,
+    /// Level trend over recent probes, negative is falling
+    pub trend_mm_per_hour: Option<f32>,
+    /// Whether the melt leaves the top of the anode ring uncovered
+    pub below_anode_ring: bool,
+}
+
+/// Charge and energy delivered through the electrodes (see `charge`)
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ChargeSnapshot {
+    /// Name of the cathode reaction the carbon estimate assumes
+    pub reaction: String,
+    /// Totals since the run started
+    pub run: ChargeTotals,
+    pub run_amp_hours: f64,
+    /// Theoretical carbon deposited over the run, from Faraday's law
+    pub run_carbon_mg: f64,
+    /// Totals since the current drive phase was entered
+    pub phase: ChargeTotals,
+    pub phase_carbon_mg: f64,
+    /// Charge at which the current drive phase ends, if any
+    pub phase_target_coulombs: Option<f64>,
+    /// Time left out of the totals because the drive wasn't being measured
+    pub unaccounted_ms: i64,
+}
+
+/// Relay output state
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct RelaySnapshot {
+    /// Furnace heater relay
+    pub heater: bool,
+    /// Anode connection relays, in channel order
+    pub anodes: Vec<bool>,
+    /// Whether the anode pattern is set by the operator rather than the drive phase
+    pub anode_override: bool,
+    /// What drives the anode relays: a fixed set of anodes, or a timed pattern
+    pub anode_pattern: String,
+}
+
+/// Everything an observer needs to know about the controller at one moment
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ControllerSnapshot {
+    /// UTC epoch milliseconds at which the snapshot was taken
+    pub epoch_ms: i64,
+    pub furnace: FurnaceSnapshot,
+    pub electrodes: ElectrodeSnapshot,
+    pub dipper: DipperSnapshot,
+    pub melt: MeltSnapshot,
+    pub charge: ChargeSnapshot,
+    pub relays: RelaySnapshot,
+    /// The latest per-anode check, if any
+    pub anode_check: Option<AnodeCheck>,
+    /// Bitwise OR of `run_log::FAULT_*` flags
+    pub faults: u8,
+    /// Names of the faults currently set
+    pub fault_names: Vec<&'static str>,
+    /// Modbus traffic counters, keyed by node ID
+    pub bus: BTreeMap<u8, NodeCounters>,
+}
+
+/// Something notable that happened to the controller
+#[derive(Debug, Clone, Serialize)]
+pub struct ControllerEvent {
+    /// UTC epoch milliseconds at which the event happened
+    pub epoch_ms: i64,
+    /// Event category: `phase`, `fault`, `dipper`, `melt`, `anodes`, `setpoint`, `charge`, `command`, or `note`
+    pub kind: &'static str,
+    /// Human-readable description
+    pub message: String,
+}
+
+impl ControllerEvent {
+    pub fn new(epoch_ms: i64, kind: &'static str, message: impl Into<String>) -> Self {
+        Self { epoch_ms, kind, message: message.into() }
+    }
+}
+
+/// Events implied by the differences between two consecutive snapshots
+pub fn state_change_events(prev: &ControllerSnapshot, cur: &ControllerSnapshot) -> Vec<ControllerEvent> {
+    let mut events = Vec::new();
+    let at = cur.epoch_ms;
+    if prev.electrodes.drive_phase != cur.electrodes.drive_phase {
+        events.push(ControllerEvent::new(at, "phase",
+            format!("{} -> {} after {}", prev.electrodes.drive_phase_name, cur.electrodes.drive_phase_name, prev.charge.phase)));
+    }
+    for name in &cur.fault_names {
+        if !prev.fault_names.contains(name) {
+            events.push(ControllerEvent::new(at, "fault", format!("{name} set")));
+        }
+    }
+    for name in &prev.fault_names {
+        if !cur.fault_names.contains(name) {
+            events.push(ControllerEvent::new(at, "fault", format!("{name} cleared")));
+        }
+    }
+    if prev.dipper.enabled != cur.dipper.enabled {
+        events.push(ControllerEvent::new(at, "dipper",
+            if cur.dipper.enabled { "monitor enabled" } else { "monitor disabled" }));
+    }
+    if prev.dipper.contact != cur.dipper.contact {
+        events.push(ControllerEvent::new(at, "dipper",
+            format!("{} -> {} at {:.3} mA, {:.2} Ω", prev.dipper.contact, cur.dipper.contact,
+                cur.electrodes.measured_ma, cur.electrodes.measured_ohms)));
+    }
+    if prev.melt.sampled_ms != cur.melt.sampled_ms && let Some(level_mm) = cur.melt.level_mm {
+        let trend = cur.melt.trend_mm_per_hour.map_or(String::new(), |trend| format!(", trend {trend:+.2} mm/h"));
+        events.push(ControllerEvent::new(at, "melt",
+            format!("level {level_mm:.2} mm, {:.1} ml{trend}", cur.melt.volume_ml.unwrap_or_default())));
+    }
+    if let Some(check) = &cur.anode_check && prev.anode_check.as_ref().map(|check| check.epoch_ms) != Some(check.epoch_ms) {
+        events.push(ControllerEvent::new(at, "anodes", format!("check at {:.1} mA: {}", check.probe_ma, check.summary())));
+    }
+    if prev.furnace.setpoint_c != cur.furnace.setpoint_c {
+        events.push(ControllerEvent::new(at, "setpoint",
+            format!("{:.1} -> {:.1} °C", prev.furnace.setpoint_c, cur.furnace.setpoint_c)));
+    }
+    events
+}
+
+/// Look up a state field by dotted path (e.g. `furnace.setpoint_c`, `relays.anodes.0`) and format it as JSON.
+/// An empty path lists the top-level fields.
+pub fn query_snapshot(snapshot: &ControllerSnapshot, path: &str) -> Result<String, String> {
+    let root = serde_json::to_value(snapshot).map_err(|e| e.to_string())?;
+    let mut value = &root;
+    for key in path.split('.').filter(|key| !key.is_empty()) {
+        value = match value {
+            serde_json::Value::Object(fields) => fields.get(key),
+            serde_json::Value::Array(items) => key.parse::<usize>().ok().and_then(|idx| items.get(idx)),
+            _ => None,
+        }.ok_or_else(|| format!("No state field {path:?}"))?;
+    }
+    match value {
+        serde_json::Value::Object(fields) if path.is_empty() =>
+            Ok(fields.keys().cloned().collect::<Vec<_>>().join(" ")),
+        _ => Ok(format!("{path} = {value}")),
+    }
+}
+
+#[derive(Default)]
+struct HubState {
+    latest: Option<ControllerSnapshot>,
+    samples: VecDeque<RunLogSample>,
+}
+
+/// Shared store the control loop publishes into, and servers read from
+pub struct TelemetryHub {
+    state: Mutex<HubState>,
+    updates: broadcast::Sender<Arc<ControllerSnapshot>>,
+    events: broadcast::Sender<ControllerEvent>,
+}
+
+impl TelemetryHub {
+    pub fn new() -> Arc<Self> {
+        let (updates, _) = broadcast::channel(TELEMETRY_BROADCAST_CAPACITY);
+        let (events, _) = broadcast::channel(EVENT_BROADCAST_CAPACITY);
+        Arc::new(Self { state: Mutex::default(), updates, events })
+    }
+
+    /// Receive every event emitted from now on
+    pub fn subscribe_events(&self) -> broadcast::Receiver<ControllerEvent> {
+        self.events.subscribe()
+    }
+
+    /// Broadcast an event to all event subscribers
+    pub fn emit(&self, event: ControllerEvent) {
+        let _ = self.events.send(event);
+    }
+
+    /// Receive every snapshot published from now on
+    pub fn subscribe(&self) -> broadcast::Receiver<Arc<ControllerSnapshot>> {
+        self.updates.subscribe()
+    }
+
+    /// Record the latest controller state and its run log sample,
+    /// emitting events for any notable changes since the previous snapshot
+    pub fn publish(&self, snapshot: ControllerSnapshot, sample: RunLogSample) {
+        // no subscribers is fine
+        let _ = self.updates.send(Arc::new(snapshot.clone()));
+        let mut state = self.state.lock().unwrap();
+        if let Some(prev) = &state.latest {
+            for event in state_change_events(prev, &snapshot) {
+                self.emit(event);
+            }
+        }
+        state.latest = Some(snapshot);
+        if state.samples.len() == TELEMETRY_HISTORY_LEN {
+            state.samples.pop_front();
+        }
+        state.samples.push_back(sample);
+    }
+
+    /// The most recently published snapshot, if any
+    pub fn latest(&self) -> Option<ControllerSnapshot> {
+        self.state.lock().unwrap().latest.clone()
+    }
+
+    /// Up to `limit` of the most recent samples taken after `since_ms` (oldest first)
+    pub fn recent_samples(&self, since_ms: Option<i64>, limit: usize) -> Vec<RunLogSample> {
+        let state = self.state.lock().unwrap();
+        let newer: Vec<&RunLogSample> = state.samples.iter().rev()
+            .take_while(|sample| since_ms.is_none_or(|since| sample.epoch_ms > since))
+            .take(limit)
+            .collect();
+        newer.into_iter().rev().cloned().collect()
+    }
+}
@@ -0,0 +1,25 @@
+//!
+//! Typed electrical quantities, so a reading can't be mistaken for the other kind.
+//!
+
+use serde::Serialize;
+
+/// An electric potential
+#[derive(Debug, Clone, Copy, Default, PartialEq, PartialOrd, Serialize)]
+pub struct Volts(pub f32);
+
+/// An electric current
+#[derive(Debug, Clone, Copy, Default, PartialEq, PartialOrd, Serialize)]
+pub struct Milliamps(pub f32);
+
+impl std::fmt::Display for Volts {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:.3} V", self.0)
+    }
+}
+
+impl std::fmt::Display for Milliamps {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:.3} mA", self.0)
+    }
+}
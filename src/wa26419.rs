@@ -0,0 +1,284 @@
+//!
+//! Driver for the Waveshare WA26419 8 channel analog output, which simulates the pyrometer's 4-20 mA loop.
+//!
+//! Each channel has an output mode register (`0x1000..0x1007`) that sets how its output register
+//! (`0x0000..0x0007`) is read: microamps over 0-20 mA (code 0) or 4-20 mA (code 1), or a raw
+//! DAC code 0-4096 (code 2). Every write is clamped to the channel's range and read back,
+//! and a mismatch is an error: an output we can't confirm is an output we don't trust.
+//!
+//! The module keeps its outputs across our restarts, so `apply_safe_defaults` puts every channel
+//! into a known mode at the bottom of its range before anything else drives it.
+//!
+
+use std::str::FromStr;
+
+use crate::*;
+use crate::units::*;
+
+/// Number of output channels
+pub const WA26419_CHANNEL_COUNT: usize = 8;
+
+/// Largest code a channel accepts in raw mode
+pub const WA26419_MAX_RAW_CODE: u16 = 4096;
+
+/// Output modes set by `apply_safe_defaults`: the pyrometer loop is 4-20 mA
+pub const WA26419_SAFE_MODES: [AnalogOutputMode; WA26419_CHANNEL_COUNT] = [AnalogOutputMode::Milliamps4To20; WA26419_CHANNEL_COUNT];
+
+/// A channel's output mode
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AnalogOutputMode {
+    Milliamps0To20,
+    Milliamps4To20,
+    /// Uncalibrated DAC code
+    RawCode,
+}
+
+impl AnalogOutputMode {
+    pub const ALL: [AnalogOutputMode; 3] = [
+        AnalogOutputMode::Milliamps0To20, AnalogOutputMode::Milliamps4To20, AnalogOutputMode::RawCode,
+    ];
+
+    /// The mode register code
+    pub fn code(self) -> u16 {
+        match self {
+            AnalogOutputMode::Milliamps0To20 => 0,
+            AnalogOutputMode::Milliamps4To20 => 1,
+            AnalogOutputMode::RawCode => 2,
+        }
+    }
+
+    pub fn from_code(code: u16) -> Result<Self, String> {
+        Self::ALL.into_iter().find(|mode| mode.code() == code)
+            .ok_or_else(|| format!("unknown WA26419 output mode code {code}"))
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            AnalogOutputMode::Milliamps0To20 => "0-20mA",
+            AnalogOutputMode::Milliamps4To20 => "4-20mA",
+            AnalogOutputMode::RawCode => "raw",
+        }
+    }
+
+    /// The output current range, for the current modes
+    pub fn range(self) -> Option<(Milliamps, Milliamps)> {
+        match self {
+            AnalogOutputMode::Milliamps0To20 => Some((Milliamps(0.), Milliamps(20.))),
+            AnalogOutputMode::Milliamps4To20 => Some((Milliamps(4.), Milliamps(20.))),
+            AnalogOutputMode::RawCode => None,
+        }
+    }
+
+    /// The output register value at the bottom of the range: the safe output
+    pub fn safe_word(self) -> u16 {
+        self.range().map_or(0, |(low, _)| milliamps_to_word(low))
+    }
+}
+
+impl FromStr for AnalogOutputMode {
+    type Err = String;
+
+    /// Parse a mode name (`0-20mA`, `4-20mA` or `raw`) or a mode code
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        if let Ok(code) = text.parse::<u16>() {
+            return Self::from_code(code);
+        }
+        Self::ALL.into_iter().find(|mode| mode.name().eq_ignore_ascii_case(text))
+            .ok_or_else(|| format!("unknown output mode {text:?}: use 0-20mA, 4-20mA or raw"))
+    }
+}
+
+impl std::fmt::Display for AnalogOutputMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.pad(self.name())
+    }
+}
+
+/// One WA26419 on the bus
+#[derive(Debug, Clone, PartialEq)]
+pub struct Wa26419 {
+    pub node_id: u8,
+    /// Channel modes as last read or written; `None` until then
+    modes: [Option<AnalogOutputMode>; WA26419_CHANNEL_COUNT],
+}
+
+impl Default for Wa26419 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Wa26419 {
+    /// The WA26419 at our usual node ID, channel modes not yet known
+    pub fn new() -> Self {
+        Self { node_id: NODEID_WA26419_8CH_DAC, modes: [None; WA26419_CHANNEL_COUNT] }
+    }
+
+    pub fn at_node(self, node_id: u8) -> Self {
+        Self { node_id, ..self }
+    }
+
+    /// The mode of channel `channel` (1-8), if known
+    pub fn mode(&self, channel: u8) -> Result<Option<AnalogOutputMode>, String> {
+        Ok(self.modes[channel_index(channel)?])
+    }
+
+    /// Read all eight channel modes in one request
+    pub async fn read_modes(&mut self, ctx: &mut tokio_modbus::client::Context)
+    -> Result<[AnalogOutputMode; WA26419_CHANNEL_COUNT], Box<dyn std::error::Error>>
+    {
+        let words = self.read_words(ctx, &WA26419_MODES).await?;
+        let mut modes = [AnalogOutputMode::Milliamps0To20; WA26419_CHANNEL_COUNT];
+        for (mode, word) in modes.iter_mut().zip(words) {
+            *mode = AnalogOutputMode::from_code(word)?;
+        }
+        self.modes = modes.map(Some);
+        Ok(modes)
+    }
+
+    /// Set one channel's mode, and verify it took. The output is set to the bottom of the new range
+    /// first, since the value it held means something else in the new mode.
+    pub async fn set_mode(&mut self, ctx: &mut tokio_modbus::client::Context, channel: u8, mode: AnalogOutputMode)
+    -> Result<(), Box<dyn std::error::Error>>
+    {
+        let idx = channel_index(channel)?;
+        self.modes[idx] = None;
+        self.write_verified(ctx, &WA26419_OUTPUTS[idx..=idx], &[mode.safe_word()]).await?;
+        self.write_verified(ctx, &WA26419_MODES[idx..=idx], &[mode.code()]).await?;
+        self.modes[idx] = Some(mode);
+        Ok(())
+    }
+
+    /// Set all eight channel modes in one request, and verify they took.
+    /// Every output is set to the bottom of its new range first.
+    pub async fn set_modes(&mut self, ctx: &mut tokio_modbus::client::Context, modes: [AnalogOutputMode; WA26419_CHANNEL_COUNT])
+    -> Result<(), Box<dyn std::error::Error>>
+    {
+        self.modes = [None; WA26419_CHANNEL_COUNT];
+        self.write_verified(ctx, &WA26419_OUTPUTS, &modes.map(AnalogOutputMode::safe_word)).await?;
+        self.write_verified(ctx, &WA26419_MODES, &modes.map(AnalogOutputMode::code)).await?;
+        self.modes = modes.map(Some);
+        Ok(())
+    }
+
+    /// Put every channel in its safe mode at the bottom of its range
+    pub async fn apply_safe_defaults(&mut self, ctx: &mut tokio_modbus::client::Context) -> Result<(), Box<dyn std::error::Error>> {
+        self.set_modes(ctx, WA26419_SAFE_MODES).await
+    }
+
+    /// The output current a request on channel `channel` (1-8) becomes: clamped to its mode's range,
+    /// and rounded to the register's 1 µA resolution
+    pub fn clamp(&self, channel: u8, milliamps: Milliamps) -> Result<Milliamps, String> {
+        let mode = self.known_mode(channel)?;
+        let (low, high) = mode.range()
+            .ok_or_else(|| format!("WA26419 channel {channel} is in raw mode: set a code, not a current"))?;
+        let limited = if milliamps.0.is_nan() { low.0 } else { milliamps.0.clamp(low.0, high.0) };
+        Ok(word_to_milliamps(milliamps_to_word(Milliamps(limited))))
+    }
+
+    /// Set one channel's output current, clamped, and verify it took. Returns the current actually ordered.
+    pub async fn set_output(&mut self, ctx: &mut tokio_modbus::client::Context, channel: u8, milliamps: Milliamps)
+    -> Result<Milliamps, Box<dyn std::error::Error>>
+    {
+        let idx = channel_index(channel)?;
+        self.ensure_modes(ctx).await?;
+        let ordered = self.clamp(channel, milliamps)?;
+        self.write_verified(ctx, &WA26419_OUTPUTS[idx..=idx], &[milliamps_to_word(ordered)]).await?;
+        Ok(ordered)
+    }
+
+    /// Set all eight output currents in one request, clamped, and verify they took.
+    /// Returns the currents actually ordered.
+    pub async fn set_outputs(&mut self, ctx: &mut tokio_modbus::client::Context, milliamps: [Milliamps; WA26419_CHANNEL_COUNT])
+    -> Result<[Milliamps; WA26419_CHANNEL_COUNT], Box<dyn std::error::Error>>
+    {
+        self.ensure_modes(ctx).await?;
+        let mut ordered = [Milliamps(0.); WA26419_CHANNEL_COUNT];
+        for (idx, (ordered, requested)) in ordered.iter_mut().zip(milliamps).enumerate() {
+            *ordered = self.clamp(idx as u8 + 1, requested)?;
+        }
+        self.write_verified(ctx, &WA26419_OUTPUTS, &ordered.map(milliamps_to_word)).await?;
+        Ok(ordered)
+    }
+
+    /// Set a raw-mode channel's DAC code, and verify it took
+    pub async fn set_code(&mut self, ctx: &mut tokio_modbus::client::Context, channel: u8, code: u16)
+    -> Result<(), Box<dyn std::error::Error>>
+    {
+        let idx = channel_index(channel)?;
+        self.ensure_modes(ctx).await?;
+        if self.known_mode(channel)? != AnalogOutputMode::RawCode {
+            return Err(format!("WA26419 channel {channel} is not in raw mode: set a current, not a code").into());
+        }
+        if code > WA26419_MAX_RAW_CODE {
+            return Err(format!("code {code} outside 0..{WA26419_MAX_RAW_CODE}").into());
+        }
+        self.write_verified(ctx, &WA26419_OUTPUTS[idx..=idx], &[code]).await
+    }
+
+    /// Read all eight output registers: milliamps in the current modes, codes in raw mode
+    pub async fn read_outputs(&mut self, ctx: &mut tokio_modbus::client::Context)
+    -> Result<[u16; WA26419_CHANNEL_COUNT], Box<dyn std::error::Error>>
+    {
+        let words = self.read_words(ctx, &WA26419_OUTPUTS).await?;
+        let mut outputs = [0; WA26419_CHANNEL_COUNT];
+        outputs.copy_from_slice(&words);
+        Ok(outputs)
+    }
+
+    async fn ensure_modes(&mut self, ctx: &mut tokio_modbus::client::Context) -> Result<(), Box<dyn std::error::Error>> {
+        if self.modes.iter().any(Option::is_none) {
+            self.read_modes(ctx).await?;
+        }
+        Ok(())
+    }
+
+    fn known_mode(&self, channel: u8) -> Result<AnalogOutputMode, String> {
+        self.modes[channel_index(channel)?].ok_or_else(|| format!("WA26419 channel {channel} mode not read yet"))
+    }
+
+    /// Raw words of a run of consecutive registers
+    async fn read_words<T: RegisterValue>(&self, ctx: &mut tokio_modbus::client::Context, registers: &[Register<T>])
+    -> Result<Vec<u16>, Box<dyn std::error::Error>>
+    {
+        let specs: Vec<&RegisterSpec> = registers.iter().map(|register| &register.spec).collect();
+        let (_, block) = read_block(ctx, self.node_id, &specs).await?;
+        if block.len() != registers.len() {
+            return Err(format!("WA26419 returned {} registers, expected {}", block.len(), registers.len()).into());
+        }
+        Ok(block)
+    }
+
+    /// Write raw words to a run of consecutive registers, then read them back
+    async fn write_verified<T: RegisterValue>(&self, ctx: &mut tokio_modbus::client::Context, registers: &[Register<T>], words: &[u16])
+    -> Result<(), Box<dyn std::error::Error>>
+    {
+        let Some(first) = registers.first() else { return Ok(()) };
+        ctx.set_slave(Slave(self.node_id));
+        if let [word] = words {
+            ctx.write_single_register(first.spec.address, *word).await??;
+        } else {
+            ctx.write_multiple_registers(first.spec.address, words).await??;
+        }
+        let readback = self.read_words(ctx, registers).await?;
+        if readback != words {
+            let names: Vec<&str> = registers.iter().map(|register| register.spec.name).collect();
+            return Err(format!("WA26419 {} read back {readback:?} after writing {words:?}", names.join(",")).into());
+        }
+        Ok(())
+    }
+}
+
+/// The output register value for a current, in microamps
+pub fn milliamps_to_word(milliamps: Milliamps) -> u16 {
+    (milliamps.0 * 1000.).round().clamp(0., u16::MAX as f32) as u16
+}
+
+pub fn word_to_milliamps(word: u16) -> Milliamps {
+    Milliamps(word as f32 / 1000.)
+}
+
+fn channel_index(channel: u8) -> Result<usize, String> {
+    (channel as usize).checked_sub(1).filter(|idx| *idx < WA26419_CHANNEL_COUNT)
+        .ok_or_else(|| format!("channel {channel} outside 1..{WA26419_CHANNEL_COUNT}"))
+}
@@ -0,0 +1,319 @@
+//!
+//! Driver for the Waveshare WA8TAI 8 channel analog input.
+//!
+//! Each channel has its own data mode register (`0x1000..0x1007`), which sets both the input range
+//! and what the channel register (`0x0000..0x0007`) then reports:
+//!
+//! | Code | Range | Channel register |
+//! |-------|----------|----------|
+//! | 0 | 0-5 V (0-10 V on the 10 V boards) | millivolts |
+//! | 1 | 1-5 V (2-10 V on the 10 V boards) | millivolts |
+//! | 2 | 0-20 mA | microamps |
+//! | 3 | 4-20 mA | microamps |
+//! | 4 | raw | ADC code 0-4096 |
+//!
+//! `Wa8tai` keeps the modes it last read or wrote, so a reading comes back as `Volts` or `Milliamps`
+//! according to its channel's mode. Raw codes are only given units by a `RawCalibration`
+//! (a linear fit measured against a reference); without one they are returned as codes.
+//!
+
+use std::str::FromStr;
+
+use crate::*;
+use crate::units::*;
+
+/// Number of input channels
+pub const WA8TAI_CHANNEL_COUNT: usize = 8;
+
+/// Largest code a channel reports in raw mode
+pub const WA8TAI_MAX_RAW_CODE: u16 = 4096;
+
+/// A channel's data mode
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AnalogInputMode {
+    /// 0-5 V, or 0-10 V on the 10 V boards
+    Volts0To5,
+    /// 1-5 V, or 2-10 V on the 10 V boards
+    Volts1To5,
+    Milliamps0To20,
+    Milliamps4To20,
+    /// Uncalibrated ADC code
+    RawCode,
+}
+
+impl AnalogInputMode {
+    pub const ALL: [AnalogInputMode; 5] = [
+        AnalogInputMode::Volts0To5, AnalogInputMode::Volts1To5,
+        AnalogInputMode::Milliamps0To20, AnalogInputMode::Milliamps4To20, AnalogInputMode::RawCode,
+    ];
+
+    /// The mode register code
+    pub fn code(self) -> u16 {
+        match self {
+            AnalogInputMode::Volts0To5 => 0,
+            AnalogInputMode::Volts1To5 => 1,
+            AnalogInputMode::Milliamps0To20 => 2,
+            AnalogInputMode::Milliamps4To20 => 3,
+            AnalogInputMode::RawCode => 4,
+        }
+    }
+
+    pub fn from_code(code: u16) -> Result<Self, String> {
+        Self::ALL.into_iter().find(|mode| mode.code() == code)
+            .ok_or_else(|| format!("unknown WA8TAI data mode code {code}"))
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            AnalogInputMode::Volts0To5 => "0-5V",
+            AnalogInputMode::Volts1To5 => "1-5V",
+            AnalogInputMode::Milliamps0To20 => "0-20mA",
+            AnalogInputMode::Milliamps4To20 => "4-20mA",
+            AnalogInputMode::RawCode => "raw",
+        }
+    }
+}
+
+impl FromStr for AnalogInputMode {
+    type Err = String;
+
+    /// Parse a mode name (`0-5V`, `1-5V`, `0-20mA`, `4-20mA` or `raw`) or a mode code
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        if let Ok(code) = text.parse::<u16>() {
+            return Self::from_code(code);
+        }
+        Self::ALL.into_iter().find(|mode| mode.name().eq_ignore_ascii_case(text))
+            .ok_or_else(|| format!("unknown input mode {text:?}: use 0-5V, 1-5V, 0-20mA, 4-20mA or raw"))
+    }
+}
+
+impl std::fmt::Display for AnalogInputMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.pad(self.name())
+    }
+}
+
+/// What a calibrated raw code stands for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AnalogUnit {
+    Volts,
+    Milliamps,
+}
+
+/// Linear conversion of a raw-mode code: `value = gain * code + offset`, in `unit`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RawCalibration {
+    pub gain: f32,
+    pub offset: f32,
+    pub unit: AnalogUnit,
+}
+
+impl RawCalibration {
+    /// The calibration through two measured points `(code, value)`
+    pub fn from_points(low: (u16, f32), high: (u16, f32), unit: AnalogUnit) -> Result<Self, String> {
+        if low.0 == high.0 {
+            return Err(format!("calibration points share code {}", low.0));
+        }
+        let gain = (high.1 - low.1) / (high.0 as f32 - low.0 as f32);
+        Ok(Self { gain, offset: low.1 - gain * low.0 as f32, unit })
+    }
+
+    pub fn apply(&self, code: u16) -> AnalogReading {
+        let value = self.gain * code as f32 + self.offset;
+        match self.unit {
+            AnalogUnit::Volts => AnalogReading::Volts(Volts(value)),
+            AnalogUnit::Milliamps => AnalogReading::Milliamps(Milliamps(value)),
+        }
+    }
+}
+
+impl FromStr for RawCalibration {
+    type Err = String;
+
+    /// Parse `<gain>,<offset>[,V|mA]`; the unit defaults to volts
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let parts: Vec<&str> = text.split(',').map(str::trim).collect();
+        let parse = |part: &str| part.parse::<f32>().ok().filter(|value| value.is_finite())
+            .ok_or_else(|| format!("bad calibration {text:?}: use <gain>,<offset>[,V|mA]"));
+        let unit = match parts.get(2).map(|unit| unit.to_ascii_lowercase()) {
+            None => AnalogUnit::Volts,
+            Some(unit) if unit == "v" => AnalogUnit::Volts,
+            Some(unit) if unit == "ma" => AnalogUnit::Milliamps,
+            Some(_) => return Err(format!("bad calibration unit in {text:?}: use V or mA")),
+        };
+        match parts.as_slice() {
+            [gain, offset] | [gain, offset, _] => Ok(Self { gain: parse(gain)?, offset: parse(offset)?, unit }),
+            _ => Err(format!("bad calibration {text:?}: use <gain>,<offset>[,V|mA]")),
+        }
+    }
+}
+
+/// One channel reading, typed by the channel's mode
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AnalogReading {
+    Volts(Volts),
+    Milliamps(Milliamps),
+    /// A raw-mode code without a calibration
+    Code(u16),
+}
+
+impl AnalogReading {
+    pub fn volts(self) -> Option<Volts> {
+        match self {
+            AnalogReading::Volts(volts) => Some(volts),
+            _ => None,
+        }
+    }
+
+    pub fn milliamps(self) -> Option<Milliamps> {
+        match self {
+            AnalogReading::Milliamps(milliamps) => Some(milliamps),
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for AnalogReading {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AnalogReading::Volts(volts) => volts.fmt(f),
+            AnalogReading::Milliamps(milliamps) => milliamps.fmt(f),
+            AnalogReading::Code(code) => write!(f, "code {code}"),
+        }
+    }
+}
+
+/// One WA8TAI on the bus
+#[derive(Debug, Clone, PartialEq)]
+pub struct Wa8tai {
+    pub node_id: u8,
+    /// Channel modes as last read or written; `None` until then
+    modes: [Option<AnalogInputMode>; WA8TAI_CHANNEL_COUNT],
+    calibrations: [Option<RawCalibration>; WA8TAI_CHANNEL_COUNT],
+}
+
+impl Default for Wa8tai {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Wa8tai {
+    /// The WA8TAI at our usual node ID, channel modes not yet known
+    pub fn new() -> Self {
+        Self { node_id: NODEID_WA8TAI_IV_ADC, modes: [None; WA8TAI_CHANNEL_COUNT], calibrations: [None; WA8TAI_CHANNEL_COUNT] }
+    }
+
+    pub fn at_node(self, node_id: u8) -> Self {
+        Self { node_id, ..self }
+    }
+
+    /// Convert channel `channel` (1-8) raw codes with `calibration` while it is in raw mode
+    pub fn set_calibration(&mut self, channel: u8, calibration: Option<RawCalibration>) -> Result<(), String> {
+        self.calibrations[channel_index(channel)?] = calibration;
+        Ok(())
+    }
+
+    /// The mode of channel `channel` (1-8), if known
+    pub fn mode(&self, channel: u8) -> Result<Option<AnalogInputMode>, String> {
+        Ok(self.modes[channel_index(channel)?])
+    }
+
+    /// Read all eight channel modes in one request
+    pub async fn read_modes(&mut self, ctx: &mut tokio_modbus::client::Context)
+    -> Result<[AnalogInputMode; WA8TAI_CHANNEL_COUNT], Box<dyn std::error::Error>>
+    {
+        let specs: Vec<&RegisterSpec> = WA8TAI_MODES.iter().map(|mode| &mode.spec).collect();
+        let (start, block) = read_block(ctx, self.node_id, &specs).await?;
+        let mut modes = [AnalogInputMode::Volts0To5; WA8TAI_CHANNEL_COUNT];
+        for (mode, register) in modes.iter_mut().zip(WA8TAI_MODES.iter()) {
+            *mode = AnalogInputMode::from_code(register.decode_in(start, &block)?)?;
+        }
+        self.modes = modes.map(Some);
+        Ok(modes)
+    }
+
+    /// Set one channel's mode, and verify it took
+    pub async fn set_mode(&mut self, ctx: &mut tokio_modbus::client::Context, channel: u8, mode: AnalogInputMode)
+    -> Result<(), Box<dyn std::error::Error>>
+    {
+        let idx = channel_index(channel)?;
+        let register = &WA8TAI_MODES[idx];
+        self.modes[idx] = None;
+        register.write_to(ctx, self.node_id, mode.code()).await?;
+        let readback = register.read_from(ctx, self.node_id).await?;
+        if readback != mode.code() {
+            return Err(format!("WA8TAI channel {channel} mode reads back {readback} after writing {} ({mode})",
+                mode.code()).into());
+        }
+        self.modes[idx] = Some(mode);
+        Ok(())
+    }
+
+    /// Set all eight channel modes in one request, and verify they took
+    pub async fn set_modes(&mut self, ctx: &mut tokio_modbus::client::Context, modes: [AnalogInputMode; WA8TAI_CHANNEL_COUNT])
+    -> Result<(), Box<dyn std::error::Error>>
+    {
+        let codes = modes.map(AnalogInputMode::code);
+        self.modes = [None; WA8TAI_CHANNEL_COUNT];
+        ctx.set_slave(Slave(self.node_id));
+        ctx.write_multiple_registers(WA8TAI_MODES[0].spec.address, &codes).await??;
+        let readback = self.read_modes(ctx).await?;
+        if readback != modes {
+            return Err(format!("WA8TAI modes read back {:?} after writing {codes:?}",
+                readback.map(AnalogInputMode::code)).into());
+        }
+        Ok(())
+    }
+
+    /// Interpret a raw channel register value according to the channel's mode
+    pub fn convert(&self, channel: u8, raw: u16) -> Result<AnalogReading, String> {
+        let idx = channel_index(channel)?;
+        let mode = self.modes[idx].ok_or_else(|| format!("WA8TAI channel {channel} mode not read yet"))?;
+        Ok(match mode {
+            AnalogInputMode::Volts0To5 | AnalogInputMode::Volts1To5 => AnalogReading::Volts(Volts(raw as f32 / 1000.)),
+            AnalogInputMode::Milliamps0To20 | AnalogInputMode::Milliamps4To20 => AnalogReading::Milliamps(Milliamps(raw as f32 / 1000.)),
+            AnalogInputMode::RawCode => match self.calibrations[idx] {
+                Some(calibration) => calibration.apply(raw),
+                None => AnalogReading::Code(raw),
+            },
+        })
+    }
+
+    /// Read one channel (1-8), reading the channel modes first if they aren't known
+    pub async fn read_channel(&mut self, ctx: &mut tokio_modbus::client::Context, channel: u8)
+    -> Result<AnalogReading, Box<dyn std::error::Error>>
+    {
+        let idx = channel_index(channel)?;
+        if self.modes[idx].is_none() {
+            self.read_modes(ctx).await?;
+        }
+        ctx.set_slave(Slave(self.node_id));
+        let raw = ctx.read_input_registers(WA8TAI_CHANNELS[idx].spec.address, 1).await??;
+        let raw = raw.first().copied().ok_or("WA8TAI returned no channel value")?;
+        Ok(self.convert(channel, raw)?)
+    }
+
+    /// Read all eight channels in one request, reading the channel modes first if they aren't known
+    pub async fn read_all(&mut self, ctx: &mut tokio_modbus::client::Context)
+    -> Result<[AnalogReading; WA8TAI_CHANNEL_COUNT], Box<dyn std::error::Error>>
+    {
+        if self.modes.iter().any(Option::is_none) {
+            self.read_modes(ctx).await?;
+        }
+        let specs: Vec<&RegisterSpec> = WA8TAI_CHANNELS.iter().map(|channel| &channel.spec).collect();
+        let (start, block) = read_block(ctx, self.node_id, &specs).await?;
+        let mut readings = [AnalogReading::Code(0); WA8TAI_CHANNEL_COUNT];
+        for (idx, (reading, register)) in readings.iter_mut().zip(WA8TAI_CHANNELS.iter()).enumerate() {
+            let raw = block.get((register.spec.address - start) as usize).copied()
+                .ok_or_else(|| format!("{} is outside the block read", register.spec.name))?;
+            *reading = self.convert(idx as u8 + 1, raw)?;
+        }
+        Ok(readings)
+    }
+}
+
+fn channel_index(channel: u8) -> Result<usize, String> {
+    (channel as usize).checked_sub(1).filter(|idx| *idx < WA8TAI_CHANNEL_COUNT)
+        .ok_or_else(|| format!("channel {channel} outside 1..{WA8TAI_CHANNEL_COUNT}"))
+}
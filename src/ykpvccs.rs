@@ -0,0 +1,334 @@
+//!
+//! Driver for the YK-PVCCS precision current sources that drive the test electrodes.
+//!
+//! The YK-PVCCS0100 (0-100 mA in 0.1 mA steps) and YK-PVCCS1000 (0-1000 mA in 1 mA steps)
+//! share one register layout: node ID, baud rate code, save-config, the drive setpoint
+//! and the source's own ammeter. The two differ only in the scale of the current registers,
+//! and nothing on the bus tells them apart, so the model is configured: in code, or with the
+//! `CRAVEN_CURRENT_SOURCE` environment variable (`ykpvccs0100` or `ykpvccs1000`).
+//! `CurrentSource::identify` checks that the configured node really is a YK-PVCCS
+//! by reading back its node ID register, and `CurrentSource::check_scale` checks the model
+//! against the electrode IV meter: a source configured as the wrong model delivers a tenth,
+//! or ten times, the current asked of it.
+//!
+//! Neither model has an output enable or limit register: the output is off at a zero
+//! setpoint, and limits are enforced here, by clamping every request to the model's range
+//! (and an optional lower limit) and rounding it to the model's resolution.
+//! Every setpoint written is read back, and a mismatch is an error.
+//!
+
+use std::str::FromStr;
+
+use crate::*;
+
+/// Environment variable naming the current source model on the bus
+pub const CURRENT_SOURCE_ENV_VAR: &str = "CRAVEN_CURRENT_SOURCE";
+
+/// Drive current for a scale check: small, yet well above the IV meter's resolution
+/// if the source turns out to be the 0-100 mA model
+pub const SCALE_CHECK_MILLIAMPS: f32 = 5.;
+
+/// Below this the IV meter sees no current (open electrodes), and the scale can't be checked
+pub const SCALE_CHECK_MIN_MEASURED_MA: f32 = 0.2;
+
+/// Largest ratio, either way, between the measured and ordered scale check currents.
+/// The models differ tenfold, so this leaves room for meter and source error.
+pub const SCALE_CHECK_MAX_RATIO: f32 = 2.;
+
+/// Baud rates for the `BAUD` register codes 0-6
+pub const YKPVCCS_BAUD_RATES: [u32; 7] = [2400, 4800, 9600, 19200, 38400, 57600, 115200];
+
+/// The YK-PVCCS variants
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CurrentSourceModel {
+    /// 0-100 mA, 0.1 mA resolution
+    Ykpvccs0100,
+    /// 0-1000 mA, 1 mA resolution
+    Ykpvccs1000,
+}
+
+impl CurrentSourceModel {
+    /// The model we have on the test stand, unless configured otherwise
+    pub const DEFAULT: CurrentSourceModel = CurrentSourceModel::Ykpvccs1000;
+
+    /// Largest drive current the model can source
+    pub fn max_milliamps(self) -> f32 {
+        match self {
+            CurrentSourceModel::Ykpvccs0100 => 100.,
+            CurrentSourceModel::Ykpvccs1000 => 1000.,
+        }
+    }
+
+    /// Smallest step in drive current
+    pub fn resolution_milliamps(self) -> f32 {
+        match self {
+            CurrentSourceModel::Ykpvccs0100 => 0.1,
+            CurrentSourceModel::Ykpvccs1000 => 1.,
+        }
+    }
+
+    /// Drive setpoint register
+    pub fn drive_register(self) -> Register<f32> {
+        match self {
+            CurrentSourceModel::Ykpvccs0100 => ykpvccs0100::DRIVE_CURRENT,
+            CurrentSourceModel::Ykpvccs1000 => ykpvccs1000::DRIVE_CURRENT,
+        }
+    }
+
+    /// Ammeter register
+    pub fn monitor_register(self) -> Register<f32> {
+        match self {
+            CurrentSourceModel::Ykpvccs0100 => ykpvccs0100::MONITOR_CURRENT,
+            CurrentSourceModel::Ykpvccs1000 => ykpvccs1000::MONITOR_CURRENT,
+        }
+    }
+
+    /// The model's full register map
+    pub fn map(self) -> &'static DeviceMap {
+        match self {
+            CurrentSourceModel::Ykpvccs0100 => &ykpvccs0100::MAP,
+            CurrentSourceModel::Ykpvccs1000 => &ykpvccs1000::MAP,
+        }
+    }
+
+    /// The model whose scale is tenfold this one's
+    pub fn other(self) -> Self {
+        match self {
+            CurrentSourceModel::Ykpvccs0100 => CurrentSourceModel::Ykpvccs1000,
+            CurrentSourceModel::Ykpvccs1000 => CurrentSourceModel::Ykpvccs0100,
+        }
+    }
+
+    /// The model named by `CRAVEN_CURRENT_SOURCE`, or the default
+    pub fn from_env() -> Result<Self, String> {
+        match std::env::var(CURRENT_SOURCE_ENV_VAR) {
+            Ok(model) => model.parse().map_err(|e| format!("{CURRENT_SOURCE_ENV_VAR}: {e}")),
+            Err(_) => Ok(Self::DEFAULT),
+        }
+    }
+}
+
+impl FromStr for CurrentSourceModel {
+    type Err = String;
+
+    /// Parse a register map model name, with or without the `yk-` prefix
+    fn from_str(model: &str) -> Result<Self, Self::Err> {
+        let model = model.to_ascii_lowercase().replace('-', "");
+        match model.trim_start_matches("yk") {
+            "pvccs0100" => Ok(CurrentSourceModel::Ykpvccs0100),
+            "pvccs1000" => Ok(CurrentSourceModel::Ykpvccs1000),
+            _ => Err(format!("unknown current source model {model:?}: use ykpvccs0100 or ykpvccs1000")),
+        }
+    }
+}
+
+impl std::fmt::Display for CurrentSourceModel {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.map().model)
+    }
+}
+
+/// Communication settings read from a current source
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CurrentSourceConfig {
+    pub node_address: u8,
+    pub baud_code: u16,
+    /// The baud rate the code stands for, if it is a known code
+    pub baud_rate: Option<u32>,
+}
+
+/// One YK-PVCCS on the bus
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CurrentSource {
+    pub model: CurrentSourceModel,
+    pub node_id: u8,
+    /// Requests above this are clamped to it; at most the model's maximum
+    pub limit_milliamps: f32,
+}
+
+impl CurrentSource {
+    /// A current source of `model` at our usual node ID, limited only by the model's range
+    pub fn new(model: CurrentSourceModel) -> Self {
+        Self { model, node_id: NODEID_YKPVCCS010_CURR_SRC, limit_milliamps: model.max_milliamps() }
+    }
+
+    /// The current source configured by `CRAVEN_CURRENT_SOURCE`
+    pub fn from_env() -> Result<Self, String> {
+        CurrentSourceModel::from_env().map(Self::new)
+    }
+
+    pub fn at_node(self, node_id: u8) -> Self {
+        Self { node_id, ..self }
+    }
+
+    /// Limit requests to below the model's maximum
+    pub fn with_limit(self, limit_milliamps: f32) -> Self {
+        Self { limit_milliamps: limit_milliamps.clamp(0., self.model.max_milliamps()), ..self }
+    }
+
+    /// Check that the node answers like a YK-PVCCS: its node ID register holds its own node ID
+    pub async fn identify(&self, ctx: &mut tokio_modbus::client::Context)
+    -> Result<CurrentSourceConfig, Box<dyn std::error::Error>>
+    {
+        let config = self.read_config(ctx).await?;
+        if config.node_address != self.node_id {
+            return Err(format!("node 0x{:02X} reports node ID 0x{:02X}: not a YK-PVCCS?",
+                self.node_id, config.node_address).into());
+        }
+        Ok(config)
+    }
+
+    /// Node ID and baud rate settings
+    pub async fn read_config(&self, ctx: &mut tokio_modbus::client::Context)
+    -> Result<CurrentSourceConfig, Box<dyn std::error::Error>>
+    {
+        use register_map::ykpvccs1000::*;
+        let (start, block) = read_block(ctx, self.node_id, &[&NODE_ADDRESS.spec, &BAUD.spec]).await?;
+        let node_address = NODE_ADDRESS.decode_in(start, &block)?;
+        let baud_code = BAUD.decode_in(start, &block)?;
+        Ok(CurrentSourceConfig {
+            node_address: u8::try_from(node_address).unwrap_or(u8::MAX),
+            baud_code,
+            baud_rate: YKPVCCS_BAUD_RATES.get(baud_code as usize).copied(),
+        })
+    }
+
+    /// The drive current a request becomes: clamped to 0..limit and rounded to the model's resolution
+    pub fn clamp(&self, milliamps: f32) -> f32 {
+        let resolution = self.model.resolution_milliamps();
+        let limited = if milliamps.is_nan() { 0. } else { milliamps.clamp(0., self.limit_milliamps) };
+        // round down at the limit, so rounding never exceeds it
+        let steps = (limited / resolution).round();
+        let steps = if steps * resolution > self.limit_milliamps { steps - 1. } else { steps };
+        steps * resolution
+    }
+
+    /// Set the drive current, clamped, and verify the setpoint register took it.
+    /// Returns the current actually ordered.
+    pub async fn set_drive(&self, ctx: &mut tokio_modbus::client::Context, milliamps: f32)
+    -> Result<f32, Box<dyn std::error::Error>>
+    {
+        let ordered = self.clamp(milliamps);
+        let drive = self.model.drive_register();
+        drive.write_to(ctx, self.node_id, ordered).await?;
+        let readback = drive.read_from(ctx, self.node_id).await?;
+        if (readback - ordered).abs() > self.model.resolution_milliamps() / 2. {
+            return Err(format!("{} setpoint reads back {readback} mA after writing {ordered} mA",
+                self.model).into());
+        }
+        Ok(ordered)
+    }
+
+    /// Check the configured model against the electrode IV meter: drive a small test setpoint,
+    /// let it settle, measure it, then turn the output off again.
+    /// Returns the measured current, or `None` if no current flows and the scale can't be told.
+    pub async fn check_scale(&self, ctx: &mut tokio_modbus::client::Context, settle: std::time::Duration)
+    -> Result<Option<f32>, Box<dyn std::error::Error>>
+    {
+        let ordered = self.set_drive(ctx, SCALE_CHECK_MILLIAMPS).await?;
+        tokio::time::sleep(settle).await;
+        let measured = read_wdcu3003_iv_adc(ctx).await;
+        self.output_off(ctx).await?;
+        let (_volts, measured_ma) = measured?;
+        if ordered <= 0. || measured_ma.is_nan() || measured_ma < SCALE_CHECK_MIN_MEASURED_MA {
+            return Ok(None);
+        }
+        let ratio = measured_ma / ordered;
+        if !(1. / SCALE_CHECK_MAX_RATIO..=SCALE_CHECK_MAX_RATIO).contains(&ratio) {
+            return Err(format!("{} test setpoint of {ordered} mA measured as {measured_ma:.2} mA at the electrodes: \
+                is the source a {}? (set {CURRENT_SOURCE_ENV_VAR} or --model)", self.model, self.model.other()).into());
+        }
+        Ok(Some(measured_ma))
+    }
+
+    /// Turn the output off (a zero setpoint: the models have no separate output enable)
+    pub async fn output_off(&self, ctx: &mut tokio_modbus::client::Context) -> Result<(), Box<dyn std::error::Error>> {
+        self.set_drive(ctx, 0.).await.map(|_| ())
+    }
+
+    /// The drive current setpoint
+    pub async fn read_setpoint(&self, ctx: &mut tokio_modbus::client::Context) -> Result<f32, Box<dyn std::error::Error>> {
+        self.model.drive_register().read_from(ctx, self.node_id).await
+    }
+
+    /// The output current measured by the source's own ammeter
+    pub async fn read_monitor(&self, ctx: &mut tokio_modbus::client::Context) -> Result<f32, Box<dyn std::error::Error>> {
+        self.model.monitor_register().read_from(ctx, self.node_id).await
+    }
+
+    /// Write a new node ID, then persist it: the device answers at the new node ID from then on
+    pub async fn set_node_address(&self, ctx: &mut tokio_modbus::client::Context, new_node_id: u8)
+    -> Result<Self, Box<dyn std::error::Error>>
+    {
+        if !(1..=NODEID_MAX).contains(&new_node_id) {
+            return Err(format!("node ID 0x{new_node_id:02X} outside 1..{NODEID_MAX}").into());
+        }
+        ykpvccs1000::NODE_ADDRESS.write_to(ctx, self.node_id, new_node_id as u16).await?;
+        let moved = self.at_node(new_node_id);
+        moved.save_config(ctx).await?;
+        Ok(moved)
+    }
+
+    /// Write a new baud rate code and persist it; it takes effect once the device restarts
+    pub async fn set_baud_code(&self, ctx: &mut tokio_modbus::client::Context, baud_code: u16)
+    -> Result<(), Box<dyn std::error::Error>>
+    {
+        if YKPVCCS_BAUD_RATES.get(baud_code as usize).is_none() {
+            return Err(format!("baud code {baud_code} outside 0..{}", YKPVCCS_BAUD_RATES.len() - 1).into());
+        }
+        ykpvccs1000::BAUD.write_to(ctx, self.node_id, baud_code).await?;
+        self.save_config(ctx).await
+    }
+
+    /// Persist the node ID and baud rate: the source forgets changes it isn't told to save
+    pub async fn save_config(&self, ctx: &mut tokio_modbus::client::Context) -> Result<(), Box<dyn std::error::Error>> {
+        ykpvccs1000::SAVE_CONFIG.write_to(ctx, self.node_id, 1).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: f32, expected: f32) {
+        assert!((actual - expected).abs() < 1e-4, "{actual} != {expected}");
+    }
+
+    #[test]
+    fn clamp_keeps_requests_in_range() {
+        let source = CurrentSource::new(CurrentSourceModel::Ykpvccs1000);
+        assert_eq!(source.clamp(f32::NAN), 0.);
+        assert_eq!(source.clamp(-5.), 0.);
+        assert_eq!(source.clamp(1500.), 1000.);
+        assert_eq!(source.clamp(f32::INFINITY), 1000.);
+        assert_eq!(source.clamp(12.4), 12.);
+        assert_eq!(source.clamp(12.6), 13.);
+    }
+
+    #[test]
+    fn clamp_rounds_down_at_a_limit_off_the_resolution() {
+        let source = CurrentSource::new(CurrentSourceModel::Ykpvccs0100).with_limit(55.55);
+        assert_close(source.clamp(55.55), 55.5);
+        assert_close(source.clamp(80.), 55.5);
+        assert_close(source.clamp(12.34), 12.3);
+        // a limit beyond the model's range is the model's range
+        let source = CurrentSource::new(CurrentSourceModel::Ykpvccs0100).with_limit(500.);
+        assert_close(source.clamp(500.), 100.);
+    }
+
+    #[test]
+    fn model_names_parse() {
+        for name in ["ykpvccs0100", "YK-PVCCS0100", "pvccs0100", "Yk-Pvccs0100"] {
+            assert_eq!(name.parse(), Ok(CurrentSourceModel::Ykpvccs0100), "{name}");
+        }
+        for name in ["ykpvccs1000", "YK-PVCCS1000", "pvccs1000"] {
+            assert_eq!(name.parse(), Ok(CurrentSourceModel::Ykpvccs1000), "{name}");
+        }
+        for model in [CurrentSourceModel::Ykpvccs0100, CurrentSourceModel::Ykpvccs1000] {
+            assert_eq!(model.to_string().parse(), Ok(model));
+        }
+        for name in ["", "ykpvccs", "ykpvccs0200", "wa8tai"] {
+            assert!(name.parse::<CurrentSourceModel>().is_err(), "{name}");
+        }
+    }
+}